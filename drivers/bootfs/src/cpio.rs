@@ -0,0 +1,283 @@
+//! CPIO newc 格式解析
+//!
+//! 每个条目由 110 字节的 ASCII 头、以 NUL 结尾的文件名和文件数据组成，头加文件名、文件数据分别按 4 字节对齐。
+//! 名为 `TRAILER!!!` 的条目表示归档结束。
+
+/// newc 格式的魔数
+pub const NEWC_MAGIC: &[u8; 6] = b"070701";
+
+/// 头的长度：魔数加 13 个 8 位十六进制字段
+const HEADER_SIZE: usize = 110;
+
+/// 结束条目的文件名
+const TRAILER: &str = "TRAILER!!!";
+
+/// `mode` 中的文件类型（与 `st_mode` 相同）
+pub const MODE_TYPE_MASK: u32 = 0o170000;
+pub const MODE_DIRECTORY: u32 = 0o040000;
+pub const MODE_REGULAR: u32 = 0o100000;
+pub const MODE_SYMLINK: u32 = 0o120000;
+
+/// 头中各字段的序号
+const FIELD_MODE: usize = 1;
+const FIELD_FILESIZE: usize = 6;
+const FIELD_NAMESIZE: usize = 11;
+
+/// 解析错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpioError {
+    /// 魔数不是 newc 格式
+    BadMagic,
+    /// 头中的字段不是十六进制数
+    BadHeader,
+    /// 文件名不是 UTF-8 或没有 NUL 结尾
+    BadName,
+    /// 条目超出了归档末尾
+    Truncated,
+}
+
+/// 归档中的一个条目
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<'a> {
+    /// 文件名，保留归档中的原样（通常带 `./` 前缀）
+    pub name: &'a str,
+    pub mode: u32,
+    /// 文件数据在归档中的偏移
+    pub data_offset: usize,
+    pub data: &'a [u8],
+}
+
+/// 依次返回归档中的条目，遇到结束条目或错误后停止
+pub struct Entries<'a> {
+    image: &'a [u8],
+    offset: usize,
+    done: bool,
+}
+
+/// 遍历归档 `image` 中的条目
+pub fn entries(image: &[u8]) -> Entries<'_> {
+    Entries {
+        image,
+        offset: 0,
+        done: false,
+    }
+}
+
+const fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+fn field(header: &[u8], index: usize) -> Result<u32, CpioError> {
+    let start = NEWC_MAGIC.len() + index * 8;
+    let digits =
+        core::str::from_utf8(&header[start..start + 8]).map_err(|_| CpioError::BadHeader)?;
+    u32::from_str_radix(digits, 16).map_err(|_| CpioError::BadHeader)
+}
+
+impl<'a> Entries<'a> {
+    fn parse(&mut self) -> Result<Option<Entry<'a>>, CpioError> {
+        let header = self
+            .image
+            .get(self.offset..self.offset + HEADER_SIZE)
+            .ok_or(CpioError::Truncated)?;
+        if &header[..NEWC_MAGIC.len()] != NEWC_MAGIC {
+            return Err(CpioError::BadMagic);
+        }
+
+        let mode = field(header, FIELD_MODE)?;
+        let file_size = field(header, FIELD_FILESIZE)? as usize;
+        let name_size = field(header, FIELD_NAMESIZE)? as usize;
+
+        // 文件名长度包括结尾的 NUL
+        let name_start = self.offset + HEADER_SIZE;
+        let name = self
+            .image
+            .get(name_start..name_start + name_size)
+            .ok_or(CpioError::Truncated)?;
+        let name = name
+            .strip_suffix(&[0])
+            .and_then(|name| core::str::from_utf8(name).ok())
+            .ok_or(CpioError::BadName)?;
+
+        let data_offset = align4(name_start + name_size);
+        let data = self
+            .image
+            .get(data_offset..data_offset + file_size)
+            .ok_or(CpioError::Truncated)?;
+        self.offset = align4(data_offset + file_size);
+
+        if name == TRAILER {
+            return Ok(None);
+        }
+        Ok(Some(Entry {
+            name,
+            mode,
+            data_offset,
+            data,
+        }))
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<Entry<'a>, CpioError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.parse() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, vec::Vec};
+
+    use super::*;
+
+    /// 按 newc 格式追加一个条目，头加文件名、文件数据分别补齐到 4 字节
+    fn push_entry(image: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let fields = [
+            0,
+            mode,
+            0,
+            0,
+            1,
+            0,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            name.len() as u32 + 1,
+            0,
+        ];
+        image.extend_from_slice(NEWC_MAGIC);
+        for value in fields {
+            image.extend_from_slice(format!("{value:08X}").as_bytes());
+        }
+        image.extend_from_slice(name.as_bytes());
+        image.push(0);
+        image.resize(align4(image.len()), 0);
+        image.extend_from_slice(data);
+        image.resize(align4(image.len()), 0);
+    }
+
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut image = Vec::new();
+        for (name, data) in files {
+            push_entry(&mut image, name, MODE_REGULAR | 0o644, data);
+        }
+        push_entry(&mut image, TRAILER, 0, &[]);
+        image
+    }
+
+    #[test]
+    fn parses_entries_until_trailer() {
+        let mut image = archive(&[("./init", b"hello"), ("./etc/conf", b"abcd")]);
+        // 结束条目之后的内容（例如填充到块大小的零）被忽略
+        image.extend_from_slice(&[0; 512]);
+
+        let entries: Vec<_> = entries(&image).collect::<Result<_, _>>().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "./init");
+        assert_eq!(entries[0].mode & MODE_TYPE_MASK, MODE_REGULAR);
+        assert_eq!(entries[0].data, b"hello");
+        assert_eq!(entries[0].data_offset % 4, 0);
+        assert_eq!(
+            &image[entries[1].data_offset..entries[1].data_offset + 4],
+            b"abcd"
+        );
+        assert_eq!(entries[1].name, "./etc/conf");
+    }
+
+    #[test]
+    fn trailer_only_archive_is_empty() {
+        let image = archive(&[]);
+        assert!(entries(&image).next().is_none());
+    }
+
+    #[test]
+    fn truncated_header() {
+        let image = archive(&[("./init", b"hello")]);
+        let mut iter = entries(&image[..HEADER_SIZE - 1]);
+        assert_eq!(iter.next().unwrap().unwrap_err(), CpioError::Truncated);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn truncated_data() {
+        let image = archive(&[("./init", b"hello")]);
+        let first = entries(&image).next().unwrap().unwrap();
+        let cut = first.data_offset + 2;
+        assert_eq!(
+            entries(&image[..cut]).next().unwrap().unwrap_err(),
+            CpioError::Truncated
+        );
+    }
+
+    #[test]
+    fn missing_trailer_is_truncated() {
+        let mut image = Vec::new();
+        push_entry(&mut image, "./init", MODE_REGULAR, b"hello");
+        let mut iter = entries(&image);
+        assert!(iter.next().unwrap().is_ok());
+        assert_eq!(iter.next().unwrap().unwrap_err(), CpioError::Truncated);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn misaligned_header() {
+        // 第一个条目的数据（5 字节）后缺少填充，下一个头不在 4 字节边界上
+        let mut image = Vec::new();
+        push_entry(&mut image, "./init", MODE_REGULAR, b"hello");
+        image.truncate(image.len() - 3);
+        push_entry(&mut image, TRAILER, 0, &[]);
+
+        let mut iter = entries(&image);
+        assert!(iter.next().unwrap().is_ok());
+        assert_eq!(iter.next().unwrap().unwrap_err(), CpioError::BadMagic);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut image = archive(&[("./init", b"hello")]);
+        image[5] = b'7';
+        assert_eq!(
+            entries(&image).next().unwrap().unwrap_err(),
+            CpioError::BadMagic
+        );
+    }
+
+    #[test]
+    fn bad_header_field() {
+        let mut image = archive(&[("./init", b"hello")]);
+        // 文件大小字段中出现非十六进制字符
+        image[NEWC_MAGIC.len() + FIELD_FILESIZE * 8] = b'G';
+        assert_eq!(
+            entries(&image).next().unwrap().unwrap_err(),
+            CpioError::BadHeader
+        );
+    }
+
+    #[test]
+    fn name_without_nul() {
+        let mut image = archive(&[("./init", b"hello")]);
+        image[HEADER_SIZE + "./init".len()] = b'x';
+        assert_eq!(
+            entries(&image).next().unwrap().unwrap_err(),
+            CpioError::BadName
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Bootfs：只读的 initramfs 文件系统
+//!
+//! 内核把整个 initramfs（CPIO newc 归档）作为只读 VMO 交给 init，init 再把它交给 bootfs 服务，
+//! bootfs 服务挂载在 namespace 的 `/boot` 下，以 VMO 的形式提供其中的文件。
+//! init 在 namespace 启动之前也用这里的解析器直接从归档中加载服务程序。
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod cpio;
+
+use alloc::vec::Vec;
+use libradon::memory::{MappingFlags, Vmo, map_vmo};
+
+use crate::cpio::{CpioError, MODE_DIRECTORY, MODE_REGULAR, MODE_SYMLINK, MODE_TYPE_MASK};
+
+/// bootfs 在 namespace 中的挂载点
+pub const BOOTFS_MOUNT_POINT: &str = "/boot";
+
+/// bootfs 的驱动服务名
+pub const BOOTFS_SERVICE_NAME: &str = "bootfs";
+
+/// initramfs VMO 在 init 和 bootfs 服务中的初始句柄序号
+pub const IMAGE_HANDLE_INDEX: usize = 0;
+
+/// 节点类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Regular,
+    Directory,
+    Symlink,
+    Other,
+}
+
+impl NodeKind {
+    fn from_mode(mode: u32) -> Self {
+        match mode & MODE_TYPE_MASK {
+            MODE_REGULAR => Self::Regular,
+            MODE_DIRECTORY => Self::Directory,
+            MODE_SYMLINK => Self::Symlink,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// 归档中的一个文件或目录
+#[derive(Debug, Clone, Copy)]
+pub struct Node<'a> {
+    /// 规范化后的路径，根目录为空字符串
+    pub path: &'a str,
+    pub kind: NodeKind,
+    /// 文件数据在归档中的偏移
+    pub data_offset: usize,
+    /// 文件数据，符号链接为链接目标
+    pub data: &'a [u8],
+}
+
+impl Node<'_> {
+    /// 路径的最后一个部分
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or("")
+    }
+
+    /// 所在目录的路径
+    fn parent(&self) -> &str {
+        self.path.rsplit_once('/').map_or("", |(parent, _)| parent)
+    }
+}
+
+/// 去掉路径开头的 `.`、`./` 和首尾的 `/`，`.`、`/` 和空路径都表示根目录
+pub fn normalize(path: &str) -> &str {
+    let path = path
+        .strip_prefix('.')
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        .unwrap_or(path);
+    path.trim_matches('/')
+}
+
+/// 解析好的归档，节点编号在归档的生命周期内不变
+pub struct Bootfs<'a> {
+    nodes: Vec<Node<'a>>,
+}
+
+impl<'a> Bootfs<'a> {
+    /// 解析归档，根目录总是编号 0
+    pub fn new(image: &'a [u8]) -> Result<Self, CpioError> {
+        let mut nodes = Vec::new();
+        nodes.push(Node {
+            path: "",
+            kind: NodeKind::Directory,
+            data_offset: 0,
+            data: &[],
+        });
+
+        for entry in cpio::entries(image) {
+            let entry = entry?;
+            let path = normalize(entry.name);
+            if path.is_empty() {
+                continue;
+            }
+            nodes.push(Node {
+                path,
+                kind: NodeKind::from_mode(entry.mode),
+                data_offset: entry.data_offset,
+                data: entry.data,
+            });
+        }
+        Ok(Self { nodes })
+    }
+
+    /// 查找路径对应的节点编号，同名条目出现多次时以最后一次为准
+    pub fn lookup(&self, path: &str) -> Option<usize> {
+        let path = normalize(path);
+        self.nodes.iter().rposition(|node| node.path == path)
+    }
+
+    /// 按编号取得节点
+    pub fn node(&self, id: usize) -> Option<&Node<'a>> {
+        self.nodes.get(id)
+    }
+
+    /// 查找路径对应的节点
+    pub fn open(&self, path: &str) -> Option<&Node<'a>> {
+        self.lookup(path).and_then(|id| self.node(id))
+    }
+
+    /// 目录 `dir` 下的直接子节点
+    pub fn children<'s>(&'s self, dir: &'s Node<'a>) -> impl Iterator<Item = &'s Node<'a>> {
+        self.nodes
+            .iter()
+            .filter(move |node| !node.path.is_empty() && node.parent() == dir.path)
+    }
+}
+
+/// 把 initramfs VMO 只读映射到当前进程，映射一直保留到进程退出
+pub fn map_image(vmo: &Vmo) -> radon_kernel::Result<&'static [u8]> {
+    let size = vmo.size()?;
+    let ptr = map_vmo(vmo, 0, size, MappingFlags::READ)?;
+    Ok(unsafe { core::slice::from_raw_parts(ptr, size) })
+}
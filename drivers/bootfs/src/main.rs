@@ -0,0 +1,183 @@
+#![no_std]
+#![no_main]
+
+use core::mem::offset_of;
+
+use alloc::{string::String, vec, vec::Vec};
+use bootfs::{BOOTFS_MOUNT_POINT, BOOTFS_SERVICE_NAME, Bootfs, IMAGE_HANDLE_INDEX, Node, NodeKind};
+use libdriver::{
+    DriverOp, Request, RequestHandler, Response, ServiceBuilder, server::RequestContext,
+};
+use libradon::{
+    error,
+    handle::OwnedHandle,
+    info,
+    memory::{Vmo, VmoOptions},
+};
+use namespace::{
+    client::NamespaceClient,
+    protocol::{
+        MountFlags, NAMESPACE_FILE_TYPE_DIRECTORY, NAMESPACE_FILE_TYPE_REGULAR,
+        NAMESPACE_FILE_TYPE_SYMLINK, NAMESPACE_FILE_TYPE_UNKNOWN, NAMESPACE_INTERNAL_ERROR,
+        NAMESPACE_INVALID_ARGUMENT, NAMESPACE_RESOLVE_FAILED, NAMESPACE_UNKNOWN_OP, NsDirEntry,
+    },
+};
+use radon_kernel::{EINVAL, Error};
+
+extern crate alloc;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Bootfs 进程主入口
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    match libradon::init() {
+        Ok(()) => match bootfs_main() {
+            Ok(()) => {
+                libradon::process::exit(0);
+            }
+            Err(_) => {
+                error!("bootfs: main function have some problems");
+                libradon::process::exit(-1)
+            }
+        },
+        Err(_) => libradon::process::exit(-1),
+    }
+}
+
+fn file_type(kind: NodeKind) -> i32 {
+    match kind {
+        NodeKind::Regular => NAMESPACE_FILE_TYPE_REGULAR,
+        NodeKind::Directory => NAMESPACE_FILE_TYPE_DIRECTORY,
+        NodeKind::Symlink => NAMESPACE_FILE_TYPE_SYMLINK,
+        NodeKind::Other => NAMESPACE_FILE_TYPE_UNKNOWN,
+    }
+}
+
+/// 新建 VMO 并写入 `data`，空内容也分配一页
+fn vmo_with_data(data: &[u8]) -> radon_kernel::Result<Vmo> {
+    let size = data.len().max(1).next_multiple_of(PAGE_SIZE);
+    let vmo = Vmo::create(size, VmoOptions::COMMIT)?;
+    vmo.write(0, data)?;
+    Ok(vmo)
+}
+
+pub struct BootfsRequestHandler {
+    /// 整个 initramfs，文件数据页对齐时直接创建它的 COW 子 VMO
+    image: Vmo,
+    fs: Bootfs<'static>,
+}
+
+impl BootfsRequestHandler {
+    /// 文件内容的 VMO
+    ///
+    /// CPIO 中的数据只按 4 字节对齐，不在页边界上的文件需要复制一份。
+    fn file_vmo(&self, node: &Node) -> radon_kernel::Result<Vmo> {
+        if !node.data.is_empty() && node.data_offset.is_multiple_of(PAGE_SIZE) {
+            self.image.create_child(node.data_offset, node.data.len())
+        } else {
+            vmo_with_data(node.data)
+        }
+    }
+
+    /// 目录内容的 VMO，格式与 rootns 相同：连续的 `NsDirEntry` 头加文件名
+    fn directory_vmo(&self, node: &Node) -> radon_kernel::Result<(Vmo, usize)> {
+        let mut dentries = Vec::new();
+        for child in self.fs.children(node) {
+            let name = child.name();
+            let dentry_len = offset_of!(NsDirEntry, name) + name.len();
+            dentries.extend_from_slice(
+                NsDirEntry {
+                    rec_len: dentry_len,
+                    name_len: name.len(),
+                    file_type: file_type(child.kind),
+                    name: [0u8; 256],
+                }
+                .to_bytes(),
+            );
+            dentries.extend_from_slice(name.as_bytes());
+        }
+        Ok((vmo_with_data(&dentries)?, dentries.len()))
+    }
+
+    fn open(&self, request: &Request) -> Response {
+        let id = request.header.request_id;
+        let Ok(path) = String::from_utf8(request.data.clone()) else {
+            return Response::error(id, NAMESPACE_INVALID_ARGUMENT);
+        };
+        let Some(node_id) = self.fs.lookup(&path) else {
+            return Response::error(id, NAMESPACE_RESOLVE_FAILED);
+        };
+        let node = self.fs.node(node_id).expect("lookup returned a valid id");
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&file_type(node.kind).to_le_bytes());
+        data.extend_from_slice(&node_id.to_le_bytes());
+        Response::success(id).with_data(data)
+    }
+
+    fn read(&self, request: &Request) -> Response {
+        let id = request.header.request_id;
+        let Some(node) = request
+            .data
+            .get(..size_of::<usize>())
+            .map(|bytes| usize::from_le_bytes(bytes.try_into().unwrap()))
+            .and_then(|node_id| self.fs.node(node_id))
+        else {
+            return Response::error(id, NAMESPACE_INVALID_ARGUMENT);
+        };
+
+        let result = match node.kind {
+            NodeKind::Regular | NodeKind::Symlink => {
+                self.file_vmo(node).map(|vmo| (vmo, node.data.len()))
+            }
+            NodeKind::Directory => self.directory_vmo(node),
+            NodeKind::Other => return Response::error(id, NAMESPACE_INTERNAL_ERROR),
+        };
+        let Ok((mut vmo, size)) = result else {
+            return Response::error(id, NAMESPACE_INTERNAL_ERROR);
+        };
+        vmo.with_nodrop(true);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&file_type(node.kind).to_le_bytes());
+        data.extend_from_slice(&(size as u64).to_le_bytes());
+        Response::success(id)
+            .with_data(data)
+            .with_handles(vec![vmo.handle()])
+    }
+}
+
+impl RequestHandler for BootfsRequestHandler {
+    fn handle(&self, request: &Request, _ctx: &RequestContext) -> Response {
+        match DriverOp::from(request.header.op) {
+            DriverOp::Open => self.open(request),
+            DriverOp::Read => self.read(request),
+            _ => Response::error(request.header.request_id, NAMESPACE_UNKNOWN_OP),
+        }
+    }
+}
+
+fn bootfs_main() -> radon_kernel::Result<()> {
+    // init 把 initramfs VMO 作为第一个初始句柄交给我们
+    let handle = libradon::process::get_init_handle(IMAGE_HANDLE_INDEX)?;
+    let image = Vmo::from_handle(OwnedHandle::from_raw(handle.raw()));
+    let fs = Bootfs::new(bootfs::map_image(&image)?).map_err(|e| {
+        error!("bootfs: failed to parse initramfs: {:?}", e);
+        Error::new(EINVAL)
+    })?;
+
+    NamespaceClient::connect()?.bind(
+        BOOTFS_MOUNT_POINT,
+        BOOTFS_SERVICE_NAME,
+        MountFlags::READABLE | MountFlags::EXECUTABLE,
+    )?;
+    info!("bootfs: mounted initramfs at {}", BOOTFS_MOUNT_POINT);
+
+    let service = ServiceBuilder::new(BOOTFS_SERVICE_NAME)
+        .build(BootfsRequestHandler { image, fs })
+        .map_err(|_| Error::new(EINVAL))?;
+    service.run().map_err(|_| Error::new(EINVAL))?;
+
+    Ok(())
+}
@@ -8,6 +8,11 @@ pub const NAMESPACE_BIND_FAILED: i32 = 3;
 pub const NAMESPACE_RESOLVE_FAILED: i32 = 4;
 pub const NAMESPACE_INTERNAL_ERROR: i32 = 5;
 
+/// 文件服务（rootns、bootfs）的请求格式：
+///
+/// - `DriverOp::Open`：数据为服务内的路径，响应数据为文件类型 (i32) 和文件编号 (usize)
+/// - `DriverOp::Read`：数据为文件编号 (usize)，响应数据为文件类型 (i32) 和内容长度 (u64)，
+///   句柄为内容的 VMO；目录的内容为连续的 [`NsDirEntry`] 头加文件名
 pub const NAMESPACE_FILE_TYPE_UNKNOWN: i32 = 0;
 pub const NAMESPACE_FILE_TYPE_REGULAR: i32 = 1;
 pub const NAMESPACE_FILE_TYPE_DIRECTORY: i32 = 2;
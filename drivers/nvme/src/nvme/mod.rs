@@ -7,7 +7,7 @@ use core::sync::atomic::{AtomicU16, Ordering};
 use radon_kernel::{EINVAL, EIO, ENOMEM, ETIMEDOUT, Error, Result};
 use spin::{Mutex, RwLock};
 
-use libdriver::dma::{DmaRegion, PhysAddr};
+use libdriver::dma::{DmaRegion, PhysAddr, PinnedRegion};
 use libdriver::mmio::MmioRegion;
 
 use crate::nvme::regs::{ControllerCapabilities, NvmeRegs};
@@ -1071,6 +1071,44 @@ impl NvmeNamespace {
         Ok(())
     }
 
+    /// 直接读取到 pin 住的缓冲区（无需物理连续）
+    pub fn read_pinned(&self, lba: u64, buffer: &PinnedRegion, block_count: u16) -> Result<()> {
+        let data_len = block_count as usize * self.info.block_size as usize;
+        if buffer.size() < data_len {
+            return Err(Error::new(EINVAL));
+        }
+
+        let prp = PrpBuilder::from_segments(buffer.segments())?;
+        let entry =
+            SubmissionEntry::read(0, self.info.nsid, lba, block_count, prp.prp1(), prp.prp2());
+
+        let cid = self
+            .io_queue
+            .submit(self.controller.regs(), entry, Some(prp), None)?;
+
+        self.io_queue.wait_completion(self.controller.regs(), cid)?;
+        Ok(())
+    }
+
+    /// 直接从 pin 住的缓冲区写入（无需物理连续）
+    pub fn write_pinned(&self, lba: u64, buffer: &PinnedRegion, block_count: u16) -> Result<()> {
+        let data_len = block_count as usize * self.info.block_size as usize;
+        if buffer.size() < data_len {
+            return Err(Error::new(EINVAL));
+        }
+
+        let prp = PrpBuilder::from_segments(buffer.segments())?;
+        let entry =
+            SubmissionEntry::write(0, self.info.nsid, lba, block_count, prp.prp1(), prp.prp2());
+
+        let cid = self
+            .io_queue
+            .submit(self.controller.regs(), entry, Some(prp), None)?;
+
+        self.io_queue.wait_completion(self.controller.regs(), cid)?;
+        Ok(())
+    }
+
     /// 读取到用户缓冲区
     ///
     /// 内部分配 DMA 缓冲区并复制数据
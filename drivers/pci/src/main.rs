@@ -14,7 +14,7 @@ use libdriver::{
 };
 use libradon::{
     debug, error, info,
-    memory::{MappingFlags, Vmo, map_vmo},
+    memory::{CachePolicy, MappingFlags, Vmo, map_vmo},
 };
 use pci_types::{
     Bar, BaseClass, CommandRegister, ConfigRegionAccess, DeviceId, DeviceRevision, EndpointHeader,
@@ -342,6 +342,7 @@ fn pci_main() -> radon_kernel::Result<()> {
         let region_size = bus_count * (1 << 20);
 
         let vmo = Vmo::create_physical(aligned_region_base_addr as usize, region_size)?;
+        vmo.set_cache_policy(CachePolicy::UncachedDevice)?;
         let vaddr = map_vmo(
             &vmo,
             0,
@@ -28,6 +28,7 @@ use libdriver::{
 use libradon::{
     debug, error,
     memory::{Vmo, VmoOptions},
+    syscall::ClockId,
 };
 use namespace::{
     client::NamespaceClient,
@@ -87,7 +88,11 @@ impl Device for Partition {
     }
 
     fn now(&mut self) -> Option<efs::fs::types::Timespec> {
-        None
+        let ns = libradon::syscall::clock_read(ClockId::Realtime).ok()?;
+        Some(efs::fs::types::Timespec {
+            tv_sec: efs::fs::types::Time((ns / 1_000_000_000) as i64),
+            tv_nsec: (ns % 1_000_000_000) as u32,
+        })
     }
 }
 
@@ -175,7 +180,7 @@ impl RequestHandler for RootNSRequestHandler {
                         );
                     }
                 };
-                let (handle, file_ty) = match file.clone() {
+                let (handle, file_ty, size) = match file.clone() {
                     Ext2TypeWithFile::Regular(mut regular) => {
                         if regular.size().0 == 0 {
                             let mut vmo = match Vmo::create(
@@ -191,7 +196,7 @@ impl RequestHandler for RootNSRequestHandler {
                                 }
                             };
                             vmo.with_nodrop(true);
-                            (vmo.handle(), NAMESPACE_FILE_TYPE_REGULAR)
+                            (vmo.handle(), NAMESPACE_FILE_TYPE_REGULAR, 0)
                         } else {
                             let mut vmo = match Vmo::create(
                                 (regular.size().0 as usize + 4095usize) & !4095usize,
@@ -231,7 +236,7 @@ impl RequestHandler for RootNSRequestHandler {
                                 offset += tmp.len();
                             }
                             vmo.with_nodrop(true);
-                            (vmo.handle(), NAMESPACE_FILE_TYPE_REGULAR)
+                            (vmo.handle(), NAMESPACE_FILE_TYPE_REGULAR, regular.size().0)
                         }
                     }
                     Ext2TypeWithFile::Directory(directory) => {
@@ -292,7 +297,11 @@ impl RequestHandler for RootNSRequestHandler {
                             );
                         }
                         vmo.with_nodrop(true);
-                        (vmo.handle(), NAMESPACE_FILE_TYPE_DIRECTORY)
+                        (
+                            vmo.handle(),
+                            NAMESPACE_FILE_TYPE_DIRECTORY,
+                            dentries.len() as u64,
+                        )
                     }
                     _ => {
                         return Response::error(
@@ -302,8 +311,11 @@ impl RequestHandler for RootNSRequestHandler {
                     }
                 };
 
+                let mut data = Vec::new();
+                data.extend_from_slice(&file_ty.to_le_bytes());
+                data.extend_from_slice(&size.to_le_bytes());
                 Response::success(request.header.request_id)
-                    .with_data(file_ty.to_le_bytes().to_vec())
+                    .with_data(data)
                     .with_handles(vec![handle])
             }
             _ => Response::error(request.header.request_id, 1),
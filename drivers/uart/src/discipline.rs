@@ -0,0 +1,261 @@
+//! 线路规程：回显、行编辑和 Ctrl-C
+
+use alloc::vec::Vec;
+
+use crate::LineMode;
+
+/// 一行最多缓存的字节数，超出的输入被丢弃
+pub const MAX_LINE: usize = 4096;
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_U: u8 = 0x15;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+/// 处理一个输入字节的结果
+#[derive(Debug, PartialEq, Eq)]
+pub enum Input {
+    /// 没有可以交给读者的内容
+    None,
+    /// 交给读者的数据
+    Data(Vec<u8>),
+    /// 收到 Ctrl-C
+    Interrupt,
+}
+
+pub struct LineDiscipline {
+    mode: LineMode,
+    line: Vec<u8>,
+}
+
+impl LineDiscipline {
+    pub fn new(mode: LineMode) -> Self {
+        Self {
+            mode,
+            line: Vec::new(),
+        }
+    }
+
+    pub fn mode(&self) -> LineMode {
+        self.mode
+    }
+
+    /// 切换模式，关闭行编辑时把未完成的行交给读者
+    pub fn set_mode(&mut self, mode: LineMode) -> Input {
+        self.mode = mode;
+        if mode.contains(LineMode::ICANON) || self.line.is_empty() {
+            Input::None
+        } else {
+            Input::Data(core::mem::take(&mut self.line))
+        }
+    }
+
+    /// 处理输入字节 `byte`，需要回显的内容追加到 `echo`
+    pub fn input(&mut self, byte: u8, echo: &mut Vec<u8>) -> Input {
+        let byte = if byte == b'\r' && self.mode.contains(LineMode::ICRNL) {
+            b'\n'
+        } else {
+            byte
+        };
+
+        if byte == CTRL_C && self.mode.contains(LineMode::ISIG) {
+            self.line.clear();
+            if self.mode.contains(LineMode::ECHO) {
+                echo.extend_from_slice(b"^C\r\n");
+            }
+            return Input::Interrupt;
+        }
+
+        if !self.mode.contains(LineMode::ICANON) {
+            if self.mode.contains(LineMode::ECHO) {
+                self.echo(byte, echo);
+            }
+            return Input::Data(alloc::vec![byte]);
+        }
+
+        match byte {
+            BACKSPACE | DELETE => {
+                if self.line.pop().is_some() && self.mode.contains(LineMode::ECHO) {
+                    echo.extend_from_slice(b"\x08 \x08");
+                }
+                Input::None
+            }
+            CTRL_U => {
+                if self.mode.contains(LineMode::ECHO) {
+                    for _ in 0..self.line.len() {
+                        echo.extend_from_slice(b"\x08 \x08");
+                    }
+                }
+                self.line.clear();
+                Input::None
+            }
+            // 把未完成的行交给读者，空行时读者收到空数据表示文件结束
+            CTRL_D => Input::Data(core::mem::take(&mut self.line)),
+            b'\n' => {
+                if self.mode.contains(LineMode::ECHO) {
+                    self.echo(byte, echo);
+                }
+                self.line.push(byte);
+                Input::Data(core::mem::take(&mut self.line))
+            }
+            _ => {
+                if self.line.len() < MAX_LINE {
+                    if self.mode.contains(LineMode::ECHO) {
+                        self.echo(byte, echo);
+                    }
+                    self.line.push(byte);
+                }
+                Input::None
+            }
+        }
+    }
+
+    /// 把 `data` 按输出规则追加到 `out`
+    pub fn output(&self, data: &[u8], out: &mut Vec<u8>) {
+        for &byte in data {
+            if byte == b'\n' && self.mode.contains(LineMode::ONLCR) {
+                out.push(b'\r');
+            }
+            out.push(byte);
+        }
+    }
+
+    fn echo(&self, byte: u8, echo: &mut Vec<u8>) {
+        match byte {
+            b'\n' => self.output(b"\n", echo),
+            // 其他控制字符显示为 ^X
+            0..0x20 if byte != b'\t' => echo.extend_from_slice(&[b'^', byte + b'@']),
+            _ => echo.push(byte),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// 依次输入 `bytes`，返回最后一次的结果和全部回显
+    fn feed(discipline: &mut LineDiscipline, bytes: &[u8]) -> (Input, Vec<u8>) {
+        let mut echo = Vec::new();
+        let mut last = Input::None;
+        for &byte in bytes {
+            last = discipline.input(byte, &mut echo);
+        }
+        (last, echo)
+    }
+
+    #[test]
+    fn canonical_line_is_delivered_on_enter() {
+        let mut discipline = LineDiscipline::new(LineMode::default());
+        let (input, echo) = feed(&mut discipline, b"ls");
+        assert_eq!(input, Input::None);
+        assert_eq!(echo, b"ls");
+
+        let (input, echo) = feed(&mut discipline, b"\r");
+        assert_eq!(input, Input::Data(b"ls\n".to_vec()));
+        assert_eq!(echo, b"\r\n");
+    }
+
+    #[test]
+    fn no_echo_without_echo_flag() {
+        let mut discipline = LineDiscipline::new(LineMode::default() - LineMode::ECHO);
+        let (input, echo) = feed(&mut discipline, b"ab\n");
+        assert_eq!(input, Input::Data(b"ab\n".to_vec()));
+        assert!(echo.is_empty());
+    }
+
+    #[test]
+    fn control_characters_are_echoed_as_caret() {
+        let mut discipline = LineDiscipline::new(LineMode::default());
+        let (_, echo) = feed(&mut discipline, b"\x1b\t");
+        assert_eq!(echo, b"^[\t");
+    }
+
+    #[test]
+    fn backspace_erases_last_byte() {
+        let mut discipline = LineDiscipline::new(LineMode::default());
+        let (_, echo) = feed(&mut discipline, b"ab\x08c");
+        assert_eq!(echo, b"ab\x08 \x08c");
+
+        let (input, _) = feed(&mut discipline, b"\x7f\n");
+        assert_eq!(input, Input::Data(b"a\n".to_vec()));
+    }
+
+    #[test]
+    fn backspace_on_empty_line_is_ignored() {
+        let mut discipline = LineDiscipline::new(LineMode::default());
+        let (input, echo) = feed(&mut discipline, b"\x08\x7f");
+        assert_eq!(input, Input::None);
+        assert!(echo.is_empty());
+    }
+
+    #[test]
+    fn ctrl_u_erases_line() {
+        let mut discipline = LineDiscipline::new(LineMode::default());
+        let (_, echo) = feed(&mut discipline, b"ab\x15");
+        assert_eq!(echo, b"ab\x08 \x08\x08 \x08");
+
+        let (input, _) = feed(&mut discipline, b"\n");
+        assert_eq!(input, Input::Data(b"\n".to_vec()));
+    }
+
+    #[test]
+    fn ctrl_c_interrupts_and_discards_line() {
+        let mut discipline = LineDiscipline::new(LineMode::default());
+        let (input, echo) = feed(&mut discipline, b"abc\x03");
+        assert_eq!(input, Input::Interrupt);
+        assert_eq!(echo, b"abc^C\r\n");
+
+        let (input, _) = feed(&mut discipline, b"\n");
+        assert_eq!(input, Input::Data(b"\n".to_vec()));
+    }
+
+    #[test]
+    fn ctrl_c_is_data_without_isig() {
+        let mut discipline =
+            LineDiscipline::new(LineMode::default() - LineMode::ISIG - LineMode::ICANON);
+        let (input, echo) = feed(&mut discipline, b"\x03");
+        assert_eq!(input, Input::Data(alloc::vec![CTRL_C]));
+        assert_eq!(echo, b"^C");
+    }
+
+    #[test]
+    fn ctrl_d_delivers_pending_line() {
+        let mut discipline = LineDiscipline::new(LineMode::default());
+        let (input, _) = feed(&mut discipline, b"ab\x04");
+        assert_eq!(input, Input::Data(b"ab".to_vec()));
+
+        // 空行时读者收到空数据
+        let (input, _) = feed(&mut discipline, b"\x04");
+        assert_eq!(input, Input::Data(Vec::new()));
+    }
+
+    #[test]
+    fn raw_mode_delivers_each_byte() {
+        let mut discipline = LineDiscipline::new(LineMode::ECHO);
+        let (input, echo) = feed(&mut discipline, b"\x08");
+        assert_eq!(input, Input::Data(alloc::vec![BACKSPACE]));
+        assert_eq!(echo, b"^H");
+    }
+
+    #[test]
+    fn leaving_canonical_mode_flushes_line() {
+        let mut discipline = LineDiscipline::new(LineMode::default());
+        feed(&mut discipline, b"ab");
+        assert_eq!(
+            discipline.set_mode(LineMode::ECHO),
+            Input::Data(b"ab".to_vec())
+        );
+    }
+
+    #[test]
+    fn output_translates_newlines() {
+        let discipline = LineDiscipline::new(LineMode::default());
+        let mut out = Vec::new();
+        discipline.output(b"a\nb", &mut out);
+        assert_eq!(out, b"a\r\nb");
+    }
+}
@@ -0,0 +1,64 @@
+//! UART：用户态的 16550 串口驱动
+//!
+//! 驱动从内核取得串口控制台的 I/O 端口和中断，之后内核只在 panic 时使用串口。
+//! 协议：
+//! - `Open`：返回一个 channel，驱动把输入推送到这里，每条消息第一个字节是 `STREAM_*`；
+//!   后打开的会替换之前的读者，没有读者时输入留在接收缓冲区中
+//! - `Read`：取走接收缓冲区中最多 `length` 个字节，不阻塞
+//! - `Write`：`IoRequest` 后跟数据，返回写入的字节数
+//! - `Ioctl`：`UART_IOCTL_*`，返回值为 u64
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod discipline;
+
+use bitflags::bitflags;
+
+/// UART 的驱动服务名
+pub const UART_SERVICE_NAME: &str = "uart";
+
+pub const UART_UNKNOWN_OP: i32 = 1;
+pub const UART_INVALID_ARGUMENT: i32 = 2;
+pub const UART_INTERNAL_ERROR: i32 = 3;
+
+/// 读取波特率
+pub const UART_IOCTL_GET_BAUD: u32 = 1;
+/// 设置波特率，参数为波特率
+pub const UART_IOCTL_SET_BAUD: u32 = 2;
+/// 读取 `LineMode`
+pub const UART_IOCTL_GET_MODE: u32 = 3;
+/// 设置 `LineMode`，参数为标志位
+pub const UART_IOCTL_SET_MODE: u32 = 4;
+
+/// 输入数据，后面跟着字节
+pub const STREAM_DATA: u8 = 0;
+/// 收到 Ctrl-C
+pub const STREAM_INTERRUPT: u8 = 1;
+
+/// 没有指定波特率时使用
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+
+bitflags! {
+    /// 线路规程
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct LineMode: u32 {
+        /// 回显输入
+        const ECHO   = 0b00001;
+        /// 按行编辑，回车后才交给读者
+        const ICANON = 0b00010;
+        /// Ctrl-C 作为 `STREAM_INTERRUPT` 投递
+        const ISIG   = 0b00100;
+        /// 输入的 `\r` 转换为 `\n`
+        const ICRNL  = 0b01000;
+        /// 输出的 `\n` 转换为 `\r\n`
+        const ONLCR  = 0b10000;
+    }
+}
+
+impl Default for LineMode {
+    fn default() -> Self {
+        Self::all()
+    }
+}
@@ -0,0 +1,344 @@
+#![no_std]
+#![no_main]
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use libdriver::{
+    DriverOp, Request, RequestHandler, Response, ServiceBuilder,
+    protocol::{IoRequest, IoctlRequest},
+    server::RequestContext,
+};
+use libradon::{
+    channel::Channel, error, info, interrupt::Interrupt, ioport, serial, signal::Signals,
+};
+use radon_kernel::{EINVAL, Error};
+use spin::Mutex;
+use uart::{
+    DEFAULT_BAUD_RATE, LineMode, STREAM_DATA, STREAM_INTERRUPT, UART_INTERNAL_ERROR,
+    UART_INVALID_ARGUMENT, UART_IOCTL_GET_BAUD, UART_IOCTL_GET_MODE, UART_IOCTL_SET_BAUD,
+    UART_IOCTL_SET_MODE, UART_SERVICE_NAME, UART_UNKNOWN_OP,
+    discipline::{Input, LineDiscipline},
+};
+
+extern crate alloc;
+
+/// 没有读者时最多缓存的输入字节数，超出的输入被丢弃
+const RX_CAPACITY: usize = 64 * 1024;
+
+/// 发送 FIFO 的深度
+const TX_FIFO_DEPTH: usize = 16;
+
+/// 除数为 1 时的波特率
+const BASE_BAUD: u32 = 115200;
+
+/// 寄存器偏移
+const REG_DATA: u16 = 0;
+const REG_IER: u16 = 1;
+const REG_FCR: u16 = 2;
+const REG_LCR: u16 = 3;
+const REG_MCR: u16 = 4;
+const REG_LSR: u16 = 5;
+
+const IER_RX_AVAILABLE: u8 = 0x01;
+const IER_TX_EMPTY: u8 = 0x02;
+/// 启用并清空 FIFO，接收触发阈值 14 字节
+const FCR_ENABLE_FIFO: u8 = 0xc7;
+const LCR_8N1: u8 = 0x03;
+const LCR_DLAB: u8 = 0x80;
+/// DTR、RTS 和 OUT2，OUT2 打开中断输出
+const MCR_DTR_RTS_OUT2: u8 = 0x0b;
+const LSR_DATA_READY: u8 = 0x01;
+const LSR_TX_EMPTY: u8 = 0x20;
+
+/// UART 进程主入口
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    match libradon::init() {
+        Ok(()) => match uart_main() {
+            Ok(()) => {
+                libradon::process::exit(0);
+            }
+            Err(_) => {
+                error!("uart: main function have some problems");
+                libradon::process::exit(-1)
+            }
+        },
+        Err(_) => libradon::process::exit(-1),
+    }
+}
+
+/// 16550 的寄存器
+struct Uart16550 {
+    base: u16,
+}
+
+impl Uart16550 {
+    fn read(&self, reg: u16) -> u8 {
+        unsafe { ioport::inb(self.base + reg) }
+    }
+
+    fn write(&self, reg: u16, value: u8) {
+        unsafe { ioport::outb(self.base + reg, value) }
+    }
+
+    /// 8N1，启用 FIFO 和接收中断
+    fn init(&self, baud_rate: u32) {
+        self.write(REG_IER, 0);
+        self.set_baud_rate(baud_rate);
+        self.write(REG_FCR, FCR_ENABLE_FIFO);
+        self.write(REG_MCR, MCR_DTR_RTS_OUT2);
+        // 清掉初始化之前残留的输入
+        while self.read(REG_LSR) & LSR_DATA_READY != 0 {
+            self.read(REG_DATA);
+        }
+        self.write(REG_IER, IER_RX_AVAILABLE);
+    }
+
+    fn set_baud_rate(&self, baud_rate: u32) {
+        let divisor = (BASE_BAUD / baud_rate) as u16;
+        self.write(REG_LCR, LCR_DLAB);
+        self.write(REG_DATA, divisor as u8);
+        self.write(REG_IER, (divisor >> 8) as u8);
+        self.write(REG_LCR, LCR_8N1);
+    }
+
+    fn try_read(&self) -> Option<u8> {
+        (self.read(REG_LSR) & LSR_DATA_READY != 0).then(|| self.read(REG_DATA))
+    }
+
+    fn tx_empty(&self) -> bool {
+        self.read(REG_LSR) & LSR_TX_EMPTY != 0
+    }
+
+    fn set_tx_interrupt(&self, enabled: bool) {
+        let ier = if enabled {
+            IER_RX_AVAILABLE | IER_TX_EMPTY
+        } else {
+            IER_RX_AVAILABLE
+        };
+        self.write(REG_IER, ier);
+    }
+}
+
+struct UartState {
+    uart: Uart16550,
+    baud_rate: u32,
+    discipline: LineDiscipline,
+    /// 没有读者时缓存的输入
+    rx: VecDeque<u8>,
+    /// 等待发送的输出
+    tx: VecDeque<u8>,
+    /// 最后一次 `Open` 得到输入流的客户端
+    reader: Option<Channel>,
+}
+
+impl UartState {
+    /// 把输入交给读者，没有读者或读者已经关闭时留在接收缓冲区中
+    fn deliver(&mut self, input: Input) {
+        let message = match input {
+            Input::None => return,
+            Input::Data(data) => {
+                let mut message = vec![STREAM_DATA];
+                message.extend_from_slice(&data);
+                message
+            }
+            Input::Interrupt => vec![STREAM_INTERRUPT],
+        };
+
+        if let Some(reader) = &self.reader {
+            if reader.send(&message).is_ok() {
+                return;
+            }
+            self.reader = None;
+        }
+
+        if message[0] == STREAM_DATA {
+            let room = RX_CAPACITY.saturating_sub(self.rx.len());
+            self.rx.extend(message[1..].iter().take(room));
+        }
+    }
+
+    /// 尽量填满发送 FIFO，还有剩余时打开发送中断
+    fn flush(&mut self) {
+        if self.uart.tx_empty() {
+            for _ in 0..TX_FIFO_DEPTH {
+                let Some(byte) = self.tx.pop_front() else {
+                    break;
+                };
+                self.uart.write(REG_DATA, byte);
+            }
+        }
+        self.uart.set_tx_interrupt(!self.tx.is_empty());
+    }
+
+    fn queue_output(&mut self, data: &[u8]) {
+        let mut out = Vec::with_capacity(data.len());
+        self.discipline.output(data, &mut out);
+        self.tx.extend(out);
+    }
+}
+
+pub struct UartRequestHandler {
+    interrupt: Interrupt,
+    state: Mutex<UartState>,
+}
+
+impl UartRequestHandler {
+    fn open(&self, request: &Request) -> Response {
+        let id = request.header.request_id;
+        let Ok((ours, mut theirs)) = Channel::create_pair() else {
+            return Response::error(id, UART_INTERNAL_ERROR);
+        };
+        theirs.with_nodrop(true);
+
+        let mut state = self.state.lock();
+        state.reader = Some(ours);
+        // 把之前缓存的输入先交给新的读者
+        if !state.rx.is_empty() {
+            let data: Vec<u8> = state.rx.drain(..).collect();
+            state.deliver(Input::Data(data));
+        }
+
+        Response::success(id).with_handles(vec![theirs.handle()])
+    }
+
+    fn read(&self, request: &Request) -> Response {
+        let id = request.header.request_id;
+        let Some(io_request) = parse::<IoRequest>(&request.data) else {
+            return Response::error(id, UART_INVALID_ARGUMENT);
+        };
+
+        let mut state = self.state.lock();
+        let count = state.rx.len().min(io_request.length as usize);
+        let data: Vec<u8> = state.rx.drain(..count).collect();
+        Response::success(id).with_data(data)
+    }
+
+    fn write(&self, request: &Request) -> Response {
+        let id = request.header.request_id;
+        let header_len = size_of::<IoRequest>();
+        let Some(io_request) = parse::<IoRequest>(&request.data) else {
+            return Response::error(id, UART_INVALID_ARGUMENT);
+        };
+        let Some(data) = request
+            .data
+            .get(header_len..header_len + io_request.length as usize)
+        else {
+            return Response::error(id, UART_INVALID_ARGUMENT);
+        };
+
+        let mut state = self.state.lock();
+        state.queue_output(data);
+        state.flush();
+        Response::success(id).with_data(io_request.length.to_le_bytes().to_vec())
+    }
+
+    fn ioctl(&self, request: &Request) -> Response {
+        let id = request.header.request_id;
+        let Some(ioctl_request) = parse::<IoctlRequest>(&request.data) else {
+            return Response::error(id, UART_INVALID_ARGUMENT);
+        };
+
+        let mut state = self.state.lock();
+        let value = match ioctl_request.cmd {
+            UART_IOCTL_GET_BAUD => state.baud_rate as u64,
+            UART_IOCTL_SET_BAUD => {
+                let Some(baud_rate) = u32::try_from(ioctl_request.arg)
+                    .ok()
+                    .filter(|&baud| baud > 0 && baud <= BASE_BAUD)
+                else {
+                    return Response::error(id, UART_INVALID_ARGUMENT);
+                };
+                state.uart.set_baud_rate(baud_rate);
+                state.baud_rate = baud_rate;
+                0
+            }
+            UART_IOCTL_GET_MODE => state.discipline.mode().bits() as u64,
+            UART_IOCTL_SET_MODE => {
+                let Some(mode) = u32::try_from(ioctl_request.arg)
+                    .ok()
+                    .and_then(LineMode::from_bits)
+                else {
+                    return Response::error(id, UART_INVALID_ARGUMENT);
+                };
+                let input = state.discipline.set_mode(mode);
+                state.deliver(input);
+                0
+            }
+            _ => return Response::error(id, UART_UNKNOWN_OP),
+        };
+        Response::success(id).with_data(value.to_le_bytes().to_vec())
+    }
+}
+
+/// 从请求数据的开头读出 `T`
+fn parse<T: Copy>(data: &[u8]) -> Option<T> {
+    (data.len() >= size_of::<T>())
+        .then(|| unsafe { core::ptr::read_unaligned(data.as_ptr() as *const T) })
+}
+
+impl RequestHandler for UartRequestHandler {
+    fn handle(&self, request: &Request, _ctx: &RequestContext) -> Response {
+        match DriverOp::from(request.header.op) {
+            DriverOp::Open => self.open(request),
+            DriverOp::Read => self.read(request),
+            DriverOp::Write => self.write(request),
+            DriverOp::Ioctl => self.ioctl(request),
+            _ => Response::error(request.header.request_id, UART_UNKNOWN_OP),
+        }
+    }
+
+    /// 串口中断：收完所有输入，再继续发送
+    fn on_signal(&self, _key: u64, _signals: Signals) {
+        let mut state = self.state.lock();
+        let mut echo = Vec::new();
+        while let Some(byte) = state.uart.try_read() {
+            let input = state.discipline.input(byte, &mut echo);
+            state.deliver(input);
+        }
+        state.tx.extend(echo);
+        state.flush();
+        drop(state);
+
+        if self.interrupt.ack().is_err() {
+            error!("uart: failed to ack irq {}", self.interrupt.irq());
+        }
+    }
+}
+
+fn uart_main() -> radon_kernel::Result<()> {
+    let console = serial::console_info()?;
+    ioport::request(console.port, serial::SERIAL_PORT_COUNT as u16)?;
+    let interrupt = Interrupt::create(console.irq as u32)?;
+
+    let baud_rate = match console.baud_rate {
+        0 => DEFAULT_BAUD_RATE,
+        baud => baud,
+    };
+    let uart = Uart16550 { base: console.port };
+    uart.init(baud_rate);
+    info!(
+        "uart: serial console at {:#x}, irq {}, {} baud",
+        console.port, console.irq, baud_rate
+    );
+
+    let irq_handle = interrupt.handle();
+    let service = ServiceBuilder::new(UART_SERVICE_NAME)
+        .build(UartRequestHandler {
+            interrupt,
+            state: Mutex::new(UartState {
+                uart,
+                baud_rate,
+                discipline: LineDiscipline::new(LineMode::default()),
+                rx: VecDeque::new(),
+                tx: VecDeque::new(),
+                reader: None,
+            }),
+        })
+        .map_err(|_| Error::new(EINVAL))?;
+    service
+        .watch(0, &irq_handle, Signals::SIGNALED)
+        .map_err(|_| Error::new(EINVAL))?;
+    service.run().map_err(|_| Error::new(EINVAL))?;
+
+    Ok(())
+}
@@ -6,7 +6,16 @@ extern crate alloc;
 pub mod elf;
 pub mod program;
 
-use libradon::{error, info, process::Process};
+use alloc::{collections::BTreeMap, string::String, vec};
+use bootfs::{BOOTFS_MOUNT_POINT, BOOTFS_SERVICE_NAME, Bootfs, IMAGE_HANDLE_INDEX, NodeKind};
+use libradon::{
+    bootopts, error,
+    handle::{Handle, OwnedHandle, Rights},
+    info,
+    memory::Vmo,
+    process::Process,
+    warn,
+};
 
 use bootstrap::BootstrapHandler;
 
@@ -30,15 +39,32 @@ pub extern "C" fn _start() -> ! {
 }
 
 fn init_main() -> Result<(), InitError> {
+    // 内核命令行中内核不识别的选项
+    let options = bootopts::receive().unwrap_or_else(|_| {
+        warn!("init: no boot options from kernel");
+        BTreeMap::new()
+    });
+    for (key, value) in &options {
+        info!("init: boot option {}={}", key, value);
+    }
+
+    // 内核交给我们的 initramfs，服务程序都从这里加载
+    let image = BootImage::open()?;
+
     // 创建 bootstrap 处理器
     let bootstrap = BootstrapHandler::new().map_err(|_| InitError::BootstrapFailed)?;
 
     // 启动 Name Server
-    start_nameserver(&bootstrap)?;
+    start_nameserver(&bootstrap, &image)?;
     info!("Nameserver started.");
 
+    // 启动 bootfs，它在 namespace 启动后挂载到 /boot
+    if !skipped(&options, BOOTFS_SERVICE_NAME) {
+        start_bootfs(&bootstrap, &image)?;
+    }
+
     // 启动其他核心服务
-    start_core_services(&bootstrap)?;
+    start_core_services(&bootstrap, &image, &options)?;
     info!("Core services started.");
 
     // 启动用户服务
@@ -51,10 +77,59 @@ fn init_main() -> Result<(), InitError> {
     Ok(())
 }
 
-static NAMESERVER_ELF: &'static [u8] = include_bytes!("../../nameserver/build/nameserver.elf");
+/// Name Server 在 initramfs 中的路径
+const NAMESERVER_PATH: &str = "/boot/bin/nameserver";
+/// bootfs 服务在 initramfs 中的路径
+const BOOTFS_PATH: &str = "/boot/bin/bootfs";
+/// 按顺序列出核心服务路径的文件，每行一个，`#` 开头的行为注释
+const SERVICES_PATH: &str = "/boot/etc/services";
+
+/// 内核交给 init 的 initramfs
+struct BootImage {
+    vmo: Vmo,
+    fs: Bootfs<'static>,
+}
+
+impl BootImage {
+    fn open() -> Result<Self, InitError> {
+        let handle = libradon::process::get_init_handle(IMAGE_HANDLE_INDEX)
+            .map_err(|_| InitError::BootImageFailed)?;
+        let vmo = Vmo::from_handle(OwnedHandle::from_raw(handle.raw()));
+        let image = bootfs::map_image(&vmo).map_err(|_| InitError::BootImageFailed)?;
+        let fs = Bootfs::new(image).map_err(|e| {
+            error!("init: failed to parse initramfs: {:?}", e);
+            InitError::BootImageFailed
+        })?;
+        Ok(Self { vmo, fs })
+    }
+
+    /// 读取 `/boot` 下的文件
+    ///
+    /// init 是所有进程的 bootstrap 服务端，不能阻塞等待 namespace，
+    /// 所以直接在归档中查找，得到的内容与 bootfs 服务在 `/boot` 下提供的相同。
+    fn read(&self, path: &str) -> Result<&'static [u8], InitError> {
+        let node = path
+            .strip_prefix(BOOTFS_MOUNT_POINT)
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+            .and_then(|rest| self.fs.open(rest))
+            .filter(|node| node.kind == NodeKind::Regular)
+            .ok_or_else(|| {
+                warn!("init: {} not found", path);
+                InitError::NotFound
+            })?;
+        Ok(node.data)
+    }
+}
+
+/// 服务是否被启动选项 `init.skip=<服务名>,...` 排除，例如 `init.skip=nvme,ahci`
+fn skipped(options: &BTreeMap<String, String>, name: &str) -> bool {
+    options
+        .get("init.skip")
+        .is_some_and(|skip| skip.split(',').any(|skip| skip == name))
+}
 
 /// 启动 Name Server
-fn start_nameserver(bootstrap: &BootstrapHandler) -> Result<(), InitError> {
+fn start_nameserver(bootstrap: &BootstrapHandler, image: &BootImage) -> Result<(), InitError> {
     // 创建 Name Server 进程
     let mut ns_process = Process::create("nameserver")
         .map_err(|_| InitError::ProcessFailed)?
@@ -70,8 +145,10 @@ fn start_nameserver(bootstrap: &BootstrapHandler) -> Result<(), InitError> {
     // 注册 Name Server 为我们的子进程（特权）
     let _child_id = bootstrap.add_child(ns_bootstrap, true);
 
-    let loaded =
-        ProgramLoader::load(&ns_process, NAMESERVER_ELF).map_err(|_| InitError::ProcessFailed)?;
+    let loaded = with_aligned(image.read(NAMESERVER_PATH)?, |elf| {
+        ProgramLoader::load(&ns_process, elf)
+    })
+    .map_err(|_| InitError::ProcessFailed)?;
 
     ns_process
         .create_thread("ns_main", loaded.entry, loaded.stack_top, 0)
@@ -87,21 +164,48 @@ fn start_nameserver(bootstrap: &BootstrapHandler) -> Result<(), InitError> {
     Ok(())
 }
 
-static ACPI_ELF: &'static [u8] = include_bytes!("../../drivers/acpi/build/acpi.elf");
-static PCI_ELF: &'static [u8] = include_bytes!("../../drivers/pci/build/pci.elf");
-static NVME_ELF: &'static [u8] = include_bytes!("../../drivers/nvme/build/nvme.elf");
-static AHCI_ELF: &'static [u8] = include_bytes!("../../drivers/ahci/build/ahci.elf");
-static NAMESPACE_ELF: &'static [u8] = include_bytes!("../../drivers/namespace/build/namespace.elf");
-static ROOTNS_ELF: &'static [u8] = include_bytes!("../../drivers/rootns/build/rootns.elf");
+/// 启动 bootfs，把 initramfs VMO 作为它的第一个初始句柄
+fn start_bootfs(bootstrap: &BootstrapHandler, image: &BootImage) -> Result<(), InitError> {
+    start_service(
+        bootstrap,
+        image,
+        BOOTFS_PATH,
+        true,
+        &[(
+            image.vmo.handle(),
+            Rights::READ | Rights::WAIT | Rights::MAP | Rights::DUPLICATE | Rights::TRANSFER,
+        )],
+    )?;
+    Ok(())
+}
 
 /// 启动核心服务
-fn start_core_services(bootstrap: &BootstrapHandler) -> Result<(), InitError> {
-    start_service(bootstrap, "acpi", ACPI_ELF, true)?;
-    start_service(bootstrap, "pci", PCI_ELF, true)?;
-    start_service(bootstrap, "nvme", NVME_ELF, true)?;
-    start_service(bootstrap, "ahci", AHCI_ELF, true)?;
-    start_service(bootstrap, "namespace", NAMESPACE_ELF, true)?;
-    start_service(bootstrap, "rootns", ROOTNS_ELF, true)?;
+///
+/// 服务按 `/boot/etc/services` 中的顺序启动，添加驱动只需要把程序放进 initramfs 并写进这个文件。
+/// 找不到的服务给出警告后跳过。
+fn start_core_services(
+    bootstrap: &BootstrapHandler,
+    image: &BootImage,
+    options: &BTreeMap<String, String>,
+) -> Result<(), InitError> {
+    let services =
+        core::str::from_utf8(image.read(SERVICES_PATH)?).map_err(|_| InitError::BootImageFailed)?;
+
+    for path in services
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        if skipped(options, service_name(path)) {
+            info!("init: skipping service {}", path);
+            continue;
+        }
+        match start_service(bootstrap, image, path, true, &[]) {
+            Ok(_) => {}
+            Err(InitError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+    }
     Ok(())
 }
 
@@ -110,19 +214,30 @@ fn start_user_services(_bootstrap: &BootstrapHandler) -> Result<(), InitError> {
     Ok(())
 }
 
-/// 启动一个服务进程
+/// 服务名，即路径的最后一个部分
+fn service_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// 启动 `path` 处的服务程序，`handles` 为交给服务的初始句柄
 fn start_service(
     bootstrap: &BootstrapHandler,
-    name: &str,
-    buf: &[u8],
+    image: &BootImage,
+    path: &str,
     privileged: bool,
+    handles: &[(Handle, Rights)],
 ) -> Result<Process, InitError> {
-    // 创建 Name Server 进程
-    let mut process = Process::create(name)
+    let name = service_name(path);
+    let elf = image.read(path)?;
+
+    // 创建进程
+    let mut builder = Process::create(name)
         .map_err(|_| InitError::ProcessFailed)?
-        .bootstrap(true)
-        .build()
-        .map_err(|_| InitError::ProcessFailed)?;
+        .bootstrap(true);
+    for &(handle, rights) in handles {
+        builder = builder.add_handle(handle, rights);
+    }
+    let mut process = builder.build().map_err(|_| InitError::ProcessFailed)?;
 
     // 获取 bootstrap channel
     let process_bootstrap = process.take_bootstrap().ok_or(InitError::ProcessFailed)?;
@@ -130,7 +245,8 @@ fn start_service(
     // 注册
     let _child_id = bootstrap.add_child(process_bootstrap, privileged);
 
-    let loaded = ProgramLoader::load(&process, buf).map_err(|_| InitError::ProcessFailed)?;
+    let loaded = with_aligned(elf, |elf| ProgramLoader::load(&process, elf))
+        .map_err(|_| InitError::ProcessFailed)?;
 
     process
         .create_thread(name, loaded.entry, loaded.stack_top, 0)
@@ -142,6 +258,18 @@ fn start_service(
     Ok(process)
 }
 
+/// ELF 解析器按结构体直接读取头部，需要 8 字节对齐，而 CPIO 中的数据只保证 4 字节对齐，不对齐时先复制一份
+fn with_aligned<R>(data: &[u8], f: impl FnOnce(&[u8]) -> R) -> R {
+    if data.as_ptr().align_offset(align_of::<u64>()) == 0 {
+        return f(data);
+    }
+    let mut words = vec![0u64; data.len().div_ceil(size_of::<u64>())];
+    let bytes =
+        unsafe { core::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, data.len()) };
+    bytes.copy_from_slice(data);
+    f(bytes)
+}
+
 /// 运行事件循环
 fn run_event_loop(bootstrap: &BootstrapHandler) -> Result<(), InitError> {
     // 处理 bootstrap 请求
@@ -155,4 +283,8 @@ fn run_event_loop(bootstrap: &BootstrapHandler) -> Result<(), InitError> {
 enum InitError {
     BootstrapFailed,
     ProcessFailed,
+    /// 没有拿到或无法解析 initramfs
+    BootImageFailed,
+    /// initramfs 中没有这个文件
+    NotFound,
 }
@@ -1,12 +1,27 @@
 //! 程序加载器
 
 use libradon::{
-    memory::{MappingFlags, Vmo, VmoOptions, map_vmo_at_in_vmar},
+    memory::{MappingFlags, Vmo, VmoOptions, allocate_vmar, map_vmo_at_in_vmar},
     process::Process,
 };
 use radon_kernel::layout;
 
-use super::elf::{ElfError, ElfParser};
+use super::elf::{ElfError, ElfParser, LoadSegment};
+
+/// 按段权限生成映射标志，不再一律映射为可读可写可执行
+fn segment_flags(segment: &LoadSegment) -> MappingFlags {
+    let mut flags = MappingFlags::empty();
+    if segment.is_readable() {
+        flags |= MappingFlags::READ;
+    }
+    if segment.is_writable() {
+        flags |= MappingFlags::WRITE;
+    }
+    if segment.is_executable() {
+        flags |= MappingFlags::EXECUTE;
+    }
+    flags
+}
 
 /// 加载器错误
 #[derive(Debug)]
@@ -72,20 +87,26 @@ impl ProgramLoader {
                 &vmo,
                 offset,
                 size,
-                MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
+                segment_flags(&segment),
                 aligned_vaddr as *mut u8,
             )
             .map_err(|_| LoaderError::OutOfMemory)?;
         }
 
-        // 分配栈
+        // 分配栈：栈放在单独的子 VMAR 中，底部留出保护页，其他映射不会紧贴栈底
         let aligned_size = layout::DEFAULT_STACK_SIZE;
         let stack_bottom = layout::STACK_TOP - aligned_size;
+        let (stack_vmar, _) = allocate_vmar(
+            vmar_handle,
+            Some(stack_bottom - layout::STACK_GUARD_SIZE - layout::USER_SPACE_START),
+            aligned_size + layout::STACK_GUARD_SIZE,
+        )
+        .map_err(|_| LoaderError::OutOfMemory)?;
         let mut vmo =
             Vmo::create(aligned_size, VmoOptions::COMMIT).map_err(|_| LoaderError::OutOfMemory)?;
         vmo.with_nodrop(true);
         map_vmo_at_in_vmar(
-            vmar_handle,
+            stack_vmar.handle(),
             &vmo,
             0,
             aligned_size,
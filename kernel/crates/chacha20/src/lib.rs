@@ -0,0 +1,110 @@
+//! ChaCha20 分组函数（RFC 8439 §2.3）
+//!
+//! 内核随机数发生器使用它生成密钥流。与内核的其他部分分开，可以在宿主机上用 RFC 中的
+//! 测试向量验证。
+
+#![cfg_attr(not(test), no_std)]
+
+/// 一个块的字节数
+pub const BLOCK_SIZE: usize = 64;
+
+/// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// 计算一个块，密钥和 nonce 按小端字序给出
+pub fn block(key: &[u32; 8], counter: u32, nonce: &[u32; 3]) -> [u8; BLOCK_SIZE] {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter;
+    input[13..].copy_from_slice(nonce);
+
+    let mut state = input;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; BLOCK_SIZE];
+    for (i, word) in state.iter().enumerate() {
+        let word = word.wrapping_add(input[i]);
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 8439 §2.1.1
+    #[test]
+    fn quarter_round_test_vector() {
+        let mut state = [0u32; 16];
+        state[..4].copy_from_slice(&[0x1111_1111, 0x0102_0304, 0x9b8d_6f43, 0x0123_4567]);
+        quarter_round(&mut state, 0, 1, 2, 3);
+        assert_eq!(
+            state[..4],
+            [0xea2a_92f4, 0xcb1c_f8ce, 0x4581_472e, 0x5881_c4bb]
+        );
+    }
+
+    /// RFC 8439 §2.3.2
+    #[test]
+    fn block_test_vector() {
+        let mut key = [0u32; 8];
+        for (i, word) in key.iter_mut().enumerate() {
+            let base = (i * 4) as u8;
+            *word = u32::from_le_bytes([base, base + 1, base + 2, base + 3]);
+        }
+        let nonce = [0x0900_0000, 0x4a00_0000, 0];
+
+        let expected: [u32; 16] = [
+            0xe4e7_f110,
+            0x1559_3bd1,
+            0x1fdd_0f50,
+            0xc471_20a3,
+            0xc7f4_d1c7,
+            0x0368_c033,
+            0x9aaa_2204,
+            0x4e6c_d4c3,
+            0x4664_82d2,
+            0x09aa_9f07,
+            0x05d7_c214,
+            0xa202_8bd9,
+            0xd19c_12b5,
+            0xb94e_16de,
+            0xe883_d0cb,
+            0x4e3c_50a2,
+        ];
+        let mut expected_bytes = [0u8; BLOCK_SIZE];
+        for (i, word) in expected.iter().enumerate() {
+            expected_bytes[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+
+        assert_eq!(block(&key, 1, &nonce), expected_bytes);
+    }
+
+    #[test]
+    fn counter_changes_block() {
+        let key = [0u32; 8];
+        assert_ne!(block(&key, 0, &[0; 3]), block(&key, 1, &[0; 3]));
+    }
+}
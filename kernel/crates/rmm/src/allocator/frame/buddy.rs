@@ -181,11 +181,21 @@ impl<A: Arch> BuddyAllocator<A> {
 
 impl<A: Arch> FrameAllocator for BuddyAllocator<A> {
     unsafe fn allocate(&mut self, count: FrameCount) -> Option<PhysicalAddress> {
+        unsafe { self.allocate_aligned(count, FrameCount::new(1)) }
+    }
+
+    unsafe fn allocate_aligned(
+        &mut self,
+        count: FrameCount,
+        align: FrameCount,
+    ) -> Option<PhysicalAddress> {
         unsafe {
             if self.table_virt.data() == 0 {
                 return None;
             }
 
+            let align_size = align.data().max(1) << A::PAGE_SHIFT;
+
             for entry_i in 0..Self::BUDDY_ENTRIES {
                 let virt = self
                     .table_virt
@@ -196,15 +206,23 @@ impl<A: Arch> FrameAllocator for BuddyAllocator<A> {
                 let mut free_count = 0;
                 for page in entry.skip..entry.pages() {
                     let usage = entry.usage(page)?;
-                    if usage.0 == 0 {
-                        free_count += 1;
+                    if usage.0 != 0 {
+                        free_count = 0;
+                        continue;
+                    }
 
-                        if free_count == count.data() {
-                            break;
+                    // A run may only start on an aligned frame
+                    if free_count == 0 {
+                        let page_phys = entry.base.add(page << A::PAGE_SHIFT);
+                        if !page_phys.data().is_multiple_of(align_size) {
+                            continue;
                         }
-                    } else {
-                        free_page = page + 1;
-                        free_count = 0;
+                        free_page = page;
+                    }
+
+                    free_count += 1;
+                    if free_count == count.data() {
+                        break;
                     }
                 }
 
@@ -48,6 +48,25 @@ pub trait FrameAllocator {
 
     unsafe fn free(&mut self, address: PhysicalAddress, count: FrameCount);
 
+    /// Allocates `count` frames starting at a multiple of `align` frames. Allocators that cannot
+    /// honor the alignment only succeed when no alignment is requested.
+    ///
+    /// # Safety
+    /// Same requirements as `allocate`.
+    unsafe fn allocate_aligned(
+        &mut self,
+        count: FrameCount,
+        align: FrameCount,
+    ) -> Option<PhysicalAddress> {
+        unsafe {
+            if align.data() <= 1 {
+                self.allocate(count)
+            } else {
+                None
+            }
+        }
+    }
+
     unsafe fn allocate_one(&mut self) -> Option<PhysicalAddress> {
         unsafe { self.allocate(FrameCount::new(1)) }
     }
@@ -71,6 +90,13 @@ where
     unsafe fn free(&mut self, address: PhysicalAddress, count: FrameCount) {
         unsafe { T::free(self, address, count) }
     }
+    unsafe fn allocate_aligned(
+        &mut self,
+        count: FrameCount,
+        align: FrameCount,
+    ) -> Option<PhysicalAddress> {
+        unsafe { T::allocate_aligned(self, count, align) }
+    }
     unsafe fn allocate_one(&mut self) -> Option<PhysicalAddress> {
         unsafe { T::allocate_one(self) }
     }
@@ -31,6 +31,10 @@ impl Arch for EmulateArch {
     const ENTRY_ADDRESS_WIDTH: usize = X8664Arch::ENTRY_ADDRESS_WIDTH;
 
     const ENTRY_FLAG_WRITE_COMBINING: usize = X8664Arch::ENTRY_FLAG_WRITE_COMBINING;
+    const ENTRY_FLAG_UNCACHED: usize = X8664Arch::ENTRY_FLAG_UNCACHED;
+    const ENTRY_FLAG_UNCACHED_DEVICE: usize = X8664Arch::ENTRY_FLAG_UNCACHED_DEVICE;
+    const ENTRY_FLAG_HUGE: usize = X8664Arch::ENTRY_FLAG_HUGE;
+    const HUGE_PAGE_LEVEL: usize = X8664Arch::HUGE_PAGE_LEVEL;
 
     unsafe fn init() -> &'static [MemoryArea] {
         unsafe {
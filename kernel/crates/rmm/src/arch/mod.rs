@@ -44,6 +44,10 @@ pub trait Arch: Clone + Copy {
     const ENTRY_FLAG_GLOBAL: usize;
     const ENTRY_FLAG_NO_GLOBAL: usize;
     const ENTRY_FLAG_WRITE_COMBINING: usize;
+    const ENTRY_FLAG_UNCACHED: usize = 0; // Uncached, may be overridden by MTRRs
+    const ENTRY_FLAG_UNCACHED_DEVICE: usize = 0; // Strongly uncached, for device registers
+    const ENTRY_FLAG_HUGE: usize = 0; // Leaf flag for entries above level 0
+    const HUGE_PAGE_LEVEL: usize = 0; // Highest level that may hold a leaf entry, 0 if unsupported
 
     const PHYS_OFFSET: usize;
 
@@ -61,6 +65,12 @@ pub trait Arch: Clone + Copy {
     const ENTRY_ADDRESS_MASK: usize = Self::ENTRY_ADDRESS_SIZE - 1; // Mask of physical address, starting at 0th bit
     const ENTRY_FLAGS_MASK: usize = !(Self::ENTRY_ADDRESS_MASK << Self::ENTRY_ADDRESS_SHIFT);
 
+    /// Size of the region covered by one entry at `level`
+    #[inline(always)]
+    fn level_size(level: usize) -> usize {
+        1 << (level * Self::PAGE_ENTRY_SHIFT + Self::PAGE_SHIFT)
+    }
+
     unsafe fn init() -> &'static [MemoryArea];
 
     #[inline(always)]
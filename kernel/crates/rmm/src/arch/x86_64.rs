@@ -17,12 +17,17 @@ impl Arch for X8664Arch {
     const ENTRY_FLAG_READONLY: usize = 0;
     const ENTRY_FLAG_READWRITE: usize = 1 << 1;
     const ENTRY_FLAG_PAGE_USER: usize = 1 << 2;
-    // Not used: const ENTRY_FLAG_HUGE: usize = 1 << 7;
+    const ENTRY_FLAG_HUGE: usize = 1 << 7;
+    const HUGE_PAGE_LEVEL: usize = 2; // 2 MiB in the PD, 1 GiB in the PDP
     const ENTRY_FLAG_GLOBAL: usize = 1 << 8;
     const ENTRY_FLAG_NO_GLOBAL: usize = 0;
     const ENTRY_FLAG_NO_EXEC: usize = 1 << 63;
     const ENTRY_FLAG_EXEC: usize = 0;
-    const ENTRY_FLAG_WRITE_COMBINING: usize = 1 << 7;
+    // Cache types select PAT entries through PWT (bit 3) and PCD (bit 4) only, which requires
+    // the PAT MSR to hold WB, WC, UC-, UC in entries 0 to 3
+    const ENTRY_FLAG_WRITE_COMBINING: usize = 1 << 3;
+    const ENTRY_FLAG_UNCACHED: usize = 1 << 4;
+    const ENTRY_FLAG_UNCACHED_DEVICE: usize = 1 << 4 | 1 << 3;
 
     const PHYS_OFFSET: usize = Self::PAGE_NEGATIVE_MASK + (Self::PAGE_ADDRESS_SIZE >> 1) as usize; // PML4 slot 256 and onwards
 
@@ -66,7 +71,7 @@ impl Arch for X8664Arch {
 #[cfg(test)]
 mod tests {
     use super::{VirtualAddress, X8664Arch};
-    use crate::Arch;
+    use crate::{Arch, PageFlags};
 
     #[test]
     fn constants() {
@@ -85,6 +90,10 @@ mod tests {
         assert_eq!(X8664Arch::ENTRY_FLAGS_MASK, 0xFFF0_0000_0000_0FFF);
 
         assert_eq!(X8664Arch::PHYS_OFFSET, 0xFFFF_8000_0000_0000);
+
+        assert_eq!(X8664Arch::level_size(0), 0x1000);
+        assert_eq!(X8664Arch::level_size(1), 0x20_0000);
+        assert_eq!(X8664Arch::level_size(2), 0x4000_0000);
     }
     #[test]
     fn is_canonical() {
@@ -103,4 +112,13 @@ mod tests {
         no(0x1337_8000_0000_0000);
         no(0x0000_8000_0000_0000);
     }
+
+    #[test]
+    fn write_xor_execute() {
+        let flags = PageFlags::<X8664Arch>::new();
+        assert!(!flags.is_write_execute());
+        assert!(!flags.write(true).is_write_execute());
+        assert!(!flags.execute(true).is_write_execute());
+        assert!(flags.write(true).execute(true).is_write_execute());
+    }
 }
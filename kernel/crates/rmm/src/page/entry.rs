@@ -56,4 +56,11 @@ impl<A: Arch> PageEntry<A> {
     pub fn present(&self) -> bool {
         self.data & A::ENTRY_FLAG_PRESENT != 0
     }
+
+    /// Whether this entry is a huge page leaf. Only meaningful above level 0, where the same bit
+    /// may have another meaning.
+    #[inline(always)]
+    pub fn huge(&self) -> bool {
+        A::ENTRY_FLAG_HUGE != 0 && self.data & A::ENTRY_FLAG_HUGE != 0
+    }
 }
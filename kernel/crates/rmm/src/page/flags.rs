@@ -62,6 +62,18 @@ impl<A: Arch> PageFlags<A> {
         self.custom_flag(A::ENTRY_FLAG_WRITE_COMBINING, value)
     }
 
+    #[must_use]
+    #[inline(always)]
+    pub fn uncached(self, value: bool) -> Self {
+        self.custom_flag(A::ENTRY_FLAG_UNCACHED, value)
+    }
+
+    #[must_use]
+    #[inline(always)]
+    pub fn uncached_device(self, value: bool) -> Self {
+        self.custom_flag(A::ENTRY_FLAG_UNCACHED_DEVICE, value)
+    }
+
     #[inline(always)]
     pub fn has_flag(&self, flag: usize) -> bool {
         self.data & flag == flag
@@ -105,7 +117,6 @@ impl<A: Arch> PageFlags<A> {
     #[must_use]
     #[inline(always)]
     pub fn execute(self, value: bool) -> Self {
-        //TODO: write xor execute?
         // Architecture may use no exec or exec, support either
         self.custom_flag(A::ENTRY_FLAG_NO_EXEC, !value)
             .custom_flag(A::ENTRY_FLAG_EXEC, value)
@@ -117,6 +128,13 @@ impl<A: Arch> PageFlags<A> {
         self.data & (A::ENTRY_FLAG_NO_EXEC | A::ENTRY_FLAG_EXEC) == A::ENTRY_FLAG_EXEC
     }
 
+    /// Returns true if the page is both writable and executable, violating W^X.
+    /// Callers enforcing W^X should reject such flags before mapping.
+    #[inline(always)]
+    pub fn is_write_execute(&self) -> bool {
+        self.has_write() && self.has_execute()
+    }
+
     #[must_use]
     #[inline(always)]
     pub fn global(self, value: bool) -> Self {
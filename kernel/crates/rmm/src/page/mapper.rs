@@ -66,17 +66,39 @@ impl<A: Arch, F: FrameAllocator> PageMapper<A, F> {
         f: impl FnOnce(PhysicalAddress, PageFlags<A>) -> (PhysicalAddress, PageFlags<A>),
     ) -> Option<(PageFlags<A>, PhysicalAddress, PageFlush<A>)> {
         unsafe {
-            self.visit(virt, |p1, i| {
-                let old_entry = p1.entry(i)?;
-                let old_phys = old_entry.address().ok()?;
-                let old_flags = old_entry.flags();
-                let (new_phys, new_flags) = f(old_phys, old_flags);
-                // TODO: Higher-level PageEntry::new interface?
-                let new_entry = PageEntry::new(new_phys.data(), new_flags.data());
-                p1.set_entry(i, new_entry);
-                Some((old_flags, old_phys, PageFlush::new(virt)))
-            })
-            .flatten()
+            let mut p1 = self.walk(virt, 0, false)?;
+            let i = p1.index_of(virt)?;
+            let old_entry = p1.entry(i)?;
+            let old_phys = old_entry.address().ok()?;
+            let old_flags = old_entry.flags();
+            let (new_phys, new_flags) = f(old_phys, old_flags);
+            // TODO: Higher-level PageEntry::new interface?
+            let new_entry = PageEntry::new(new_phys.data(), new_flags.data());
+            p1.set_entry(i, new_entry);
+            Some((old_flags, old_phys, PageFlush::new(virt)))
+        }
+    }
+    /// Changes the flags of the leaf entry covering `virt` at whatever level it is, without
+    /// splitting huge pages. Returns the old flags, the physical base and the level of the leaf.
+    ///
+    /// # Safety
+    /// The caller must flush the TLB for the whole leaf and keep the mapping consistent.
+    pub unsafe fn remap_leaf_with(
+        &mut self,
+        virt: VirtualAddress,
+        map_flags: impl FnOnce(PageFlags<A>) -> PageFlags<A>,
+    ) -> Option<(PageFlags<A>, PhysicalAddress, usize, PageFlush<A>)> {
+        unsafe {
+            let (mut table, i) = self.leaf(virt)?;
+            let old_entry = table.entry(i)?;
+            let old_phys = old_entry.address().ok()?;
+            let old_flags = old_entry.flags();
+            let mut new_flags = map_flags(old_flags).data();
+            if table.level() > 0 {
+                new_flags |= A::ENTRY_FLAG_HUGE;
+            }
+            table.set_entry(i, PageEntry::new(old_phys.data(), new_flags));
+            Some((old_flags, old_phys, table.level(), PageFlush::new(virt)))
         }
     }
     pub unsafe fn remap_with(
@@ -119,33 +141,64 @@ impl<A: Arch, F: FrameAllocator> PageMapper<A, F> {
             //TODO: verify virt and phys are aligned
             //TODO: verify flags have correct bits
             let entry = PageEntry::new(phys.data(), flags.data());
-            let mut table = self.table();
-            loop {
-                let i = table.index_of(virt)?;
-                if table.level() == 0 {
-                    //TODO: check for overwriting entry
-                    table.set_entry(i, entry);
-                    return Some(PageFlush::new(virt));
-                } else {
-                    let next_opt = table.next(i);
-                    let next = match next_opt {
-                        Some(some) => some,
-                        None => {
-                            let next_phys = self.allocator.allocate_one()?;
-                            //TODO: correct flags?
-                            let flags = A::ENTRY_FLAG_DEFAULT_TABLE
-                                | if virt.kind() == TableKind::User {
-                                    A::ENTRY_FLAG_TABLE_USER
-                                } else {
-                                    0
-                                };
-                            table.set_entry(i, PageEntry::new(next_phys.data(), flags));
-                            table.next(i)?
-                        }
-                    };
-                    table = next;
-                }
+            let mut table = self.walk(virt, 0, true)?;
+            let i = table.index_of(virt)?;
+            //TODO: check for overwriting entry
+            table.set_entry(i, entry);
+            Some(PageFlush::new(virt))
+        }
+    }
+    /// Maps `phys` at `virt` with a single leaf entry at `level`, which must not exceed
+    /// `A::HUGE_PAGE_LEVEL`. Both addresses must be aligned to `A::level_size(level)`.
+    ///
+    /// Fails if the slot is already in use, either by a leaf or by a lower level table.
+    ///
+    /// # Safety
+    /// The physical range must be valid to map with `flags`.
+    pub unsafe fn map_phys_huge(
+        &mut self,
+        virt: VirtualAddress,
+        phys: PhysicalAddress,
+        flags: PageFlags<A>,
+        level: usize,
+    ) -> Option<PageFlush<A>> {
+        unsafe {
+            if level == 0 {
+                return self.map_phys(virt, phys, flags);
+            }
+            let size = A::level_size(level);
+            if level > A::HUGE_PAGE_LEVEL
+                || !virt.data().is_multiple_of(size)
+                || !phys.data().is_multiple_of(size)
+            {
+                return None;
+            }
+
+            let mut table = self.walk(virt, level, true)?;
+            let i = table.index_of(virt)?;
+            if table.entry(i)?.present() {
+                return None;
             }
+            table.set_entry(
+                i,
+                PageEntry::new(phys.data(), flags.data() | A::ENTRY_FLAG_HUGE),
+            );
+            Some(PageFlush::new(virt))
+        }
+    }
+    /// Splits the huge page covering `virt` into entries of the next lower level that map the
+    /// same memory with the same flags. Returns the level of the split leaf.
+    ///
+    /// # Safety
+    /// The page table must be owned by this mapper.
+    pub unsafe fn split(&mut self, virt: VirtualAddress) -> Option<usize> {
+        unsafe {
+            let (mut table, i) = self.leaf(virt)?;
+            if table.level() == 0 {
+                return None;
+            }
+            split_entry(&mut table, i, &mut self.allocator)?;
+            Some(table.level())
         }
     }
     pub unsafe fn map_linearly(
@@ -158,26 +211,71 @@ impl<A: Arch, F: FrameAllocator> PageMapper<A, F> {
             self.map_phys(virt, phys, flags).map(|flush| (virt, flush))
         }
     }
-    fn visit<T>(
-        &self,
+    /// Descends to the table at `level` covering `virt`, splitting huge pages on the way.
+    /// Missing tables are allocated when `create` is set.
+    unsafe fn walk(
+        &mut self,
         virt: VirtualAddress,
-        f: impl FnOnce(&mut PageTable<A>, usize) -> T,
-    ) -> Option<T> {
+        level: usize,
+        create: bool,
+    ) -> Option<PageTable<A>> {
+        let mut table = self.table();
+        unsafe {
+            while table.level() > level {
+                let i = table.index_of(virt)?;
+                let entry = table.entry(i)?;
+                if entry.present() && entry.huge() {
+                    split_entry(&mut table, i, &mut self.allocator)?;
+                }
+                table = match table.next(i) {
+                    Some(next) => next,
+                    None if create => {
+                        let next_phys = self.allocator.allocate_one()?;
+                        //TODO: correct flags?
+                        let flags = A::ENTRY_FLAG_DEFAULT_TABLE
+                            | if virt.kind() == TableKind::User {
+                                A::ENTRY_FLAG_TABLE_USER
+                            } else {
+                                0
+                            };
+                        table.set_entry(i, PageEntry::new(next_phys.data(), flags));
+                        table.next(i)?
+                    }
+                    None => return None,
+                };
+            }
+        }
+        Some(table)
+    }
+    /// Finds the leaf entry covering `virt`, returning its table and index
+    fn leaf(&self, virt: VirtualAddress) -> Option<(PageTable<A>, usize)> {
         let mut table = self.table();
         unsafe {
             loop {
                 let i = table.index_of(virt)?;
-                if table.level() == 0 {
-                    return Some(f(&mut table, i));
-                } else {
-                    table = table.next(i)?;
+                let entry = table.entry(i)?;
+                if table.level() == 0 || (entry.present() && entry.huge()) {
+                    return Some((table, i));
                 }
+                table = table.next(i)?;
             }
         }
     }
+    /// Translates `virt` to the leaf entry covering it, returning the physical base of the leaf,
+    /// its flags and its level.
+    pub fn translate_leaf(
+        &self,
+        virt: VirtualAddress,
+    ) -> Option<(PhysicalAddress, PageFlags<A>, usize)> {
+        let (table, i) = self.leaf(virt)?;
+        let entry = unsafe { table.entry(i)? };
+        Some((entry.address().ok()?, entry.flags(), table.level()))
+    }
     pub fn translate(&self, virt: VirtualAddress) -> Option<(PhysicalAddress, PageFlags<A>)> {
-        let entry = self.visit(virt, |p1, i| unsafe { p1.entry(i) })??;
-        Some((entry.address().ok()?, entry.flags()))
+        let (phys, flags, level) = self.translate_leaf(virt)?;
+        // Address of the page inside a huge page
+        let offset = virt.data() & (A::level_size(level) - 1) & !A::PAGE_OFFSET_MASK;
+        Some((phys.add(offset), flags))
     }
 
     pub unsafe fn unmap(
@@ -201,32 +299,113 @@ impl<A: Arch, F: FrameAllocator> PageMapper<A, F> {
             //TODO: verify virt is aligned
             let mut table = self.table();
             let level = table.level();
-            unmap_phys_inner(virt, &mut table, level, unmap_parents, &mut self.allocator)
-                .map(|(pa, pf)| (pa, pf, PageFlush::new(virt)))
+            unmap_phys_inner(
+                virt,
+                &mut table,
+                level,
+                unmap_parents,
+                true,
+                &mut self.allocator,
+            )
+            .map(|(pa, pf, _)| (pa, pf, PageFlush::new(virt)))
+        }
+    }
+    /// Unmaps the leaf entry covering `virt` at whatever level it is, without splitting huge
+    /// pages. Returns the physical base, flags and level of the removed leaf.
+    ///
+    /// # Safety
+    /// The caller must flush the TLB for the whole leaf before reusing its memory.
+    pub unsafe fn unmap_leaf(
+        &mut self,
+        virt: VirtualAddress,
+        unmap_parents: bool,
+    ) -> Option<(PhysicalAddress, PageFlags<A>, usize, PageFlush<A>)> {
+        unsafe {
+            let mut table = self.table();
+            let level = table.level();
+            unmap_phys_inner(
+                virt,
+                &mut table,
+                level,
+                unmap_parents,
+                false,
+                &mut self.allocator,
+            )
+            .map(|(pa, pf, level)| (pa, pf, level, PageFlush::new(virt)))
         }
     }
 }
+/// Replaces the huge page at `table[i]` with a table of the next lower level
+unsafe fn split_entry<A: Arch>(
+    table: &mut PageTable<A>,
+    i: usize,
+    allocator: &mut impl FrameAllocator,
+) -> Option<()> {
+    unsafe {
+        let entry = table.entry(i)?;
+        let phys = entry.address().ok()?;
+        let level = table.level() - 1;
+
+        let mut flags = entry.flags().data() & !A::ENTRY_FLAG_HUGE;
+        if level > 0 {
+            flags |= A::ENTRY_FLAG_HUGE;
+        }
+
+        let next_phys = allocator.allocate_one()?;
+        let mut next = PageTable::<A>::new(table.entry_base(i)?, next_phys, level);
+        for j in 0..A::PAGE_ENTRIES {
+            let child = phys.add(j * A::level_size(level));
+            next.set_entry(j, PageEntry::new(child.data(), flags));
+        }
+
+        let table_flags = A::ENTRY_FLAG_DEFAULT_TABLE
+            | if entry.flags().has_flag(A::ENTRY_FLAG_PAGE_USER) {
+                A::ENTRY_FLAG_TABLE_USER
+            } else {
+                0
+            };
+        table.set_entry(i, PageEntry::new(next_phys.data(), table_flags));
+        Some(())
+    }
+}
 unsafe fn unmap_phys_inner<A: Arch>(
     virt: VirtualAddress,
     table: &mut PageTable<A>,
     initial_level: usize,
     unmap_parents: bool,
+    split: bool,
     allocator: &mut impl FrameAllocator,
-) -> Option<(PhysicalAddress, PageFlags<A>)> {
+) -> Option<(PhysicalAddress, PageFlags<A>, usize)> {
     unsafe {
         let i = table.index_of(virt)?;
 
+        let entry = table.entry(i)?;
+        if table.level() > 0 && entry.present() && entry.huge() {
+            if split {
+                split_entry(table, i, allocator)?;
+            } else {
+                table.set_entry(i, PageEntry::new(0, 0));
+                return Some((entry.address().ok()?, entry.flags(), table.level()));
+            }
+        }
+
         if table.level() == 0 {
             let entry_opt = table.entry(i);
             table.set_entry(i, PageEntry::new(0, 0));
             let entry = entry_opt?;
 
-            Some((entry.address().ok()?, entry.flags()))
+            Some((entry.address().ok()?, entry.flags(), 0))
         } else {
             let mut subtable = table.next(i)?;
 
-            let res =
-                unmap_phys_inner(virt, &mut subtable, initial_level, unmap_parents, allocator)?;
+            let res = unmap_phys_inner(
+                virt,
+                &mut subtable,
+                initial_level,
+                unmap_parents,
+                split,
+                allocator,
+            )?;
 
             //TODO: This is a bad idea for architectures where the kernel mappings are done in the process tables,
             // as these mappings may become out of sync
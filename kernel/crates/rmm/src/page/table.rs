@@ -113,9 +113,14 @@ impl<A: Arch> PageTable<A> {
                 return None;
             }
 
+            let entry = self.entry(i)?;
+            if entry.huge() {
+                return None;
+            }
+
             Some(PageTable::new(
                 self.entry_base(i)?,
-                self.entry(i)?.address().ok()?,
+                entry.address().ok()?,
                 self.level - 1,
             ))
         }
@@ -0,0 +1,32 @@
+use super::Ptrace;
+
+/// 内核调试器（GDB 远程协议）需要的体系结构支持
+pub trait DebugArch {
+    /// 断点指令
+    const BREAKPOINT: &'static [u8];
+    /// `g` 包中的寄存器数量
+    const REGISTER_COUNT: usize;
+    /// 调试串口使用的设备中断号
+    const SERIAL_IRQ: u8;
+    /// 调试串口寄存器的 I/O 端口基址，串口不在 I/O 空间时为 None
+    const SERIAL_IO_BASE: Option<u16>;
+
+    /// 按 GDB 的编号和字节序读取寄存器，写入 `out` 并返回字节数，编号无效时返回 None
+    fn read_register(regs: &Ptrace, index: usize, out: &mut [u8]) -> Option<usize>;
+    /// 按 GDB 的编号写入寄存器，返回是否成功；没有保存的寄存器忽略写入
+    fn write_register(regs: &mut Ptrace, index: usize, value: &[u8]) -> bool;
+    /// 设置或清除单步标志
+    fn set_single_step(regs: &mut Ptrace, enable: bool);
+    /// 保存调用者当前的指令指针、栈指针和帧指针，用于没有中断现场的情况（例如 panic）
+    fn capture_current(regs: &mut Ptrace);
+
+    /// 初始化调试串口，没有串口时返回 false
+    fn serial_init() -> bool;
+    /// 读取一个字节，没有数据时返回 None
+    fn serial_read() -> Option<u8>;
+    fn serial_write(byte: u8);
+    /// 开启调试串口的接收中断，用于响应 GDB 的中断请求
+    fn serial_enable_irq();
+    /// 在当前位置触发断点异常
+    fn breakpoint();
+}
@@ -3,6 +3,8 @@ pub trait IrqRegsArch {
     fn set_ip(&mut self, ip: u64);
     fn get_sp(&self) -> u64;
     fn set_sp(&mut self, sp: u64);
+    /// 帧指针，用于按帧指针链回溯调用栈
+    fn get_fp(&self) -> u64;
 
     fn get_ret_value(&self) -> u64;
     fn set_ret_value(&mut self, ret_value: u64);
@@ -16,6 +18,8 @@ pub trait IrqRegsArch {
     fn get_syscall_args(&self) -> (u64, u64, u64, u64, u64, u64);
 
     fn set_user_space(&mut self, user: bool);
+    /// 中断现场是否来自用户态
+    fn is_user_mode(&self) -> bool;
 
     fn to_bytes(&self) -> &[u8];
 }
@@ -23,6 +27,13 @@ pub trait IrqRegsArch {
 pub trait IrqArch {
     fn enable_global_irq();
     fn disable_global_irq();
+
+    /// 可以交给用户态驱动的设备中断数量，中断号从 0 开始
+    const DEVICE_IRQ_COUNT: usize;
+    /// 把设备中断路由到当前 CPU，路由后保持屏蔽；中断到达时调用 `object::interrupt::handle_irq`
+    fn route_device_irq(irq: u8);
+    /// 屏蔽或解除屏蔽设备中断
+    fn set_device_irq_masked(irq: u8, masked: bool);
 }
 
 pub trait IrqControllerArch {
@@ -2,7 +2,15 @@
 mod x86_64;
 #[cfg(target_arch = "x86_64")]
 pub use self::x86_64::*;
+#[cfg(target_arch = "riscv64")]
+mod riscv64;
+#[cfg(target_arch = "riscv64")]
+pub use self::riscv64::*;
 
 pub mod cache;
+pub mod debug;
 pub mod irq;
+pub mod random;
 pub mod time;
+pub mod tlb;
+pub mod user;
@@ -0,0 +1,10 @@
+pub trait RandomArch {
+    /// 从硬件随机数发生器读取 64 位随机数，不支持时返回 None
+    fn hardware_random() -> Option<u64>;
+    /// 从硬件熵源读取 64 位种子（x86 的 RDSEED），不支持时返回 None
+    ///
+    /// 与 [`RandomArch::hardware_random`] 不同，这里的输出直接来自熵源，适合作为 CSPRNG 的种子。
+    fn hardware_seed() -> Option<u64>;
+    /// 读取高精度的周期计数器，用于收集时间抖动
+    fn cycle_counter() -> u64;
+}
@@ -0,0 +1,13 @@
+use core::arch::global_asm;
+
+use limine::{paging::Mode, request::PagingModeRequest};
+
+/// rmm 使用 Sv48，直接映射区位于 `0xffff_8000_0000_0000`
+#[used]
+#[unsafe(link_section = ".requests")]
+static PAGING_MODE_REQUEST: PagingModeRequest = PagingModeRequest::new().with_mode(Mode::SV48);
+
+global_asm!(
+    include_str!("start.S"),
+    percpu = sym super::smp::PERCPU,
+);
@@ -0,0 +1,5 @@
+use crate::arch::cache::CacheArch;
+
+pub struct RiscV64CacheArch;
+
+impl CacheArch for RiscV64CacheArch {}
@@ -0,0 +1,81 @@
+use super::irq::Ptrace;
+use crate::arch::debug::DebugArch;
+
+/// `ebreak`
+const EBREAK: [u8; 4] = [0x73, 0x00, 0x10, 0x00];
+
+/// GDB riscv 寄存器编号：x0-x31，之后是 pc，都是 8 字节
+fn register(regs: &mut Ptrace, index: usize) -> Option<&mut u64> {
+    match index {
+        32 => Some(&mut regs.sepc),
+        _ => regs.gpr(index),
+    }
+}
+
+pub struct RiscV64DebugArch;
+
+/// QEMU virt 只有一个 MMIO 串口，已经用作控制台，没有单独的调试串口，GDB 桩不可用
+impl DebugArch for RiscV64DebugArch {
+    const BREAKPOINT: &'static [u8] = &EBREAK;
+    const REGISTER_COUNT: usize = 33;
+    const SERIAL_IRQ: u8 = 0;
+    const SERIAL_IO_BASE: Option<u16> = None;
+
+    fn read_register(regs: &Ptrace, index: usize, out: &mut [u8]) -> Option<usize> {
+        if index >= Self::REGISTER_COUNT {
+            return None;
+        }
+        // x0 恒为 0
+        let mut regs = *regs;
+        let value = register(&mut regs, index).map_or(0, |value| *value);
+        out[..8].copy_from_slice(&value.to_le_bytes());
+        Some(8)
+    }
+
+    fn write_register(regs: &mut Ptrace, index: usize, value: &[u8]) -> bool {
+        if index >= Self::REGISTER_COUNT || value.len() != 8 {
+            return false;
+        }
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(value);
+        if let Some(register) = register(regs, index) {
+            *register = u64::from_le_bytes(bytes);
+        }
+        true
+    }
+
+    /// 没有硬件单步，GDB 会改用软件断点
+    fn set_single_step(_regs: &mut Ptrace, _enable: bool) {}
+
+    #[inline(always)]
+    fn capture_current(regs: &mut Ptrace) {
+        unsafe {
+            core::arch::asm!(
+                "auipc {ip}, 0",
+                "mv {sp}, sp",
+                "mv {fp}, s0",
+                ip = out(reg) regs.sepc,
+                sp = out(reg) regs.sp,
+                fp = out(reg) regs.s0,
+                options(nomem, nostack, preserves_flags),
+            );
+            core::arch::asm!("csrr {}, sstatus", out(reg) regs.sstatus, options(nomem, nostack));
+        }
+    }
+
+    fn serial_init() -> bool {
+        false
+    }
+
+    fn serial_read() -> Option<u8> {
+        None
+    }
+
+    fn serial_write(_byte: u8) {}
+
+    fn serial_enable_irq() {}
+
+    fn breakpoint() {
+        unsafe { core::arch::asm!("ebreak", options(nomem, nostack)) };
+    }
+}
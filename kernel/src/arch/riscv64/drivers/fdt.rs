@@ -0,0 +1,140 @@
+//! 设备树：RISC-V 上代替 ACPI 描述 hart 和设备
+
+use alloc::vec::Vec;
+use fdt::{Fdt, node::FdtNode};
+use limine::request::DeviceTreeBlobRequest;
+use rmm::Arch;
+use spin::Lazy;
+
+use super::map_physical;
+use crate::arch::CurrentRmmArch;
+
+/// 设备树头部的大小，其中 `totalsize` 位于偏移 4
+const FDT_HEADER_SIZE: usize = 40;
+
+#[used]
+#[unsafe(link_section = ".requests")]
+static DTB_REQUEST: DeviceTreeBlobRequest = DeviceTreeBlobRequest::new();
+
+/// 引导程序传来的设备树，没有时为 None
+///
+/// 设备树所在的内存可能被引导程序回收，这里复制一份到内核堆中。
+pub static FDT: Lazy<Option<Fdt<'static>>> = Lazy::new(|| {
+    let virt = DTB_REQUEST.get_response()?.dtb_ptr() as usize;
+    let phys = virt - CurrentRmmArch::PHYS_OFFSET;
+
+    let header = map_physical(phys, FDT_HEADER_SIZE);
+    let size = u32::from_be(unsafe { ((header + 4) as *const u32).read_unaligned() }) as usize;
+    let blob = map_physical(phys, size);
+    let blob = unsafe { core::slice::from_raw_parts(blob as *const u8, size) };
+
+    match Fdt::new(Vec::leak(blob.to_vec())) {
+        Ok(fdt) => Some(fdt),
+        Err(error) => {
+            warn!("Invalid device tree: {:?}", error);
+            None
+        }
+    }
+});
+
+/// 把大端的单元格（1 个或 2 个 u32）读成整数
+fn cells(bytes: &[u8]) -> Option<usize> {
+    match bytes.len() {
+        4 => Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize),
+        8 => Some(u64::from_be_bytes(bytes.try_into().ok()?) as usize),
+        _ => None,
+    }
+}
+
+fn okay(node: &FdtNode) -> bool {
+    node.property("status")
+        .and_then(|status| status.as_str())
+        .is_none_or(|status| status == "okay" || status == "ok")
+}
+
+/// `/cpus` 下的 hart 节点
+fn cpu_nodes() -> impl Iterator<Item = FdtNode<'static, 'static>> {
+    FDT.as_ref()
+        .and_then(|fdt| fdt.find_node("/cpus"))
+        .into_iter()
+        .flat_map(|cpus| cpus.children())
+        .filter(|node| node.name.starts_with("cpu@"))
+}
+
+fn hart_id(node: &FdtNode) -> Option<usize> {
+    cells(node.property("reg")?.value)
+}
+
+/// 设备树中可用的 hart 编号，按编号排序
+pub fn harts() -> Vec<usize> {
+    let mut harts: Vec<usize> = cpu_nodes()
+        .filter(okay)
+        .filter_map(|node| hart_id(&node))
+        .collect();
+    harts.sort_unstable();
+    harts
+}
+
+/// `/chosen` 中的 `boot-hartid`，不是所有引导程序都会提供
+pub fn boot_hartid() -> Option<usize> {
+    let chosen = FDT.as_ref()?.find_node("/chosen")?;
+    cells(chosen.property("boot-hartid")?.value)
+}
+
+/// `time` 计数器的频率，写在 `/cpus` 或各个 hart 节点中
+pub fn timebase_frequency() -> Option<u64> {
+    let cpus = FDT.as_ref()?.find_node("/cpus")?;
+    cpus.property("timebase-frequency")
+        .or_else(|| cpu_nodes().find_map(|node| node.property("timebase-frequency")))
+        .and_then(|property| cells(property.value))
+        .map(|frequency| frequency as u64)
+}
+
+/// 第一个兼容 `compatible` 中任一项且已启用的设备
+pub fn find_compatible(compatible: &[&str]) -> Option<FdtNode<'static, 'static>> {
+    FDT.as_ref()?.all_nodes().find(|node| {
+        okay(node)
+            && node
+                .compatible()
+                .is_some_and(|list| list.all().any(|name| compatible.contains(&name)))
+    })
+}
+
+/// 设备的第一段寄存器：物理地址和大小
+pub fn reg(node: &FdtNode) -> Option<(usize, usize)> {
+    let region = node.reg()?.next()?;
+    Some((region.starting_address as usize, region.size?))
+}
+
+/// 中断控制器 `controller` 的各个上下文对应的 hart 和中断号
+///
+/// `interrupts-extended` 由 (hart 本地中断控制器的 phandle, 中断号) 组成，第 n 项就是上下文 n。
+pub fn interrupt_contexts(controller: &FdtNode) -> Vec<(usize, usize)> {
+    // hart 本地中断控制器的 phandle 到 hart 编号
+    let phandles: Vec<(usize, usize)> = cpu_nodes()
+        .filter_map(|cpu| {
+            let hartid = hart_id(&cpu)?;
+            let intc = cpu
+                .children()
+                .find(|child| child.name.starts_with("interrupt-controller"))?;
+            Some((cells(intc.property("phandle")?.value)?, hartid))
+        })
+        .collect();
+
+    let Some(property) = controller.property("interrupts-extended") else {
+        return Vec::new();
+    };
+    property
+        .value
+        .chunks_exact(8)
+        .map(|entry| {
+            let phandle = cells(&entry[..4]).unwrap_or(0);
+            let irq = cells(&entry[4..]).unwrap_or(0);
+            let hartid = phandles
+                .iter()
+                .find(|&&(handle, _)| handle == phandle)
+                .map_or(usize::MAX, |&(_, hartid)| hartid);
+            (hartid, irq)
+        })
+        .collect()
+}
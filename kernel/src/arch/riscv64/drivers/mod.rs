@@ -0,0 +1,33 @@
+pub mod fdt;
+pub mod plic;
+pub mod rtc;
+pub mod sbi;
+
+use rmm::{Arch, PageFlags, PageMapper, PhysicalAddress, TableKind};
+
+use crate::{
+    arch::CurrentRmmArch,
+    init::memory::{FRAME_ALLOCATOR, PAGE_SIZE, align_down, align_up},
+};
+
+/// 把物理地址范围映射到直接映射区，返回 `phys` 对应的虚拟地址
+///
+/// 内核页表只映射了内存，设备寄存器和设备树要在访问前映射。已经映射的页面保持不变。
+pub fn map_physical(phys: usize, size: usize) -> usize {
+    let start = align_down(phys);
+    let end = align_up(phys + size);
+
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    let mut mapper =
+        PageMapper::<CurrentRmmArch, _>::current(TableKind::Kernel, &mut *frame_allocator);
+    for addr in (start..end).step_by(PAGE_SIZE) {
+        let phys = PhysicalAddress::new(addr);
+        let virt = unsafe { CurrentRmmArch::phys_to_virt(phys) };
+        if let Some(flusher) = unsafe { mapper.map_phys(virt, phys, PageFlags::new().write(true)) }
+        {
+            flusher.flush();
+        }
+    }
+
+    unsafe { CurrentRmmArch::phys_to_virt(PhysicalAddress::new(phys)) }.data()
+}
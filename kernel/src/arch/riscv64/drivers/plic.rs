@@ -0,0 +1,145 @@
+//! 平台级中断控制器（PLIC）
+//!
+//! 每个中断源有一个优先级，优先级为 0 的中断源不会触发，用来屏蔽中断。每个 hart 的 S 模式
+//! 有一个上下文，上下文中的使能位决定中断发给哪个 hart。
+
+use spin::Once;
+
+use super::{fdt, map_physical};
+use crate::{arch::smp::get_hartid, memory::tlb::MAX_CPUS};
+
+/// 可以交给用户态驱动的中断源数量
+pub const DEVICE_IRQ_COUNT: usize = 64;
+
+const PRIORITY_BASE: usize = 0x0000;
+const ENABLE_BASE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT_BASE: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const CONTEXT_THRESHOLD: usize = 0x0;
+const CONTEXT_CLAIM: usize = 0x4;
+
+/// 设备中断的优先级，阈值为 0 时任何非零优先级都能触发
+const PRIORITY_ENABLED: u32 = 1;
+
+/// S 模式的外部中断号，`interrupts-extended` 中用它区分 M 模式和 S 模式的上下文
+const IRQ_S_EXTERNAL: usize = 9;
+
+struct Plic {
+    base: usize,
+    /// hart 编号到 S 模式上下文
+    contexts: [Option<usize>; MAX_CPUS],
+}
+
+static PLIC: Once<Plic> = Once::new();
+
+impl Plic {
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base + offset) as *mut u32
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { self.reg(offset).read_volatile() }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { self.reg(offset).write_volatile(value) };
+    }
+
+    fn context(&self) -> Option<usize> {
+        self.contexts.get(get_hartid()).copied().flatten()
+    }
+}
+
+/// 从设备树找到 PLIC 并初始化当前 hart 的上下文
+pub fn init() {
+    let Some(node) = fdt::find_compatible(&["riscv,plic0", "sifive,plic-1.0.0"]) else {
+        warn!("PLIC not found, device interrupts are unavailable");
+        return;
+    };
+    let Some((phys, size)) = fdt::reg(&node) else {
+        warn!("PLIC has no registers");
+        return;
+    };
+
+    let mut contexts = [None; MAX_CPUS];
+    for (context, (hartid, irq)) in fdt::interrupt_contexts(&node).into_iter().enumerate() {
+        if irq == IRQ_S_EXTERNAL && hartid < contexts.len() {
+            contexts[hartid] = Some(context);
+        }
+    }
+    // 没有 `interrupts-extended` 时按 QEMU virt 的布局：hart n 的 M 模式为 2n，S 模式为 2n + 1
+    if contexts.iter().all(Option::is_none) {
+        for hartid in fdt::harts().into_iter().filter(|&hartid| hartid < MAX_CPUS) {
+            contexts[hartid] = Some(2 * hartid + 1);
+        }
+    }
+
+    let plic = PLIC.call_once(|| Plic {
+        base: map_physical(phys, size),
+        contexts,
+    });
+    // 所有中断源先屏蔽
+    for irq in 1..DEVICE_IRQ_COUNT {
+        plic.write(PRIORITY_BASE + irq * 4, 0);
+    }
+    info!("PLIC at {:#x}", phys);
+
+    init_hart();
+}
+
+/// 关闭当前 hart 上所有中断源的使能，阈值设为 0
+pub fn init_hart() {
+    let Some(plic) = PLIC.get() else {
+        return;
+    };
+    let Some(context) = plic.context() else {
+        warn!("No PLIC context for hart {}", get_hartid());
+        return;
+    };
+    for word in 0..DEVICE_IRQ_COUNT / 32 {
+        plic.write(ENABLE_BASE + context * ENABLE_STRIDE + word * 4, 0);
+    }
+    plic.write(
+        CONTEXT_BASE + context * CONTEXT_STRIDE + CONTEXT_THRESHOLD,
+        0,
+    );
+}
+
+/// 把中断源发给当前 hart，保持屏蔽
+pub fn route(irq: u8) {
+    let Some(plic) = PLIC.get() else {
+        return;
+    };
+    let Some(context) = plic.context() else {
+        return;
+    };
+    let irq = irq as usize;
+    set_masked(irq as u8, true);
+    let offset = ENABLE_BASE + context * ENABLE_STRIDE + irq / 32 * 4;
+    plic.write(offset, plic.read(offset) | (1 << (irq % 32)));
+}
+
+pub fn set_masked(irq: u8, masked: bool) {
+    if let Some(plic) = PLIC.get() {
+        let priority = if masked { 0 } else { PRIORITY_ENABLED };
+        plic.write(PRIORITY_BASE + irq as usize * 4, priority);
+    }
+}
+
+/// 取出当前 hart 上优先级最高的等待中断，没有时返回 None
+pub fn claim() -> Option<u32> {
+    let plic = PLIC.get()?;
+    let context = plic.context()?;
+    let irq = plic.read(CONTEXT_BASE + context * CONTEXT_STRIDE + CONTEXT_CLAIM);
+    (irq != 0).then_some(irq)
+}
+
+/// 通知 PLIC 中断处理完毕，同一中断源之后才能再次发给这个上下文
+pub fn complete(irq: u32) {
+    if let Some(plic) = PLIC.get()
+        && let Some(context) = plic.context()
+    {
+        plic.write(CONTEXT_BASE + context * CONTEXT_STRIDE + CONTEXT_CLAIM, irq);
+    }
+}
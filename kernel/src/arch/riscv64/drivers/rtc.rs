@@ -0,0 +1,37 @@
+//! Goldfish RTC（QEMU virt 的实时时钟）
+
+use spin::Once;
+
+use super::{fdt, map_physical};
+
+/// 读低 32 位时锁存高 32 位，必须先读低位
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// 寄存器的虚拟地址
+static RTC: Once<usize> = Once::new();
+
+/// 映射 RTC 的寄存器，没有 RTC 时什么也不做
+///
+/// 映射只能在启动时进行，之后当前页表可能是用户进程的页表。
+pub fn init() {
+    let Some((phys, size)) =
+        fdt::find_compatible(&["google,goldfish-rtc"]).and_then(|node| fdt::reg(&node))
+    else {
+        return;
+    };
+    RTC.call_once(|| map_physical(phys, size));
+}
+
+/// 读取 Unix 时间戳（秒），没有 RTC 时返回 None
+pub fn read_unix_time() -> Option<u64> {
+    let base = *RTC.get()?;
+    let nanos = unsafe {
+        let low = ((base + TIME_LOW) as *const u32).read_volatile() as u64;
+        let high = ((base + TIME_HIGH) as *const u32).read_volatile() as u64;
+        (high << 32) | low
+    };
+    Some(nanos / NANOS_PER_SEC)
+}
@@ -0,0 +1,101 @@
+//! SBI 调用（OpenSBI 提供的 M 模式服务）
+
+use core::arch::asm;
+
+/// 基础扩展
+const EXT_BASE: usize = 0x10;
+/// 定时器扩展 "TIME"
+const EXT_TIME: usize = 0x5449_4d45;
+/// IPI 扩展 "sPI"
+const EXT_IPI: usize = 0x0073_5049;
+/// hart 状态管理扩展 "HSM"
+const EXT_HSM: usize = 0x0048_534d;
+/// 系统复位扩展 "SRST"
+const EXT_SRST: usize = 0x5352_5354;
+/// 调试控制台扩展 "DBCN"
+const EXT_DBCN: usize = 0x4442_434e;
+/// 旧版控制台输出，没有 DBCN 时使用
+const LEGACY_CONSOLE_PUTCHAR: usize = 0x01;
+
+const BASE_PROBE_EXTENSION: usize = 3;
+const DBCN_WRITE_BYTE: usize = 2;
+const HSM_HART_START: usize = 0;
+const HSM_HART_GET_STATUS: usize = 2;
+const SRST_RESET_TYPE_COLD_REBOOT: usize = 1;
+const SRST_RESET_TYPE_SHUTDOWN: usize = 0;
+
+/// `hart_get_status` 的返回值：hart 正在运行
+pub const HSM_STATUS_STARTED: usize = 0;
+
+/// SBI 返回的错误码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SbiError(pub isize);
+
+#[inline(always)]
+fn ecall(ext: usize, fid: usize, args: [usize; 3]) -> Result<usize, SbiError> {
+    let error: isize;
+    let value: usize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") args[0] => error,
+            inlateout("a1") args[1] => value,
+            in("a2") args[2],
+            in("a6") fid,
+            in("a7") ext,
+            options(nostack),
+        );
+    }
+    if error == 0 {
+        Ok(value)
+    } else {
+        Err(SbiError(error))
+    }
+}
+
+/// 固件是否实现了扩展 `ext`
+pub fn probe_extension(ext: usize) -> bool {
+    ecall(EXT_BASE, BASE_PROBE_EXTENSION, [ext, 0, 0]).is_ok_and(|value| value != 0)
+}
+
+/// 在时间计数器到达 `deadline` 时触发 S 模式定时器中断，同时清除正在等待的定时器中断
+pub fn set_timer(deadline: u64) {
+    let _ = ecall(EXT_TIME, 0, [deadline as usize, 0, 0]);
+}
+
+/// 向 `hart_mask` 中的 hart 发送软件中断，掩码的第 0 位对应 hart `hart_mask_base`
+pub fn send_ipi(hart_mask: usize, hart_mask_base: usize) {
+    let _ = ecall(EXT_IPI, 0, [hart_mask, hart_mask_base, 0]);
+}
+
+/// 启动处于停止状态的 hart，它以关闭分页的 S 模式从物理地址 `start` 开始执行，
+/// `a0` 为 hart 编号，`a1` 为 `opaque`
+pub fn hart_start(hartid: usize, start: usize, opaque: usize) -> Result<(), SbiError> {
+    ecall(EXT_HSM, HSM_HART_START, [hartid, start, opaque]).map(|_| ())
+}
+
+/// hart 的 HSM 状态
+pub fn hart_status(hartid: usize) -> Result<usize, SbiError> {
+    ecall(EXT_HSM, HSM_HART_GET_STATUS, [hartid, 0, 0])
+}
+
+/// 冷重启，固件不支持 SRST 时返回
+pub fn reboot() {
+    let _ = ecall(EXT_SRST, 0, [SRST_RESET_TYPE_COLD_REBOOT, 0, 0]);
+}
+
+/// 关机，固件不支持 SRST 时返回
+pub fn shutdown() {
+    let _ = ecall(EXT_SRST, 0, [SRST_RESET_TYPE_SHUTDOWN, 0, 0]);
+}
+
+/// 通过固件的控制台输出一个字节
+pub fn console_write_byte(byte: u8) {
+    static DBCN: spin::Lazy<bool> = spin::Lazy::new(|| probe_extension(EXT_DBCN));
+
+    if *DBCN {
+        let _ = ecall(EXT_DBCN, DBCN_WRITE_BYTE, [byte as usize, 0, 0]);
+    } else {
+        let _ = ecall(LEGACY_CONSOLE_PUTCHAR, 0, [byte as usize, 0, 0]);
+    }
+}
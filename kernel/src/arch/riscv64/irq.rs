@@ -0,0 +1,654 @@
+use core::mem::offset_of;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use rmm::VirtualAddress;
+
+use super::{
+    drivers::{plic, sbi},
+    smp::{PerCpu, this_cpu},
+    time,
+};
+use crate::{
+    arch::{
+        CurrentUserAccessArch, current_task_ptr,
+        irq::{IrqArch, IrqRegsArch},
+        user::UserAccessArch,
+    },
+    crash,
+    gdbstub::{self, Trap},
+    ktrace::{KTRACE_EVENT_IRQ_ENTER, KTRACE_EVENT_IRQ_EXIT, KTRACE_GROUP_IRQ},
+    layout::USER_SPACE_END,
+    object::{interrupt, vmar::FaultAccess},
+    smp::ONLINE_CPUS,
+    task::{get_current_task, kill_current_on_fault, timer_tick},
+    trace,
+};
+
+/// sstatus.SIE：S 模式全局中断开关
+const SSTATUS_SIE: usize = 1 << 1;
+/// sstatus.SPIE：陷入前的 SIE
+const SSTATUS_SPIE: usize = 1 << 5;
+/// sstatus.SPP：陷入前处于 S 模式
+const SSTATUS_SPP: usize = 1 << 8;
+/// sstatus.SUM：允许 S 模式访问用户页面
+pub(super) const SSTATUS_SUM: usize = 1 << 18;
+/// sstatus.UXL：用户态为 64 位
+const SSTATUS_UXL_64: usize = 2 << 32;
+
+/// sie/sip 中的软件中断、定时器中断和外部中断
+const SIE_SSIE: usize = 1 << 1;
+pub(super) const SIE_STIE: usize = 1 << 5;
+const SIE_SEIE: usize = 1 << 9;
+
+/// scause 最高位表示中断
+const SCAUSE_INTERRUPT: usize = 1 << 63;
+
+const INTERRUPT_SOFTWARE: usize = 1;
+const INTERRUPT_TIMER: usize = 5;
+const INTERRUPT_EXTERNAL: usize = 9;
+
+const EXCEPTION_INSTRUCTION_MISALIGNED: usize = 0;
+const EXCEPTION_INSTRUCTION_ACCESS_FAULT: usize = 1;
+const EXCEPTION_ILLEGAL_INSTRUCTION: usize = 2;
+const EXCEPTION_BREAKPOINT: usize = 3;
+const EXCEPTION_LOAD_MISALIGNED: usize = 4;
+const EXCEPTION_LOAD_ACCESS_FAULT: usize = 5;
+const EXCEPTION_STORE_MISALIGNED: usize = 6;
+const EXCEPTION_STORE_ACCESS_FAULT: usize = 7;
+const EXCEPTION_USER_ECALL: usize = 8;
+const EXCEPTION_INSTRUCTION_PAGE_FAULT: usize = 12;
+const EXCEPTION_LOAD_PAGE_FAULT: usize = 13;
+const EXCEPTION_STORE_PAGE_FAULT: usize = 15;
+
+/// 外部中断控制器和定时器已经由启动 hart 初始化，其他 hart 等待它之后再开启自己的部分
+pub static IRQ_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// 陷入现场，通用寄存器按 x1 到 x31 的顺序排列，`x<n>` 保存在第 `n - 1` 个字段
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ptrace {
+    pub(super) ra: u64,
+    pub(super) sp: u64,
+    pub(super) gp: u64,
+    pub(super) tp: u64,
+    pub(super) t0: u64,
+    pub(super) t1: u64,
+    pub(super) t2: u64,
+    pub(super) s0: u64,
+    pub(super) s1: u64,
+    pub(super) a0: u64,
+    pub(super) a1: u64,
+    pub(super) a2: u64,
+    pub(super) a3: u64,
+    pub(super) a4: u64,
+    pub(super) a5: u64,
+    pub(super) a6: u64,
+    pub(super) a7: u64,
+    pub(super) s2: u64,
+    pub(super) s3: u64,
+    pub(super) s4: u64,
+    pub(super) s5: u64,
+    pub(super) s6: u64,
+    pub(super) s7: u64,
+    pub(super) s8: u64,
+    pub(super) s9: u64,
+    pub(super) s10: u64,
+    pub(super) s11: u64,
+    pub(super) t3: u64,
+    pub(super) t4: u64,
+    pub(super) t5: u64,
+    pub(super) t6: u64,
+    pub(super) sepc: u64,
+    pub(super) sstatus: u64,
+    pub(super) scause: u64,
+    pub(super) stval: u64,
+    pub(super) reserved: u64,
+}
+
+unsafe impl crate::syscall::user::Pod for Ptrace {}
+
+impl Ptrace {
+    /// 通用寄存器 `x<n>`（1 到 31）
+    pub(super) fn gpr(&mut self, n: usize) -> Option<&mut u64> {
+        let regs: &mut [u64; 36] = unsafe { &mut *(self as *mut Self as *mut [u64; 36]) };
+        (1..32).contains(&n).then(|| &mut regs[n - 1])
+    }
+}
+
+impl core::fmt::Display for Ptrace {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        const NAMES: [&str; 31] = [
+            "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+            "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3",
+            "t4", "t5", "t6",
+        ];
+        let mut regs = *self;
+        for (i, name) in NAMES.iter().enumerate() {
+            writeln!(
+                f,
+                "{}: {:#x}",
+                name,
+                regs.gpr(i + 1).map_or(0, |value| *value)
+            )?;
+        }
+        writeln!(f, "sepc: {:#x}", self.sepc)?;
+        writeln!(f, "sstatus: {:#x}", self.sstatus)?;
+        writeln!(f, "scause: {:#x}", self.scause)?;
+        write!(f, "stval: {:#x}", self.stval)?;
+        Ok(())
+    }
+}
+
+impl IrqRegsArch for Ptrace {
+    fn get_ip(&self) -> u64 {
+        self.sepc
+    }
+
+    fn set_ip(&mut self, ip: u64) {
+        self.sepc = ip;
+    }
+
+    fn get_sp(&self) -> u64 {
+        self.sp
+    }
+
+    fn set_sp(&mut self, sp: u64) {
+        self.sp = sp;
+    }
+
+    fn get_fp(&self) -> u64 {
+        self.s0
+    }
+
+    fn get_ret_value(&self) -> u64 {
+        self.a0
+    }
+
+    fn set_ret_value(&mut self, ret_value: u64) {
+        self.a0 = ret_value
+    }
+
+    /// 开启帧指针时返回地址保存在 `fp - 8`
+    fn get_ret_address(&self) -> u64 {
+        unsafe { (self.s0 as *const u64).offset(-1).read_volatile() }
+    }
+
+    fn set_ret_address(&mut self, ret_address: u64) {
+        unsafe { (self.s0 as *mut u64).offset(-1).write_volatile(ret_address) };
+    }
+
+    fn get_syscall_idx(&self) -> u64 {
+        self.a7
+    }
+
+    fn get_syscall_args(&self) -> (u64, u64, u64, u64, u64, u64) {
+        (self.a0, self.a1, self.a2, self.a3, self.a4, self.a5)
+    }
+
+    fn get_args(&self) -> (u64, u64, u64, u64, u64, u64) {
+        (self.a0, self.a1, self.a2, self.a3, self.a4, self.a5)
+    }
+
+    fn set_args(&mut self, args: (u64, u64, u64, u64, u64, u64)) {
+        self.a0 = args.0;
+        self.a1 = args.1;
+        self.a2 = args.2;
+        self.a3 = args.3;
+        self.a4 = args.4;
+        self.a5 = args.5;
+    }
+
+    /// `sret` 后开中断；内核线程不经过 `sret` 进入，只在被中断后返回时用到
+    fn set_user_space(&mut self, user: bool) {
+        let mut sstatus = SSTATUS_UXL_64 | SSTATUS_SPIE;
+        if !user {
+            sstatus |= SSTATUS_SPP;
+        }
+        self.sstatus = sstatus as u64;
+    }
+
+    fn is_user_mode(&self) -> bool {
+        self.sstatus as usize & SSTATUS_SPP == 0
+    }
+
+    fn to_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8, size_of::<Self>()) }
+    }
+}
+
+pub fn enable() {
+    unsafe { core::arch::asm!("csrsi sstatus, {}", const SSTATUS_SIE, options(nomem, nostack)) };
+}
+
+pub fn disable() {
+    unsafe { core::arch::asm!("csrci sstatus, {}", const SSTATUS_SIE, options(nomem, nostack)) };
+}
+
+pub fn enabled() -> bool {
+    let sstatus: usize;
+    unsafe { core::arch::asm!("csrr {}, sstatus", out(reg) sstatus, options(nomem, nostack)) };
+    sstatus & SSTATUS_SIE != 0
+}
+
+/// 当前 hart 是否有等待的软件中断
+pub fn soft_pending() -> bool {
+    let sip: usize;
+    unsafe { core::arch::asm!("csrr {}, sip", out(reg) sip, options(nomem, nostack)) };
+    sip & SIE_SSIE != 0
+}
+
+pub fn clear_soft_pending() {
+    unsafe { core::arch::asm!("csrc sip, {}", in(reg) SIE_SSIE, options(nomem, nostack)) };
+}
+
+pub struct RiscV64IrqArch;
+
+impl IrqArch for RiscV64IrqArch {
+    fn enable_global_irq() {
+        enable();
+    }
+
+    fn disable_global_irq() {
+        disable();
+    }
+
+    /// PLIC 中断源 0 到 63，0 号保留不会触发
+    const DEVICE_IRQ_COUNT: usize = plic::DEVICE_IRQ_COUNT;
+
+    fn route_device_irq(irq: u8) {
+        plic::route(irq);
+    }
+
+    fn set_device_irq_masked(irq: u8, masked: bool) {
+        plic::set_masked(irq, masked);
+    }
+}
+
+/// panic 或调试器停止时通知其他 hart
+///
+/// RISC-V 没有 NMI，这里发送的是普通的软件中断，关着中断的 hart 要等重新开中断后才会停下。
+pub fn send_nmi_to_others() {
+    // 只发给已启动的 hart，被禁用的 hart 仍处于停止状态
+    let others = ONLINE_CPUS.load(Ordering::SeqCst) & !(1 << this_cpu().hartid());
+    if others != 0 {
+        sbi::send_ipi(others as usize, 0);
+    }
+}
+
+/// 记录中断进入，未开启中断跟踪时返回 None
+#[inline]
+fn trace_irq_enter(vector: u8) -> Option<u64> {
+    if !trace::enabled(KTRACE_GROUP_IRQ) {
+        return None;
+    }
+    trace::record(KTRACE_EVENT_IRQ_ENTER, [vector as u64, 0, 0]);
+    Some(trace::timestamp())
+}
+
+/// 记录中断返回和处理耗时
+#[inline]
+fn trace_irq_exit(vector: u8, start: Option<u64>) {
+    if let Some(start) = start
+        && trace::enabled(KTRACE_GROUP_IRQ)
+    {
+        let duration = trace::timestamp().saturating_sub(start);
+        trace::record(KTRACE_EVENT_IRQ_EXIT, [vector as u64, duration, 0]);
+    }
+}
+
+fn report_stack_overflow(address: usize) {
+    // 此时可能已经持有当前任务的写锁，不能阻塞
+    if let Some(task) = get_current_task()
+        && let Some(task) = task.try_read()
+        && task.in_stack_guard(address)
+    {
+        error!(
+            "Kernel stack overflow in task {} ({})",
+            task.tid(),
+            task.get_name()
+        );
+    }
+}
+
+/// 修复用户地址上的缺页
+///
+/// 不获取任何锁：内核态访问用户内存时可能正持有进程或调度器的锁。
+fn handle_user_page_fault(address: usize, access: FaultAccess) -> bool {
+    let Some(task) = (unsafe { current_task_ptr().as_ref() }) else {
+        return false;
+    };
+    let Some(vmar) = task.root_vmar() else {
+        return false;
+    };
+
+    vmar.handle_page_fault(VirtualAddress::new(address), access)
+        .is_ok()
+}
+
+fn do_page_fault(regs: &mut Ptrace, cause: usize) {
+    let address = regs.stval as usize;
+    let user_address = address < USER_SPACE_END;
+
+    // 内核态在访问窗口（SUM 置位）之外碰到了用户页面，不能当作普通缺页修复；
+    // SUM 也不允许内核执行用户页面
+    let sum_violation = !regs.is_user_mode()
+        && user_address
+        && (regs.sstatus as usize & SSTATUS_SUM == 0 || cause == EXCEPTION_INSTRUCTION_PAGE_FAULT);
+
+    let access = match cause {
+        EXCEPTION_INSTRUCTION_PAGE_FAULT => FaultAccess::Execute,
+        EXCEPTION_STORE_PAGE_FAULT => FaultAccess::Write,
+        _ => FaultAccess::Read,
+    };
+
+    // 用户地址：页面可能被 decommit 过，交给进程的 VMAR 重新建立映射
+    if user_address && !sum_violation && handle_user_page_fault(address, access) {
+        return;
+    }
+
+    // 复制用户内存时碰到无法修复的页面，让复制返回失败
+    if user_address
+        && !regs.is_user_mode()
+        && let Some(fixup) = CurrentUserAccessArch::fixup(regs.sepc as usize)
+    {
+        regs.sepc = fixup as u64;
+        return;
+    }
+
+    // 用户态访问了无法修复的地址（例如栈保护页），只终止该线程
+    if regs.is_user_mode() {
+        warn!("User page fault at {address:#x} (cause {cause})");
+        kill_current_on_fault("Page Fault", regs.sepc as usize);
+    }
+
+    crash::save_fault_regs(regs);
+    warn!("Exception: Page Fault");
+    warn!("Cause: {}", cause);
+    warn!("Fault Address: {address:#x}");
+    report_stack_overflow(address);
+    if sum_violation {
+        error!("SUM violation: kernel accessed user memory at {address:#x}");
+    }
+    panic!("{}", regs);
+}
+
+/// 软件中断：TLB 刷新请求，或者 panic 和调试器要求停下
+fn do_software_interrupt(regs: &mut Ptrace) {
+    clear_soft_pending();
+    let trace_start = trace_irq_enter(INTERRUPT_SOFTWARE as u8);
+    // 没有本 CPU 的刷新请求时什么也不做
+    crate::memory::tlb::handle_shootdown_ipi();
+    trace_irq_exit(INTERRUPT_SOFTWARE as u8, trace_start);
+
+    if crash::panicking() {
+        crash::stop_current_cpu(regs);
+    }
+    if gdbstub::stopping() {
+        gdbstub::park_current_cpu(regs);
+    }
+}
+
+fn do_timer_interrupt(regs: &mut Ptrace) {
+    let trace_start = trace_irq_enter(INTERRUPT_TIMER as u8);
+    // 设置下一次定时器的同时清除本次中断
+    time::rearm_timer();
+    crate::random::add_interrupt_entropy();
+    if crate::profiler::running() {
+        crate::profiler::sample(regs);
+    }
+    // 调度可能切换到其他任务，在此之前结束中断记录
+    trace_irq_exit(INTERRUPT_TIMER as u8, trace_start);
+    timer_tick();
+}
+
+/// 设备中断：屏蔽它并通知绑定的中断对象，驱动应答后才解除屏蔽
+fn do_external_interrupt() {
+    while let Some(irq) = plic::claim() {
+        let vector = INTERRUPT_EXTERNAL as u8;
+        let trace_start = trace_irq_enter(vector);
+        crate::random::add_interrupt_entropy();
+        if let Ok(irq) = u8::try_from(irq)
+            && (irq as usize) < plic::DEVICE_IRQ_COUNT
+        {
+            plic::set_masked(irq, true);
+            interrupt::handle_irq(irq);
+        }
+        plic::complete(irq);
+        trace_irq_exit(vector, trace_start);
+    }
+}
+
+/// 断点指令的长度，压缩指令 `c.ebreak` 为 2 字节
+fn instruction_length(address: u64) -> u64 {
+    let low = unsafe { (address as *const u16).read_volatile() };
+    if low & 0b11 == 0b11 { 4 } else { 2 }
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn do_trap(regs: *mut Ptrace) {
+    let regs = unsafe { regs.as_mut_unchecked() };
+    let scause = regs.scause as usize;
+    let cause = scause & !SCAUSE_INTERRUPT;
+
+    if scause & SCAUSE_INTERRUPT != 0 {
+        match cause {
+            INTERRUPT_SOFTWARE => do_software_interrupt(regs),
+            INTERRUPT_TIMER => do_timer_interrupt(regs),
+            INTERRUPT_EXTERNAL => do_external_interrupt(),
+            _ => warn!("Unexpected interrupt {}", cause),
+        }
+        return;
+    }
+
+    match cause {
+        EXCEPTION_USER_ECALL => {
+            // 返回到 ecall 的下一条指令
+            regs.sepc += 4;
+            crate::syscall::syscall_handler(regs);
+        }
+        EXCEPTION_INSTRUCTION_PAGE_FAULT
+        | EXCEPTION_LOAD_PAGE_FAULT
+        | EXCEPTION_STORE_PAGE_FAULT => do_page_fault(regs, cause),
+        EXCEPTION_BREAKPOINT => {
+            // gdbstub 认为指令指针停在断点指令之后
+            regs.sepc += instruction_length(regs.sepc);
+            if !gdbstub::handle_trap(regs, Trap::Breakpoint) {
+                crash::save_fault_regs(regs);
+                error!("Exception: Breakpoint");
+                panic!("{}", regs);
+            }
+        }
+        EXCEPTION_ILLEGAL_INSTRUCTION => {
+            if regs.is_user_mode() {
+                kill_current_on_fault("Illegal Instruction", regs.sepc as usize);
+            }
+            crash::save_fault_regs(regs);
+            error!("Exception: Illegal Instruction");
+            panic!("{}", regs);
+        }
+        EXCEPTION_INSTRUCTION_MISALIGNED
+        | EXCEPTION_INSTRUCTION_ACCESS_FAULT
+        | EXCEPTION_LOAD_MISALIGNED
+        | EXCEPTION_LOAD_ACCESS_FAULT
+        | EXCEPTION_STORE_MISALIGNED
+        | EXCEPTION_STORE_ACCESS_FAULT => {
+            if regs.is_user_mode() {
+                warn!("User access fault ({}) at {:#x}", cause, regs.stval);
+                kill_current_on_fault("Access Fault", regs.sepc as usize);
+            }
+            crash::save_fault_regs(regs);
+            error!("Exception: Access Fault ({}) at {:#x}", cause, regs.stval);
+            report_stack_overflow(regs.stval as usize);
+            panic!("{}", regs);
+        }
+        _ => {
+            crash::save_fault_regs(regs);
+            error!("Exception: Unknown ({})", cause);
+            panic!("{}", regs);
+        }
+    }
+}
+
+/// 陷入入口
+///
+/// 内核态时 `sscratch` 为 0，现场直接压在当前栈上；用户态时 `sscratch` 指向当前 hart 的
+/// [`PerCpu`]，现场放在任务内核栈的顶部，与 `Task::pt_regs` 的位置相同。
+/// `stvec` 的直接模式要求入口 4 字节对齐，naked 函数只保证 2 字节，所以用 `global_asm!` 定义。
+core::arch::global_asm!(
+    ".align 2",
+    ".global trap_entry",
+    "trap_entry:",
+    "csrrw sp, sscratch, sp",
+    "bnez sp, 1f",
+    // 来自内核态：换回原来的栈
+    "csrrw sp, sscratch, sp",
+    "addi sp, sp, -{frame}",
+    "sd t0, 4*8(sp)",
+    "addi t0, sp, {frame}",
+    "sd t0, 1*8(sp)",
+    "sd tp, 3*8(sp)",
+    "j 2f",
+    // 来自用户态：sp 为每 CPU 数据，sscratch 为用户栈
+    "1:",
+    "sd t0, {scratch}(sp)",
+    "mv t0, sp",
+    "ld sp, {kernel_sp}(t0)",
+    "addi sp, sp, -{frame}",
+    "sd tp, 3*8(sp)",
+    "mv tp, t0",
+    "ld t0, {scratch}(tp)",
+    "sd t0, 4*8(sp)",
+    "csrrw t0, sscratch, zero",
+    "sd t0, 1*8(sp)",
+    "2:",
+    "sd ra, 0*8(sp)",
+    "sd gp, 2*8(sp)",
+    "sd t1, 5*8(sp)",
+    "sd t2, 6*8(sp)",
+    "sd s0, 7*8(sp)",
+    "sd s1, 8*8(sp)",
+    "sd a0, 9*8(sp)",
+    "sd a1, 10*8(sp)",
+    "sd a2, 11*8(sp)",
+    "sd a3, 12*8(sp)",
+    "sd a4, 13*8(sp)",
+    "sd a5, 14*8(sp)",
+    "sd a6, 15*8(sp)",
+    "sd a7, 16*8(sp)",
+    "sd s2, 17*8(sp)",
+    "sd s3, 18*8(sp)",
+    "sd s4, 19*8(sp)",
+    "sd s5, 20*8(sp)",
+    "sd s6, 21*8(sp)",
+    "sd s7, 22*8(sp)",
+    "sd s8, 23*8(sp)",
+    "sd s9, 24*8(sp)",
+    "sd s10, 25*8(sp)",
+    "sd s11, 26*8(sp)",
+    "sd t3, 27*8(sp)",
+    "sd t4, 28*8(sp)",
+    "sd t5, 29*8(sp)",
+    "sd t6, 30*8(sp)",
+    "csrr t0, sepc",
+    "sd t0, 31*8(sp)",
+    "csrr t0, sstatus",
+    "sd t0, 32*8(sp)",
+    "csrr t0, scause",
+    "sd t0, 33*8(sp)",
+    "csrr t0, stval",
+    "sd t0, 34*8(sp)",
+    "mv a0, sp",
+    "call {do_trap}",
+    "tail {return_from_interrupt}",
+    frame = const size_of::<Ptrace>(),
+    scratch = const offset_of!(PerCpu, scratch),
+    kernel_sp = const offset_of!(PerCpu, kernel_sp),
+    do_trap = sym do_trap,
+    return_from_interrupt = sym return_from_interrupt,
+);
+
+unsafe extern "C" {
+    unsafe fn trap_entry();
+}
+
+#[unsafe(naked)]
+pub extern "C" fn kernel_thread_entry() {
+    core::arch::naked_asm!(
+        "ld a2, {a2}(sp)",
+        "addi sp, sp, {frame}",
+        "jalr a2",
+        "tail {exit_current}",
+        a2 = const offset_of!(Ptrace, a2),
+        frame = const size_of::<Ptrace>(),
+        exit_current = sym crate::task::exit_current,
+    );
+}
+
+/// 按 sp 指向的现场返回
+///
+/// 返回用户态时 `sscratch` 重新指向每 CPU 数据，同时恢复用户的 tp；返回内核态时保留当前的
+/// tp，任务可能已经换到其他 hart 上。
+#[unsafe(naked)]
+pub extern "C" fn return_from_interrupt() {
+    core::arch::naked_asm!(
+        // 恢复 sscratch 之后不能再被中断
+        "csrci sstatus, {sie}",
+        "ld t0, 32*8(sp)",
+        "li t1, {spp}",
+        "and t1, t0, t1",
+        "bnez t1, 1f",
+        "csrw sscratch, tp",
+        "ld tp, 3*8(sp)",
+        "1:",
+        "csrw sstatus, t0",
+        "ld t0, 31*8(sp)",
+        "csrw sepc, t0",
+        "ld ra, 0*8(sp)",
+        "ld gp, 2*8(sp)",
+        "ld t0, 4*8(sp)",
+        "ld t1, 5*8(sp)",
+        "ld t2, 6*8(sp)",
+        "ld s0, 7*8(sp)",
+        "ld s1, 8*8(sp)",
+        "ld a0, 9*8(sp)",
+        "ld a1, 10*8(sp)",
+        "ld a2, 11*8(sp)",
+        "ld a3, 12*8(sp)",
+        "ld a4, 13*8(sp)",
+        "ld a5, 14*8(sp)",
+        "ld a6, 15*8(sp)",
+        "ld a7, 16*8(sp)",
+        "ld s2, 17*8(sp)",
+        "ld s3, 18*8(sp)",
+        "ld s4, 19*8(sp)",
+        "ld s5, 20*8(sp)",
+        "ld s6, 21*8(sp)",
+        "ld s7, 22*8(sp)",
+        "ld s8, 23*8(sp)",
+        "ld s9, 24*8(sp)",
+        "ld s10, 25*8(sp)",
+        "ld s11, 26*8(sp)",
+        "ld t3, 27*8(sp)",
+        "ld t4, 28*8(sp)",
+        "ld t5, 29*8(sp)",
+        "ld t6, 30*8(sp)",
+        "ld sp, 1*8(sp)",
+        "sret",
+        sie = const SSTATUS_SIE,
+        spp = const SSTATUS_SPP,
+    );
+}
+
+/// 设置陷入入口并打开软件中断和外部中断，定时器中断在设置第一个期限时打开
+pub fn init() {
+    unsafe {
+        core::arch::asm!(
+            "csrw stvec, {entry}",
+            "csrw sscratch, zero",
+            "csrs sie, {sie}",
+            entry = in(reg) trap_entry as *const () as usize,
+            sie = in(reg) SIE_SSIE | SIE_SEIE,
+            options(nostack),
+        );
+    }
+}
@@ -0,0 +1,160 @@
+mod boot;
+pub mod cache;
+pub mod debug;
+pub mod drivers;
+pub mod irq;
+pub mod random;
+pub mod rmm;
+pub mod smp;
+pub mod syscall;
+pub mod time;
+pub mod tlb;
+pub mod user;
+
+use crate::task::ArcTask;
+use crate::task::Task;
+
+pub use self::cache::RiscV64CacheArch as CurrentCacheArch;
+pub use self::debug::RiscV64DebugArch as CurrentDebugArch;
+pub use self::irq::Ptrace;
+pub use self::irq::RiscV64IrqArch as CurrentIrqArch;
+pub use self::irq::kernel_thread_entry;
+pub use self::irq::return_from_interrupt;
+pub use self::irq::send_nmi_to_others;
+pub use self::random::RiscV64RandomArch as CurrentRandomArch;
+pub use self::smp::get_hartid as get_archid;
+pub use self::time::RiscV64TimeArch as CurrentTimeArch;
+pub use self::tlb::RiscV64TlbArch as CurrentTlbArch;
+pub use self::user::RiscV64UserAccessArch as CurrentUserAccessArch;
+use ::rmm::Arch;
+pub use ::rmm::RiscV64Sv48Arch as CurrentRmmArch;
+use ::rmm::TableKind;
+
+/// 内核和用户态都使用软浮点，切换任务时没有浮点状态需要保存
+#[repr(C)]
+#[derive(Debug)]
+pub struct ArchContext {
+    pub ip: usize,
+    pub sp: usize,
+}
+
+impl ArchContext {
+    pub fn new() -> Option<Self> {
+        Some(Self { ip: 0, sp: 0 })
+    }
+}
+
+#[unsafe(no_mangle)]
+unsafe extern "C" fn do_switch_to(_prev: *mut Task, next: *const Task) {
+    smp::this_cpu().set_current_task(next);
+
+    let next = next.as_ref_unchecked();
+
+    if let Some(process) = next.process() {
+        let root_vmar = process.read().root_vmar().unwrap();
+        let page_table_addr = root_vmar.page_table_addr().unwrap();
+
+        crate::memory::tlb::switch_address_space(root_vmar.active_cpus(), || unsafe {
+            CurrentRmmArch::set_table(TableKind::User, page_table_addr)
+        });
+    }
+}
+
+use core::mem::offset_of;
+
+/// 保存被调用者保存的寄存器并切换栈，新任务从 `ArchContext::ip` 开始执行
+///
+/// `Task` 较大，字段偏移可能超出 12 位立即数，先算出地址再访问。
+#[unsafe(naked)]
+pub extern "C" fn switch_to_inner(prev: *mut Task, next: *const Task) {
+    core::arch::naked_asm!(
+        "addi sp, sp, -112",
+        "sd ra, 0(sp)",
+        "sd s0, 8(sp)",
+        "sd s1, 16(sp)",
+        "sd s2, 24(sp)",
+        "sd s3, 32(sp)",
+        "sd s4, 40(sp)",
+        "sd s5, 48(sp)",
+        "sd s6, 56(sp)",
+        "sd s7, 64(sp)",
+        "sd s8, 72(sp)",
+        "sd s9, 80(sp)",
+        "sd s10, 88(sp)",
+        "sd s11, 96(sp)",
+        "li t0, {sp_off}",
+        "add t1, a0, t0",
+        "sd sp, 0(t1)",
+        "li t0, {ip_off}",
+        "add t1, a0, t0",
+        "lla t2, 1f",
+        "sd t2, 0(t1)",
+        "li t0, {sp_off}",
+        "add t1, a1, t0",
+        "ld sp, 0(t1)",
+        "li t0, {ip_off}",
+        "add t1, a1, t0",
+        "ld ra, 0(t1)",
+        "tail do_switch_to",
+        "1:",
+        "ld ra, 0(sp)",
+        "ld s0, 8(sp)",
+        "ld s1, 16(sp)",
+        "ld s2, 24(sp)",
+        "ld s3, 32(sp)",
+        "ld s4, 40(sp)",
+        "ld s5, 48(sp)",
+        "ld s6, 56(sp)",
+        "ld s7, 64(sp)",
+        "ld s8, 72(sp)",
+        "ld s9, 80(sp)",
+        "ld s10, 88(sp)",
+        "ld s11, 96(sp)",
+        "addi sp, sp, 112",
+        "ret",
+        sp_off = const(offset_of!(Task, arch_context) + offset_of!(ArchContext, sp)),
+        ip_off = const(offset_of!(Task, arch_context) + offset_of!(ArchContext, ip)),
+    )
+}
+
+pub fn switch_to(prev: ArcTask, next: ArcTask) {
+    // 从用户态陷入时使用下一个任务的内核栈
+    smp::this_cpu().set_kernel_sp(next.read().get_kernel_stack_top().data());
+    let prev = prev.as_mut_ptr();
+    let next = next.as_mut_ptr() as *const _;
+    switch_to_inner(prev, next);
+}
+
+/// 当前 CPU 上正在运行的任务，还没有切换过任务时为空
+///
+/// 不经过调度器的锁，IPI 和 panic 中也可以调用。
+pub fn current_task_ptr() -> *const Task {
+    smp::this_cpu().current_task()
+}
+
+/// 关中断并停机，有中断等待时 `wfi` 也会返回，所以放在循环中
+pub fn halt() -> ! {
+    loop {
+        irq::disable();
+        unsafe { core::arch::asm!("wfi", options(nomem, nostack)) };
+    }
+}
+
+/// 通过 SBI 重启机器
+///
+/// 固件不支持系统复位扩展时停机。
+pub fn reboot() -> ! {
+    irq::disable();
+    drivers::sbi::reboot();
+    error!("SBI system reset is not supported, halting");
+    halt()
+}
+
+pub fn early_init() {
+    irq::init();
+    crate::smp::init();
+    drivers::plic::init();
+    time::init();
+    irq::IRQ_INITIALIZED.store(true, core::sync::atomic::Ordering::SeqCst);
+    syscall::init();
+}
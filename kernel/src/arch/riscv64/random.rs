@@ -0,0 +1,18 @@
+use crate::arch::random::RandomArch;
+
+pub struct RiscV64RandomArch;
+
+impl RandomArch for RiscV64RandomArch {
+    /// Zkr 扩展的 `seed` CSR 在 S 模式默认不可访问，不使用硬件随机数
+    fn hardware_random() -> Option<u64> {
+        None
+    }
+
+    fn hardware_seed() -> Option<u64> {
+        None
+    }
+
+    fn cycle_counter() -> u64 {
+        super::time::read_time()
+    }
+}
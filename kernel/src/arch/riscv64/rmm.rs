@@ -0,0 +1,21 @@
+use rmm::{Arch, PageFlags, VirtualAddress};
+
+use crate::arch::CurrentRmmArch;
+
+pub unsafe fn page_flags<A: Arch>(virt: VirtualAddress) -> PageFlags<A> {
+    use crate::kernel_executable_offsets::*;
+    let virt_addr = virt.data();
+
+    if virt_addr >= __text_start() && virt_addr < __text_end() {
+        PageFlags::new().execute(true)
+    } else if virt_addr >= __rodata_start() && virt_addr < __rodata_end() {
+        PageFlags::new()
+    } else {
+        PageFlags::new().write(true)
+    }
+}
+
+/// 用户映射可以使用的最高大页层级，rmm 的 Sv48 还不支持大页
+pub fn max_huge_page_level() -> usize {
+    CurrentRmmArch::HUGE_PAGE_LEVEL
+}
@@ -0,0 +1,242 @@
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use alloc::vec::Vec;
+use rmm::{Arch, FrameAllocator, FrameCount, PageMapper, TableKind, VirtualAddress};
+
+use super::{
+    drivers::{
+        fdt, plic,
+        sbi::{self, HSM_STATUS_STARTED},
+    },
+    irq::{IRQ_INITIALIZED, clear_soft_pending, soft_pending},
+    time,
+};
+use crate::{
+    arch::{CurrentIrqArch, CurrentRmmArch, irq::IrqArch},
+    cmdline,
+    consts::STACK_SIZE,
+    init::memory::{FRAME_ALLOCATOR, KERNEL_PAGE_TABLE_PHYS, PAGE_SIZE},
+    memory::{DummyFrameAllocator, tlb::MAX_CPUS},
+    smp::{BSP_CPUARCHID, CPU_COUNT, CPUID_TO_ARCHID, ONLINE_CPUS},
+    task::{
+        TASK_INITIALIZED, Task,
+        sched::{SCHEDULERS, Scheduler},
+    },
+};
+
+unsafe extern "C" {
+    unsafe fn _ap_start() -> !;
+    unsafe fn _ap_start_virt() -> !;
+}
+
+/// satp 中 Sv48 的模式编号
+const SATP_MODE_SV48: usize = 9 << 60;
+
+/// 每个 hart 的数据，内核态时 `tp` 指向它，用户态时保存在 `sscratch` 中
+///
+/// 陷入入口按偏移访问这些字段，顺序不能改变。
+#[repr(C)]
+pub struct PerCpu {
+    hartid: AtomicUsize,
+    /// 从用户态陷入时使用的内核栈顶
+    pub(super) kernel_sp: AtomicUsize,
+    /// 陷入入口切换栈时暂存 t0
+    pub(super) scratch: AtomicUsize,
+    current_task: AtomicPtr<Task>,
+}
+
+impl PerCpu {
+    const fn new() -> Self {
+        Self {
+            hartid: AtomicUsize::new(0),
+            kernel_sp: AtomicUsize::new(0),
+            scratch: AtomicUsize::new(0),
+            current_task: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    pub fn hartid(&self) -> usize {
+        self.hartid.load(Ordering::Relaxed)
+    }
+
+    pub fn set_kernel_sp(&self, sp: usize) {
+        self.kernel_sp.store(sp, Ordering::Relaxed);
+    }
+
+    pub fn current_task(&self) -> *const Task {
+        self.current_task.load(Ordering::Relaxed)
+    }
+
+    pub fn set_current_task(&self, task: *const Task) {
+        self.current_task
+            .store(task as *mut Task, Ordering::Relaxed);
+    }
+}
+
+/// 按 hart 编号索引
+pub static PERCPU: [PerCpu; MAX_CPUS] = [const { PerCpu::new() }; MAX_CPUS];
+
+/// 交给 SBI HSM 启动的 hart 的参数，`_ap_start` 按偏移读取
+#[repr(C)]
+struct ApBoot {
+    satp: AtomicUsize,
+    /// 开启分页后继续执行的虚拟地址
+    entry: AtomicUsize,
+    stack_top: AtomicUsize,
+    percpu: AtomicUsize,
+}
+
+static AP_BOOT: [ApBoot; MAX_CPUS] = [const {
+    ApBoot {
+        satp: AtomicUsize::new(0),
+        entry: AtomicUsize::new(0),
+        stack_top: AtomicUsize::new(0),
+        percpu: AtomicUsize::new(0),
+    }
+}; MAX_CPUS];
+
+/// 当前 hart 的每 CPU 数据
+#[inline]
+pub fn this_cpu() -> &'static PerCpu {
+    let tp: usize;
+    unsafe {
+        core::arch::asm!("mv {}, tp", out(reg) tp, options(nomem, nostack, preserves_flags));
+        &*(tp as *const PerCpu)
+    }
+}
+
+/// 当前 hart 的编号，不获取任何锁
+pub fn get_hartid() -> usize {
+    this_cpu().hartid()
+}
+
+/// 内核映像中的地址对应的物理地址
+fn kernel_virt_to_phys(virt: usize) -> usize {
+    let mapper = PageMapper::<CurrentRmmArch, _>::current(TableKind::Kernel, DummyFrameAllocator);
+    let page = virt & !(PAGE_SIZE - 1);
+    let (phys, _) = mapper
+        .translate(VirtualAddress::new(page))
+        .expect("kernel address is not mapped");
+    phys.data() + (virt - page)
+}
+
+/// 找出正在执行的 hart：设备树没有给出 `boot-hartid` 时，向每个运行中的 hart 发送 IPI，
+/// 当前 hart 的 `sip` 中出现等待的软件中断即是自己
+fn boot_hartid(harts: &[usize]) -> usize {
+    if let Some(hartid) = fdt::boot_hartid() {
+        return hartid;
+    }
+
+    for &hartid in harts {
+        if sbi::hart_status(hartid) != Ok(HSM_STATUS_STARTED) {
+            continue;
+        }
+        clear_soft_pending();
+        sbi::send_ipi(1, hartid);
+        let found = (0..0x1000).any(|_| {
+            spin_loop();
+            soft_pending()
+        });
+        clear_soft_pending();
+        if found {
+            return hartid;
+        }
+    }
+    panic!("Failed to identify the boot hart");
+}
+
+/// 通过 SBI HSM 启动 hart，它开启分页后进入 `ap_kmain`
+fn start_hart(hartid: usize) -> bool {
+    let stack_frame_count = FrameCount::new(STACK_SIZE / PAGE_SIZE);
+    let Some(stack) = (unsafe { FRAME_ALLOCATOR.lock().allocate(stack_frame_count) }) else {
+        warn!("Failed to allocate the boot stack for hart {}", hartid);
+        return false;
+    };
+    let stack_top = unsafe { CurrentRmmArch::phys_to_virt(stack) }.add(STACK_SIZE);
+
+    let boot = &AP_BOOT[hartid];
+    let root = KERNEL_PAGE_TABLE_PHYS.load(Ordering::SeqCst);
+    boot.satp.store(
+        SATP_MODE_SV48 | (root >> CurrentRmmArch::PAGE_SHIFT),
+        Ordering::SeqCst,
+    );
+    boot.entry
+        .store(_ap_start_virt as *const () as usize, Ordering::SeqCst);
+    boot.stack_top.store(stack_top.data(), Ordering::SeqCst);
+    boot.percpu
+        .store(&PERCPU[hartid] as *const PerCpu as usize, Ordering::SeqCst);
+
+    let start = kernel_virt_to_phys(_ap_start as *const () as usize);
+    let opaque = kernel_virt_to_phys(boot as *const ApBoot as usize);
+    if let Err(error) = sbi::hart_start(hartid, start, opaque) {
+        warn!("Failed to start hart {}: {:?}", hartid, error);
+        return false;
+    }
+    true
+}
+
+pub fn init() {
+    let harts = fdt::harts();
+    let bsp = boot_hartid(&harts);
+    assert!(bsp < MAX_CPUS, "Hart ID out of range");
+    PERCPU[bsp].hartid.store(bsp, Ordering::SeqCst);
+    // 之前 tp 指向的是第一个每 CPU 数据，此后才能正确读取当前 hart 编号
+    unsafe { core::arch::asm!("mv tp, {}", in(reg) &PERCPU[bsp], options(nomem, nostack)) };
+    BSP_CPUARCHID.store(bsp, Ordering::SeqCst);
+
+    let mut disabled = cmdline::options().disabled_cpus;
+    let bsp_bit = 1u64 << bsp;
+    if disabled & bsp_bit != 0 {
+        log::warn!("The bootstrap processor cannot be disabled");
+        disabled &= !bsp_bit;
+    }
+
+    // 被禁用的 hart 保持停止，不分配编号
+    let harts: Vec<usize> = harts
+        .into_iter()
+        .filter(|&hartid| disabled.checked_shr(hartid as u32).unwrap_or(0) & 1 == 0)
+        .collect();
+    let mut cpu_id = 0;
+    for hartid in harts {
+        // TLB 刷新用 hart 编号作为 CPU 掩码的位号
+        if hartid >= MAX_CPUS {
+            warn!("Hart {} out of range, leaving it stopped", hartid);
+            continue;
+        }
+        PERCPU[hartid].hartid.store(hartid, Ordering::SeqCst);
+        if hartid != bsp && !start_hart(hartid) {
+            continue;
+        }
+
+        CPU_COUNT.store(cpu_id + 1, Ordering::SeqCst);
+        ONLINE_CPUS.fetch_or(1 << hartid, Ordering::SeqCst);
+        SCHEDULERS.lock().insert(hartid, Scheduler::new());
+        CPUID_TO_ARCHID.lock().insert(cpu_id, hartid);
+        cpu_id += 1;
+    }
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn ap_kmain(_hartid: usize) -> ! {
+    CurrentIrqArch::disable_global_irq();
+    super::irq::init();
+
+    while !IRQ_INITIALIZED.load(Ordering::SeqCst) {
+        spin_loop();
+    }
+
+    plic::init_hart();
+    time::start_timer();
+
+    super::syscall::init();
+
+    while !TASK_INITIALIZED.load(Ordering::SeqCst) {
+        spin_loop();
+    }
+
+    loop {
+        CurrentIrqArch::enable_global_irq();
+        spin_loop();
+    }
+}
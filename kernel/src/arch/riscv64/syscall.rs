@@ -0,0 +1,9 @@
+/// scounteren.TM：允许用户态读取 `time`，vDSO 依赖它计时
+const SCOUNTEREN_TM: usize = 1 << 1;
+
+/// 系统调用通过 `ecall` 陷入，由 `irq::do_trap` 分发，这里只需要开放用户态计时
+pub fn init() {
+    unsafe {
+        core::arch::asm!("csrs scounteren, {}", in(reg) SCOUNTEREN_TM, options(nomem, nostack))
+    };
+}
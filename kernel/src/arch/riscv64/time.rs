@@ -0,0 +1,100 @@
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Once;
+
+use super::{
+    drivers::{fdt, rtc, sbi},
+    irq::SIE_STIE,
+};
+use crate::{arch::time::TimeArch, cmdline, vdso::VdsoData};
+
+/// 设备树没有给出时基频率时使用 QEMU virt 的 10 MHz
+const DEFAULT_TIMEBASE_FREQUENCY: u64 = 10_000_000;
+/// `time` 换算为纳秒时的定点位数
+const TIME_SHIFT: u32 = 32;
+
+/// `time` 的换算参数，从计数器为 0 开始计时
+static CLOCK: Once<VdsoData> = Once::new();
+
+/// 两次定时器中断之间的计数
+static TICK_INTERVAL: AtomicU64 = AtomicU64::new(0);
+
+pub fn read_time() -> u64 {
+    let time: u64;
+    unsafe { core::arch::asm!("rdtime {}", out(reg) time, options(nomem, nostack)) };
+    time
+}
+
+/// 从设备树读取时基频率，然后开启当前 hart 的定时器
+pub fn init() {
+    let frequency = fdt::timebase_frequency().unwrap_or_else(|| {
+        warn!(
+            "Timebase frequency not found, assuming {} Hz",
+            DEFAULT_TIMEBASE_FREQUENCY
+        );
+        DEFAULT_TIMEBASE_FREQUENCY
+    });
+
+    let mult = (1_000_000_000u128 << TIME_SHIFT) / frequency as u128;
+    CLOCK.call_once(|| VdsoData {
+        counter_usable: 1,
+        counter_base: 0,
+        ns_base: 0,
+        mult: mult as u64,
+        shift: TIME_SHIFT,
+        ..VdsoData::default()
+    });
+    TICK_INTERVAL.store(
+        (frequency / cmdline::options().sched_hz as u64).max(1),
+        Ordering::SeqCst,
+    );
+    info!("Timebase frequency: {} Hz", frequency);
+    rtc::init();
+
+    start_timer();
+}
+
+/// 设置第一个期限并打开定时器中断
+pub fn start_timer() {
+    rearm_timer();
+    unsafe { core::arch::asm!("csrs sie, {}", in(reg) SIE_STIE, options(nomem, nostack)) };
+}
+
+/// 设置下一次定时器中断，同时清除正在等待的定时器中断
+pub fn rearm_timer() {
+    sbi::set_timer(read_time() + TICK_INTERVAL.load(Ordering::Relaxed));
+}
+
+pub struct RiscV64TimeArch;
+
+impl TimeArch for RiscV64TimeArch {
+    fn nano_time() -> u64 {
+        match CLOCK.get() {
+            Some(clock) => clock.counter_to_nanos(read_time()),
+            // 时基频率确定之前按默认频率换算
+            None => read_time() * (1_000_000_000 / DEFAULT_TIMEBASE_FREQUENCY),
+        }
+    }
+
+    fn delay(ns: u64) {
+        let timeout = RiscV64TimeArch::nano_time() + ns;
+        while RiscV64TimeArch::nano_time() < timeout {
+            spin_loop();
+        }
+    }
+
+    fn fill_vdso(data: &mut VdsoData) {
+        if let Some(clock) = CLOCK.get() {
+            data.counter_usable = clock.counter_usable;
+            data.counter_base = clock.counter_base;
+            data.ns_base = clock.ns_base;
+            data.mult = clock.mult;
+            data.shift = clock.shift;
+        }
+    }
+
+    fn read_rtc() -> Option<u64> {
+        rtc::read_unix_time()
+    }
+}
@@ -0,0 +1,47 @@
+use rmm::{Arch, VirtualAddress};
+
+use super::{drivers::sbi, irq, smp::get_hartid};
+use crate::{
+    arch::{CurrentRmmArch, tlb::TlbArch},
+    init::memory::PAGE_SIZE,
+};
+
+pub struct RiscV64TlbArch;
+
+impl TlbArch for RiscV64TlbArch {
+    fn current_cpu() -> usize {
+        // 从 tp 指向的每 CPU 数据读取，不经过任何锁
+        get_hartid()
+    }
+
+    fn flush_range(start: usize, end: usize) {
+        let mut addr = start;
+        while addr < end {
+            unsafe { CurrentRmmArch::invalidate(VirtualAddress::new(addr)) };
+            addr += PAGE_SIZE;
+        }
+    }
+
+    fn flush_all() {
+        unsafe { CurrentRmmArch::invalidate_all() };
+    }
+
+    /// hart 编号就是 CPU 掩码的位号，一次 SBI 调用发给所有目标
+    fn send_shootdown_ipi(cpus: u64) {
+        if cpus != 0 {
+            sbi::send_ipi(cpus as usize, 0);
+        }
+    }
+
+    fn irq_save() -> bool {
+        let enabled = irq::enabled();
+        irq::disable();
+        enabled
+    }
+
+    fn irq_restore(enabled: bool) {
+        if enabled {
+            irq::enable();
+        }
+    }
+}
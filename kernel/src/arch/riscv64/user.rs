@@ -0,0 +1,70 @@
+use super::irq::SSTATUS_SUM;
+use crate::arch::user::UserAccessArch;
+
+// 访问用户内存的逐字节复制，缺页且无法修复时缺页处理跳到 user_copy_fixup 返回 1
+core::arch::global_asm!(
+    ".global user_copy",
+    "user_copy:",
+    "beqz a2, 2f",
+    ".global user_copy_begin",
+    "user_copy_begin:",
+    "1:",
+    "lb t0, 0(a1)",
+    "sb t0, 0(a0)",
+    "addi a0, a0, 1",
+    "addi a1, a1, 1",
+    "addi a2, a2, -1",
+    "bnez a2, 1b",
+    ".global user_copy_end",
+    "user_copy_end:",
+    "2:",
+    "li a0, 0",
+    "ret",
+    ".global user_copy_fixup",
+    "user_copy_fixup:",
+    "li a0, 1",
+    "ret",
+);
+
+unsafe extern "C" {
+    unsafe fn user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    static user_copy_begin: u8;
+    static user_copy_end: u8;
+    static user_copy_fixup: u8;
+}
+
+pub struct RiscV64UserAccessArch;
+
+impl UserAccessArch for RiscV64UserAccessArch {
+    fn user_access_begin() -> bool {
+        let sstatus: usize;
+        // 不能声明 nomem：编译器不得把用户内存访问移出 SUM 置位的区间
+        unsafe {
+            core::arch::asm!(
+                "csrrs {}, sstatus, {}",
+                out(reg) sstatus,
+                in(reg) SSTATUS_SUM,
+                options(nostack),
+            )
+        };
+        sstatus & SSTATUS_SUM != 0
+    }
+
+    fn user_access_end(enabled: bool) {
+        if !enabled {
+            unsafe { core::arch::asm!("csrc sstatus, {}", in(reg) SSTATUS_SUM, options(nostack)) };
+        }
+    }
+
+    unsafe fn copy(dst: *mut u8, src: *const u8, len: usize) -> bool {
+        unsafe { user_copy(dst, src, len) == 0 }
+    }
+
+    fn fixup(pc: usize) -> Option<usize> {
+        let begin = &raw const user_copy_begin as usize;
+        let end = &raw const user_copy_end as usize;
+        (begin..end)
+            .contains(&pc)
+            .then_some(&raw const user_copy_fixup as usize)
+    }
+}
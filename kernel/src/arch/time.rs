@@ -1,4 +1,10 @@
+use crate::vdso::VdsoData;
+
 pub trait TimeArch {
     fn nano_time() -> u64;
     fn delay(ns: u64);
+    /// 填写 vDSO 中用户态计时所需的参数，计数器不可用时保持 `counter_usable` 为 0
+    fn fill_vdso(data: &mut VdsoData);
+    /// 读取硬件实时时钟，返回未经时区校正的 Unix 时间戳（秒），没有可用的时钟时返回 None
+    fn read_rtc() -> Option<u64>;
 }
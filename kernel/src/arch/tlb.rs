@@ -0,0 +1,14 @@
+pub trait TlbArch {
+    /// 当前 CPU 的硬件编号（用作 CPU 掩码的位号），不获取任何锁
+    fn current_cpu() -> usize;
+    /// 使当前 CPU 上 `[start, end)` 的 TLB 项失效
+    fn flush_range(start: usize, end: usize);
+    /// 使当前 CPU 上全部用户 TLB 项失效
+    fn flush_all();
+    /// 向 `cpus` 掩码中的每个 CPU 发送 TLB 刷新 IPI
+    fn send_shootdown_ipi(cpus: u64);
+    /// 关闭中断，返回之前是否开启
+    fn irq_save() -> bool;
+    /// 恢复 `irq_save` 之前的中断状态
+    fn irq_restore(enabled: bool);
+}
@@ -0,0 +1,14 @@
+pub trait UserAccessArch {
+    /// 允许内核访问用户页面（关闭 SMAP 一类的保护），返回之前是否已经允许
+    fn user_access_begin() -> bool;
+    /// 恢复 `user_access_begin` 之前的状态
+    fn user_access_end(enabled: bool);
+    /// 在内核和用户内存之间复制 `len` 字节，访问的用户页面无法修复时返回 false
+    ///
+    /// # Safety
+    ///
+    /// 内核一侧的内存必须有效，调用者必须处于用户访问窗口内。
+    unsafe fn copy(dst: *mut u8, src: *const u8, len: usize) -> bool;
+    /// `pc` 是 `copy` 中可能缺页的指令时，返回缺页无法修复后继续执行的地址
+    fn fixup(pc: usize) -> Option<usize>;
+}
@@ -0,0 +1,173 @@
+use x86_64::instructions::port::Port;
+use x86_64::registers::rflags::RFlags;
+
+use crate::arch::{
+    debug::DebugArch,
+    drivers::apic::ioapic_add_entry,
+    x86_64::{
+        gdt::Selectors,
+        irq::{InterruptIndex, Ptrace},
+    },
+};
+
+/// 调试串口（COM2）
+const SERIAL_BASE: u16 = 0x2f8;
+const SERIAL_IRQ: u8 = 3;
+
+const REG_DATA: u16 = 0;
+const REG_INTERRUPT_ENABLE: u16 = 1;
+const REG_FIFO_CONTROL: u16 = 2;
+const REG_LINE_CONTROL: u16 = 3;
+const REG_MODEM_CONTROL: u16 = 4;
+const REG_LINE_STATUS: u16 = 5;
+const REG_SCRATCH: u16 = 7;
+
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_THR_EMPTY: u8 = 1 << 5;
+
+fn serial_port(reg: u16) -> Port<u8> {
+    Port::new(SERIAL_BASE + reg)
+}
+
+/// GDB amd64 寄存器编号：rax, rbx, rcx, rdx, rsi, rdi, rbp, rsp, r8-r15, rip 为 8 字节，
+/// eflags, cs, ss, ds, es, fs, gs 为 4 字节
+fn register(regs: &mut Ptrace, index: usize) -> Option<&mut u64> {
+    Some(match index {
+        0 => &mut regs.rax,
+        1 => &mut regs.rbx,
+        2 => &mut regs.rcx,
+        3 => &mut regs.rdx,
+        4 => &mut regs.rsi,
+        5 => &mut regs.rdi,
+        6 => &mut regs.rbp,
+        7 => &mut regs.rsp,
+        8 => &mut regs.r8,
+        9 => &mut regs.r9,
+        10 => &mut regs.r10,
+        11 => &mut regs.r11,
+        12 => &mut regs.r12,
+        13 => &mut regs.r13,
+        14 => &mut regs.r14,
+        15 => &mut regs.r15,
+        16 => &mut regs.rip,
+        17 => &mut regs.rflags,
+        18 => &mut regs.cs,
+        19 => &mut regs.ss,
+        _ => return None,
+    })
+}
+
+fn register_size(index: usize) -> usize {
+    if index <= 16 { 8 } else { 4 }
+}
+
+pub struct X8664DebugArch;
+
+impl DebugArch for X8664DebugArch {
+    const BREAKPOINT: &'static [u8] = &[0xcc];
+    const REGISTER_COUNT: usize = 24;
+    const SERIAL_IRQ: u8 = SERIAL_IRQ;
+    const SERIAL_IO_BASE: Option<u16> = Some(SERIAL_BASE);
+
+    fn read_register(regs: &Ptrace, index: usize, out: &mut [u8]) -> Option<usize> {
+        if index >= Self::REGISTER_COUNT {
+            return None;
+        }
+        let size = register_size(index);
+        // ds、es、fs、gs 没有保存，报告为 0
+        let mut regs = *regs;
+        let value = register(&mut regs, index).map_or(0, |value| *value);
+        out[..size].copy_from_slice(&value.to_le_bytes()[..size]);
+        Some(size)
+    }
+
+    fn write_register(regs: &mut Ptrace, index: usize, value: &[u8]) -> bool {
+        if index >= Self::REGISTER_COUNT || value.len() != register_size(index) {
+            return false;
+        }
+        // 段寄存器由中断返回使用，不允许修改
+        if (18..Self::REGISTER_COUNT).contains(&index) {
+            return true;
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..value.len()].copy_from_slice(value);
+        if let Some(register) = register(regs, index) {
+            *register = u64::from_le_bytes(bytes);
+        }
+        true
+    }
+
+    fn set_single_step(regs: &mut Ptrace, enable: bool) {
+        let mut flags = RFlags::from_bits_truncate(regs.rflags);
+        flags.set(RFlags::TRAP_FLAG, enable);
+        regs.rflags = flags.bits();
+    }
+
+    #[inline(always)]
+    fn capture_current(regs: &mut Ptrace) {
+        unsafe {
+            core::arch::asm!(
+                "lea {ip}, [rip]",
+                "mov {sp}, rsp",
+                "mov {bp}, rbp",
+                ip = out(reg) regs.rip,
+                sp = out(reg) regs.rsp,
+                bp = out(reg) regs.rbp,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+        regs.rflags = x86_64::registers::rflags::read_raw();
+        let (code, data) = Selectors::get_kernel_segments();
+        regs.cs = code.0 as u64;
+        regs.ss = data.0 as u64;
+    }
+
+    fn serial_init() -> bool {
+        unsafe {
+            // 没有串口时读回的是 0xff
+            serial_port(REG_SCRATCH).write(0x5a);
+            if serial_port(REG_SCRATCH).read() != 0x5a {
+                return false;
+            }
+
+            serial_port(REG_INTERRUPT_ENABLE).write(0x00);
+            // 115200 8N1
+            serial_port(REG_LINE_CONTROL).write(0x80);
+            serial_port(REG_DATA).write(0x01);
+            serial_port(REG_INTERRUPT_ENABLE).write(0x00);
+            serial_port(REG_LINE_CONTROL).write(0x03);
+            serial_port(REG_FIFO_CONTROL).write(0xc7);
+            // DTR、RTS 和 OUT2（中断输出）
+            serial_port(REG_MODEM_CONTROL).write(0x0b);
+        }
+        true
+    }
+
+    fn serial_read() -> Option<u8> {
+        unsafe {
+            (serial_port(REG_LINE_STATUS).read() & LINE_STATUS_DATA_READY != 0)
+                .then(|| serial_port(REG_DATA).read())
+        }
+    }
+
+    fn serial_write(byte: u8) {
+        unsafe {
+            while serial_port(REG_LINE_STATUS).read() & LINE_STATUS_THR_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            serial_port(REG_DATA).write(byte);
+        }
+    }
+
+    fn serial_enable_irq() {
+        unsafe {
+            ioapic_add_entry(SERIAL_IRQ, InterruptIndex::DebugSerial as u8);
+            // 接收数据中断
+            serial_port(REG_INTERRUPT_ENABLE).write(0x01);
+        }
+    }
+
+    fn breakpoint() {
+        x86_64::instructions::interrupts::int3();
+    }
+}
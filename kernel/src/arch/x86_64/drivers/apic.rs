@@ -20,9 +20,11 @@ use crate::{
         smp::get_lapicid,
         x86_64::irq::{INTERRUPT_INDEX_OFFSET, InterruptIndex},
     },
-    consts::SCHED_HZ,
+    cmdline,
     drivers::acpi::ACPI_TABLES,
     init::memory::FRAME_ALLOCATOR,
+    memory::tlb::MAX_CPUS,
+    smp::ONLINE_CPUS,
 };
 
 pub struct IoApic {
@@ -62,22 +64,67 @@ fn resolve(irq: u8) -> u32 {
         .map_or(u32::from(irq), |over| over.gsi)
 }
 
+/// 找到负责 `gsi` 的 I/O APIC，回调的第二个参数是它在该 I/O APIC 中的引脚号
 fn use_ioapic<F>(gsi: u32, cb: F)
 where
-    F: FnOnce(&mut IoApic),
+    F: FnOnce(&mut IoApic, u8),
 {
     if let Some(ioapic) = IOAPICS
         .lock()
         .iter_mut()
         .find(|apic| gsi >= apic.gsi_start && gsi < apic.gsi_start + apic.count as u32)
     {
-        cb(ioapic)
+        let pin = (gsi - ioapic.gsi_start) as u8;
+        cb(ioapic, pin)
     }
 }
 
 pub unsafe fn ioapic_add_entry(irq: u8, vector: u8) {
     let gsi = resolve(irq);
-    use_ioapic(gsi, |ioapic| ioapic.map(irq, vector));
+    use_ioapic(gsi, |ioapic, pin| ioapic.map(pin, vector));
+}
+
+/// 屏蔽或解除屏蔽 ISA 中断 `irq`
+pub unsafe fn ioapic_set_masked(irq: u8, masked: bool) {
+    let gsi = resolve(irq);
+    use_ioapic(gsi, |ioapic, pin| unsafe {
+        if masked {
+            ioapic.ioapic.disable_irq(pin)
+        } else {
+            ioapic.ioapic.enable_irq(pin)
+        }
+    });
+}
+
+/// panic 时向其他 CPU 发送 NMI
+///
+/// 持有 LAPIC 锁的 CPU 可能再也不会释放它，等待一段时间后强制解锁。
+pub fn send_nmi_to_others() {
+    if !APIC_INITIALIZED.load(core::sync::atomic::Ordering::SeqCst) {
+        return;
+    }
+
+    let mut guard = None;
+    for _ in 0..0x10_0000 {
+        guard = LAPIC.try_lock();
+        if guard.is_some() {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    let mut guard = guard.unwrap_or_else(|| {
+        unsafe { LAPIC.force_unlock() };
+        LAPIC.lock()
+    });
+
+    // 只发给已启动的 CPU，被禁用的 CPU 仍在 Limine 中等待，没有内核的中断处理程序
+    if let Some(lapic) = guard.as_mut() {
+        let self_id = unsafe { lapic.id() };
+        let online = ONLINE_CPUS.load(core::sync::atomic::Ordering::SeqCst);
+        for id in (0..MAX_CPUS as u32).filter(|&id| id != self_id && online & (1 << id) != 0) {
+            unsafe { lapic.send_nmi(id) };
+        }
+    }
 }
 
 const TIMER_CALIBRATION_ITERATION: u32 = 5;
@@ -103,7 +150,7 @@ pub unsafe fn calibrate_timer() {
     }
 
     let average_ticks_per_ms = lapic_total_ticks / TIMER_CALIBRATION_ITERATION;
-    let calibrated_timer_initial = average_ticks_per_ms * 1000 / SCHED_HZ as u32;
+    let calibrated_timer_initial = average_ticks_per_ms * 1000 / cmdline::options().sched_hz as u32;
 
     lapic.set_timer_mode(TimerMode::Periodic);
     lapic.set_timer_initial(calibrated_timer_initial);
@@ -1,2 +1,3 @@
 pub mod apic;
 pub mod hpet;
+pub mod rtc;
@@ -0,0 +1,143 @@
+use x86_64::instructions::port::Port;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+const REG_CENTURY: u8 = 0x32;
+
+/// 状态寄存器 A：正在更新时间
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// 状态寄存器 B：24 小时制
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// 状态寄存器 B：二进制格式（否则为 BCD）
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// 12 小时制下小时寄存器的 PM 位
+const HOUR_PM: u8 = 1 << 7;
+
+/// 读取时最多重试的次数，避免 RTC 损坏时卡在启动阶段
+const MAX_READ_ATTEMPTS: usize = 16;
+/// 等待更新结束时最多读取状态寄存器的次数，每次端口访问约 1 微秒，正常的更新不超过 2 毫秒
+const MAX_UPDATE_WAIT: usize = 100_000;
+
+fn read_register(reg: u8) -> u8 {
+    // 最高位为 1 时屏蔽 NMI
+    unsafe {
+        Port::<u8>::new(CMOS_ADDRESS).write(reg | 0x80);
+        Port::<u8>::new(CMOS_DATA).read()
+    }
+}
+
+fn update_in_progress() -> bool {
+    read_register(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+/// 等待更新结束后读取各个时间寄存器，RTC 一直处于更新状态时返回 None
+fn read_raw() -> Option<RawTime> {
+    (0..MAX_UPDATE_WAIT).find(|_| !update_in_progress())?;
+    Some(RawTime {
+        second: read_register(REG_SECONDS),
+        minute: read_register(REG_MINUTES),
+        hour: read_register(REG_HOURS),
+        day: read_register(REG_DAY),
+        month: read_register(REG_MONTH),
+        year: read_register(REG_YEAR),
+        century: read_register(REG_CENTURY),
+    })
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value & 0x0f) + (value >> 4) * 10
+}
+
+/// 从 1970-01-01 到给定日期的天数
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// 读取 CMOS RTC 的时间，返回 Unix 时间戳（秒）
+///
+/// RTC 记录的时间不带时区，是否为 UTC 由调用者决定。连续读到两次相同的值才认为有效，
+/// 避免读到更新了一半的时间。
+pub fn read_unix_time() -> Option<u64> {
+    let mut last = read_raw()?;
+    let mut stable = None;
+    for _ in 0..MAX_READ_ATTEMPTS {
+        let current = read_raw()?;
+        if current == last {
+            stable = Some(current);
+            break;
+        }
+        last = current;
+    }
+    let mut raw = stable?;
+
+    let status_b = read_register(REG_STATUS_B);
+    let pm = raw.hour & HOUR_PM != 0;
+    raw.hour &= !HOUR_PM;
+
+    if status_b & STATUS_B_BINARY == 0 {
+        raw.second = from_bcd(raw.second);
+        raw.minute = from_bcd(raw.minute);
+        raw.hour = from_bcd(raw.hour);
+        raw.day = from_bcd(raw.day);
+        raw.month = from_bcd(raw.month);
+        raw.year = from_bcd(raw.year);
+        raw.century = from_bcd(raw.century);
+    }
+
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12 小时制：12 AM 为 0 点，12 PM 为 12 点
+        raw.hour %= 12;
+        if pm {
+            raw.hour += 12;
+        }
+    }
+
+    // 世纪寄存器不是所有平台都有，没有时按 2000 年以后处理
+    let century = if (19..=30).contains(&raw.century) {
+        raw.century as i64
+    } else {
+        20
+    };
+    let year = century * 100 + raw.year as i64;
+
+    if raw.second > 59
+        || raw.minute > 59
+        || raw.hour > 23
+        || !(1..=31).contains(&raw.day)
+        || !(1..=12).contains(&raw.month)
+        || year < 1970
+    {
+        return None;
+    }
+
+    let days = days_from_civil(year, raw.month as u32, raw.day as u32);
+    let seconds =
+        days * 86400 + raw.hour as i64 * 3600 + raw.minute as i64 * 60 + raw.second as i64;
+    Some(seconds as u64)
+}
@@ -0,0 +1,196 @@
+use core::{
+    alloc::Layout,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+
+use alloc::alloc::{alloc_zeroed, dealloc};
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+
+/// XCR0 中内核支持保存的状态：x87、SSE、AVX，以及 AVX-512 的 opmask/ZMM_Hi256/Hi16_ZMM
+const XCR0_X87: u64 = 1 << 0;
+const XCR0_SSE: u64 = 1 << 1;
+const XCR0_AVX: u64 = 1 << 2;
+const XCR0_AVX512: u64 = (1 << 5) | (1 << 6) | (1 << 7);
+
+/// FXSAVE 区域的大小，不支持 XSAVE 时使用
+const FXSAVE_SIZE: usize = 512;
+/// XSAVE 区域要求 64 字节对齐
+const XSAVE_ALIGN: usize = 64;
+
+/// 是否使用 XSAVE 系列指令
+static XSAVE_ENABLED: AtomicBool = AtomicBool::new(false);
+/// 是否支持 XSAVEOPT
+static XSAVEOPT_SUPPORTED: AtomicBool = AtomicBool::new(false);
+/// 写入 XCR0 的状态位，所有 CPU 相同
+static XSAVE_FEATURES: AtomicU64 = AtomicU64::new(0);
+/// 每个任务的 FPU 状态区大小，由 BSP 根据 CPUID 确定
+static XSAVE_SIZE: AtomicUsize = AtomicUsize::new(FXSAVE_SIZE);
+
+unsafe fn xsetbv(index: u32, value: u64) {
+    unsafe {
+        core::arch::asm!(
+            "xsetbv",
+            in("ecx") index,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nomem, nostack),
+        )
+    };
+}
+
+/// 检测 XSAVE 支持并设置 XCR0，每个 CPU 都需要调用
+///
+/// 第一次调用（BSP）决定启用的状态位和状态区大小，AP 沿用相同的设置。
+pub fn init() {
+    // CPUID.01H:ECX[26] XSAVE
+    let features = unsafe { core::arch::x86_64::__cpuid(1) }.ecx;
+    if features & (1 << 26) == 0 {
+        return;
+    }
+
+    let mut cr4 = Cr4::read();
+    cr4.insert(Cr4Flags::OSXSAVE);
+    unsafe { Cr4::write(cr4) };
+
+    let mut xcr0 = XSAVE_FEATURES.load(Ordering::Relaxed);
+    let first = xcr0 == 0;
+    if first {
+        // CPUID.0DH.0:EDX:EAX 为 CPU 支持的 XCR0 位
+        let leaf = unsafe { core::arch::x86_64::__cpuid_count(0xd, 0) };
+        let supported = ((leaf.edx as u64) << 32) | leaf.eax as u64;
+
+        xcr0 = XCR0_X87 | XCR0_SSE;
+        if supported & XCR0_AVX != 0 {
+            xcr0 |= XCR0_AVX;
+            // AVX-512 的三个状态必须同时开启
+            if supported & XCR0_AVX512 == XCR0_AVX512 {
+                xcr0 |= XCR0_AVX512;
+            }
+        }
+
+        // CPUID.0DH.1:EAX[0] XSAVEOPT
+        let leaf = unsafe { core::arch::x86_64::__cpuid_count(0xd, 1) };
+        XSAVEOPT_SUPPORTED.store(leaf.eax & 1 != 0, Ordering::Relaxed);
+        XSAVE_FEATURES.store(xcr0, Ordering::Relaxed);
+    }
+
+    unsafe { xsetbv(0, xcr0) };
+
+    // 设置 XCR0 后 CPUID.0DH.0:EBX 为已启用状态所需的区域大小。
+    // 已有任务按这个大小分配了状态区，之后不能再改变。
+    if first {
+        let size = unsafe { core::arch::x86_64::__cpuid_count(0xd, 0) }.ebx as usize;
+        XSAVE_SIZE.store(size.max(FXSAVE_SIZE), Ordering::Relaxed);
+        XSAVE_ENABLED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// 置位 CR0.TS，之后第一条 FPU/SIMD 指令触发 #NM
+pub fn disable_access() {
+    let mut cr0 = Cr0::read();
+    cr0.insert(Cr0Flags::TASK_SWITCHED);
+    unsafe { Cr0::write(cr0) };
+}
+
+/// 清除 CR0.TS，允许访问 FPU/SIMD 寄存器
+pub fn enable_access() {
+    unsafe { core::arch::asm!("clts", options(nomem, nostack)) };
+}
+
+/// 任务的 FPU/SIMD 状态
+///
+/// 状态区按 CPUID 报告的大小分配。切换任务时不恢复状态，而是置位 CR0.TS；
+/// 任务第一次使用 FPU 时在 #NM 中恢复，切出时只保存实际恢复过的状态。
+#[derive(Debug)]
+pub struct FpState {
+    area: NonNull<u8>,
+    /// 状态是否已加载到当前 CPU 的寄存器中
+    loaded: bool,
+}
+
+unsafe impl Send for FpState {}
+unsafe impl Sync for FpState {}
+
+impl FpState {
+    fn layout() -> Layout {
+        Layout::from_size_align(XSAVE_SIZE.load(Ordering::Relaxed), XSAVE_ALIGN).unwrap()
+    }
+
+    /// 分配初始状态，内核堆不足时返回 None
+    pub fn new() -> Option<Self> {
+        let area = NonNull::new(unsafe { alloc_zeroed(Self::layout()) })?;
+
+        // 传统区域中的 FCW 和 MXCSR，XSTATE_BV 为 0 时其余状态按初始值恢复
+        unsafe {
+            area.as_ptr().cast::<u16>().write(0x037f);
+            area.as_ptr().add(24).cast::<u32>().write(0x1f80);
+        }
+
+        Some(Self {
+            area,
+            loaded: false,
+        })
+    }
+
+    /// 保存寄存器中的状态
+    pub fn save(&mut self) {
+        let area = self.area.as_ptr();
+        if !XSAVE_ENABLED.load(Ordering::Relaxed) {
+            unsafe { core::arch::x86_64::_fxsave64(area) };
+        } else if XSAVEOPT_SUPPORTED.load(Ordering::Relaxed) {
+            unsafe {
+                core::arch::asm!(
+                    "xsaveopt64 [{}]",
+                    in(reg) area,
+                    in("eax") u32::MAX,
+                    in("edx") u32::MAX,
+                    options(nostack),
+                )
+            };
+        } else {
+            unsafe {
+                core::arch::asm!(
+                    "xsave64 [{}]",
+                    in(reg) area,
+                    in("eax") u32::MAX,
+                    in("edx") u32::MAX,
+                    options(nostack),
+                )
+            };
+        }
+    }
+
+    /// 把状态恢复到寄存器
+    pub fn restore(&mut self) {
+        let area = self.area.as_ptr();
+        if XSAVE_ENABLED.load(Ordering::Relaxed) {
+            unsafe {
+                core::arch::asm!(
+                    "xrstor64 [{}]",
+                    in(reg) area,
+                    in("eax") u32::MAX,
+                    in("edx") u32::MAX,
+                    options(nostack, readonly),
+                )
+            };
+        } else {
+            unsafe { core::arch::x86_64::_fxrstor64(area) };
+        }
+        self.loaded = true;
+    }
+
+    /// 切出任务时调用：只有本次运行期间恢复过的状态才需要保存
+    pub fn save_if_loaded(&mut self) {
+        if self.loaded {
+            self.save();
+            self.loaded = false;
+        }
+    }
+}
+
+impl Drop for FpState {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.area.as_ptr(), Self::layout()) };
+    }
+}
@@ -1,3 +1,7 @@
+use alloc::vec::Vec;
+use core::mem::offset_of;
+use core::ops::Range;
+use rmm::{Arch, FrameAllocator, FrameCount};
 use spin::Lazy;
 use x86_64::VirtAddr;
 use x86_64::instructions::segmentation::{CS, SS, Segment};
@@ -6,14 +10,33 @@ use x86_64::structures::gdt::GlobalDescriptorTable;
 use x86_64::structures::gdt::{Descriptor, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
 
+use crate::{
+    arch::CurrentRmmArch,
+    init::memory::{FRAME_ALLOCATOR, PAGE_SIZE},
+    object::process::PROCESS_MAX_IO_PORT_RANGES,
+};
+
 pub const IO_BITMAP_SIZE: usize = 65536 / 8;
 
+/// 双重错误使用的 IST 编号
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+/// 双重错误专用栈的大小，内核栈溢出时仍然可以在这里打印现场
+const DOUBLE_FAULT_STACK_SIZE: usize = 16 * PAGE_SIZE;
+/// NMI 使用的 IST 编号，NMI 可能在 syscall 入口切换栈之前到达
+pub const NMI_IST_INDEX: u16 = 1;
+const NMI_STACK_SIZE: usize = 4 * PAGE_SIZE;
+
+/// I/O 位图紧跟在 TSS 后面，置位的端口在用户态不可访问
 #[repr(C)]
 pub struct CpuInfo {
     gdt: GlobalDescriptorTable,
     tss: TaskStateSegment,
     io_bitmap: [u8; IO_BITMAP_SIZE],
+    /// 位图之后必须有一个全 1 的字节
+    io_bitmap_end: u8,
     selectors: Option<Selectors>,
+    /// 位图中当前放行的端口范围
+    io_ports: Vec<Range<u16>>,
 }
 
 impl Default for CpuInfo {
@@ -21,25 +44,80 @@ impl Default for CpuInfo {
         Self {
             gdt: GlobalDescriptorTable::new(),
             tss: TaskStateSegment::new(),
-            io_bitmap: [0u8; IO_BITMAP_SIZE],
+            io_bitmap: [0xff; IO_BITMAP_SIZE],
+            io_bitmap_end: 0xff,
             selectors: None,
+            // 预留空间，切换任务时不分配内存
+            io_ports: Vec::with_capacity(PROCESS_MAX_IO_PORT_RANGES),
+        }
+    }
+}
+
+fn set_io_bitmap(bitmap: &mut [u8; IO_BITMAP_SIZE], ports: Range<u16>, deny: bool) {
+    for port in ports {
+        let (byte, bit) = (port as usize / 8, port % 8);
+        if deny {
+            bitmap[byte] |= 1 << bit;
+        } else {
+            bitmap[byte] &= !(1 << bit);
         }
     }
 }
 
+/// TSS 描述符，段界限包括 I/O 位图和它后面的结束字节
+fn tss_descriptor(tss: &'static TaskStateSegment) -> Descriptor {
+    let Descriptor::SystemSegment(mut low, high) = Descriptor::tss_segment(tss) else {
+        unreachable!("TSS descriptor is a system segment");
+    };
+    let limit = (size_of::<TaskStateSegment>() + IO_BITMAP_SIZE) as u64;
+    low &= !(0xffff | (0xf << 48));
+    low |= (limit & 0xffff) | ((limit >> 16) & 0xf) << 48;
+    Descriptor::SystemSegment(low, high)
+}
+
 impl CpuInfo {
     #[inline]
     pub fn set_ring0_rsp(&mut self, rsp: u64) {
         self.tss.privilege_stack_table[0] = VirtAddr::new(rsp);
     }
+
+    /// 让 I/O 位图只放行 `ports`，只改动前后两组范围涉及的位
+    pub fn load_io_ports(&mut self, ports: &[Range<u16>]) {
+        if self.io_ports == ports {
+            return;
+        }
+        for range in self.io_ports.drain(..) {
+            set_io_bitmap(&mut self.io_bitmap, range, true);
+        }
+        for range in ports {
+            set_io_bitmap(&mut self.io_bitmap, range.clone(), false);
+        }
+        self.io_ports.extend_from_slice(ports);
+    }
 }
 
 impl CpuInfo {
     pub fn init(&mut self) {
+        for (index, size) in [
+            (DOUBLE_FAULT_IST_INDEX, DOUBLE_FAULT_STACK_SIZE),
+            (NMI_IST_INDEX, NMI_STACK_SIZE),
+        ] {
+            let stack = unsafe {
+                FRAME_ALLOCATOR
+                    .lock()
+                    .allocate(FrameCount::new(size / PAGE_SIZE))
+            }
+            .expect("No memory to allocate interrupt stack");
+            let stack = unsafe { CurrentRmmArch::phys_to_virt(stack) };
+            self.tss.interrupt_stack_table[index as usize] =
+                VirtAddr::new(stack.add(size).data() as u64);
+        }
+
         let (mut gdt, mut selectors) = COMMON_GDT.clone();
 
+        self.tss.iomap_base = (offset_of!(CpuInfo, io_bitmap) - offset_of!(CpuInfo, tss)) as u16;
         let tss_ref = unsafe { &*(&self.tss as *const _) };
-        let tss_selector = Some(gdt.append(Descriptor::tss_segment(tss_ref)));
+        let tss_selector = Some(gdt.append(tss_descriptor(tss_ref)));
         selectors.tss_selector = tss_selector;
 
         self.gdt = gdt;
@@ -1,44 +1,62 @@
+use core::sync::atomic::Ordering;
+
 use spin::Lazy;
 use x86_64::{
-    registers::control::Cr2,
+    registers::{control::Cr2, model_specific::GsBase, rflags::RFlags},
     structures::idt::{InterruptDescriptorTable, PageFaultErrorCode},
 };
 
+use rmm::VirtualAddress;
+
+use super::user::SMAP_ENABLED;
+
 use crate::{
     arch::{
-        drivers::apic::LAPIC,
-        gdt::Selectors,
+        CurrentUserAccessArch, current_task_ptr,
+        drivers::apic::{LAPIC, ioapic_add_entry, ioapic_set_masked},
+        fpu,
+        gdt::{DOUBLE_FAULT_IST_INDEX, NMI_IST_INDEX, Selectors},
         irq::{IrqArch, IrqRegsArch},
+        user::UserAccessArch,
     },
-    task::schedule,
+    crash,
+    gdbstub::{self, Trap},
+    ktrace::{KTRACE_EVENT_IRQ_ENTER, KTRACE_EVENT_IRQ_EXIT, KTRACE_GROUP_IRQ},
+    layout::USER_SPACE_END,
+    object::{interrupt, vmar::FaultAccess},
+    task::{Task, get_current_task, kill_current_on_fault, timer_tick},
+    trace,
 };
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Ptrace {
-    r15: u64,
-    r14: u64,
-    r13: u64,
-    r12: u64,
-    r11: u64,
-    r10: u64,
-    r9: u64,
-    r8: u64,
-    rbx: u64,
-    rcx: u64,
-    rdx: u64,
-    rsi: u64,
-    rdi: u64,
-    rbp: u64,
-    rax: u64,
-    reserved: u64,
-    errcode: u64,
-    rip: u64,
-    cs: u64,
-    rflags: u64,
-    rsp: u64,
-    ss: u64,
+    pub(super) r15: u64,
+    pub(super) r14: u64,
+    pub(super) r13: u64,
+    pub(super) r12: u64,
+    pub(super) r11: u64,
+    pub(super) r10: u64,
+    pub(super) r9: u64,
+    pub(super) r8: u64,
+    pub(super) rbx: u64,
+    pub(super) rcx: u64,
+    pub(super) rdx: u64,
+    pub(super) rsi: u64,
+    pub(super) rdi: u64,
+    pub(super) rbp: u64,
+    pub(super) rax: u64,
+    pub(super) reserved: u64,
+    pub(super) errcode: u64,
+    pub(super) rip: u64,
+    pub(super) cs: u64,
+    pub(super) rflags: u64,
+    pub(super) rsp: u64,
+    pub(super) ss: u64,
 }
+
+unsafe impl crate::syscall::user::Pod for Ptrace {}
+
 impl core::fmt::Display for Ptrace {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         writeln!(f, "r15: {:#x}", self.r15)?;
@@ -134,6 +152,10 @@ impl IrqRegsArch for Ptrace {
         self.rsp = sp;
     }
 
+    fn get_fp(&self) -> u64 {
+        self.rbp
+    }
+
     fn get_ret_value(&self) -> u64 {
         self.rax
     }
@@ -182,6 +204,10 @@ impl IrqRegsArch for Ptrace {
         self.ss = data.0 as u64;
     }
 
+    fn is_user_mode(&self) -> bool {
+        self.cs & 3 != 0
+    }
+
     fn to_bytes(&self) -> &[u8] {
         unsafe { core::slice::from_raw_parts(self as *const _ as *const u8, size_of::<Self>()) }
     }
@@ -197,11 +223,29 @@ impl IrqArch for X8664IrqArch {
     fn disable_global_irq() {
         x86_64::instructions::interrupts::disable();
     }
+
+    /// ISA 中断 0 到 15
+    const DEVICE_IRQ_COUNT: usize = DEVICE_IRQ_COUNT;
+
+    fn route_device_irq(irq: u8) {
+        unsafe {
+            ioapic_add_entry(irq, InterruptIndex::Device as u8 + irq);
+            ioapic_set_masked(irq, true);
+        }
+    }
+
+    fn set_device_irq_masked(irq: u8, masked: bool) {
+        unsafe { ioapic_set_masked(irq, masked) };
+    }
 }
 
 #[unsafe(no_mangle)]
 extern "C" fn do_general_protection_fault(regs: *mut Ptrace) {
     let regs = unsafe { regs.as_mut_unchecked() };
+    if regs.is_user_mode() {
+        kill_current_on_fault("General Protection Fault", regs.rip as usize);
+    }
+    crash::save_fault_regs(regs);
     error!("Exception: General Protection Fault");
     panic!("{}", regs);
 }
@@ -220,6 +264,10 @@ extern "C" fn general_protection_fault() {
 #[unsafe(no_mangle)]
 extern "C" fn do_invalid_opcode(regs: *mut Ptrace) {
     let regs = unsafe { regs.as_mut_unchecked() };
+    if regs.is_user_mode() {
+        kill_current_on_fault("Invalid Opcode", regs.rip as usize);
+    }
+    crash::save_fault_regs(regs);
     error!("Exception: Invalid Opcode");
     panic!("{}", regs);
 }
@@ -236,10 +284,43 @@ extern "C" fn invalid_opcode() -> ! {
     );
 }
 
+/// 任务切换后第一次使用 FPU/SIMD：恢复当前任务的状态
+#[unsafe(no_mangle)]
+extern "C" fn do_device_not_available(regs: *mut Ptrace) {
+    let regs = unsafe { regs.as_mut_unchecked() };
+    if regs.cs & 3 == 0 {
+        crash::save_fault_regs(regs);
+        error!("Exception: Device Not Available in kernel mode");
+        panic!("{}", regs);
+    }
+
+    fpu::enable_access();
+    // GsBase 始终指向当前任务，不需要获取调度器的锁
+    let task = GsBase::read().as_u64() as *mut Task;
+    unsafe { (*task).arch_context.fpu.restore() };
+}
+
+#[unsafe(naked)]
+extern "C" fn device_not_available() {
+    core::arch::naked_asm!(
+        "sub rsp, 0x8",
+        push_context!(),
+        "mov rdi, rsp",
+        "call do_device_not_available",
+        pop_context!(),
+        "iretq",
+    );
+}
+
 #[unsafe(no_mangle)]
 extern "C" fn do_double_fault(regs: *mut Ptrace) {
     let regs = unsafe { regs.as_mut_unchecked() };
+    crash::save_fault_regs(regs);
     error!("Exception: Double Fault");
+    // 最常见的原因是内核栈溢出，缺页处理本身又无栈可用
+    if let Ok(address) = Cr2::read() {
+        report_stack_overflow(address.as_u64() as usize);
+    }
     panic!("{}\nUnrecoverable fault occured, halting!", regs);
 }
 
@@ -257,12 +338,66 @@ extern "C" fn double_fault() -> ! {
 #[unsafe(no_mangle)]
 extern "C" fn do_page_fault(regs: *mut Ptrace) {
     let regs = unsafe { regs.as_mut_unchecked() };
-    warn!("Exception: Page Fault");
     let page_fault_errcode = PageFaultErrorCode::from_bits_truncate(regs.errcode);
+
+    // 内核态在访问窗口（AC 置位）之外碰到了用户页面，不能当作普通缺页修复
+    let smap_violation = SMAP_ENABLED.load(Ordering::Relaxed)
+        && regs.cs & 3 == 0
+        && !RFlags::from_bits_truncate(regs.rflags).contains(RFlags::ALIGNMENT_CHECK)
+        && page_fault_errcode.contains(PageFaultErrorCode::PROTECTION_VIOLATION);
+    // 内核态执行用户页面（SMEP）同样不能修复
+    let smep_violation =
+        regs.cs & 3 == 0 && page_fault_errcode.contains(PageFaultErrorCode::INSTRUCTION_FETCH);
+
+    let access = if page_fault_errcode.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+        FaultAccess::Execute
+    } else if page_fault_errcode.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+        FaultAccess::Write
+    } else {
+        FaultAccess::Read
+    };
+
+    // 用户地址：页面可能被 decommit 过，交给进程的 VMAR 重新建立映射
+    if let Ok(address) = Cr2::read()
+        && (address.as_u64() as usize) < USER_SPACE_END
+        && !smap_violation
+        && !smep_violation
+        && handle_user_page_fault(address.as_u64() as usize, access)
+    {
+        return;
+    }
+
+    // 复制用户内存时碰到无法修复的页面，让复制返回失败
+    if regs.cs & 3 == 0
+        && Cr2::read().is_ok_and(|address| (address.as_u64() as usize) < USER_SPACE_END)
+        && let Some(fixup) = CurrentUserAccessArch::fixup(regs.rip as usize)
+    {
+        regs.rip = fixup as u64;
+        return;
+    }
+
+    // 用户态访问了无法修复的地址（例如栈保护页），只终止该线程
+    if regs.is_user_mode() {
+        if let Ok(address) = Cr2::read() {
+            warn!("User page fault at {address:#x} ({page_fault_errcode:?})");
+        }
+        kill_current_on_fault("Page Fault", regs.rip as usize);
+    }
+
+    crash::save_fault_regs(regs);
+    warn!("Exception: Page Fault");
     warn!("Page Fault Error Code: {:#?}", page_fault_errcode);
     match Cr2::read() {
         Ok(address) => {
             warn!("Fault Address: {address:#x}");
+            let address = address.as_u64() as usize;
+            report_stack_overflow(address);
+            if smap_violation && address < USER_SPACE_END {
+                error!("SMAP violation: kernel accessed user memory at {address:#x}");
+            }
+            if smep_violation && address < USER_SPACE_END {
+                error!("SMEP violation: kernel executed user memory at {address:#x}");
+            }
         }
         Err(error) => {
             warn!("Invalid virtual address: {error:?}");
@@ -271,6 +406,35 @@ extern "C" fn do_page_fault(regs: *mut Ptrace) {
     panic!("{}", regs);
 }
 
+fn report_stack_overflow(address: usize) {
+    // 此时可能已经持有当前任务的写锁，不能阻塞
+    if let Some(task) = get_current_task()
+        && let Some(task) = task.try_read()
+        && task.in_stack_guard(address)
+    {
+        error!(
+            "Kernel stack overflow in task {} ({})",
+            task.tid(),
+            task.get_name()
+        );
+    }
+}
+
+/// 修复用户地址上的缺页
+///
+/// 不获取任何锁：内核态访问用户内存时可能正持有进程或调度器的锁。
+fn handle_user_page_fault(address: usize, access: FaultAccess) -> bool {
+    let Some(task) = (unsafe { current_task_ptr().as_ref() }) else {
+        return false;
+    };
+    let Some(vmar) = task.root_vmar() else {
+        return false;
+    };
+
+    vmar.handle_page_fault(VirtualAddress::new(address), access)
+        .is_ok()
+}
+
 #[unsafe(naked)]
 extern "C" fn page_fault() {
     core::arch::naked_asm!(
@@ -282,6 +446,74 @@ extern "C" fn page_fault() {
     );
 }
 
+/// NMI 只用于 panic 时停止其他 CPU，其余来源的 NMI 忽略
+#[unsafe(no_mangle)]
+extern "C" fn do_nmi(regs: *mut Ptrace) {
+    let regs = unsafe { regs.as_mut_unchecked() };
+    if crash::panicking() {
+        crash::stop_current_cpu(regs);
+    }
+    if gdbstub::stopping() {
+        gdbstub::park_current_cpu(regs);
+    }
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn do_breakpoint(regs: *mut Ptrace) {
+    let regs = unsafe { regs.as_mut_unchecked() };
+    if !gdbstub::handle_trap(regs, Trap::Breakpoint) {
+        crash::save_fault_regs(regs);
+        error!("Exception: Breakpoint");
+        panic!("{}", regs);
+    }
+}
+
+#[unsafe(naked)]
+extern "C" fn breakpoint() {
+    core::arch::naked_asm!(
+        "sub rsp, 0x8",
+        push_context!(),
+        "mov rdi, rsp",
+        "call do_breakpoint",
+        pop_context!(),
+        "iretq",
+    );
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn do_debug(regs: *mut Ptrace) {
+    let regs = unsafe { regs.as_mut_unchecked() };
+    if !gdbstub::handle_trap(regs, Trap::SingleStep) {
+        crash::save_fault_regs(regs);
+        error!("Exception: Debug");
+        panic!("{}", regs);
+    }
+}
+
+#[unsafe(naked)]
+extern "C" fn debug() {
+    core::arch::naked_asm!(
+        "sub rsp, 0x8",
+        push_context!(),
+        "mov rdi, rsp",
+        "call do_debug",
+        pop_context!(),
+        "iretq",
+    );
+}
+
+#[unsafe(naked)]
+extern "C" fn nmi() {
+    core::arch::naked_asm!(
+        "sub rsp, 0x8",
+        push_context!(),
+        "mov rdi, rsp",
+        "call do_nmi",
+        pop_context!(),
+        "iretq",
+    );
+}
+
 pub const INTERRUPT_INDEX_OFFSET: u8 = 32;
 
 #[derive(Debug, Clone, Copy)]
@@ -290,6 +522,12 @@ pub enum InterruptIndex {
     Timer = INTERRUPT_INDEX_OFFSET,
     ApicError,
     ApicSpurious,
+    /// 调试串口（GDB 桩）
+    DebugSerial,
+    /// 交给用户态驱动的设备中断，ISA 中断 n 使用 `Device + n`
+    Device = 0x30,
+    /// 跨 CPU TLB 刷新
+    TlbShootdown = 0xf0,
 }
 
 pub static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
@@ -298,6 +536,10 @@ pub static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     unsafe {
         idt.invalid_opcode
             .set_handler_addr(x86_64::VirtAddr::new(invalid_opcode as *const () as u64));
+        idt.device_not_available
+            .set_handler_addr(x86_64::VirtAddr::new(
+                device_not_available as *const () as u64,
+            ));
         idt.page_fault
             .set_handler_addr(x86_64::VirtAddr::new(page_fault as *const () as u64));
         idt.general_protection_fault
@@ -305,21 +547,102 @@ pub static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
                 general_protection_fault as *const () as u64,
             ));
         idt.double_fault
-            .set_handler_addr(x86_64::VirtAddr::new(double_fault as *const () as u64));
+            .set_handler_addr(x86_64::VirtAddr::new(double_fault as *const () as u64))
+            .set_stack_index(DOUBLE_FAULT_IST_INDEX);
+        idt.breakpoint
+            .set_handler_addr(x86_64::VirtAddr::new(breakpoint as *const () as u64));
+        idt.debug
+            .set_handler_addr(x86_64::VirtAddr::new(debug as *const () as u64));
+        idt.non_maskable_interrupt
+            .set_handler_addr(x86_64::VirtAddr::new(nmi as *const () as u64))
+            .set_stack_index(NMI_IST_INDEX);
 
         idt[InterruptIndex::Timer as u8]
             .set_handler_addr(x86_64::VirtAddr::new(timer_interrupt as *const () as u64));
+        idt[InterruptIndex::DebugSerial as u8].set_handler_addr(x86_64::VirtAddr::new(
+            debug_serial_interrupt as *const () as u64,
+        ));
+        idt[InterruptIndex::TlbShootdown as u8].set_handler_addr(x86_64::VirtAddr::new(
+            tlb_shootdown_interrupt as *const () as u64,
+        ));
+        for (irq, entry) in DEVICE_INTERRUPTS.iter().enumerate() {
+            idt[InterruptIndex::Device as u8 + irq as u8]
+                .set_handler_addr(x86_64::VirtAddr::new(*entry as *const () as u64));
+        }
     }
 
     idt
 });
 
+/// 记录中断进入，未开启中断跟踪时返回 None
+#[inline]
+fn trace_irq_enter(vector: u8) -> Option<u64> {
+    if !trace::enabled(KTRACE_GROUP_IRQ) {
+        return None;
+    }
+    trace::record(KTRACE_EVENT_IRQ_ENTER, [vector as u64, 0, 0]);
+    Some(trace::timestamp())
+}
+
+/// 记录中断返回和处理耗时
+#[inline]
+fn trace_irq_exit(vector: u8, start: Option<u64>) {
+    if let Some(start) = start
+        && trace::enabled(KTRACE_GROUP_IRQ)
+    {
+        let duration = trace::timestamp().saturating_sub(start);
+        trace::record(KTRACE_EVENT_IRQ_EXIT, [vector as u64, duration, 0]);
+    }
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn do_timer_interrupt(regs: *mut Ptrace) {
+    let regs = unsafe { regs.as_mut_unchecked() };
+    let trace_start = trace_irq_enter(InterruptIndex::Timer as u8);
+    crate::random::add_interrupt_entropy();
+    if crate::profiler::running() {
+        crate::profiler::sample(regs);
+    }
+    if let Some(lapic) = LAPIC.lock().as_mut() {
+        unsafe { lapic.end_of_interrupt() };
+    }
+    // 调度可能切换到其他任务，在此之前结束中断记录
+    trace_irq_exit(InterruptIndex::Timer as u8, trace_start);
+    timer_tick();
+}
+
+/// 调试串口收到数据，先应答中断，调试器可能停留很久
 #[unsafe(no_mangle)]
-extern "C" fn do_timer_interrupt(_regs: *mut Ptrace) {
+extern "C" fn do_debug_serial_interrupt(regs: *mut Ptrace) {
+    let regs = unsafe { regs.as_mut_unchecked() };
+    if let Some(lapic) = LAPIC.lock().as_mut() {
+        unsafe { lapic.end_of_interrupt() };
+    }
+    gdbstub::handle_serial_irq(regs);
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn do_tlb_shootdown_interrupt(_regs: *mut Ptrace) {
+    let trace_start = trace_irq_enter(InterruptIndex::TlbShootdown as u8);
+    crate::memory::tlb::handle_shootdown_ipi();
+    if let Some(lapic) = LAPIC.lock().as_mut() {
+        unsafe { lapic.end_of_interrupt() };
+    }
+    trace_irq_exit(InterruptIndex::TlbShootdown as u8, trace_start);
+}
+
+/// 设备中断：屏蔽它并通知绑定的中断对象，驱动应答后才解除屏蔽
+#[unsafe(no_mangle)]
+extern "C" fn do_device_interrupt(_regs: *mut Ptrace, irq: u8) {
+    let vector = InterruptIndex::Device as u8 + irq;
+    let trace_start = trace_irq_enter(vector);
+    crate::random::add_interrupt_entropy();
+    unsafe { ioapic_set_masked(irq, true) };
+    interrupt::handle_irq(irq);
     if let Some(lapic) = LAPIC.lock().as_mut() {
         unsafe { lapic.end_of_interrupt() };
     }
-    schedule();
+    trace_irq_exit(vector, trace_start);
 }
 
 #[unsafe(naked)]
@@ -351,6 +674,75 @@ pub extern "C" fn timer_interrupt() {
     );
 }
 
+#[unsafe(naked)]
+extern "C" fn debug_serial_interrupt() {
+    core::arch::naked_asm!(
+        "sub rsp, 0x8",
+        push_context!(),
+        "mov rdi, rsp",
+        "call do_debug_serial_interrupt",
+        pop_context!(),
+        "iretq",
+    );
+}
+
+#[unsafe(naked)]
+pub extern "C" fn tlb_shootdown_interrupt() {
+    core::arch::naked_asm!(
+        "sub rsp, 0x8",
+        push_context!(),
+        "mov rdi, rsp",
+        "call do_tlb_shootdown_interrupt",
+        pop_context!(),
+        "iretq",
+    );
+}
+
+/// ISA 设备中断的数量
+pub const DEVICE_IRQ_COUNT: usize = 16;
+
+/// 为每个设备中断生成入口，把中断号传给 `do_device_interrupt`
+macro_rules! device_interrupts {
+    ($($irq:literal => $name:ident),* $(,)?) => {
+        $(
+        #[unsafe(naked)]
+        extern "C" fn $name() {
+            core::arch::naked_asm!(
+                "sub rsp, 0x8",
+                push_context!(),
+                "mov rdi, rsp",
+                "mov esi, {irq}",
+                "call do_device_interrupt",
+                pop_context!(),
+                "iretq",
+                irq = const $irq,
+            );
+        }
+        )*
+
+        const DEVICE_INTERRUPTS: [extern "C" fn(); DEVICE_IRQ_COUNT] = [$($name),*];
+    };
+}
+
+device_interrupts!(
+    0 => device_interrupt_0,
+    1 => device_interrupt_1,
+    2 => device_interrupt_2,
+    3 => device_interrupt_3,
+    4 => device_interrupt_4,
+    5 => device_interrupt_5,
+    6 => device_interrupt_6,
+    7 => device_interrupt_7,
+    8 => device_interrupt_8,
+    9 => device_interrupt_9,
+    10 => device_interrupt_10,
+    11 => device_interrupt_11,
+    12 => device_interrupt_12,
+    13 => device_interrupt_13,
+    14 => device_interrupt_14,
+    15 => device_interrupt_15,
+);
+
 pub fn init() {
     IDT.load();
 }
@@ -1,77 +1,45 @@
 mod boot;
 pub mod cache;
+pub mod debug;
 pub mod drivers;
+pub mod fpu;
 pub mod gdt;
 pub mod irq;
+pub mod random;
 pub mod rmm;
 pub mod smp;
 pub mod syscall;
 pub mod time;
+pub mod tlb;
+pub mod user;
 
 use crate::arch::smp::LAPICID_TO_CPUINFO;
 use crate::task::ArcTask;
 use crate::task::Task;
 
 pub use self::cache::X8664CacheArch as CurrentCacheArch;
+pub use self::debug::X8664DebugArch as CurrentDebugArch;
+pub use self::drivers::apic::send_nmi_to_others;
+pub use self::fpu::FpState;
 pub use self::irq::Ptrace;
 pub use self::irq::X8664IrqArch as CurrentIrqArch;
 pub use self::irq::kernel_thread_entry;
 pub use self::irq::return_from_interrupt;
+pub use self::random::X8664RandomArch as CurrentRandomArch;
 pub use self::smp::get_lapicid as get_archid;
 pub use self::time::X8664TimeArch as CurrentTimeArch;
+pub use self::tlb::X8664TlbArch as CurrentTlbArch;
+pub use self::user::X8664UserAccessArch as CurrentUserAccessArch;
 use ::rmm::Arch;
 use ::rmm::TableKind;
 pub use ::rmm::X8664Arch as CurrentRmmArch;
-use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags, Efer, EferFlags};
 use x86_64::registers::model_specific::FsBase;
 use x86_64::registers::model_specific::GsBase;
-
-#[repr(C, align(16))]
-#[derive(Debug, Copy, Clone, Default)]
-pub struct FpState {
-    // 0
-    fcw: u16,
-    fsw: u16,
-    ftw: u16,
-    fop: u16,
-    word2: u64,
-    // 16
-    word3: u64,
-    mxcsr: u32,
-    mxcsr_mask: u32,
-    // 32
-    mm: [u64; 16],
-    // 160
-    xmm: [u64; 32],
-    // 416
-    rest: [u64; 12],
-}
-
-impl FpState {
-    pub fn new() -> Self {
-        assert!(core::mem::size_of::<Self>() == 512);
-        Self {
-            mxcsr: 0x1f80,
-            fcw: 0x037f,
-            ..Self::default()
-        }
-    }
-
-    pub fn save(&mut self) {
-        unsafe {
-            core::arch::x86_64::_fxsave64(self as *mut FpState as *mut u8);
-        }
-    }
-
-    pub fn restore(&self) {
-        unsafe {
-            core::arch::x86_64::_fxrstor64(self as *const FpState as *const u8);
-        }
-    }
-}
+use x86_64::registers::model_specific::Msr;
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug)]
 pub struct ArchContext {
     pub ip: usize,
     pub sp: usize,
@@ -80,6 +48,19 @@ pub struct ArchContext {
     pub fpu: FpState,
 }
 
+impl ArchContext {
+    /// 创建空的上下文，FPU 状态区分配失败时返回 None
+    pub fn new() -> Option<Self> {
+        Some(Self {
+            ip: 0,
+            sp: 0,
+            fsbase: 0,
+            gsbase: 0,
+            fpu: FpState::new()?,
+        })
+    }
+}
+
 #[unsafe(no_mangle)]
 unsafe extern "C" fn do_switch_to(prev: *mut Task, next: *const Task) {
     GsBase::write(x86_64::VirtAddr::new(next as u64));
@@ -88,14 +69,12 @@ unsafe extern "C" fn do_switch_to(prev: *mut Task, next: *const Task) {
     let next = next.as_ref_unchecked();
 
     if let Some(process) = next.process() {
-        let page_table_addr = process
-            .read()
-            .root_vmar()
-            .unwrap()
-            .page_table_addr()
-            .unwrap();
-
-        CurrentRmmArch::set_table(TableKind::User, page_table_addr);
+        let root_vmar = process.read().root_vmar().unwrap();
+        let page_table_addr = root_vmar.page_table_addr().unwrap();
+
+        crate::memory::tlb::switch_address_space(root_vmar.active_cpus(), || unsafe {
+            CurrentRmmArch::set_table(TableKind::User, page_table_addr)
+        });
     }
 
     prev.arch_context.fsbase = FsBase::read().as_u64() as usize;
@@ -104,11 +83,13 @@ unsafe extern "C" fn do_switch_to(prev: *mut Task, next: *const Task) {
     FsBase::write(x86_64::VirtAddr::new(next.arch_context.fsbase as u64));
     // GsBase::write(x86_64::VirtAddr::new(next.arch_context.gsbase as u64));
 
-    prev.arch_context.fpu.save();
-    next.arch_context.fpu.restore();
+    // 下一个任务第一次使用 FPU 时才在 #NM 中恢复它的状态
+    prev.arch_context.fpu.save_if_loaded();
+    fpu::disable_access();
 }
 
 use core::mem::offset_of;
+use core::ops::Range;
 
 #[unsafe(naked)]
 pub extern "C" fn switch_to_inner(prev: *mut Task, next: *const Task) {
@@ -139,14 +120,29 @@ pub extern "C" fn switch_to_inner(prev: *mut Task, next: *const Task) {
 }
 
 pub fn switch_to(prev: ArcTask, next: ArcTask) {
+    {
+        let mut cpu_infos = LAPICID_TO_CPUINFO.lock();
+        let cpu_info = cpu_infos.get_mut(&get_archid()).unwrap();
+        cpu_info.set_ring0_rsp(next.read().get_kernel_stack_top().data() as u64);
+        // 内核线程不访问用户态的 I/O 端口，位图保持原样
+        if let Some(process) = next.read().process() {
+            cpu_info.load_io_ports(process.read().io_ports());
+        }
+    }
+    let prev = prev.as_mut_ptr();
+    let next = next.as_mut_ptr() as *const _;
+    switch_to_inner(prev, next);
+}
+
+/// 当前进程的 I/O 端口权限改变后，立即更新当前 CPU 的 I/O 位图
+///
+/// 进程在其他 CPU 上的线程在下一次被调度时才获得新的权限。
+pub fn load_io_ports(ports: &[Range<u16>]) {
     LAPICID_TO_CPUINFO
         .lock()
         .get_mut(&get_archid())
         .unwrap()
-        .set_ring0_rsp(next.read().get_kernel_stack_top().data() as u64);
-    let prev = prev.as_mut_ptr();
-    let next = next.as_mut_ptr() as *const _;
-    switch_to_inner(prev, next);
+        .load_io_ports(ports);
 }
 
 pub fn init_sse() {
@@ -161,10 +157,89 @@ pub fn init_sse() {
     unsafe { Cr4::write(cr4) };
 }
 
+/// PAT 表项：0 WB，1 WC，2 UC-，3 UC，4~7 与 0~3 相同
+///
+/// 页表项只用 PWT/PCD 选择前四项，与 rmm 中缓存类型标志的定义对应。
+const PAT_VALUE: u64 = 0x0007_0106_0007_0106;
+
+pub fn init_pat() {
+    unsafe { Msr::new(0x277).write(PAT_VALUE) };
+}
+
+/// 开启 NX、SMEP、SMAP 和内核写保护，CPU 不支持的功能跳过
+///
+/// 开启 SMAP 后内核只能在 `syscall::user` 提供的访问窗口内读写用户内存。
+pub fn init_protection() {
+    unsafe { Efer::write(Efer::read() | EferFlags::NO_EXECUTE_ENABLE) };
+
+    // 内核写只读页（例如 COW 页面）时同样触发缺页
+    let mut cr0 = Cr0::read();
+    cr0.insert(Cr0Flags::WRITE_PROTECT);
+    unsafe { Cr0::write(cr0) };
+
+    // CPUID.07H:EBX[7] SMEP，EBX[20] SMAP
+    let features = unsafe { core::arch::x86_64::__cpuid_count(7, 0) }.ebx;
+    let mut cr4 = Cr4::read();
+    if features & (1 << 7) != 0 {
+        cr4.insert(Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION);
+    }
+    if features & (1 << 20) != 0 {
+        cr4.insert(Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION);
+        user::SMAP_ENABLED.store(true, core::sync::atomic::Ordering::Relaxed);
+    }
+    unsafe { Cr4::write(cr4) };
+}
+
+/// GsBase 指向的当前任务，本 CPU 进入 idle 任务之前为空
+///
+/// 不经过调度器的锁，NMI 和 panic 中也可以调用。
+pub fn current_task_ptr() -> *const Task {
+    GsBase::read().as_u64() as *const Task
+}
+
+/// 关中断并停机，NMI 仍然会打断
+pub fn halt() -> ! {
+    loop {
+        x86_64::instructions::interrupts::disable();
+        x86_64::instructions::hlt();
+    }
+}
+
+/// 重启机器
+///
+/// 先通过键盘控制器复位，不成功时加载空的 IDT 触发三重错误。两种方式都是热重启，内存内容保留。
+pub fn reboot() -> ! {
+    x86_64::instructions::interrupts::disable();
+    unsafe {
+        let mut status = x86_64::instructions::port::Port::<u8>::new(0x64);
+        // 等待输入缓冲区为空
+        for _ in 0..0x10000 {
+            if status.read() & 0x2 == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        status.write(0xfe);
+    }
+
+    let empty = x86_64::structures::DescriptorTablePointer {
+        limit: 0,
+        base: x86_64::VirtAddr::zero(),
+    };
+    unsafe {
+        x86_64::instructions::tables::lidt(&empty);
+        core::arch::asm!("int3", options(noreturn));
+    }
+}
+
 pub fn early_init() {
     init_sse();
+    fpu::init();
+    init_pat();
+    init_protection();
     crate::smp::init();
     crate::arch::x86_64::irq::init();
     crate::arch::x86_64::drivers::apic::init();
+    time::init();
     crate::arch::x86_64::syscall::init();
 }
@@ -0,0 +1,47 @@
+use spin::Lazy;
+use x86_64::instructions::random::RdRand;
+
+use crate::arch::random::RandomArch;
+
+/// RDSEED 暂时没有熵可用时的重试次数
+const RDSEED_RETRIES: usize = 64;
+
+/// CPUID.07H:EBX[18] RDSEED
+static RDSEED_SUPPORTED: Lazy<bool> =
+    Lazy::new(|| unsafe { core::arch::x86_64::__cpuid_count(7, 0) }.ebx & (1 << 18) != 0);
+
+pub struct X8664RandomArch;
+
+impl RandomArch for X8664RandomArch {
+    fn hardware_random() -> Option<u64> {
+        RdRand::new()?.get_u64()
+    }
+
+    fn hardware_seed() -> Option<u64> {
+        if !*RDSEED_SUPPORTED {
+            return None;
+        }
+        for _ in 0..RDSEED_RETRIES {
+            let value: u64;
+            let ok: u8;
+            unsafe {
+                core::arch::asm!(
+                    "rdseed {value}",
+                    "setc {ok}",
+                    value = out(reg) value,
+                    ok = out(reg_byte) ok,
+                    options(nomem, nostack),
+                )
+            };
+            if ok != 0 {
+                return Some(value);
+            }
+            core::hint::spin_loop();
+        }
+        None
+    }
+
+    fn cycle_counter() -> u64 {
+        unsafe { core::arch::x86_64::_rdtsc() }
+    }
+}
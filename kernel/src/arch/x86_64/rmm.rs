@@ -1,4 +1,7 @@
 use rmm::{Arch, PageFlags, VirtualAddress};
+use spin::Lazy;
+
+use crate::arch::CurrentRmmArch;
 
 pub unsafe fn page_flags<A: Arch>(virt: VirtualAddress) -> PageFlags<A> {
     use crate::kernel_executable_offsets::*;
@@ -12,3 +15,15 @@ pub unsafe fn page_flags<A: Arch>(virt: VirtualAddress) -> PageFlags<A> {
         PageFlags::new().write(true)
     }
 }
+
+static MAX_HUGE_PAGE_LEVEL: Lazy<usize> = Lazy::new(|| {
+    // CPUID.80000001H:EDX[26] 表示支持 1 GiB 页
+    let gigabyte_pages = unsafe { core::arch::x86_64::__cpuid(0x8000_0001) }.edx & (1 << 26) != 0;
+    let level = if gigabyte_pages { 2 } else { 1 };
+    level.min(CurrentRmmArch::HUGE_PAGE_LEVEL)
+});
+
+/// 用户映射可以使用的最高大页层级（1 = 2 MiB，2 = 1 GiB）
+pub fn max_huge_page_level() -> usize {
+    *MAX_HUGE_PAGE_LEVEL
+}
@@ -5,19 +5,23 @@ use limine::mp::Cpu;
 use rmm::{Arch, PhysicalAddress, TableKind};
 use spin::Mutex;
 use x2apic::lapic::TimerMode;
+use x86_64::{VirtAddr, registers::model_specific::GsBase};
 
 use crate::{
     arch::{
         CurrentIrqArch, CurrentRmmArch,
         drivers::apic::{APIC_INITIALIZED, LAPIC, LAPIC_TIMER_INITIAL, disable_pic},
+        fpu,
         gdt::CpuInfo,
-        init_sse,
+        init_pat, init_protection, init_sse,
         irq::IrqArch,
     },
+    cmdline,
     init::memory::KERNEL_PAGE_TABLE_PHYS,
-    smp::{BSP_CPUARCHID, CPU_COUNT, CPUID_TO_ARCHID, MP_REQUEST},
+    memory::tlb::MAX_CPUS,
+    smp::{BSP_CPUARCHID, CPU_COUNT, CPUID_TO_ARCHID, MP_REQUEST, ONLINE_CPUS},
     task::{
-        TASK_INITIALIZED,
+        TASK_INITIALIZED, get_scheduler_by_archid,
         sched::{SCHEDULERS, Scheduler},
     },
 };
@@ -38,11 +42,23 @@ pub fn init() {
         mp_response.bsp_lapic_id() as usize,
         core::sync::atomic::Ordering::SeqCst,
     );
-    CPU_COUNT.store(
-        mp_response.cpus().len(),
-        core::sync::atomic::Ordering::SeqCst,
-    );
-    for (i, cpu) in mp_response.cpus().iter().enumerate() {
+    let mut disabled = cmdline::options().disabled_cpus;
+    let bsp_bit = 1u64.checked_shl(mp_response.bsp_lapic_id()).unwrap_or(0);
+    if disabled & bsp_bit != 0 {
+        log::warn!("The bootstrap processor cannot be disabled");
+        disabled &= !bsp_bit;
+    }
+
+    // 被禁用的 CPU 留在 Limine 中等待，不分配编号
+    let cpus = mp_response
+        .cpus()
+        .iter()
+        .filter(|cpu| disabled.checked_shr(cpu.lapic_id).unwrap_or(0) & 1 == 0);
+    for (i, cpu) in cpus.enumerate() {
+        // TLB 刷新用 LAPIC ID 作为 CPU 掩码的位号
+        assert!((cpu.lapic_id as usize) < MAX_CPUS, "LAPIC ID out of range");
+        CPU_COUNT.store(i + 1, core::sync::atomic::Ordering::SeqCst);
+        ONLINE_CPUS.fetch_or(1 << cpu.lapic_id, core::sync::atomic::Ordering::SeqCst);
         LAPICID_TO_CPUINFO
             .lock()
             .insert(cpu.lapic_id as usize, CpuInfo::default());
@@ -62,6 +78,18 @@ pub fn init() {
         .init();
 }
 
+/// 让 GsBase 指向本 CPU 的 idle 任务
+///
+/// 启动代码此后作为 idle 任务运行，`current_cpu` 不必等到第一次任务切换就能从
+/// GsBase 读取 LAPIC ID。
+pub fn load_idle_task(lapic_id: usize) {
+    let idle = get_scheduler_by_archid(lapic_id)
+        .read()
+        .get_current_task()
+        .expect("No idle task");
+    GsBase::write(VirtAddr::new(idle.as_mut_ptr() as u64));
+}
+
 #[unsafe(no_mangle)]
 extern "C" fn ap_kmain(cpu: &Cpu) -> ! {
     CurrentIrqArch::disable_global_irq();
@@ -71,6 +99,9 @@ extern "C" fn ap_kmain(cpu: &Cpu) -> ! {
     unsafe { CurrentRmmArch::set_table(TableKind::Kernel, physical_address) };
 
     init_sse();
+    fpu::init();
+    init_pat();
+    init_protection();
 
     LAPICID_TO_CPUINFO
         .lock()
@@ -101,6 +132,7 @@ extern "C" fn ap_kmain(cpu: &Cpu) -> ! {
     while !TASK_INITIALIZED.load(core::sync::atomic::Ordering::SeqCst) {
         spin_loop();
     }
+    load_idle_task(cpu.lapic_id as usize);
 
     loop {
         CurrentIrqArch::enable_global_irq();
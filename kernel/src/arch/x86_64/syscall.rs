@@ -31,7 +31,8 @@ unsafe extern "C" fn x8664_syscall_handler() {
 }
 
 pub fn init() {
-    SFMask::write(RFlags::INTERRUPT_FLAG);
+    // 进入内核时同时清除 AC，用户态不能借此绕过 SMAP
+    SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::ALIGNMENT_CHECK);
     LStar::write(VirtAddr::from_ptr(x8664_syscall_handler as *const ()));
 
     let (kernel_code, kernel_data) = Selectors::get_kernel_segments();
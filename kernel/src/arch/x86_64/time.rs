@@ -1,12 +1,78 @@
 use core::hint::spin_loop;
 
-use crate::arch::{drivers::hpet::HPET, time::TimeArch};
+use spin::Once;
+
+use crate::{
+    arch::{
+        drivers::{hpet::HPET, rtc},
+        time::TimeArch,
+    },
+    vdso::VdsoData,
+};
+
+/// 校准 TSC 使用的 HPET 时间窗口
+const TSC_CALIBRATION_NS: u64 = 10_000_000;
+/// TSC 换算为纳秒时的定点位数
+const TSC_SHIFT: u32 = 32;
+
+/// 校准后的 TSC 换算参数，只有不变 TSC 才会设置
+static TSC_CLOCK: Once<VdsoData> = Once::new();
+
+fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+fn hpet_nano_time() -> u64 {
+    HPET.elapsed().as_nanos() as u64
+}
+
+/// 检测不变 TSC 并用 HPET 校准频率，之后内核和用户态都用 TSC 计时
+///
+/// TSC 的换算基准取自 HPET，切换前后的时间是连续的。
+pub fn init() {
+    // CPUID.80000007H:EDX[8] 不变 TSC，频率不随电源状态变化
+    let invariant = unsafe { core::arch::x86_64::__cpuid(0x8000_0007) }.edx & (1 << 8) != 0;
+    if !invariant {
+        warn!("TSC is not invariant, falling back to HPET");
+        return;
+    }
+
+    let start_ns = hpet_nano_time();
+    let start_tsc = rdtsc();
+    while hpet_nano_time() - start_ns < TSC_CALIBRATION_NS {
+        spin_loop();
+    }
+    let end_tsc = rdtsc();
+    let end_ns = hpet_nano_time();
+
+    let ticks = end_tsc - start_tsc;
+    if ticks == 0 {
+        return;
+    }
+    let mult = (((end_ns - start_ns) as u128) << TSC_SHIFT) / ticks as u128;
+
+    TSC_CLOCK.call_once(|| VdsoData {
+        counter_usable: 1,
+        counter_base: end_tsc,
+        ns_base: end_ns,
+        mult: mult as u64,
+        shift: TSC_SHIFT,
+        ..VdsoData::default()
+    });
+    info!(
+        "TSC frequency: {} kHz",
+        ticks as u128 * 1_000_000 / (end_ns - start_ns) as u128
+    );
+}
 
 pub struct X8664TimeArch;
 
 impl TimeArch for X8664TimeArch {
     fn nano_time() -> u64 {
-        HPET.elapsed().as_nanos() as u64
+        match TSC_CLOCK.get() {
+            Some(clock) => clock.counter_to_nanos(rdtsc()),
+            None => hpet_nano_time(),
+        }
     }
 
     fn delay(ns: u64) {
@@ -15,4 +81,18 @@ impl TimeArch for X8664TimeArch {
             spin_loop();
         }
     }
+
+    fn fill_vdso(data: &mut VdsoData) {
+        if let Some(clock) = TSC_CLOCK.get() {
+            data.counter_usable = clock.counter_usable;
+            data.counter_base = clock.counter_base;
+            data.ns_base = clock.ns_base;
+            data.mult = clock.mult;
+            data.shift = clock.shift;
+        }
+    }
+
+    fn read_rtc() -> Option<u64> {
+        rtc::read_unix_time()
+    }
 }
@@ -0,0 +1,62 @@
+use rmm::{Arch, VirtualAddress};
+use x86_64::instructions::interrupts;
+
+use crate::{
+    arch::{
+        CurrentRmmArch, current_task_ptr, drivers::apic::LAPIC, tlb::TlbArch,
+        x86_64::irq::InterruptIndex,
+    },
+    init::memory::PAGE_SIZE,
+};
+
+pub struct X8664TlbArch;
+
+impl TlbArch for X8664TlbArch {
+    fn current_cpu() -> usize {
+        // 中断处理中也会调用，不能经过 LAPIC 的锁；从 GsBase 指向的当前任务读取，
+        // 不执行 cpuid（在虚拟机中会退出到宿主）
+        match unsafe { current_task_ptr().as_ref() } {
+            Some(task) => task.archid(),
+            // 本 CPU 还没有进入 idle 任务，只在启动阶段出现
+            None => (unsafe { core::arch::x86_64::__cpuid(1) }.ebx >> 24) as usize,
+        }
+    }
+
+    fn flush_range(start: usize, end: usize) {
+        let mut addr = start;
+        while addr < end {
+            unsafe { CurrentRmmArch::invalidate(VirtualAddress::new(addr)) };
+            addr += PAGE_SIZE;
+        }
+    }
+
+    fn flush_all() {
+        unsafe { CurrentRmmArch::invalidate_all() };
+    }
+
+    fn send_shootdown_ipi(cpus: u64) {
+        let mut lapic = LAPIC.lock();
+        let Some(lapic) = lapic.as_mut() else {
+            return;
+        };
+
+        let mut mask = cpus;
+        while mask != 0 {
+            let cpu = mask.trailing_zeros();
+            unsafe { lapic.send_ipi(InterruptIndex::TlbShootdown as u8, cpu) };
+            mask &= mask - 1;
+        }
+    }
+
+    fn irq_save() -> bool {
+        let enabled = interrupts::are_enabled();
+        interrupts::disable();
+        enabled
+    }
+
+    fn irq_restore(enabled: bool) {
+        if enabled {
+            interrupts::enable();
+        }
+    }
+}
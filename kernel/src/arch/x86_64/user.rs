@@ -0,0 +1,65 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::registers::rflags::{self, RFlags};
+
+use crate::arch::user::UserAccessArch;
+
+/// CPU 支持 SMAP 并已开启，不支持时 stac/clac 是非法指令
+pub static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+// 访问用户内存的复制，`rep movsb` 缺页且无法修复时缺页处理跳到 user_copy_fixup 返回 1
+core::arch::global_asm!(
+    ".global user_copy",
+    "user_copy:",
+    "mov rcx, rdx",
+    ".global user_copy_begin",
+    "user_copy_begin:",
+    "rep movsb",
+    ".global user_copy_end",
+    "user_copy_end:",
+    "xor eax, eax",
+    "ret",
+    ".global user_copy_fixup",
+    "user_copy_fixup:",
+    "mov eax, 1",
+    "ret",
+);
+
+unsafe extern "C" {
+    unsafe fn user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    static user_copy_begin: u8;
+    static user_copy_end: u8;
+    static user_copy_fixup: u8;
+}
+
+pub struct X8664UserAccessArch;
+
+impl UserAccessArch for X8664UserAccessArch {
+    fn user_access_begin() -> bool {
+        if !SMAP_ENABLED.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        let enabled = rflags::read().contains(RFlags::ALIGNMENT_CHECK);
+        // 不能声明 nomem：编译器不得把用户内存访问移出 stac/clac 之间
+        unsafe { core::arch::asm!("stac", options(nostack)) };
+        enabled
+    }
+
+    fn user_access_end(enabled: bool) {
+        if !enabled {
+            unsafe { core::arch::asm!("clac", options(nostack)) };
+        }
+    }
+
+    unsafe fn copy(dst: *mut u8, src: *const u8, len: usize) -> bool {
+        unsafe { user_copy(dst, src, len) == 0 }
+    }
+
+    fn fixup(pc: usize) -> Option<usize> {
+        let begin = &raw const user_copy_begin as usize;
+        let end = &raw const user_copy_end as usize;
+        (begin..end)
+            .contains(&pc)
+            .then_some(&raw const user_copy_fixup as usize)
+    }
+}
@@ -0,0 +1,24 @@
+//! 内核转发给 init 的启动选项
+//!
+//! 内核与用户态共用这里的定义。init 的 bootstrap channel 中的第一条消息由内核发送，
+//! 以小端的 [`BOOT_OPTIONS_MAGIC`] 开头，其后是若干个以 `\0` 结尾的 `key=value`，
+//! 即命令行中内核不识别的 `key=value` 选项，顺序与命令行相同。
+
+/// 启动选项消息的魔数
+pub const BOOT_OPTIONS_MAGIC: u32 = 0x424F_5054; // "BOPT"
+
+/// 启动选项消息的最大长度，超出的选项被丢弃
+pub const BOOT_OPTIONS_MAX: usize = 4096;
+
+/// 解析启动选项消息，返回各个选项的名字和值，魔数不对时返回 None
+pub fn parse(data: &[u8]) -> Option<impl Iterator<Item = (&str, &str)>> {
+    let (magic, options) = data.split_first_chunk::<4>()?;
+    if u32::from_le_bytes(*magic) != BOOT_OPTIONS_MAGIC {
+        return None;
+    }
+    Some(
+        options
+            .split(|&byte| byte == 0)
+            .filter_map(|option| core::str::from_utf8(option).ok()?.split_once('=')),
+    )
+}
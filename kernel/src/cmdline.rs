@@ -0,0 +1,223 @@
+//! 内核命令行
+//!
+//! 命令行由 Limine 配置中的 `cmdline` 提供，选项之间以空白分隔，形如 `key` 或 `key=value`。
+//! 内核识别的选项解析为 [`Options`]，其余的 `key=value` 选项通过 bootstrap channel 转发给 init。
+//!
+//! | 选项 | 含义 | 默认值 |
+//! | --- | --- | --- |
+//! | `loglevel=off\|error\|warn\|info\|debug\|trace` | 内核日志级别 | `trace` |
+//! | `init=<路径>` | initramfs 中 init 程序的路径 | `/sbin/init` |
+//! | `console=ttyS<n>\|<端口>` | 串口控制台，`ttyS0` 到 `ttyS3` 或十六进制 I/O 端口 | SPCR 表，没有时 `ttyS0` |
+//! | `panic_reboot[=秒数]` | panic 后等待一段时间重启，而不是停机 | 停机 |
+//! | `disable_cpus=<id>,...` | 不启动的 CPU（硬件编号），不能包括 BSP | 无 |
+//! | `sched_hz=<频率>` | 时钟中断频率 | `SCHED_HZ` |
+//! | `sched_slice=<次数>` | 每个时间片包含的时钟中断次数 | 1 |
+//! | `gdb[=wait]` | 开启内核调试器，见 [`crate::gdbstub`] | 关闭 |
+//! | `aslr=on\|off` | 随机化用户态 VMAR 中自动分配的地址 | `ASLR` |
+
+use limine::request::ExecutableCmdlineRequest;
+use log::LevelFilter;
+use spin::Once;
+
+use crate::consts::{ASLR, SCHED_HZ};
+
+#[used]
+#[unsafe(link_section = ".requests")]
+static CMDLINE_REQUEST: ExecutableCmdlineRequest = ExecutableCmdlineRequest::new();
+
+/// 内核自己处理的选项，不转发给 init
+const KERNEL_OPTIONS: &[&str] = &[
+    "loglevel",
+    "init",
+    "console",
+    "panic_reboot",
+    "disable_cpus",
+    "sched_hz",
+    "sched_slice",
+    "gdb",
+    "aslr",
+];
+
+/// 串口控制台 `ttyS0` 到 `ttyS3` 的 I/O 端口
+pub const SERIAL_PORTS: [u16; 4] = [0x3f8, 0x2f8, 0x3e8, 0x2e8];
+
+/// `panic_reboot` 不带秒数时的等待时间
+const DEFAULT_REBOOT_DELAY_SECS: u64 = 5;
+
+/// `sched_hz` 的取值范围
+const SCHED_HZ_RANGE: core::ops::RangeInclusive<usize> = 10..=10000;
+
+/// 内核识别的启动选项
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub log_level: LevelFilter,
+    pub init_path: &'static str,
+    /// 串口控制台的 I/O 端口，None 表示由 ACPI SPCR 表决定
+    pub console_port: Option<u16>,
+    /// panic 后重启前等待的秒数，None 表示停机
+    pub panic_reboot: Option<u64>,
+    /// 不启动的 CPU 的硬件编号掩码
+    pub disabled_cpus: u64,
+    pub sched_hz: usize,
+    pub sched_slice: usize,
+    /// 是否对自动分配的地址随机化（ASLR）
+    pub aslr: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            log_level: LevelFilter::Trace,
+            init_path: "/sbin/init",
+            console_port: None,
+            panic_reboot: None,
+            disabled_cpus: 0,
+            sched_hz: SCHED_HZ,
+            sched_slice: 1,
+            aslr: ASLR,
+        }
+    }
+}
+
+static OPTIONS: Once<Options> = Once::new();
+
+/// 完整的命令行，没有或不是合法 UTF-8 时为空
+pub fn cmdline() -> &'static str {
+    CMDLINE_REQUEST
+        .get_response()
+        .and_then(|response| response.cmdline().to_str().ok())
+        .unwrap_or("")
+}
+
+/// 依次返回每个选项的名字和值，不带值的选项值为 None
+fn entries() -> impl Iterator<Item = (&'static str, Option<&'static str>)> {
+    cmdline()
+        .split_ascii_whitespace()
+        .map(|option| match option.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (option, None),
+        })
+}
+
+/// 查找选项 `key`
+///
+/// 不带值的选项返回空字符串，同名选项出现多次时以最后一次为准。不分配内存，panic 时也可以调用。
+pub fn get(key: &str) -> Option<&'static str> {
+    entries()
+        .filter(|&(name, _)| name == key)
+        .map(|(_, value)| value.unwrap_or(""))
+        .last()
+}
+
+fn parse_log_level(value: &str) -> Option<LevelFilter> {
+    value.parse().ok()
+}
+
+fn parse_console(value: &str) -> Option<u16> {
+    if let Some(index) = value.strip_prefix("ttyS") {
+        return SERIAL_PORTS.get(index.parse::<usize>().ok()?).copied();
+    }
+    u16::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+fn parse_panic_reboot(value: &str) -> Option<u64> {
+    if value.is_empty() {
+        Some(DEFAULT_REBOOT_DELAY_SECS)
+    } else {
+        value.parse().ok()
+    }
+}
+
+fn parse_cpu_mask(value: &str) -> Option<u64> {
+    value.split(',').try_fold(0u64, |mask, id| {
+        let id = id.parse::<u32>().ok()?;
+        Some(mask | 1u64.checked_shl(id)?)
+    })
+}
+
+fn parse_sched_hz(value: &str) -> Option<usize> {
+    value.parse().ok().filter(|hz| SCHED_HZ_RANGE.contains(hz))
+}
+
+fn parse_sched_slice(value: &str) -> Option<usize> {
+    value.parse().ok().filter(|&slice| slice != 0)
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// 选项 `key` 的值能否解析，内核不识别的选项总是返回 true
+fn valid(key: &str, value: &str) -> bool {
+    match key {
+        "loglevel" => parse_log_level(value).is_some(),
+        "init" => !value.is_empty(),
+        "console" => parse_console(value).is_some(),
+        "panic_reboot" => parse_panic_reboot(value).is_some(),
+        "disable_cpus" => parse_cpu_mask(value).is_some(),
+        "sched_hz" => parse_sched_hz(value).is_some(),
+        "sched_slice" => parse_sched_slice(value).is_some(),
+        "aslr" => parse_switch(value).is_some(),
+        _ => true,
+    }
+}
+
+impl Options {
+    /// 解析命令行，无法解析的值使用默认值
+    fn parse() -> Self {
+        let mut options = Self::default();
+        let value = |key: &str| get(key).filter(|value| valid(key, value));
+
+        if let Some(level) = value("loglevel").and_then(parse_log_level) {
+            options.log_level = level;
+        }
+        if let Some(path) = value("init") {
+            options.init_path = path;
+        }
+        if let Some(port) = value("console").and_then(parse_console) {
+            options.console_port = Some(port);
+        }
+        options.panic_reboot = value("panic_reboot").and_then(parse_panic_reboot);
+        if let Some(mask) = value("disable_cpus").and_then(parse_cpu_mask) {
+            options.disabled_cpus = mask;
+        }
+        if let Some(hz) = value("sched_hz").and_then(parse_sched_hz) {
+            options.sched_hz = hz;
+        }
+        if let Some(slice) = value("sched_slice").and_then(parse_sched_slice) {
+            options.sched_slice = slice;
+        }
+        if let Some(aslr) = value("aslr").and_then(parse_switch) {
+            options.aslr = aslr;
+        }
+        options
+    }
+}
+
+/// 内核识别的启动选项，第一次调用时解析命令行
+pub fn options() -> &'static Options {
+    OPTIONS.call_once(Options::parse)
+}
+
+/// 内核不识别、需要转发给 init 的 `key=value` 选项
+pub fn forwarded() -> impl Iterator<Item = (&'static str, &'static str)> {
+    entries().filter_map(|(name, value)| {
+        let value = value?;
+        (!name.is_empty() && !KERNEL_OPTIONS.contains(&name)).then_some((name, value))
+    })
+}
+
+/// 记录命令行，并对无法解析的选项给出警告，需要在日志初始化之后调用
+pub fn report() {
+    log::info!("Command line: {}", cmdline());
+    for (name, value) in entries() {
+        let value = value.unwrap_or("");
+        if KERNEL_OPTIONS.contains(&name) && !valid(name, value) {
+            log::warn!("Ignoring invalid option {}={}", name, value);
+        }
+    }
+}
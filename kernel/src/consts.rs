@@ -1,3 +1,7 @@
 pub const STACK_SIZE: usize = 4 * 1024 * 1024;
 
+/// 默认的时钟中断频率，可以用命令行选项 `sched_hz` 修改
 pub const SCHED_HZ: usize = 100;
+
+/// 默认是否对用户态 VMAR 中自动分配的地址随机化（ASLR），可以用命令行选项 `aslr` 修改
+pub const ASLR: bool = true;
@@ -0,0 +1,354 @@
+//! panic 时停止所有 CPU 并保存崩溃记录
+//!
+//! 崩溃记录写在固定的物理内存区域，启动时这段内存不交给帧分配器，热重启后内容仍在。
+//! 下一次启动时打印上一次的记录并清除。
+
+use core::fmt::{self, Write};
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use rmm::{Arch, PhysicalAddress};
+use spin::Mutex;
+
+use crate::{
+    arch::{
+        CurrentRmmArch, CurrentTimeArch, CurrentTlbArch, Ptrace, current_task_ptr,
+        send_nmi_to_others, time::TimeArch, tlb::TlbArch,
+    },
+    init::memory::{CRASH_REGION_RESERVED, PAGE_SIZE},
+    memory::tlb::MAX_CPUS,
+    smp::CPU_COUNT,
+};
+
+/// 崩溃记录所在的物理地址
+#[cfg(target_arch = "x86_64")]
+pub const CRASH_RECORD_PHYS: usize = 0x0100_0000;
+/// QEMU virt 的内存从 0x8000_0000 开始，开头是 OpenSBI，同样取内存中 16 MiB 处
+#[cfg(target_arch = "riscv64")]
+pub const CRASH_RECORD_PHYS: usize = 0x8100_0000;
+/// 崩溃记录区域的大小
+pub const CRASH_RECORD_SIZE: usize = 8 * PAGE_SIZE;
+
+const CRASH_MAGIC: u64 = u64::from_le_bytes(*b"RADNCRSH");
+const CRASH_VERSION: u32 = 1;
+const MESSAGE_MAX: usize = 2048;
+/// 崩溃记录保存的调用栈深度
+pub const BACKTRACE_MAX: usize = 32;
+const TASK_NAME_MAX: usize = 32;
+/// 等待其他 CPU 响应 NMI 的时间
+const STOP_TIMEOUT_NS: u64 = 100_000_000;
+
+/// CPU 状态有效
+const CPU_VALID: u32 = 1 << 0;
+/// 保存了寄存器
+const CPU_HAS_REGS: u32 = 1 << 1;
+/// 发生 panic 的 CPU
+const CPU_PANICKED: u32 = 1 << 2;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CpuState {
+    flags: u32,
+    cpu: u32,
+    pid: u64,
+    tid: u64,
+    name_len: u32,
+    reserved: u32,
+    name: [u8; TASK_NAME_MAX],
+    regs: Ptrace,
+}
+
+#[repr(C)]
+struct CrashRecord {
+    magic: u64,
+    version: u32,
+    size: u32,
+    checksum: u64,
+    /// panic 时的单调时间（纳秒）
+    timestamp: u64,
+    message_len: u32,
+    backtrace_len: u32,
+    message: [u8; MESSAGE_MAX],
+    backtrace: [u64; BACKTRACE_MAX],
+    /// 以 CPU 的硬件编号为下标
+    cpus: [CpuState; MAX_CPUS],
+}
+
+const _: () = assert!(size_of::<CrashRecord>() <= CRASH_RECORD_SIZE);
+
+/// 发生 panic 的 CPU 编号加一，0 表示没有
+static PANIC_CPU: AtomicUsize = AtomicUsize::new(0);
+/// 已经响应 NMI 停下的 CPU 数
+static STOPPED_CPUS: AtomicUsize = AtomicUsize::new(0);
+/// 崩溃记录是否已经开始写入
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+/// 各 CPU 最近一次致命异常的寄存器，panic 时写入崩溃记录
+static FAULT_REGS: [Mutex<Option<Ptrace>>; MAX_CPUS] = [const { Mutex::new(None) }; MAX_CPUS];
+
+fn record() -> Option<*mut CrashRecord> {
+    if !CRASH_REGION_RESERVED.load(Ordering::Acquire) {
+        return None;
+    }
+    let virt = unsafe { CurrentRmmArch::phys_to_virt(PhysicalAddress::new(CRASH_RECORD_PHYS)) };
+    Some(virt.data() as *mut CrashRecord)
+}
+
+/// 记录校验和，计算时 `checksum` 字段视为 0
+fn checksum(record: *const CrashRecord) -> u64 {
+    let bytes =
+        unsafe { core::slice::from_raw_parts(record as *const u8, size_of::<CrashRecord>()) };
+    let checksum_offset = core::mem::offset_of!(CrashRecord, checksum);
+    // FNV-1a
+    bytes
+        .iter()
+        .enumerate()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, (i, &byte)| {
+            let byte = if (checksum_offset..checksum_offset + 8).contains(&i) {
+                0
+            } else {
+                byte
+            };
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// 是否有 CPU 正在 panic
+pub fn panicking() -> bool {
+    PANIC_CPU.load(Ordering::Acquire) != 0
+}
+
+/// 致命异常的处理函数在 panic 之前调用，保存当前 CPU 的寄存器
+pub fn save_fault_regs(regs: &Ptrace) {
+    let cpu = CurrentTlbArch::current_cpu();
+    if let Some(mut slot) = FAULT_REGS[cpu].try_lock() {
+        *slot = Some(*regs);
+    }
+}
+
+/// 当前 CPU 最近一次致命异常的寄存器
+pub fn fault_regs() -> Option<Ptrace> {
+    let cpu = CurrentTlbArch::current_cpu();
+    FAULT_REGS[cpu].try_lock().and_then(|regs| *regs)
+}
+
+/// 开始处理 panic，当前 CPU 是第一个 panic 的 CPU 时返回 true
+///
+/// 同一 CPU 在处理 panic 时再次 panic，或者其他 CPU 已经在处理 panic 时返回 false，调用者应直接停机。
+pub fn begin() -> bool {
+    let cpu = CurrentTlbArch::current_cpu();
+    PANIC_CPU
+        .compare_exchange(0, cpu + 1, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+}
+
+/// 清空崩溃记录，保存当前 CPU 的状态，然后用 NMI 停止其他 CPU
+///
+/// 其他 CPU 停下时可能持有串口等锁，之后的输出由调用者负责强制解锁。
+pub fn stop_other_cpus() {
+    if let Some(record) = record() {
+        unsafe {
+            core::ptr::write_bytes(record as *mut u8, 0, size_of::<CrashRecord>());
+            (*record).timestamp = CurrentTimeArch::nano_time();
+        }
+        RECORDING.store(true, Ordering::Release);
+    }
+
+    let cpu = CurrentTlbArch::current_cpu();
+    save_cpu_state(cpu, fault_regs(), CPU_PANICKED);
+
+    send_nmi_to_others();
+
+    let others = CPU_COUNT.load(Ordering::SeqCst).saturating_sub(1);
+    let start = CurrentTimeArch::nano_time();
+    while STOPPED_CPUS.load(Ordering::Acquire) < others
+        && CurrentTimeArch::nano_time().saturating_sub(start) < STOP_TIMEOUT_NS
+    {
+        core::hint::spin_loop();
+    }
+}
+
+/// 在 NMI 中调用，保存当前 CPU 的状态后停机
+pub fn stop_current_cpu(regs: &mut Ptrace) -> ! {
+    let cpu = CurrentTlbArch::current_cpu();
+    if PANIC_CPU.load(Ordering::Acquire) != cpu + 1 {
+        save_cpu_state(cpu, Some(*regs), 0);
+        crate::gdbstub::register_halted_cpu(cpu, regs);
+        STOPPED_CPUS.fetch_add(1, Ordering::AcqRel);
+    }
+    crate::arch::halt()
+}
+
+fn save_cpu_state(cpu: usize, regs: Option<Ptrace>, flags: u32) {
+    let Some(record) = record() else {
+        return;
+    };
+    if !RECORDING.load(Ordering::Acquire) || cpu >= MAX_CPUS {
+        return;
+    }
+
+    let state = unsafe { &mut *addr_of_mut!((*record).cpus[cpu]) };
+    state.cpu = cpu as u32;
+    if let Some(regs) = regs {
+        state.regs = regs;
+        state.flags |= CPU_HAS_REGS;
+    }
+
+    // 所有 CPU 都已经或即将停下，直接读取任务，不获取锁
+    if let Some(task) = unsafe { current_task_ptr().as_ref() } {
+        state.tid = task.tid() as u64;
+        let name = task.name().as_bytes();
+        let len = name.len().min(TASK_NAME_MAX);
+        state.name[..len].copy_from_slice(&name[..len]);
+        state.name_len = len as u32;
+        if let Some(process) = task.process()
+            && let Some(process) = process.try_read()
+        {
+            state.pid = process.pid() as u64;
+        }
+    }
+    state.flags |= CPU_VALID | flags;
+}
+
+/// 把格式化输出写入崩溃记录的消息，超出部分丢弃
+struct MessageWriter<'a> {
+    buf: &'a mut [u8; MESSAGE_MAX],
+    len: usize,
+}
+
+impl Write for MessageWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(MESSAGE_MAX - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// 写入 panic 消息和调用栈
+pub fn record_panic(message: fmt::Arguments, backtrace: &[u64]) {
+    let Some(record) = record() else {
+        return;
+    };
+    if !RECORDING.load(Ordering::Acquire) {
+        return;
+    }
+
+    let record = unsafe { &mut *record };
+    let mut writer = MessageWriter {
+        buf: &mut record.message,
+        len: 0,
+    };
+    let _ = writer.write_fmt(message);
+    record.message_len = writer.len as u32;
+
+    let len = backtrace.len().min(BACKTRACE_MAX);
+    record.backtrace[..len].copy_from_slice(&backtrace[..len]);
+    record.backtrace_len = len as u32;
+}
+
+/// 写入校验和与魔数，此后记录在下次启动时有效
+pub fn finish() {
+    let Some(record) = record() else {
+        return;
+    };
+    if !RECORDING.swap(false, Ordering::AcqRel) {
+        return;
+    }
+
+    unsafe {
+        (*record).version = CRASH_VERSION;
+        (*record).size = size_of::<CrashRecord>() as u32;
+        (*record).checksum = checksum(record);
+        core::ptr::write_volatile(addr_of_mut!((*record).magic), CRASH_MAGIC);
+    }
+}
+
+/// 字节串中合法 UTF-8 的最长前缀，截断时可能切开多字节字符
+fn valid_prefix(bytes: &[u8]) -> &str {
+    match core::str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(err) => unsafe { core::str::from_utf8_unchecked(&bytes[..err.valid_up_to()]) },
+    }
+}
+
+fn print_cpu_states(record: &CrashRecord) {
+    for state in record
+        .cpus
+        .iter()
+        .filter(|state| state.flags & CPU_VALID != 0)
+    {
+        let name_len = (state.name_len as usize).min(TASK_NAME_MAX);
+        let name = valid_prefix(&state.name[..name_len]);
+        error!(
+            "CPU {}{}: pid {} tid {} ({})",
+            state.cpu,
+            if state.flags & CPU_PANICKED != 0 {
+                " (panicked)"
+            } else {
+                ""
+            },
+            state.pid,
+            state.tid,
+            name
+        );
+        if state.flags & CPU_HAS_REGS != 0 {
+            error!("{}", state.regs);
+        }
+    }
+}
+
+/// panic 时打印各 CPU 停下时运行的任务和寄存器
+pub fn dump_cpu_states() {
+    match record() {
+        Some(record) => print_cpu_states(unsafe { &*record }),
+        None => error!("Crash record region is not available, CPU states were not saved"),
+    }
+}
+
+/// 打印上一次启动留下的崩溃记录并清除
+///
+/// 在帧分配器初始化之后调用。
+pub fn report_previous() {
+    let Some(record) = record() else {
+        warn!("Crash record region is not available");
+        return;
+    };
+
+    let record = unsafe { &mut *record };
+    if record.magic != CRASH_MAGIC {
+        return;
+    }
+    record.magic = 0;
+    if record.version != CRASH_VERSION
+        || record.size as usize != size_of::<CrashRecord>()
+        || record.checksum != checksum(record)
+    {
+        warn!("Ignoring corrupted crash record from previous boot");
+        return;
+    }
+
+    let message_len = (record.message_len as usize).min(MESSAGE_MAX);
+    let message = valid_prefix(&record.message[..message_len]);
+    error!(
+        "Previous boot panicked at {}.{:09}s: {}",
+        record.timestamp / 1_000_000_000,
+        record.timestamp % 1_000_000_000,
+        message
+    );
+
+    let backtrace_len = (record.backtrace_len as usize).min(BACKTRACE_MAX);
+    if backtrace_len != 0 {
+        error!("Backtrace (symbols from the current kernel):");
+    }
+    for (i, &address) in record.backtrace[..backtrace_len].iter().enumerate() {
+        error!(
+            "{:4}:{:#19x} -> {}",
+            i,
+            address,
+            crate::panic::symbol(address as usize)
+        );
+    }
+
+    print_cpu_states(record);
+}
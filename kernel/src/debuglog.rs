@@ -0,0 +1,44 @@
+//! 调试日志记录的格式
+//!
+//! 内核与用户态共用这里的定义。`SYS_DEBUGLOG_READ` 每次返回一条记录：
+//! [`LogRecordHeader`] 之后依次是进程名和消息，长度分别由 `name_len` 和 `message_len` 给出。
+
+/// 日志级别，与 `log::Level` 的数值相同
+pub const LOG_LEVEL_ERROR: u32 = 1;
+pub const LOG_LEVEL_WARN: u32 = 2;
+pub const LOG_LEVEL_INFO: u32 = 3;
+pub const LOG_LEVEL_DEBUG: u32 = 4;
+pub const LOG_LEVEL_TRACE: u32 = 5;
+
+/// `SYS_LOG` 的级别参数为 0 时按原样输出（`print!`），记录中按 INFO 保存
+pub const LOG_LEVEL_RAW: u32 = 0;
+
+/// 记录中进程名的最大长度，超出部分被截断
+pub const LOG_NAME_MAX: usize = 32;
+/// 记录中消息的最大长度，超出部分被截断
+pub const LOG_MESSAGE_MAX: usize = 256;
+/// 一条记录的最大长度，读取缓冲区不能小于这个值
+pub const LOG_RECORD_MAX: usize = size_of::<LogRecordHeader>() + LOG_NAME_MAX + LOG_MESSAGE_MAX;
+
+/// 创建读取器时的选项：跳过已有的记录，只读取之后写入的记录
+pub const DEBUGLOG_FROM_NOW: usize = 1 << 0;
+
+/// 日志记录头
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogRecordHeader {
+    /// 记录序号，从 0 开始连续递增
+    pub sequence: u64,
+    /// 写入时的单调时间（纳秒）
+    pub timestamp: u64,
+    /// 进程 ID，内核记录为 0
+    pub pid: u64,
+    /// 线程 ID，内核记录为 0
+    pub tid: u64,
+    /// 日志级别（`LOG_LEVEL_*`）
+    pub level: u32,
+    /// 上次读取之后因缓冲区被覆盖而丢失的记录数
+    pub dropped: u32,
+    pub name_len: u16,
+    pub message_len: u16,
+}
@@ -0,0 +1,29 @@
+//! 帧缓冲交接
+//!
+//! 内核与用户态共用这里的定义。`SYS_KRES_GET_FRAMEBUFFER` 把 Limine 提供的帧缓冲作为物理 VMO 交给显示服务，
+//! 同时写出一个 [`FramebufferInfo`] 描述它的几何信息和像素格式。
+
+/// 帧缓冲的几何信息和像素格式
+///
+/// 像素按 `bpp` 位存放，每个颜色分量在像素中占 `*_mask_size` 位、从第 `*_mask_shift` 位开始。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FramebufferInfo {
+    /// 宽度（像素）
+    pub width: u32,
+    /// 高度（像素）
+    pub height: u32,
+    /// 每行的字节数，可能大于 `width * bpp / 8`
+    pub pitch: u32,
+    /// 每个像素的位数
+    pub bpp: u32,
+    /// VMO 的大小（字节，页对齐）
+    pub size: u64,
+    pub red_mask_size: u8,
+    pub red_mask_shift: u8,
+    pub green_mask_size: u8,
+    pub green_mask_shift: u8,
+    pub blue_mask_size: u8,
+    pub blue_mask_shift: u8,
+    pub reserved: [u8; 2],
+}
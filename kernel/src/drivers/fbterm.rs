@@ -35,10 +35,13 @@ impl DrawTarget for Display {
     }
 }
 
+/// 终端使用的帧缓冲在 `FRAME_BUFFERS` 中的序号
+pub const FRAMEBUFFER_INDEX: usize = 0;
+
 impl Default for Display {
     fn default() -> Self {
         let frame_buffers = FRAME_BUFFERS.lock();
-        let frame_buffer = frame_buffers.iter().next().unwrap();
+        let frame_buffer = &frame_buffers[FRAMEBUFFER_INDEX];
 
         let shifts = (
             frame_buffer.red_mask_shift as u8,
@@ -68,13 +71,31 @@ pub static TERMINAL: Lazy<Mutex<Terminal<Display>>> = Lazy::new(|| {
 
 pub static TERMINAL_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
+/// 帧缓冲已经交给用户态的显示服务，终端不再绘制
+static RELEASED: AtomicBool = AtomicBool::new(false);
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
-    if TERMINAL_INITIALIZED.load(core::sync::atomic::Ordering::SeqCst) {
+    if TERMINAL_INITIALIZED.load(core::sync::atomic::Ordering::SeqCst)
+        && !RELEASED.load(core::sync::atomic::Ordering::SeqCst)
+    {
         TERMINAL.lock().write_fmt(args).unwrap();
     }
 }
 
+/// 把帧缓冲交给用户态，返回前等待正在进行的绘制结束
+pub fn release() {
+    RELEASED.store(true, core::sync::atomic::Ordering::SeqCst);
+    if TERMINAL_INITIALIZED.load(core::sync::atomic::Ordering::SeqCst) {
+        drop(TERMINAL.lock());
+    }
+}
+
+/// panic 时重新接管帧缓冲，覆盖用户态绘制的内容
+pub fn reclaim() {
+    RELEASED.store(false, core::sync::atomic::Ordering::SeqCst);
+}
+
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => (
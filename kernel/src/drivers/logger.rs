@@ -1,16 +1,18 @@
 use log::{Level, Record, set_logger, set_max_level};
 use log::{LevelFilter, Log, Metadata};
 
+use crate::{cmdline, object::debuglog};
+
 pub fn init() {
     set_logger(&Logger).unwrap();
-    set_max_level(LevelFilter::Trace);
+    set_max_level(cmdline::options().log_level);
 }
 
 macro_rules! log_output {
     ($color:expr, $level:expr, $args:expr, $($extra:tt)*) => {
-        let content = format!("[{}] {}{}", format_args!("\x1b[{}m{}\x1b[0m", $color, $level), $args, format_args!($($extra)*));
-        crate::serial_println!("{}", content);
-        crate::println!("{}", content);
+        // 直接格式化到各个输出，不经过堆：panic 时被停下的 CPU 可能正持有堆锁
+        crate::serial_println!("[\x1b[{}m{}\x1b[0m] {}{}", $color, $level, $args, format_args!($($extra)*));
+        crate::println!("[\x1b[{}m{}\x1b[0m] {}{}", $color, $level, $args, format_args!($($extra)*));
     };
 }
 
@@ -46,6 +48,7 @@ impl Log for Logger {
         if self.enabled(record.metadata()) {
             let with_location = matches!(record.level(), Level::Debug);
             self.log_message(record, with_location);
+            debuglog::write_kernel(record.level() as u32, *record.args());
         }
     }
 
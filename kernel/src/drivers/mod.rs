@@ -2,4 +2,7 @@ pub mod acpi;
 pub mod fbterm;
 pub mod framebuffer;
 pub mod logger;
+#[cfg(target_arch = "x86_64")]
 pub mod ns16550;
+#[cfg(target_arch = "riscv64")]
+pub mod sbi_console;
@@ -1,15 +1,125 @@
 use core::fmt::Write;
-use spin::{Lazy, Mutex};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use acpi::{
+    address::AddressSpace,
+    sdt::spcr::{Spcr, SpcrInterfaceType},
+};
+use spin::{Lazy, Mutex, Once};
 use uart_16550::SerialPort;
 
-pub static SERIAL: Lazy<Mutex<SerialPort>> = Lazy::new(|| {
-    let mut serial_port = unsafe { SerialPort::new(0x3f8) };
+use crate::{cmdline, drivers::acpi::ACPI_TABLES, serial::SerialConsoleInfo};
+
+/// 既没有 `console=` 也没有 SPCR 表时使用 ttyS0
+const DEFAULT_PORT: u16 = cmdline::SERIAL_PORTS[0];
+
+/// 启动早期使用的端口，SPCR 表要等 ACPI 表可用后才能读取
+fn early_port() -> u16 {
+    cmdline::options().console_port.unwrap_or(DEFAULT_PORT)
+}
+
+pub static SERIAL: Lazy<Mutex<SerialPort>> = Lazy::new(|| Mutex::new(open(early_port())));
+
+/// 最终选定的串口控制台
+static CONSOLE: Once<SerialConsoleInfo> = Once::new();
+
+/// 串口已经交给用户态的 UART 驱动，不再输出日志
+static RELEASED: AtomicBool = AtomicBool::new(false);
+
+fn open(port: u16) -> SerialPort {
+    let mut serial_port = unsafe { SerialPort::new(port) };
     serial_port.init();
-    Mutex::new(serial_port)
-});
+    serial_port
+}
+
+/// PC 标准串口的中断号：ttyS1 和 ttyS3 为 3，其余为 4
+fn legacy_irq(port: u16) -> u8 {
+    match port {
+        0x2f8 | 0x2e8 => 3,
+        _ => 4,
+    }
+}
+
+/// 读取 SPCR 表描述的串口，只支持 I/O 端口上的 16550 兼容串口
+fn from_spcr() -> Option<SerialConsoleInfo> {
+    let tables = ACPI_TABLES.lock();
+    let spcr = tables.as_ref()?.find_table::<Spcr>()?;
+    if !matches!(
+        spcr.interface_type(),
+        SpcrInterfaceType::Full16550
+            | SpcrInterfaceType::Full16450
+            | SpcrInterfaceType::Generic16550
+    ) {
+        return None;
+    }
+
+    let address = spcr.base_address()?.ok()?;
+    if address.address_space != AddressSpace::SystemIo {
+        return None;
+    }
+    let port = u16::try_from(address.address).ok()?;
+    let irq = spcr
+        .irq()
+        .or_else(|| u8::try_from(spcr.global_system_interrupt()?).ok())
+        .unwrap_or_else(|| legacy_irq(port));
+
+    Some(SerialConsoleInfo {
+        port,
+        irq,
+        reserved: 0,
+        baud_rate: spcr.baud_rate().map_or(0, |baud| baud.get()),
+    })
+}
+
+/// 确定串口控制台，需要在 ACPI 表可用之后调用
+///
+/// 命令行指定了 `console=` 时使用它，否则使用 SPCR 表描述的串口，都没有时使用 ttyS0。
+/// 选定的端口与启动早期使用的不同时切换过去。
+pub fn init_console() {
+    let console = CONSOLE.call_once(|| {
+        let spcr = cmdline::options()
+            .console_port
+            .is_none()
+            .then(from_spcr)
+            .flatten();
+        spcr.unwrap_or_else(|| SerialConsoleInfo {
+            port: early_port(),
+            irq: legacy_irq(early_port()),
+            reserved: 0,
+            baud_rate: 0,
+        })
+    });
+
+    if console.port != early_port() {
+        log::info!("Switching serial console to {:#x}", console.port);
+        *SERIAL.lock() = open(console.port);
+    }
+}
+
+/// 串口控制台的位置，`init_console` 之前为 None
+pub fn console() -> Option<SerialConsoleInfo> {
+    CONSOLE.get().copied()
+}
+
+/// 把串口控制台交给用户态，返回前等待正在进行的输出结束
+pub fn release() {
+    RELEASED.store(true, Ordering::SeqCst);
+    drop(SERIAL.lock());
+}
+
+/// panic 时重新接管串口，UART 驱动可能改过线路参数，按内核的设置重新初始化
+pub fn reclaim() {
+    if RELEASED.swap(false, Ordering::SeqCst) {
+        let port = CONSOLE.get().map_or(early_port(), |console| console.port);
+        *SERIAL.lock() = open(port);
+    }
+}
 
 #[doc(hidden)]
 pub fn _print(args: core::fmt::Arguments) {
+    if RELEASED.load(Ordering::SeqCst) {
+        return;
+    }
     x86_64::instructions::interrupts::without_interrupts(|| {
         let _ = SERIAL.lock().write_fmt(args);
     });
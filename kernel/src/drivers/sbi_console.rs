@@ -0,0 +1,45 @@
+//! 通过 SBI 调试控制台输出日志
+//!
+//! RISC-V 上由固件代为访问串口，内核不直接驱动 UART，也不把串口交给用户态。
+
+use core::fmt::{self, Write};
+
+use spin::Mutex;
+
+use crate::arch::{CurrentTlbArch, drivers::sbi, tlb::TlbArch};
+
+pub struct SbiConsole;
+
+impl Write for SbiConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                sbi::console_write_byte(b'\r');
+            }
+            sbi::console_write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+pub static SERIAL: Mutex<SbiConsole> = Mutex::new(SbiConsole);
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    let enabled = CurrentTlbArch::irq_save();
+    let _ = SERIAL.lock().write_fmt(args);
+    CurrentTlbArch::irq_restore(enabled);
+}
+
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => (
+        $crate::drivers::sbi_console::_print(format_args!($($arg)*))
+    );
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
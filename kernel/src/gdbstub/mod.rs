@@ -0,0 +1,651 @@
+//! 内核 GDB 远程调试桩
+//!
+//! 命令行带有 `gdb` 时在调试串口上提供 GDB 远程串行协议，`gdb=wait` 会在启动时停下等待 GDB 连接。
+//! 断点、单步、GDB 的中断请求（Ctrl-C）和 panic 都会进入调试器。进入时用 NMI 停下其他 CPU，
+//! 每个 CPU 对应 GDB 中的一个线程，线程号为 CPU 硬件编号加一。
+//!
+//! 在 QEMU 中使用 `make run GDBSTUB=1` 把调试串口转发到 TCP 4444 端口，然后 `target remote :4444`。
+
+mod packet;
+
+use core::fmt::Write;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use crate::{
+    arch::{
+        CurrentDebugArch, CurrentTimeArch, CurrentTlbArch, Ptrace, current_task_ptr,
+        debug::DebugArch, irq::IrqRegsArch, send_nmi_to_others, time::TimeArch, tlb::TlbArch,
+    },
+    cmdline,
+    memory::{direct_map, tlb::MAX_CPUS},
+    object::interrupt,
+    smp::CPU_COUNT,
+    task::Task,
+};
+
+use self::packet::{BREAK_REQUEST, PACKET_MAX, Response, decode_hex, parse_hex};
+
+/// 最多同时设置的软件断点数
+const MAX_BREAKPOINTS: usize = 32;
+/// 断点指令的最大长度
+const BREAKPOINT_MAX: usize = 4;
+/// 等待其他 CPU 停下的时间
+const STOP_TIMEOUT_NS: u64 = 100_000_000;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGABRT: u8 = 6;
+
+/// 进入调试器的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    /// 断点指令
+    Breakpoint,
+    /// 单步完成
+    SingleStep,
+    /// GDB 发来的中断请求
+    BreakRequest,
+}
+
+#[derive(Clone, Copy)]
+struct Breakpoint {
+    address: usize,
+    original: [u8; BREAKPOINT_MAX],
+}
+
+/// 从断点处恢复执行时，先移除断点单步执行原指令，单步完成后再放回断点
+#[derive(Clone, Copy)]
+struct StepOver {
+    cpu: usize,
+    address: usize,
+    /// 单步完成后继续运行，否则把单步结果报告给 GDB
+    resume: bool,
+}
+
+enum Action {
+    Continue,
+    Step,
+    Detach,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// GDB 在等待停止通知（发出了 c 或 s）
+static WAITING: AtomicBool = AtomicBool::new(false);
+/// 持有调试器的 CPU 编号加一，0 表示没有
+static OWNER: AtomicUsize = AtomicUsize::new(0);
+/// 每次恢复执行时递增，停下的 CPU 看到变化后返回
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// 已经停下的其他 CPU 数
+static PARKED_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// 停在调试器中的 CPU 的中断现场
+static PARKED_REGS: [AtomicPtr<Ptrace>; MAX_CPUS] =
+    [const { AtomicPtr::new(null_mut()) }; MAX_CPUS];
+/// 停在调试器中的 CPU 当时运行的任务
+static PARKED_TASKS: [AtomicPtr<Task>; MAX_CPUS] = [const { AtomicPtr::new(null_mut()) }; MAX_CPUS];
+
+static BREAKPOINTS: Mutex<[Option<Breakpoint>; MAX_BREAKPOINTS]> =
+    Mutex::new([None; MAX_BREAKPOINTS]);
+static STEP_OVER: Mutex<Option<StepOver>> = Mutex::new(None);
+
+/// 会话使用的缓冲区，只有持有调试器的 CPU 访问
+struct Session {
+    request: [u8; PACKET_MAX],
+    response: Response,
+    /// `g`、`G` 等命令操作的 CPU
+    selected: usize,
+}
+
+static SESSION: Mutex<Session> = Mutex::new(Session {
+    request: [0; PACKET_MAX],
+    response: Response::new(),
+    selected: 0,
+});
+
+/// 根据命令行初始化调试串口
+pub fn init() {
+    let Some(mode) = cmdline::get("gdb") else {
+        return;
+    };
+    if !CurrentDebugArch::serial_init() {
+        warn!("GDB stub requested but the debug serial port is missing");
+        return;
+    }
+    CurrentDebugArch::serial_enable_irq();
+    // 调试串口的中断不能再交给用户态驱动
+    interrupt::reserve(CurrentDebugArch::SERIAL_IRQ);
+    ENABLED.store(true, Ordering::SeqCst);
+    info!("GDB stub listening on the debug serial port");
+
+    if mode == "wait" {
+        info!("Waiting for GDB to attach");
+        CurrentDebugArch::breakpoint();
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 是否有 CPU 持有调试器，此时收到的 NMI 用于停下当前 CPU
+pub fn stopping() -> bool {
+    OWNER.load(Ordering::Acquire) != 0
+}
+
+/// 调试串口中断：收到 GDB 的中断请求时进入调试器
+pub fn handle_serial_irq(regs: &mut Ptrace) {
+    let mut requested = false;
+    while let Some(byte) = CurrentDebugArch::serial_read() {
+        requested |= byte == BREAK_REQUEST;
+    }
+    if requested {
+        handle_trap(regs, Trap::BreakRequest);
+    }
+}
+
+/// 处理断点和单步异常，返回是否已经处理
+///
+/// 调试器未开启或异常来自用户态时返回 false，由调用者按普通异常处理。
+pub fn handle_trap(regs: &mut Ptrace, trap: Trap) -> bool {
+    if !enabled() || regs.is_user_mode() {
+        return false;
+    }
+    let cpu = CurrentTlbArch::current_cpu();
+
+    let signal = match trap {
+        Trap::Breakpoint => {
+            // 停在断点指令处，而不是它之后
+            let address = regs.get_ip() as usize - CurrentDebugArch::BREAKPOINT.len();
+            if find_breakpoint(address).is_some() {
+                regs.set_ip(address as u64);
+            }
+            SIGTRAP
+        }
+        Trap::SingleStep => {
+            let step_over = {
+                let mut step_over = STEP_OVER.lock();
+                step_over.take_if(|step| step.cpu == cpu)
+            };
+            CurrentDebugArch::set_single_step(regs, false);
+            if let Some(step) = step_over {
+                if let Some(breakpoint) = find_breakpoint(step.address) {
+                    let _ = write_memory(breakpoint.address, CurrentDebugArch::BREAKPOINT);
+                }
+                if step.resume {
+                    return true;
+                }
+            }
+            SIGTRAP
+        }
+        Trap::BreakRequest => SIGINT,
+    };
+
+    enter(regs, signal);
+    true
+}
+
+/// panic 时进入调试器，GDB 恢复执行后返回
+///
+/// 其他 CPU 此时已经被 panic 处理停下，通过 [`register_halted_cpu`] 登记。
+pub fn handle_panic(fault_regs: Option<Ptrace>) {
+    if !enabled() {
+        return;
+    }
+    let cpu = CurrentTlbArch::current_cpu();
+    if OWNER
+        .compare_exchange(0, cpu + 1, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        // 调试器自身出错，不能再次进入
+        return;
+    }
+
+    let mut regs = fault_regs.unwrap_or_else(|| {
+        let mut regs = Ptrace::default();
+        CurrentDebugArch::capture_current(&mut regs);
+        regs
+    });
+    park_self(cpu, &mut regs);
+    error!("Kernel panicked, waiting for GDB on the debug serial port");
+    session(cpu, SIGABRT);
+}
+
+/// panic 时被 NMI 停下的 CPU 登记自己的现场，之后不会再运行
+pub fn register_halted_cpu(cpu: usize, regs: &mut Ptrace) {
+    if cpu < MAX_CPUS {
+        park_self(cpu, regs);
+    }
+}
+
+/// 在 NMI 中调用：其他 CPU 持有调试器，停在这里直到恢复执行
+pub fn park_current_cpu(regs: &mut Ptrace) {
+    let cpu = CurrentTlbArch::current_cpu();
+    if cpu >= MAX_CPUS || OWNER.load(Ordering::Acquire) == cpu + 1 {
+        return;
+    }
+
+    let generation = GENERATION.load(Ordering::Acquire);
+    park_self(cpu, regs);
+    PARKED_COUNT.fetch_add(1, Ordering::AcqRel);
+    while GENERATION.load(Ordering::Acquire) == generation {
+        core::hint::spin_loop();
+    }
+    PARKED_COUNT.fetch_sub(1, Ordering::AcqRel);
+}
+
+fn park_self(cpu: usize, regs: &mut Ptrace) {
+    PARKED_TASKS[cpu].store(current_task_ptr() as *mut Task, Ordering::Release);
+    PARKED_REGS[cpu].store(regs, Ordering::Release);
+}
+
+/// 停下其他 CPU，与 GDB 交互，然后按 GDB 的命令恢复执行
+fn enter(regs: &mut Ptrace, signal: u8) {
+    let cpu = CurrentTlbArch::current_cpu();
+    while OWNER
+        .compare_exchange(0, cpu + 1, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        // 其他 CPU 正在调试，它的 NMI 会让当前 CPU 停下
+        core::hint::spin_loop();
+    }
+
+    send_nmi_to_others();
+    let others = CPU_COUNT.load(Ordering::SeqCst).saturating_sub(1);
+    let start = CurrentTimeArch::nano_time();
+    while PARKED_COUNT.load(Ordering::Acquire) < others
+        && CurrentTimeArch::nano_time().saturating_sub(start) < STOP_TIMEOUT_NS
+    {
+        core::hint::spin_loop();
+    }
+
+    park_self(cpu, regs);
+    let action = session(cpu, signal);
+
+    match action {
+        Action::Continue | Action::Detach => resume(cpu, regs, false),
+        Action::Step => resume(cpu, regs, true),
+    }
+
+    for (parked_regs, task) in PARKED_REGS.iter().zip(PARKED_TASKS.iter()) {
+        parked_regs.store(null_mut(), Ordering::Release);
+        task.store(null_mut(), Ordering::Release);
+    }
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+    OWNER.store(0, Ordering::Release);
+}
+
+/// 设置恢复执行时的单步状态，当前位置有断点时先越过它
+fn resume(cpu: usize, regs: &mut Ptrace, step: bool) {
+    let address = regs.get_ip() as usize;
+    match find_breakpoint(address) {
+        Some(breakpoint) => {
+            let length = CurrentDebugArch::BREAKPOINT.len();
+            let _ = write_memory(address, &breakpoint.original[..length]);
+            *STEP_OVER.lock() = Some(StepOver {
+                cpu,
+                address,
+                resume: !step,
+            });
+            CurrentDebugArch::set_single_step(regs, true);
+        }
+        None => CurrentDebugArch::set_single_step(regs, step),
+    }
+}
+
+fn find_breakpoint(address: usize) -> Option<Breakpoint> {
+    BREAKPOINTS
+        .lock()
+        .iter()
+        .flatten()
+        .find(|breakpoint| breakpoint.address == address)
+        .copied()
+}
+
+fn insert_breakpoint(address: usize) -> bool {
+    let mut breakpoints = BREAKPOINTS.lock();
+    if breakpoints.iter().flatten().any(|bp| bp.address == address) {
+        return true;
+    }
+    let Some(slot) = breakpoints.iter_mut().find(|slot| slot.is_none()) else {
+        return false;
+    };
+
+    let length = CurrentDebugArch::BREAKPOINT.len();
+    let mut original = [0; BREAKPOINT_MAX];
+    if read_memory(address, &mut original[..length]) != length
+        || !write_memory(address, CurrentDebugArch::BREAKPOINT)
+    {
+        return false;
+    }
+    *slot = Some(Breakpoint { address, original });
+    true
+}
+
+fn remove_breakpoint(address: usize) -> bool {
+    let mut breakpoints = BREAKPOINTS.lock();
+    let Some(slot) = breakpoints
+        .iter_mut()
+        .find(|slot| slot.is_some_and(|bp| bp.address == address))
+    else {
+        return false;
+    };
+    let breakpoint = slot.take().unwrap();
+    write_memory(
+        address,
+        &breakpoint.original[..CurrentDebugArch::BREAKPOINT.len()],
+    )
+}
+
+fn remove_all_breakpoints() {
+    let mut breakpoints = BREAKPOINTS.lock();
+    for slot in breakpoints.iter_mut() {
+        if let Some(breakpoint) = slot.take() {
+            let _ = write_memory(
+                breakpoint.address,
+                &breakpoint.original[..CurrentDebugArch::BREAKPOINT.len()],
+            );
+        }
+    }
+}
+
+/// 读取内存，遇到未映射的页面时停止，返回读取的字节数
+fn read_memory(address: usize, buf: &mut [u8]) -> usize {
+    for (i, byte) in buf.iter_mut().enumerate() {
+        match address.checked_add(i).and_then(direct_map) {
+            Some(ptr) => *byte = unsafe { ptr.read_volatile() },
+            None => return i,
+        }
+    }
+    buf.len()
+}
+
+fn write_memory(address: usize, data: &[u8]) -> bool {
+    // 先检查所有页面，避免写入一半
+    if (0..data.len()).any(|i| address.checked_add(i).and_then(direct_map).is_none()) {
+        return false;
+    }
+    for (i, &byte) in data.iter().enumerate() {
+        let ptr = direct_map(address + i).unwrap();
+        unsafe { ptr.write_volatile(byte) };
+    }
+    true
+}
+
+fn thread_id(cpu: usize) -> usize {
+    cpu + 1
+}
+
+/// 解析线程号，0 和 -1 表示当前 CPU
+fn parse_thread(owner: usize, id: &[u8]) -> Option<usize> {
+    if id == b"-1" || id == b"0" {
+        return Some(owner);
+    }
+    let cpu = parse_hex(id)?.checked_sub(1)?;
+    (cpu < MAX_CPUS && !PARKED_REGS[cpu].load(Ordering::Acquire).is_null()).then_some(cpu)
+}
+
+fn parked_regs(cpu: usize) -> Option<&'static mut Ptrace> {
+    unsafe { PARKED_REGS[cpu].load(Ordering::Acquire).as_mut() }
+}
+
+fn stop_reply(response: &mut Response, cpu: usize, signal: u8) {
+    let _ = write!(response, "T{:02x}thread:{:x};", signal, thread_id(cpu));
+}
+
+/// 与 GDB 交互直到收到恢复执行的命令
+fn session(owner: usize, signal: u8) -> Action {
+    let mut session = SESSION.lock();
+    let Session {
+        request,
+        response,
+        selected,
+    } = &mut *session;
+    *selected = owner;
+
+    if WAITING.swap(false, Ordering::AcqRel) {
+        response.clear();
+        stop_reply(response, owner, signal);
+        packet::send(response.as_bytes());
+    }
+
+    loop {
+        let len = packet::receive(request);
+        let request = &request[..len];
+        response.clear();
+
+        let action = handle_command(request, response, owner, selected, signal);
+        if let Some(action) = action {
+            if !matches!(action, Action::Detach) {
+                WAITING.store(true, Ordering::Release);
+            }
+            return action;
+        }
+        packet::send(response.as_bytes());
+    }
+}
+
+/// 处理一条命令，恢复执行的命令返回对应的动作，其余命令把回复写入 `response`
+///
+/// 不支持的命令回复空包。
+fn handle_command(
+    request: &[u8],
+    response: &mut Response,
+    owner: usize,
+    selected: &mut usize,
+    signal: u8,
+) -> Option<Action> {
+    let Some((&command, args)) = request.split_first() else {
+        return None;
+    };
+
+    match command {
+        b'?' => stop_reply(response, owner, signal),
+        b'g' => {
+            if let Some(regs) = parked_regs(*selected) {
+                let mut buf = [0u8; 8];
+                for index in 0..CurrentDebugArch::REGISTER_COUNT {
+                    let size = CurrentDebugArch::read_register(regs, index, &mut buf).unwrap();
+                    response.push_hex(&buf[..size]);
+                }
+            } else {
+                response.push(b"E01");
+            }
+        }
+        b'G' => {
+            let ok = parked_regs(*selected).is_some_and(|regs| write_registers(regs, args));
+            response.push(if ok { b"OK" } else { b"E01" });
+        }
+        b'p' => {
+            let mut buf = [0u8; 8];
+            match parse_hex(args)
+                .zip(parked_regs(*selected))
+                .and_then(|(index, regs)| CurrentDebugArch::read_register(regs, index, &mut buf))
+            {
+                Some(size) => response.push_hex(&buf[..size]),
+                None => response.push(b"E01"),
+            }
+        }
+        b'P' => {
+            let mut buf = [0u8; 8];
+            let ok = args
+                .iter()
+                .position(|&byte| byte == b'=')
+                .and_then(|eq| {
+                    let index = parse_hex(&args[..eq])?;
+                    let size = decode_hex(&args[eq + 1..], &mut buf)?;
+                    let regs = parked_regs(*selected)?;
+                    Some(CurrentDebugArch::write_register(regs, index, &buf[..size]))
+                })
+                .unwrap_or(false);
+            response.push(if ok { b"OK" } else { b"E01" });
+        }
+        b'm' => match parse_address_length(args) {
+            Some((address, length)) => {
+                let mut buf = [0u8; 256];
+                let mut done = 0;
+                let length = length.min(response.remaining() / 2);
+                while done < length {
+                    let chunk = (length - done).min(buf.len());
+                    let read = read_memory(address.wrapping_add(done), &mut buf[..chunk]);
+                    response.push_hex(&buf[..read]);
+                    done += read;
+                    if read < chunk {
+                        break;
+                    }
+                }
+                if done == 0 && length != 0 {
+                    response.clear();
+                    response.push(b"E14");
+                }
+            }
+            None => response.push(b"E01"),
+        },
+        b'M' => {
+            let ok = args
+                .iter()
+                .position(|&byte| byte == b':')
+                .and_then(|colon| {
+                    let (address, length) = parse_address_length(&args[..colon])?;
+                    let data = &args[colon + 1..];
+                    if data.len() != length * 2 {
+                        return None;
+                    }
+                    let mut buf = [0u8; 256];
+                    for (i, chunk) in data.chunks(buf.len() * 2).enumerate() {
+                        let size = decode_hex(chunk, &mut buf)?;
+                        if !write_memory(address.wrapping_add(i * buf.len()), &buf[..size]) {
+                            return None;
+                        }
+                    }
+                    Some(())
+                })
+                .is_some();
+            response.push(if ok { b"OK" } else { b"E14" });
+        }
+        b'Z' | b'z' => {
+            // 只支持软件断点
+            if let Some(address) = args
+                .strip_prefix(b"0,")
+                .and_then(|rest| rest.split(|&byte| byte == b',').next())
+                .and_then(parse_hex)
+            {
+                let ok = if command == b'Z' {
+                    insert_breakpoint(address)
+                } else {
+                    remove_breakpoint(address)
+                };
+                response.push(if ok { b"OK" } else { b"E01" });
+            }
+        }
+        b'c' | b's' => {
+            let regs = parked_regs(owner).unwrap();
+            if let Some(address) = parse_hex(args) {
+                regs.set_ip(address as u64);
+            }
+            return Some(if command == b'c' {
+                Action::Continue
+            } else {
+                Action::Step
+            });
+        }
+        b'D' => {
+            remove_all_breakpoints();
+            packet::send(b"OK");
+            return Some(Action::Detach);
+        }
+        b'k' => {
+            remove_all_breakpoints();
+            return Some(Action::Detach);
+        }
+        b'H' => {
+            // Hg 选择寄存器操作的线程；Hc 选择恢复执行的线程，这里总是恢复所有 CPU
+            match args.split_first() {
+                Some((&b'g', id)) => match parse_thread(owner, id) {
+                    Some(cpu) => {
+                        *selected = cpu;
+                        response.push(b"OK");
+                    }
+                    None => response.push(b"E01"),
+                },
+                Some((_, _)) => response.push(b"OK"),
+                None => response.push(b"E01"),
+            }
+        }
+        b'T' => {
+            let alive = parse_thread(owner, args).is_some();
+            response.push(if alive { b"OK" } else { b"E01" });
+        }
+        b'q' => handle_query(args, response, owner),
+        _ => {}
+    }
+    None
+}
+
+fn handle_query(query: &[u8], response: &mut Response, owner: usize) {
+    if query.starts_with(b"Supported") {
+        let _ = write!(response, "PacketSize={:x}", PACKET_MAX);
+    } else if query == b"Attached" {
+        response.push(b"1");
+    } else if query == b"C" {
+        let _ = write!(response, "QC{:x}", thread_id(owner));
+    } else if query == b"fThreadInfo" {
+        response.push(b"m");
+        let mut first = true;
+        for cpu in (0..MAX_CPUS).filter(|&cpu| parked_regs(cpu).is_some()) {
+            if !first {
+                response.push(b",");
+            }
+            first = false;
+            let _ = write!(response, "{:x}", thread_id(cpu));
+        }
+    } else if query == b"sThreadInfo" {
+        response.push(b"l");
+    } else if let Some(id) = query.strip_prefix(b"ThreadExtraInfo,") {
+        let Some(cpu) = parse_thread(owner, id) else {
+            response.push(b"E01");
+            return;
+        };
+        let mut info = HexWriter(response);
+        let _ = write!(info, "CPU {}", cpu);
+        let task = PARKED_TASKS[cpu].load(Ordering::Acquire);
+        if let Some(task) = unsafe { task.as_ref() } {
+            let _ = write!(info, ": tid {} ({})", task.tid(), task.name());
+        }
+    }
+}
+
+/// 以十六进制写入格式化输出
+struct HexWriter<'a>(&'a mut Response);
+
+impl Write for HexWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.push_hex(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// 解析 `地址,长度`
+fn parse_address_length(args: &[u8]) -> Option<(usize, usize)> {
+    let comma = args.iter().position(|&byte| byte == b',')?;
+    Some((parse_hex(&args[..comma])?, parse_hex(&args[comma + 1..])?))
+}
+
+fn write_registers(regs: &mut Ptrace, mut hex: &[u8]) -> bool {
+    let mut buf = [0u8; 8];
+    for index in 0..CurrentDebugArch::REGISTER_COUNT {
+        let Some(size) = CurrentDebugArch::read_register(regs, index, &mut buf) else {
+            return false;
+        };
+        if hex.len() < size * 2 {
+            break;
+        }
+        if decode_hex(&hex[..size * 2], &mut buf).is_none() {
+            return false;
+        }
+        CurrentDebugArch::write_register(regs, index, &buf[..size]);
+        hex = &hex[size * 2..];
+    }
+    true
+}
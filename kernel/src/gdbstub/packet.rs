@@ -0,0 +1,160 @@
+//! GDB 远程串行协议的包格式：`$内容#校验和`，校验和为内容各字节之和的低 8 位
+
+use core::fmt;
+
+use crate::arch::{CurrentDebugArch, debug::DebugArch};
+
+/// 包的最大长度，通过 qSupported 告知 GDB
+pub const PACKET_MAX: usize = 4096;
+
+/// GDB 请求中断运行中的目标
+pub const BREAK_REQUEST: u8 = 0x03;
+
+/// 发送失败时最多重传的次数
+const MAX_RETRANSMITS: usize = 8;
+
+fn read_byte() -> u8 {
+    loop {
+        if let Some(byte) = CurrentDebugArch::serial_read() {
+            return byte;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+pub fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// 解析十六进制数
+pub fn parse_hex(hex: &[u8]) -> Option<usize> {
+    if hex.is_empty() || hex.len() > size_of::<usize>() * 2 {
+        return None;
+    }
+    hex.iter().try_fold(0usize, |value, &byte| {
+        Some(value << 4 | hex_value(byte)? as usize)
+    })
+}
+
+/// 把十六进制字符串解码到 `out`，返回字节数
+pub fn decode_hex(hex: &[u8], out: &mut [u8]) -> Option<usize> {
+    if !hex.len().is_multiple_of(2) || hex.len() / 2 > out.len() {
+        return None;
+    }
+    for (i, pair) in hex.chunks_exact(2).enumerate() {
+        out[i] = hex_value(pair[0])? << 4 | hex_value(pair[1])?;
+    }
+    Some(hex.len() / 2)
+}
+
+/// 接收一个包，返回内容长度
+///
+/// 包之外的字节（包括停下时收到的中断请求）被忽略，校验和错误时请求 GDB 重传。
+pub fn receive(buf: &mut [u8; PACKET_MAX]) -> usize {
+    loop {
+        while read_byte() != b'$' {}
+
+        let mut len = 0;
+        let mut sum = 0u8;
+        let mut overflow = false;
+        loop {
+            let byte = read_byte();
+            if byte == b'#' {
+                break;
+            }
+            sum = sum.wrapping_add(byte);
+            if len < PACKET_MAX {
+                buf[len] = byte;
+                len += 1;
+            } else {
+                overflow = true;
+            }
+        }
+
+        let high = hex_value(read_byte());
+        let low = hex_value(read_byte());
+        let valid = matches!((high, low), (Some(high), Some(low)) if high << 4 | low == sum);
+        if valid && !overflow {
+            CurrentDebugArch::serial_write(b'+');
+            return len;
+        }
+        CurrentDebugArch::serial_write(b'-');
+    }
+}
+
+/// 发送一个包并等待 GDB 确认
+pub fn send(data: &[u8]) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+
+    for _ in 0..MAX_RETRANSMITS {
+        CurrentDebugArch::serial_write(b'$');
+        for &byte in data {
+            CurrentDebugArch::serial_write(byte);
+        }
+        CurrentDebugArch::serial_write(b'#');
+        CurrentDebugArch::serial_write(HEX[(sum >> 4) as usize]);
+        CurrentDebugArch::serial_write(HEX[(sum & 0xf) as usize]);
+
+        loop {
+            match read_byte() {
+                b'+' => return,
+                b'-' => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// 回复包的缓冲区，超出长度的内容被丢弃
+pub struct Response {
+    buf: [u8; PACKET_MAX],
+    len: usize,
+}
+
+impl Response {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; PACKET_MAX],
+            len: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// 剩余空间能容纳的字节数
+    pub fn remaining(&self) -> usize {
+        PACKET_MAX - self.len
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        let n = data.len().min(self.remaining());
+        self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+        self.len += n;
+    }
+
+    /// 以十六进制写入字节
+    pub fn push_hex(&mut self, data: &[u8]) {
+        for byte in data {
+            let _ = fmt::Write::write_fmt(self, format_args!("{:02x}", byte));
+        }
+    }
+}
+
+impl fmt::Write for Response {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
@@ -1,14 +1,116 @@
-use crate::{arch::CurrentRmmArch, init::memory::PAGE_SIZE};
-use linked_list_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{NonNull, null_mut};
+
+use crate::{
+    arch::CurrentRmmArch,
+    init::memory::{PAGE_SIZE, align_up},
+    memory::{irq_mutex::IrqMutex, slab},
+};
+use linked_list_allocator::Heap;
 use rmm::{PageFlags, PageMapper, VirtualAddress};
 
 use crate::init::memory::FRAME_ALLOCATOR;
 
 #[global_allocator]
-pub static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+pub static HEAP_ALLOCATOR: KernelHeap = KernelHeap(IrqMutex::new(Heap::empty()));
 
 pub const KERNEL_HEAP_START: usize = 0xffffffff_c0000000;
+/// 启动时映射的大小
 pub const KERNEL_HEAP_SIZE: usize = 64 * 1024 * 1024;
+/// 堆最多扩展到的大小
+pub const KERNEL_HEAP_MAX_SIZE: usize = 512 * 1024 * 1024;
+/// 每次扩展的最小大小
+const KERNEL_HEAP_GROW_SIZE: usize = 4 * 1024 * 1024;
+
+/// 内核堆
+///
+/// 不超过 `slab::SLAB_MAX_SIZE` 的分配优先由 slab 分配器提供，其余来自链表分配器。
+/// 链表分配器空间不足时在堆顶映射新页面扩展，直到 `KERNEL_HEAP_MAX_SIZE`。
+/// 扩展失败时分配返回空指针：`try_reserve` 等可失败的接口会得到错误，
+/// 其余分配照常进入 alloc error handler。
+///
+/// 时钟中断等路径中也会分配，堆锁和物理页分配器锁持有期间都关闭中断。
+pub struct KernelHeap(IrqMutex<Heap>);
+
+impl KernelHeap {
+    /// 当前堆大小
+    pub fn size(&self) -> usize {
+        self.0.lock().size()
+    }
+
+    /// 已使用的字节数
+    pub fn used(&self) -> usize {
+        self.0.lock().used()
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if let Some(class) = slab::class_index(layout) {
+            let ptr = slab::alloc(class);
+            if !ptr.is_null() {
+                return ptr;
+            }
+            // slab 无法取得新页面时退回链表分配器
+        }
+
+        let mut heap = self.0.lock();
+
+        if let Ok(ptr) = heap.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+
+        // 对齐最多浪费 align 字节
+        grow(&mut heap, layout.size() + layout.align());
+
+        heap.allocate_first_fit(layout)
+            .map_or(null_mut(), |ptr| ptr.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // slab 对象位于直接映射区，不在堆的地址范围内
+        let in_heap =
+            (KERNEL_HEAP_START..KERNEL_HEAP_START + KERNEL_HEAP_MAX_SIZE).contains(&(ptr as usize));
+        if !in_heap && let Some(class) = slab::class_index(layout) {
+            unsafe { slab::free(class, ptr) };
+            return;
+        }
+
+        unsafe {
+            self.0
+                .lock()
+                .deallocate(NonNull::new_unchecked(ptr), layout)
+        };
+    }
+}
+
+/// 在堆顶映射至少 `min_size` 字节的新页面，物理内存不足时只扩展已映射的部分
+fn grow(heap: &mut Heap, min_size: usize) {
+    let top = heap.top() as usize;
+    let size = align_up(min_size.max(KERNEL_HEAP_GROW_SIZE))
+        .min(KERNEL_HEAP_START + KERNEL_HEAP_MAX_SIZE - top);
+
+    let mut mapped = 0;
+    {
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        let mut mapper =
+            unsafe { PageMapper::current(rmm::TableKind::Kernel, &mut *frame_allocator) };
+        let flags = PageFlags::<CurrentRmmArch>::new().write(true);
+
+        while mapped < size {
+            let virt = VirtualAddress::new(top + mapped);
+            let Some(flusher) = (unsafe { mapper.map(virt, flags) }) else {
+                break;
+            };
+            flusher.flush();
+            mapped += PAGE_SIZE;
+        }
+    }
+
+    if mapped != 0 {
+        unsafe { heap.extend(mapped) };
+    }
+}
 
 pub fn init() {
     let mut frame_allocator = FRAME_ALLOCATOR.lock();
@@ -22,6 +124,7 @@ pub fn init() {
 
     unsafe {
         HEAP_ALLOCATOR
+            .0
             .lock()
             .init(KERNEL_HEAP_START as *mut u8, KERNEL_HEAP_SIZE)
     };
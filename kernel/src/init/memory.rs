@@ -1,15 +1,16 @@
-use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{AtomicBool, AtomicUsize};
 
 use crate::{
     arch::{CurrentRmmArch, rmm::page_flags},
-    memory::DummyFrameAllocator,
+    crash::{CRASH_RECORD_PHYS, CRASH_RECORD_SIZE},
+    memory::{DummyFrameAllocator, irq_mutex::IrqMutex},
 };
 use limine::{memory_map::EntryType, request::MemoryMapRequest, response::MemoryMapResponse};
 use rmm::{
     Arch, BuddyAllocator, BumpAllocator, MemoryArea, PageMapper, PhysicalAddress, TableKind,
     VirtualAddress,
 };
-use spin::{Lazy, Mutex};
+use spin::Lazy;
 
 pub const PAGE_SIZE: usize = CurrentRmmArch::PAGE_SIZE;
 
@@ -37,6 +38,9 @@ pub fn align_down(x: usize) -> usize {
 
 pub static KERNEL_PAGE_TABLE_PHYS: AtomicUsize = AtomicUsize::new(0);
 
+/// 崩溃记录区域位于可用内存中，已经从帧分配器中扣除
+pub static CRASH_REGION_RESERVED: AtomicBool = AtomicBool::new(false);
+
 unsafe fn map_memory<A: Arch>(
     bump_allocator: &mut BumpAllocator<A>,
     memmap_response: &MemoryMapResponse,
@@ -79,19 +83,44 @@ unsafe fn map_memory<A: Arch>(
     KERNEL_PAGE_TABLE_PHYS.store(phys.data(), core::sync::atomic::Ordering::SeqCst);
 }
 
-pub static FRAME_ALLOCATOR: Lazy<Mutex<BuddyAllocator<CurrentRmmArch>>> = Lazy::new(|| {
+/// 物理页分配器，可能在中断处理中经由堆分配获取，持有期间关闭中断
+pub static FRAME_ALLOCATOR: Lazy<IrqMutex<BuddyAllocator<CurrentRmmArch>>> = Lazy::new(|| {
     let memmap_response = MEMMAP_REQUEST.get_response().unwrap();
 
     let areas = unsafe { crate::memory::AREAS.get().as_mut_unchecked() };
     let mut area_i = 0;
 
+    let crash_start = CRASH_RECORD_PHYS;
+    let crash_end = CRASH_RECORD_PHYS + CRASH_RECORD_SIZE;
+
     for area in memmap_response.entries().iter() {
         if area.entry_type == EntryType::USABLE {
+            let start = area.base as usize;
+            let end = start + area.length as usize;
+
+            // 崩溃记录区域需要在重启后保持原样，不交给分配器
+            if start <= crash_start && crash_end <= end {
+                for (start, end) in [(start, crash_start), (crash_end, end)] {
+                    if start < end {
+                        unsafe {
+                            add_memory(
+                                areas,
+                                &mut area_i,
+                                PhysicalAddress::new(start),
+                                end - start,
+                            );
+                        }
+                    }
+                }
+                CRASH_REGION_RESERVED.store(true, core::sync::atomic::Ordering::SeqCst);
+                continue;
+            }
+
             unsafe {
                 add_memory(
                     areas,
                     &mut area_i,
-                    PhysicalAddress::new(area.base as usize),
+                    PhysicalAddress::new(start),
                     area.length as usize,
                 );
             }
@@ -110,5 +139,5 @@ pub static FRAME_ALLOCATOR: Lazy<Mutex<BuddyAllocator<CurrentRmmArch>>> = Lazy::
     let buddy_allocator =
         unsafe { BuddyAllocator::new(bump_allocator) }.expect("Failed to init mm");
 
-    Mutex::new(buddy_allocator)
+    IrqMutex::new(buddy_allocator)
 });
@@ -0,0 +1,57 @@
+//! 内核事件跟踪（ktrace）的记录格式
+//!
+//! 内核与用户态共用这里的定义。`SYS_KTRACE_CONTROL` 的 `KTRACE_ACTION_READ`
+//! 返回连续的 [`KtraceRecord`]，各 CPU 的记录按时间戳合并前不保证有序。
+
+/// 事件分组，`KTRACE_ACTION_START` 的参数为要记录的分组掩码
+pub const KTRACE_GROUP_SYSCALL: u32 = 1 << 0;
+pub const KTRACE_GROUP_CONTEXT_SWITCH: u32 = 1 << 1;
+pub const KTRACE_GROUP_IRQ: u32 = 1 << 2;
+pub const KTRACE_GROUP_IPC: u32 = 1 << 3;
+pub const KTRACE_GROUP_ALL: u32 =
+    KTRACE_GROUP_SYSCALL | KTRACE_GROUP_CONTEXT_SWITCH | KTRACE_GROUP_IRQ | KTRACE_GROUP_IPC;
+
+/// 系统调用进入，args: [调用号, 0, 0]
+pub const KTRACE_EVENT_SYSCALL_ENTER: u16 = 1;
+/// 系统调用返回，args: [调用号, 耗时（纳秒）, 返回值]
+pub const KTRACE_EVENT_SYSCALL_EXIT: u16 = 2;
+/// 上下文切换，args: [切出的 TID, 切入的 TID, 切入的 PID]
+pub const KTRACE_EVENT_CONTEXT_SWITCH: u16 = 3;
+/// 中断进入，args: [向量号, 0, 0]
+pub const KTRACE_EVENT_IRQ_ENTER: u16 = 4;
+/// 中断返回，args: [向量号, 耗时（纳秒）, 0]
+pub const KTRACE_EVENT_IRQ_EXIT: u16 = 5;
+/// Port 投递事件包，args: [Port ID, key, 0]
+pub const KTRACE_EVENT_PORT_QUEUE: u16 = 6;
+/// Channel 发送消息，args: [接收端 ID, 数据字节数, 句柄数]
+pub const KTRACE_EVENT_CHANNEL_SEND: u16 = 7;
+/// Channel 接收消息，args: [接收端 ID, 数据字节数, 句柄数]
+pub const KTRACE_EVENT_CHANNEL_RECV: u16 = 8;
+/// 缓冲区已满，最旧的记录被覆盖，args: [丢失的记录数, 0, 0]
+pub const KTRACE_EVENT_DROPPED: u16 = 9;
+
+/// 开始记录，参数为分组掩码
+pub const KTRACE_ACTION_START: usize = 0;
+/// 停止记录，已有的记录保留
+pub const KTRACE_ACTION_STOP: usize = 1;
+/// 清空所有 CPU 的缓冲区
+pub const KTRACE_ACTION_REWIND: usize = 2;
+/// 取出缓冲区中的记录，返回写入的字节数
+pub const KTRACE_ACTION_READ: usize = 3;
+
+/// 一条跟踪记录
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KtraceRecord {
+    /// 单调时间（纳秒）
+    pub timestamp: u64,
+    /// 事件发生时当前 CPU 上运行的进程，内核任务为 0
+    pub pid: u32,
+    pub tid: u32,
+    /// 事件类型（`KTRACE_EVENT_*`）
+    pub event: u16,
+    /// CPU 的硬件编号
+    pub cpu: u16,
+    pub reserved: u32,
+    pub args: [u64; 3],
+}
@@ -6,5 +6,9 @@ pub const USER_SPACE_END: usize = 0x0000_7FFF_FFFF_0000;
 pub const DEFAULT_STACK_SIZE: usize = 32 * 1024 * 1024;
 /// 栈顶地址
 pub const STACK_TOP: usize = 0x0000_7FFF_FFFF_0000;
+/// 栈底下方保留的保护页大小，栈溢出时触发缺页而不是覆盖相邻映射
+pub const STACK_GUARD_SIZE: usize = 0x1000;
+/// vDSO 数据页的地址，位于栈和保护页下方
+pub const VDSO_DATA_ADDR: usize = STACK_TOP - DEFAULT_STACK_SIZE - 0x10000;
 /// 堆起始地址（动态确定）
 pub const ALLOC_START: usize = 0x0000_6000_0000_0000;
@@ -1,8 +1,15 @@
 #![no_std]
 #![no_main]
 
+pub mod bootopts;
+pub mod debuglog;
+pub mod display;
 mod error;
+pub mod ktrace;
 pub mod layout;
 pub mod nr;
+pub mod profile;
+pub mod serial;
+pub mod vdso;
 
 pub use error::*;
@@ -7,14 +7,31 @@ use spin::Mutex;
 use crate::{
     arch::CurrentRmmArch,
     init::memory::{FRAME_ALLOCATOR, PAGE_SIZE, align_down, align_up},
+    memory::{account::MemoryAccount, vdso},
     object::{
+        channel::Channel,
         process::{ArcProcess, Process, layout, register_process},
         vmar::{MappingFlags, Vmar},
         vmo::{Vmo, VmoOptions},
     },
 };
 
-use super::elf::{ElfError, ElfParser};
+use super::elf::{ElfError, ElfParser, LoadSegment};
+
+/// 按段权限生成映射标志，不再一律映射为可读可写可执行
+fn segment_flags(segment: &LoadSegment) -> MappingFlags {
+    let mut flags = MappingFlags::SPECIFIC;
+    if segment.is_readable() {
+        flags |= MappingFlags::READ;
+    }
+    if segment.is_writable() {
+        flags |= MappingFlags::WRITE;
+    }
+    if segment.is_executable() {
+        flags |= MappingFlags::EXECUTE;
+    }
+    flags
+}
 
 /// 加载器错误
 #[derive(Debug)]
@@ -134,7 +151,15 @@ impl ProgramLoader {
 
         let user_base = VirtualAddress::new(layout::USER_SPACE_START);
         let user_size = layout::USER_SPACE_END - layout::USER_SPACE_START;
-        let vmar = Vmar::create_root(user_base, user_size, layout::ALLOC_START, new_page_table);
+        let account = MemoryAccount::new(None);
+        let vmar = Vmar::create_address_space(
+            user_base,
+            user_size,
+            layout::ALLOC_START,
+            new_page_table,
+            account.clone(),
+        );
+        vdso::map_into(&vmar).map_err(|_| LoaderError::OutOfMemory)?;
 
         // 加载所有段
         for segment in elf.load_segments() {
@@ -144,37 +169,43 @@ impl ProgramLoader {
             let memsz = segment.memsz;
             let size = align_up(vaddr + memsz) - aligned_vaddr;
 
-            let vmo =
-                Vmo::create(size, VmoOptions::COMMIT).map_err(|_| LoaderError::OutOfMemory)?;
+            let vmo = Vmo::create(size, VmoOptions::COMMIT, Some(account.clone()))
+                .map_err(|_| LoaderError::OutOfMemory)?;
             vmo.write(offset, segment.data.unwrap())
                 .map_err(|_| LoaderError::OutOfMemory)?;
             vmar.map(
                 vmo,
                 0,
                 size,
-                MappingFlags::READ
-                    | MappingFlags::WRITE
-                    | MappingFlags::EXECUTE
-                    | MappingFlags::SPECIFIC,
+                segment_flags(&segment),
+                MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
                 Some(VirtualAddress::new(aligned_vaddr)),
             )
             .map_err(|_| LoaderError::OutOfMemory)?;
         }
 
-        // 分配栈
+        // 分配栈：栈放在单独的子 VMAR 中，底部留出保护页，其他映射不会紧贴栈底
         let aligned_size = layout::DEFAULT_STACK_SIZE;
         let stack_bottom = layout::STACK_TOP - aligned_size;
+        let stack_vmar = vmar
+            .create_child(
+                Some(stack_bottom - layout::STACK_GUARD_SIZE - vmar.base().data()),
+                aligned_size + layout::STACK_GUARD_SIZE,
+            )
+            .map_err(|_| LoaderError::OutOfMemory)?;
 
-        let vmo =
-            Vmo::create(aligned_size, VmoOptions::COMMIT).map_err(|_| LoaderError::OutOfMemory)?;
-        vmar.map(
-            vmo,
-            0,
-            aligned_size,
-            MappingFlags::READ | MappingFlags::WRITE | MappingFlags::SPECIFIC,
-            Some(VirtualAddress::new(stack_bottom)),
-        )
-        .map_err(|_| LoaderError::OutOfMemory)?;
+        let vmo = Vmo::create(aligned_size, VmoOptions::COMMIT, Some(account))
+            .map_err(|_| LoaderError::OutOfMemory)?;
+        stack_vmar
+            .map(
+                vmo,
+                0,
+                aligned_size,
+                MappingFlags::READ | MappingFlags::WRITE | MappingFlags::SPECIFIC,
+                MappingFlags::READ | MappingFlags::WRITE,
+                Some(VirtualAddress::new(stack_bottom)),
+            )
+            .map_err(|_| LoaderError::OutOfMemory)?;
 
         // 计算 BRK（堆起始地址）
         let brk = VirtualAddress::new((max_end + 0xFFF) & !0xFFF);
@@ -191,19 +222,25 @@ impl ProgramLoader {
         })
     }
 
-    /// 加载并创建进程
-    pub fn load_and_create_process(elf_data: &[u8], name: &str) -> Result<ArcProcess, LoaderError> {
+    /// 加载并创建带 bootstrap channel 的进程，返回进程和 channel 的内核一端
+    pub fn load_and_create_process(
+        elf_data: &[u8],
+        name: &str,
+    ) -> Result<(ArcProcess, Arc<Channel>), LoaderError> {
         // 加载程序
         let loaded = Self::load(elf_data, name)?;
 
         // 创建进程
-        let process = Process::new(name.into(), None);
+        let (process, bootstrap) = Process::new_with_bootstrap(name.into(), None);
+        let bootstrap = bootstrap.ok_or(LoaderError::OutOfMemory)?;
 
         // 设置地址空间
         {
             let mut proc = process.write();
             proc.set_root_vmar(loaded.root_vmar.clone());
             proc.set_brk(loaded.brk);
+            // 内核直接启动的进程拥有全部特权
+            proc.set_privileged(true);
         }
 
         // 创建主线程
@@ -219,6 +256,6 @@ impl ProgramLoader {
         // 注册进程
         register_process(process.clone());
 
-        Ok(process)
+        Ok((process, bootstrap))
     }
 }
@@ -51,21 +51,41 @@ static _START_MARKER: RequestsStartMarker = RequestsStartMarker::new();
 static _END_MARKER: RequestsEndMarker = RequestsEndMarker::new();
 
 pub mod arch;
+pub mod bootopts;
+pub mod cmdline;
 pub mod consts;
+pub mod crash;
+pub mod debuglog;
+pub mod display;
 pub mod drivers;
+pub mod gdbstub;
 pub mod heap;
 pub mod init;
+pub mod ktrace;
 pub mod layout;
 pub mod loader;
 pub mod memory;
 pub mod object;
+pub mod profile;
+pub mod profiler;
+pub mod random;
+pub mod serial;
 pub mod smp;
 pub mod syscall;
 pub mod task;
+pub mod trace;
+pub mod vdso;
 
 pub use self::syscall::error::*;
 
-use crate::arch::{CurrentIrqArch, irq::IrqArch};
+use alloc::sync::Arc;
+use rmm::{Arch, PhysicalAddress};
+
+use crate::{
+    arch::{CurrentIrqArch, CurrentRmmArch, irq::IrqArch},
+    init::memory::PAGE_SIZE,
+    object::{KernelObject, Rights, process::ArcProcess, vmo::Vmo},
+};
 
 macro_rules! linker_offsets(
     ($($name:ident),*) => {
@@ -94,10 +114,20 @@ extern "C" fn kmain() -> ! {
     drivers::framebuffer::init();
 
     drivers::logger::init();
+    cmdline::report();
 
     arch::early_init();
+    #[cfg(target_arch = "x86_64")]
+    drivers::ns16550::init_console();
+
+    random::init();
+    object::debuglog::init();
+    crash::report_previous();
+    gdbstub::init();
 
     task::init().expect("Failed to execute kernel init");
+    #[cfg(target_arch = "x86_64")]
+    arch::smp::load_idle_task(arch::get_archid());
 
     info!("Kernel initialized");
 
@@ -110,6 +140,10 @@ extern "C" fn kmain() -> ! {
 extern "C" fn initial_kernel_thread() -> ! {
     info!("Initial kernel thread is running");
 
+    memory::reclaim::init();
+    memory::vdso::init();
+    syscall::clock::init();
+
     let initramfs_mod = MODULE_REQUEST.get_response().unwrap().modules()[0];
     let initramfs = unsafe {
         core::slice::from_raw_parts(
@@ -120,16 +154,20 @@ extern "C" fn initial_kernel_thread() -> ! {
 
     info!("Initramfs size: {} bytes", initramfs.len());
 
-    // 查找 init 程序
+    // 查找 init 程序，initramfs 中的路径可能带有 `./` 前缀
+    let init_path = cmdline::options().init_path.trim_start_matches('/');
     let mut init_found = false;
     for entry in cpio_reader::iter_files(initramfs) {
-        let name = entry.name();
+        let name = entry
+            .name()
+            .trim_start_matches("./")
+            .trim_start_matches('/');
 
-        if name.contains("init") {
+        if name == init_path {
             let elf_buf: &[u8] = entry.file();
             info!("Found init program, size: {} bytes", elf_buf.len());
 
-            match load_and_run_init(elf_buf) {
+            match load_and_run_init(elf_buf, initramfs) {
                 Ok(()) => {
                     init_found = true;
                     info!("Init process started successfully");
@@ -143,7 +181,10 @@ extern "C" fn initial_kernel_thread() -> ! {
     }
 
     if !init_found {
-        panic!("Init program not found in initramfs!");
+        panic!(
+            "Init program {} not found in initramfs!",
+            cmdline::options().init_path
+        );
     }
 
     // 进入调度循环
@@ -153,11 +194,13 @@ extern "C" fn initial_kernel_thread() -> ! {
     }
 }
 
-fn load_and_run_init(elf_data: &[u8]) -> Result<(), loader::LoaderError> {
+fn load_and_run_init(elf_data: &[u8], initramfs: &[u8]) -> Result<(), loader::LoaderError> {
     use loader::ProgramLoader;
 
     // 创建和启动进程
-    let process = ProgramLoader::load_and_create_process(elf_data, "init")?;
+    let (process, bootstrap) = ProgramLoader::load_and_create_process(elf_data, "init")?;
+    send_boot_options(&bootstrap);
+    add_initramfs_handle(&process, initramfs);
     {
         let mut proc = process.write();
         proc.start();
@@ -167,3 +210,53 @@ fn load_and_run_init(elf_data: &[u8]) -> Result<(), loader::LoaderError> {
 
     Ok(())
 }
+
+/// 把整个 initramfs 作为 init 的第一个初始句柄交给 init
+///
+/// 句柄指向模块物理页面的 COW 克隆且不带写权限，init 和它分发出去的子 VMO 都不能改动原始数据。
+fn add_initramfs_handle(process: &ArcProcess, initramfs: &[u8]) {
+    let virt = initramfs.as_ptr() as usize;
+    if virt < CurrentRmmArch::PHYS_OFFSET || !virt.is_multiple_of(PAGE_SIZE) {
+        warn!(
+            "Initramfs at {:#x} is not page aligned, not passing it to init",
+            virt
+        );
+        return;
+    }
+
+    let phys = PhysicalAddress::new(virt - CurrentRmmArch::PHYS_OFFSET);
+    let vmo = match Vmo::create_physical(phys, initramfs.len())
+        .and_then(|vmo| vmo.create_cow_clone(0, initramfs.len(), None))
+    {
+        Ok(vmo) => vmo,
+        Err(e) => {
+            warn!("Failed to create initramfs VMO: {:?}", e);
+            return;
+        }
+    };
+
+    process.write().add_init_handle(
+        vmo as Arc<dyn KernelObject>,
+        Rights::READ | Rights::WAIT | Rights::MAP | Rights::DUPLICATE | Rights::TRANSFER,
+    );
+}
+
+/// 把内核不识别的 `key=value` 选项通过 bootstrap channel 发送给 init
+fn send_boot_options(bootstrap: &object::channel::Channel) {
+    let mut data = bootopts::BOOT_OPTIONS_MAGIC.to_le_bytes().to_vec();
+    for (key, value) in cmdline::forwarded() {
+        if data.len() + key.len() + value.len() + 2 > bootopts::BOOT_OPTIONS_MAX {
+            warn!("Boot options too long, dropping {}={}", key, value);
+            continue;
+        }
+        info!("Forwarding option {}={} to init", key, value);
+        data.extend_from_slice(key.as_bytes());
+        data.push(b'=');
+        data.extend_from_slice(value.as_bytes());
+        data.push(0);
+    }
+
+    if let Err(e) = bootstrap.send(object::channel::Message::new(data)) {
+        warn!("Failed to send boot options to init: {:?}", e);
+    }
+}
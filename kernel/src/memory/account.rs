@@ -0,0 +1,231 @@
+//! 内存记账与限额
+//!
+//! 每个地址空间（根 VMAR）有一个账户，记录其 VMO 已提交的页面、页表和内核栈占用的页数。
+//! 子进程的账户挂在父进程账户下，用量同时计入所有祖先账户，
+//! 因此给某个进程设置的限额也约束它创建的全部子孙进程（相当于作业限额）。
+//! 超出限额的分配直接失败，不会继续消耗物理页分配器。
+//!
+//! 此外按用途统计全局用量（包括不属于任何账户的内核分配），用于内核范围的内存统计。
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use rmm::{BuddyAllocator, FrameAllocator, FrameCount, FrameUsage, PhysicalAddress};
+
+use crate::arch::CurrentRmmArch;
+
+/// 页面用途
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// VMO 已提交的页面
+    Vmo,
+    /// 用户地址空间的页表
+    PageTable,
+    /// 线程的内核栈和 syscall 栈
+    KernelStack,
+}
+
+/// 按用途统计的页数
+pub struct MemoryUsage {
+    vmo: AtomicUsize,
+    page_table: AtomicUsize,
+    kernel_stack: AtomicUsize,
+}
+
+impl MemoryUsage {
+    const fn new() -> Self {
+        Self {
+            vmo: AtomicUsize::new(0),
+            page_table: AtomicUsize::new(0),
+            kernel_stack: AtomicUsize::new(0),
+        }
+    }
+
+    fn counter(&self, kind: MemoryKind) -> &AtomicUsize {
+        match kind {
+            MemoryKind::Vmo => &self.vmo,
+            MemoryKind::PageTable => &self.page_table,
+            MemoryKind::KernelStack => &self.kernel_stack,
+        }
+    }
+
+    fn add(&self, kind: MemoryKind, pages: usize) {
+        self.counter(kind).fetch_add(pages, Ordering::Relaxed);
+    }
+
+    fn sub(&self, kind: MemoryKind, pages: usize) {
+        self.counter(kind).fetch_sub(pages, Ordering::Relaxed);
+    }
+
+    /// 某种用途的页数
+    pub fn pages(&self, kind: MemoryKind) -> usize {
+        self.counter(kind).load(Ordering::Relaxed)
+    }
+}
+
+/// 全局用量
+static GLOBAL_USAGE: MemoryUsage = MemoryUsage::new();
+
+/// 获取全局用量
+pub fn global_usage() -> &'static MemoryUsage {
+    &GLOBAL_USAGE
+}
+
+/// 内存账户
+pub struct MemoryAccount {
+    /// 父账户
+    parent: Option<Arc<MemoryAccount>>,
+    /// 本账户自身的用量
+    usage: MemoryUsage,
+    /// 包括子孙账户在内的总页数
+    total: AtomicUsize,
+    /// 总页数上限（`usize::MAX` 表示不限）
+    limit: AtomicUsize,
+}
+
+impl MemoryAccount {
+    pub fn new(parent: Option<Arc<MemoryAccount>>) -> Arc<Self> {
+        Arc::new(Self {
+            parent,
+            usage: MemoryUsage::new(),
+            total: AtomicUsize::new(0),
+            limit: AtomicUsize::new(usize::MAX),
+        })
+    }
+
+    /// 本账户自身的用量
+    pub fn usage(&self) -> &MemoryUsage {
+        &self.usage
+    }
+
+    /// 包括子孙账户在内的总页数
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// 页数上限，`None` 表示不限
+    pub fn limit(&self) -> Option<usize> {
+        match self.limit.load(Ordering::Relaxed) {
+            usize::MAX => None,
+            limit => Some(limit),
+        }
+    }
+
+    /// 设置页数上限，已有的用量不受影响
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit
+            .store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    /// 沿账户链向上计入，任何一级超出上限时撤销已计入的部分并返回 false
+    fn try_charge_total(&self, pages: usize) -> bool {
+        let mut account = Some(self);
+        while let Some(current) = account {
+            let total = current.total.fetch_add(pages, Ordering::Relaxed) + pages;
+            if total > current.limit.load(Ordering::Relaxed) {
+                let mut undo = Some(self);
+                while let Some(charged) = undo {
+                    charged.total.fetch_sub(pages, Ordering::Relaxed);
+                    if core::ptr::eq(charged, current) {
+                        break;
+                    }
+                    undo = charged.parent.as_deref();
+                }
+                return false;
+            }
+            account = current.parent.as_deref();
+        }
+        true
+    }
+
+    fn add_total(&self, pages: usize) {
+        let mut account = Some(self);
+        while let Some(current) = account {
+            current.total.fetch_add(pages, Ordering::Relaxed);
+            account = current.parent.as_deref();
+        }
+    }
+
+    fn sub_total(&self, pages: usize) {
+        let mut account = Some(self);
+        while let Some(current) = account {
+            current.total.fetch_sub(pages, Ordering::Relaxed);
+            account = current.parent.as_deref();
+        }
+    }
+}
+
+/// 计入 `pages` 页，超出账户限额时返回 false
+///
+/// `account` 为 None 表示内核自身的分配，只计入全局用量。
+pub fn charge(account: Option<&MemoryAccount>, kind: MemoryKind, pages: usize) -> bool {
+    if let Some(account) = account {
+        if !account.try_charge_total(pages) {
+            return false;
+        }
+        account.usage.add(kind, pages);
+    }
+    GLOBAL_USAGE.add(kind, pages);
+    true
+}
+
+/// 计入 `pages` 页，不检查限额
+pub fn record(account: Option<&MemoryAccount>, kind: MemoryKind, pages: usize) {
+    if let Some(account) = account {
+        account.add_total(pages);
+        account.usage.add(kind, pages);
+    }
+    GLOBAL_USAGE.add(kind, pages);
+}
+
+/// 撤销之前计入的 `pages` 页
+pub fn uncharge(account: Option<&MemoryAccount>, kind: MemoryKind, pages: usize) {
+    if let Some(account) = account {
+        account.sub_total(pages);
+        account.usage.sub(kind, pages);
+    }
+    GLOBAL_USAGE.sub(kind, pages);
+}
+
+/// 将分配的页面计入账户的物理页分配器（用于用户页表）
+pub struct AccountedFrames<'a> {
+    inner: &'a mut BuddyAllocator<CurrentRmmArch>,
+    account: &'a MemoryAccount,
+    kind: MemoryKind,
+}
+
+impl<'a> AccountedFrames<'a> {
+    pub fn new(
+        inner: &'a mut BuddyAllocator<CurrentRmmArch>,
+        account: &'a MemoryAccount,
+        kind: MemoryKind,
+    ) -> Self {
+        Self {
+            inner,
+            account,
+            kind,
+        }
+    }
+}
+
+impl FrameAllocator for AccountedFrames<'_> {
+    unsafe fn allocate(&mut self, count: FrameCount) -> Option<PhysicalAddress> {
+        if !charge(Some(self.account), self.kind, count.data()) {
+            return None;
+        }
+
+        let phys = unsafe { self.inner.allocate(count) };
+        if phys.is_none() {
+            uncharge(Some(self.account), self.kind, count.data());
+        }
+        phys
+    }
+
+    unsafe fn free(&mut self, address: PhysicalAddress, count: FrameCount) {
+        unsafe { self.inner.free(address, count) };
+        uncharge(Some(self.account), self.kind, count.data());
+    }
+
+    unsafe fn usage(&self) -> FrameUsage {
+        unsafe { self.inner.usage() }
+    }
+}
@@ -0,0 +1,73 @@
+//! 持有期间关闭本 CPU 中断的自旋锁
+//!
+//! 堆和物理页分配器的锁可能在中断处理中再次获取（例如调度器在时钟中断里扩展就绪队列），
+//! 普通自旋锁被同一 CPU 上的中断重入时会死锁。
+
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+use spin::{Mutex, MutexGuard};
+
+use crate::arch::{CurrentTlbArch, tlb::TlbArch};
+
+pub struct IrqMutex<T> {
+    inner: Mutex<T>,
+}
+
+impl<T> IrqMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: Mutex::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> IrqMutexGuard<'_, T> {
+        let irq = CurrentTlbArch::irq_save();
+        IrqMutexGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            irq,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T>> {
+        let irq = CurrentTlbArch::irq_save();
+        match self.inner.try_lock() {
+            Some(guard) => Some(IrqMutexGuard {
+                guard: ManuallyDrop::new(guard),
+                irq,
+            }),
+            None => {
+                CurrentTlbArch::irq_restore(irq);
+                None
+            }
+        }
+    }
+}
+
+pub struct IrqMutexGuard<'a, T> {
+    guard: ManuallyDrop<MutexGuard<'a, T>>,
+    /// 加锁前中断是否开启
+    irq: bool,
+}
+
+impl<T> Deref for IrqMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for IrqMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // 先释放锁再恢复中断
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        CurrentTlbArch::irq_restore(self.irq);
+    }
+}
@@ -1,5 +1,17 @@
+pub mod account;
+pub mod irq_mutex;
+pub mod reclaim;
+pub mod slab;
+pub mod tlb;
+pub mod vdso;
+
 use core::cell::SyncUnsafeCell;
-use rmm::{FrameAllocator, FrameCount, FrameUsage, PhysicalAddress};
+use rmm::{
+    Arch, FrameAllocator, FrameCount, FrameUsage, PageMapper, PhysicalAddress, TableKind,
+    VirtualAddress,
+};
+
+use crate::{arch::CurrentRmmArch, init::memory::PAGE_SIZE, layout::USER_SPACE_END};
 
 pub(crate) static AREAS: SyncUnsafeCell<[rmm::MemoryArea; 1024]> = SyncUnsafeCell::new(
     [rmm::MemoryArea {
@@ -26,3 +38,23 @@ impl FrameAllocator for DummyFrameAllocator {
         FrameUsage::new(FrameCount::new(0), FrameCount::new(0))
     }
 }
+
+/// 通过直接映射区访问当前页表中 `address` 所在的页面，未映射时返回 None
+///
+/// 只查询页表，不会触发缺页，也不获取任何锁，可以在中断和异常处理中使用。
+/// 直接映射区总是可写，修改只读的内核代码（例如设置断点）也不需要改变页表。
+pub fn direct_map(address: usize) -> Option<*mut u8> {
+    let kind = if address < USER_SPACE_END {
+        TableKind::User
+    } else {
+        TableKind::Kernel
+    };
+    let mapper = unsafe { PageMapper::<CurrentRmmArch, _>::current(kind, DummyFrameAllocator) };
+    let page = address & !(PAGE_SIZE - 1);
+    let (phys, flags) = mapper.translate(VirtualAddress::new(page))?;
+    if !flags.has_present() {
+        return None;
+    }
+    let virt = unsafe { CurrentRmmArch::phys_to_virt(phys) };
+    Some((virt.data() + address % PAGE_SIZE) as *mut u8)
+}
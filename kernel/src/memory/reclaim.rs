@@ -0,0 +1,154 @@
+//! 内存压力监测与回收
+//!
+//! 根据物理页分配器的空闲页数与水位线比较：低于低水位时唤醒回收线程，
+//! 归还 slab 分配器缓存的空闲页面、丢弃未锁定的 DISCARDABLE VMO，
+//! 并通过内存压力事件通知用户态服务。
+
+use alloc::string::ToString;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use rmm::FrameAllocator;
+use spin::{Lazy, Mutex};
+
+use crate::{
+    init::memory::FRAME_ALLOCATOR,
+    memory::slab,
+    object::{Event, KernelObject, Signals, WaitQueue, vmo::Vmo},
+    task::create_kernel_task,
+};
+
+/// 内存紧张：空闲页低于低水位
+pub const MEMORY_PRESSURE_WARNING: Signals = Signals::SIGNALED;
+/// 内存严重不足：空闲页低于最低水位
+pub const MEMORY_PRESSURE_CRITICAL: Signals = Signals::USER_0;
+
+/// 水位线（页数）
+#[derive(Debug, Clone, Copy)]
+pub struct Watermarks {
+    /// 最低水位
+    pub min: usize,
+    /// 低水位，低于此值开始回收
+    pub low: usize,
+    /// 高水位，回收到此值为止
+    pub high: usize,
+}
+
+static WATERMARKS: Lazy<Watermarks> = Lazy::new(|| {
+    let total = unsafe { FRAME_ALLOCATOR.lock().usage() }.total().data();
+    Watermarks {
+        min: total / 128,
+        low: total / 64,
+        high: total / 32,
+    }
+});
+
+/// 内存压力事件
+pub static MEMORY_PRESSURE_EVENT: Lazy<Arc<Event>> = Lazy::new(Event::new);
+
+/// 所有可丢弃的 VMO（按创建顺序）
+static DISCARDABLE_VMOS: Mutex<Vec<Weak<Vmo>>> = Mutex::new(Vec::new());
+
+static RECLAIM_WAIT: WaitQueue = WaitQueue::new();
+static RECLAIM_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// 获取水位线
+pub fn watermarks() -> Watermarks {
+    *WATERMARKS
+}
+
+/// 登记可丢弃 VMO
+pub fn register_discardable(vmo: &Arc<Vmo>) {
+    let mut vmos = DISCARDABLE_VMOS.lock();
+    vmos.retain(|v| v.strong_count() != 0);
+    vmos.push(Arc::downgrade(vmo));
+}
+
+fn free_pages() -> usize {
+    unsafe { FRAME_ALLOCATOR.lock().usage() }.free().data()
+}
+
+/// 根据空闲页数更新事件信号，返回是否低于低水位
+fn update_signals(free: usize) -> bool {
+    let marks = watermarks();
+    let event = &*MEMORY_PRESSURE_EVENT;
+
+    if free < marks.min {
+        event.signal_set(MEMORY_PRESSURE_WARNING | MEMORY_PRESSURE_CRITICAL);
+    } else if free < marks.low {
+        event.signal_clear(MEMORY_PRESSURE_CRITICAL);
+        event.signal_set(MEMORY_PRESSURE_WARNING);
+    } else if free >= marks.high {
+        event.signal_clear(MEMORY_PRESSURE_WARNING | MEMORY_PRESSURE_CRITICAL);
+    } else {
+        event.signal_clear(MEMORY_PRESSURE_CRITICAL);
+    }
+
+    free < marks.low
+}
+
+/// 检查内存压力，必要时唤醒回收线程
+///
+/// 在分配或释放物理页之后调用，调用时不能持有 FRAME_ALLOCATOR 的锁。
+pub fn update_pressure() {
+    if update_signals(free_pages()) {
+        RECLAIM_PENDING.store(true, Ordering::Release);
+        RECLAIM_WAIT.wake_one();
+    }
+}
+
+/// 归还 slab 的空闲页面，再丢弃未锁定的可丢弃 VMO，直到空闲页回到高水位，返回释放的页数
+pub fn reclaim() -> usize {
+    let target = watermarks().high;
+
+    let mut reclaimed = slab::reclaim();
+
+    let candidates: Vec<Arc<Vmo>> = {
+        let mut vmos = DISCARDABLE_VMOS.lock();
+        vmos.retain(|v| v.strong_count() != 0);
+        vmos.iter().filter_map(Weak::upgrade).collect()
+    };
+
+    for vmo in candidates {
+        if free_pages() >= target {
+            break;
+        }
+        reclaimed += vmo.try_discard();
+    }
+
+    reclaimed
+}
+
+/// 执行 `f`，因内存不足失败时同步回收一次再重试
+///
+/// `out_of_memory` 判断错误是否由内存不足引起。回收会丢弃其他 VMO 的页面并撤销它们的映射，
+/// 调用时不能持有任何 VMO 或 VMAR 的锁。
+pub fn retry_after_reclaim<T, E>(
+    mut f: impl FnMut() -> Result<T, E>,
+    out_of_memory: impl Fn(&E) -> bool,
+) -> Result<T, E> {
+    match f() {
+        Err(e) if out_of_memory(&e) && reclaim() != 0 => f(),
+        result => result,
+    }
+}
+
+extern "C" fn reclaim_thread() -> ! {
+    loop {
+        RECLAIM_WAIT.wait_until(|| RECLAIM_PENDING.swap(false, Ordering::AcqRel));
+
+        let reclaimed = reclaim();
+        if reclaimed != 0 {
+            debug!("Reclaimed {} pages", reclaimed);
+        }
+
+        // 只更新信号，不再唤醒自己，避免无可回收时空转
+        update_signals(free_pages());
+    }
+}
+
+/// 启动回收线程
+pub fn init() {
+    let _ = watermarks();
+    create_kernel_task("reclaim".to_string(), reclaim_thread as *const () as usize);
+}
@@ -0,0 +1,459 @@
+//! 固定大小内核对象的 slab 分配器
+//!
+//! 不超过 `SLAB_MAX_SIZE` 的分配按 2 的幂划分大小类，每个大小类一个 cache。
+//! slab 是直接从物理页分配器取得、按自身大小对齐的连续页面，通过直接映射区访问。
+//! 头部放在 slab 开头，对象按对象大小对齐排列在头部之后，
+//! 因此对象地址向下对齐到 slab 大小就能找到所属的 slab。
+//!
+//! 每个 CPU 为每个大小类保留一个 magazine（缓存少量空闲对象的栈），
+//! 分配和释放通常只访问本 CPU 的 magazine，空或满时才与 cache 成批交换对象。
+//! 空闲 slab 超过保留数量时归还物理页分配器，内存紧张时回收线程清空所有 magazine
+//! 并归还全部空闲 slab。
+//!
+//! 锁顺序：magazine → cache → FRAME_ALLOCATOR。
+
+use core::alloc::Layout;
+use core::ptr::null_mut;
+use rmm::{Arch, FrameAllocator, FrameCount, PhysicalAddress};
+use spin::Mutex;
+
+use crate::{
+    arch::{CurrentRmmArch, CurrentTlbArch, tlb::TlbArch},
+    init::memory::{FRAME_ALLOCATOR, PAGE_SIZE},
+    memory::tlb::MAX_CPUS,
+};
+
+/// 最小大小类（16 字节）
+const MIN_SHIFT: usize = 4;
+/// 最大大小类（2048 字节）
+const MAX_SHIFT: usize = 11;
+/// 大小类的数量
+pub const SLAB_CLASS_COUNT: usize = MAX_SHIFT - MIN_SHIFT + 1;
+/// 由 slab 分配的最大对象
+pub const SLAB_MAX_SIZE: usize = 1 << MAX_SHIFT;
+/// 每个 slab 至少容纳的对象数（包括头部占用的位置），决定大对象的 slab 大小
+const SLAB_MIN_OBJECTS: usize = 16;
+/// 每个 magazine 缓存的对象数
+const MAGAZINE_SIZE: usize = 16;
+/// magazine 与 cache 每次交换的对象数
+const MAGAZINE_BATCH: usize = MAGAZINE_SIZE / 2;
+/// 每个 cache 保留的空闲 slab 数
+const EMPTY_SLABS_KEPT: usize = 1;
+
+/// `layout` 所属的大小类，超过 `SLAB_MAX_SIZE` 时返回 None
+pub fn class_index(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    if size > SLAB_MAX_SIZE {
+        return None;
+    }
+
+    let shift = size.next_power_of_two().trailing_zeros() as usize;
+    Some(shift.saturating_sub(MIN_SHIFT))
+}
+
+const fn object_size(class: usize) -> usize {
+    1 << (class + MIN_SHIFT)
+}
+
+const fn slab_size(class: usize) -> usize {
+    let size = object_size(class) * SLAB_MIN_OBJECTS;
+    if size > PAGE_SIZE { size } else { PAGE_SIZE }
+}
+
+/// 第一个对象的偏移（头部之后按对象大小对齐）
+const fn first_offset(class: usize) -> usize {
+    let size = object_size(class);
+    size_of::<Slab>().div_ceil(size) * size
+}
+
+/// 每个 slab 容纳的对象数
+const fn capacity(class: usize) -> usize {
+    (slab_size(class) - first_offset(class)) / object_size(class)
+}
+
+/// slab 头部
+#[repr(C)]
+struct Slab {
+    next: *mut Slab,
+    prev: *mut Slab,
+    /// slab 内的空闲对象链表
+    free: *mut FreeObject,
+    /// 已分配出去的对象数（包括在 magazine 中的）
+    in_use: usize,
+}
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// slab 的侵入式双向链表
+struct SlabList {
+    head: *mut Slab,
+    len: usize,
+}
+
+impl SlabList {
+    const fn new() -> Self {
+        Self {
+            head: null_mut(),
+            len: 0,
+        }
+    }
+
+    unsafe fn push(&mut self, slab: *mut Slab) {
+        unsafe {
+            (*slab).prev = null_mut();
+            (*slab).next = self.head;
+            if !self.head.is_null() {
+                (*self.head).prev = slab;
+            }
+        }
+        self.head = slab;
+        self.len += 1;
+    }
+
+    unsafe fn remove(&mut self, slab: *mut Slab) {
+        unsafe {
+            let (prev, next) = ((*slab).prev, (*slab).next);
+            if prev.is_null() {
+                self.head = next;
+            } else {
+                (*prev).next = next;
+            }
+            if !next.is_null() {
+                (*next).prev = prev;
+            }
+        }
+        self.len -= 1;
+    }
+}
+
+/// 一个大小类的 cache
+///
+/// 部分使用的 slab 在 `partial` 上，完全空闲的在 `empty` 上，已满的不在任何链表上。
+struct SlabCache {
+    class: usize,
+    partial: SlabList,
+    empty: SlabList,
+    /// slab 总数
+    slabs: usize,
+    /// 所有 slab 空闲链表中的对象总数
+    free_objects: usize,
+}
+
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    const fn new(class: usize) -> Self {
+        Self {
+            class,
+            partial: SlabList::new(),
+            empty: SlabList::new(),
+            slabs: 0,
+            free_objects: 0,
+        }
+    }
+
+    /// slab 当前所在的链表
+    fn list(&mut self, slab: *mut Slab) -> Option<&mut SlabList> {
+        match unsafe { (*slab).in_use } {
+            0 => Some(&mut self.empty),
+            n if n == capacity(self.class) => None,
+            _ => Some(&mut self.partial),
+        }
+    }
+
+    unsafe fn unlink(&mut self, slab: *mut Slab) {
+        if let Some(list) = self.list(slab) {
+            unsafe { list.remove(slab) };
+        }
+    }
+
+    unsafe fn link(&mut self, slab: *mut Slab) {
+        if let Some(list) = self.list(slab) {
+            unsafe { list.push(slab) };
+        }
+    }
+
+    /// 从物理页分配器分配一个新的空闲 slab
+    fn grow(&mut self) -> bool {
+        let pages = FrameCount::new(slab_size(self.class) / PAGE_SIZE);
+        let Some(phys) = (unsafe { FRAME_ALLOCATOR.lock().allocate_aligned(pages, pages) }) else {
+            return false;
+        };
+
+        let base = unsafe { CurrentRmmArch::phys_to_virt(phys) }.data();
+        let size = object_size(self.class);
+
+        let mut free = null_mut();
+        for i in (0..capacity(self.class)).rev() {
+            let object = (base + first_offset(self.class) + i * size) as *mut FreeObject;
+            unsafe { (*object).next = free };
+            free = object;
+        }
+
+        let slab = base as *mut Slab;
+        unsafe {
+            slab.write(Slab {
+                next: null_mut(),
+                prev: null_mut(),
+                free,
+                in_use: 0,
+            });
+            self.empty.push(slab);
+        }
+
+        self.slabs += 1;
+        self.free_objects += capacity(self.class);
+        true
+    }
+
+    /// 取出一个对象，物理内存不足时返回空指针
+    fn alloc(&mut self) -> *mut u8 {
+        let slab = if !self.partial.head.is_null() {
+            self.partial.head
+        } else {
+            if self.empty.head.is_null() && !self.grow() {
+                return null_mut();
+            }
+            self.empty.head
+        };
+
+        self.free_objects -= 1;
+        unsafe {
+            self.unlink(slab);
+            let object = (*slab).free;
+            (*slab).free = (*object).next;
+            (*slab).in_use += 1;
+            self.link(slab);
+            object as *mut u8
+        }
+    }
+
+    /// 把对象放回所属 slab
+    unsafe fn free(&mut self, ptr: *mut u8) {
+        let slab = (ptr as usize & !(slab_size(self.class) - 1)) as *mut Slab;
+        let object = ptr as *mut FreeObject;
+
+        self.free_objects += 1;
+        unsafe {
+            self.unlink(slab);
+            (*object).next = (*slab).free;
+            (*slab).free = object;
+            (*slab).in_use -= 1;
+            self.link(slab);
+        }
+    }
+
+    /// 归还空闲 slab 直到只剩 `keep` 个，返回归还的页数
+    unsafe fn release_empty(&mut self, allocator: &mut impl FrameAllocator, keep: usize) -> usize {
+        let pages = slab_size(self.class) / PAGE_SIZE;
+        let mut released = 0;
+
+        while self.empty.len > keep {
+            let slab = self.empty.head;
+            unsafe { self.empty.remove(slab) };
+
+            let phys = PhysicalAddress::new(slab as usize - CurrentRmmArch::PHYS_OFFSET);
+            unsafe { allocator.free(phys, FrameCount::new(pages)) };
+
+            self.slabs -= 1;
+            self.free_objects -= capacity(self.class);
+            released += pages;
+        }
+
+        released
+    }
+}
+
+static CACHES: [Mutex<SlabCache>; SLAB_CLASS_COUNT] = {
+    let mut caches = [const { Mutex::new(SlabCache::new(0)) }; SLAB_CLASS_COUNT];
+    let mut class = 0;
+    while class < SLAB_CLASS_COUNT {
+        caches[class] = Mutex::new(SlabCache::new(class));
+        class += 1;
+    }
+    caches
+};
+
+/// 每个 CPU 每个大小类的空闲对象缓存
+struct Magazine {
+    objects: [*mut u8; MAGAZINE_SIZE],
+    count: usize,
+    /// 在该 CPU 上的分配次数
+    allocs: u64,
+    /// 在该 CPU 上的释放次数
+    frees: u64,
+}
+
+unsafe impl Send for Magazine {}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self {
+            objects: [null_mut(); MAGAZINE_SIZE],
+            count: 0,
+            allocs: 0,
+            frees: 0,
+        }
+    }
+}
+
+static MAGAZINES: [[Mutex<Magazine>; SLAB_CLASS_COUNT]; MAX_CPUS] =
+    [const { [const { Mutex::new(Magazine::new()) }; SLAB_CLASS_COUNT] }; MAX_CPUS];
+
+/// 关闭中断后对 `cpu`（None 表示当前 CPU）的 magazine 执行 `f`
+///
+/// 中断处理中也可能分配内存，持有 magazine 锁时必须关中断。
+fn with_magazine<R>(cpu: Option<usize>, class: usize, f: impl FnOnce(&mut Magazine) -> R) -> R {
+    let irq = CurrentTlbArch::irq_save();
+    let cpu = cpu.unwrap_or_else(CurrentTlbArch::current_cpu);
+    let result = f(&mut MAGAZINES[cpu][class].lock());
+    CurrentTlbArch::irq_restore(irq);
+    result
+}
+
+/// 分配一个 `class` 大小类的对象，物理内存不足时返回空指针
+pub fn alloc(class: usize) -> *mut u8 {
+    with_magazine(None, class, |magazine| {
+        if magazine.count == 0 {
+            let mut cache = CACHES[class].lock();
+            while magazine.count < MAGAZINE_BATCH {
+                let object = cache.alloc();
+                if object.is_null() {
+                    break;
+                }
+                magazine.objects[magazine.count] = object;
+                magazine.count += 1;
+            }
+
+            if magazine.count == 0 {
+                return null_mut();
+            }
+        }
+
+        magazine.count -= 1;
+        magazine.allocs += 1;
+        magazine.objects[magazine.count]
+    })
+}
+
+/// 释放 `alloc` 分配的对象
+pub unsafe fn free(class: usize, ptr: *mut u8) {
+    with_magazine(None, class, |magazine| {
+        if magazine.count == MAGAZINE_SIZE {
+            let mut cache = CACHES[class].lock();
+            for &object in &magazine.objects[MAGAZINE_SIZE - MAGAZINE_BATCH..] {
+                unsafe { cache.free(object) };
+            }
+            magazine.count -= MAGAZINE_BATCH;
+
+            // 可能在持有物理页分配器锁时被调用（例如释放页面后丢弃 Vec），拿不到锁就留给回收线程
+            if cache.empty.len > EMPTY_SLABS_KEPT
+                && let Some(mut allocator) = FRAME_ALLOCATOR.try_lock()
+            {
+                unsafe { cache.release_empty(&mut *allocator, EMPTY_SLABS_KEPT) };
+            }
+        }
+
+        magazine.objects[magazine.count] = ptr;
+        magazine.count += 1;
+        magazine.frees += 1;
+    })
+}
+
+/// 清空所有 magazine 并归还全部空闲 slab，返回归还的页数
+///
+/// 调用时不能持有 FRAME_ALLOCATOR 的锁。
+pub fn reclaim() -> usize {
+    let mut released = 0;
+
+    for class in 0..SLAB_CLASS_COUNT {
+        for cpu in 0..MAX_CPUS {
+            with_magazine(Some(cpu), class, |magazine| {
+                if magazine.count == 0 {
+                    return;
+                }
+                let mut cache = CACHES[class].lock();
+                for &object in &magazine.objects[..magazine.count] {
+                    unsafe { cache.free(object) };
+                }
+                magazine.count = 0;
+            });
+        }
+
+        let irq = CurrentTlbArch::irq_save();
+        let mut cache = CACHES[class].lock();
+        released += unsafe { cache.release_empty(&mut *FRAME_ALLOCATOR.lock(), 0) };
+        drop(cache);
+        CurrentTlbArch::irq_restore(irq);
+    }
+
+    released
+}
+
+/// 一个大小类的统计信息
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlabInfo {
+    /// 对象大小（字节）
+    pub object_size: usize,
+    /// 每个 slab 的大小（字节）
+    pub slab_size: usize,
+    /// slab 总数
+    pub slabs: usize,
+    /// 完全空闲的 slab 数
+    pub empty_slabs: usize,
+    /// 所有 slab 能容纳的对象总数
+    pub objects: usize,
+    /// 正在使用的对象数
+    pub in_use: usize,
+    /// 缓存在各 CPU magazine 中的对象数
+    pub cached: usize,
+    /// 累计分配次数
+    pub allocs: u64,
+    /// 累计释放次数
+    pub frees: u64,
+}
+
+/// 获取 `class` 大小类的统计信息
+pub fn info(class: usize) -> SlabInfo {
+    let mut info = SlabInfo {
+        object_size: object_size(class),
+        slab_size: slab_size(class),
+        ..SlabInfo::default()
+    };
+
+    for cpu in 0..MAX_CPUS {
+        with_magazine(Some(cpu), class, |magazine| {
+            info.cached += magazine.count;
+            info.allocs += magazine.allocs;
+            info.frees += magazine.frees;
+        });
+    }
+
+    let irq = CurrentTlbArch::irq_save();
+    let cache = CACHES[class].lock();
+    info.slabs = cache.slabs;
+    info.empty_slabs = cache.empty.len;
+    info.objects = cache.slabs * capacity(class);
+    info.in_use = info
+        .objects
+        .saturating_sub(cache.free_objects + info.cached);
+    drop(cache);
+    CurrentTlbArch::irq_restore(irq);
+
+    info
+}
+
+/// 所有 slab 占用的字节数
+pub fn size() -> usize {
+    (0..SLAB_CLASS_COUNT)
+        .map(|class| {
+            let irq = CurrentTlbArch::irq_save();
+            let slabs = CACHES[class].lock().slabs;
+            CurrentTlbArch::irq_restore(irq);
+            slabs * slab_size(class)
+        })
+        .sum()
+}
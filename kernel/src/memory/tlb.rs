@@ -0,0 +1,224 @@
+//! 跨 CPU 的 TLB 刷新（shootdown）
+//!
+//! 每个地址空间记录当前加载了它的 CPU。修改页表后先刷新本 CPU，
+//! 再向其他活动 CPU 发送一次刷新 IPI，并等待它们全部确认后才返回，
+//! 调用者随后才能释放被解除映射的物理页。
+//!
+//! 系统调用期间中断是关闭的，等待确认的 CPU 会顺带处理发给自己的刷新请求，
+//! 避免两个 CPU 互相等待。发起刷新时不能持有 VMAR 或 VMO 的锁。
+
+use alloc::sync::Arc;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicU64, Ordering};
+use rmm::{FrameAllocator, PhysicalAddress};
+use spin::Mutex;
+
+use crate::{
+    arch::{CurrentTlbArch, tlb::TlbArch},
+    init::memory::{FRAME_ALLOCATOR, PAGE_SIZE},
+    memory::account::{self, MemoryKind},
+};
+
+/// 支持的最大 CPU 编号（CPU 掩码的位数）
+pub const MAX_CPUS: usize = 64;
+
+/// 一次刷新最多记录的区间数，超过则整体刷新
+const MAX_RANGES: usize = 8;
+/// 超过该页数时整体刷新，比逐页 invlpg 更快
+const FULL_FLUSH_PAGES: usize = 32;
+
+fn cpu_bit(cpu: usize) -> u64 {
+    debug_assert!(cpu < MAX_CPUS);
+    1 << cpu
+}
+
+/// 地址空间的活动 CPU 集合
+pub struct ActiveCpus {
+    mask: AtomicU64,
+    /// 地址空间拥有的顶层页表
+    ///
+    /// 切换到内核任务时不会换掉页表，最后加载它的 CPU 可能一直停在上面，
+    /// 因此顶层页表随最后一个引用（包括各 CPU 的 `LOADED`）一起释放。
+    top_table: Option<PhysicalAddress>,
+}
+
+impl ActiveCpus {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            mask: AtomicU64::new(0),
+            top_table: None,
+        })
+    }
+
+    /// 创建拥有顶层页表 `top_table` 的地址空间
+    pub fn with_top_table(top_table: PhysicalAddress) -> Arc<Self> {
+        Arc::new(Self {
+            mask: AtomicU64::new(0),
+            top_table: Some(top_table),
+        })
+    }
+
+    /// 当前加载了该地址空间的 CPU 掩码
+    pub fn mask(&self) -> u64 {
+        self.mask.load(Ordering::SeqCst)
+    }
+
+    /// 是否拥有顶层页表
+    pub fn owns_top_table(&self) -> bool {
+        self.top_table.is_some()
+    }
+}
+
+impl Drop for ActiveCpus {
+    fn drop(&mut self) {
+        if let Some(top_table) = self.top_table {
+            unsafe { FRAME_ALLOCATOR.lock().free_one(top_table) };
+            account::uncharge(None, MemoryKind::PageTable, 1);
+        }
+    }
+}
+
+/// 每个 CPU 当前加载的地址空间
+static LOADED: [Mutex<Option<Arc<ActiveCpus>>>; MAX_CPUS] = [const { Mutex::new(None) }; MAX_CPUS];
+
+/// 切换当前 CPU 的地址空间
+///
+/// `load` 负责真正切换页表。先登记到新地址空间再切换页表，
+/// 切换完成后才从旧地址空间中移除，保证不会漏掉刷新请求。
+pub fn switch_address_space(next: &Arc<ActiveCpus>, load: impl FnOnce()) {
+    let cpu = CurrentTlbArch::current_cpu();
+    let bit = cpu_bit(cpu);
+
+    let mut loaded = LOADED[cpu].lock();
+
+    next.mask.fetch_or(bit, Ordering::SeqCst);
+    load();
+
+    if let Some(prev) = loaded.replace(next.clone())
+        && !Arc::ptr_eq(&prev, next)
+    {
+        prev.mask.fetch_and(!bit, Ordering::SeqCst);
+    }
+}
+
+/// 待刷新的地址范围
+#[derive(Clone, Copy)]
+pub struct TlbBatch {
+    ranges: [(usize, usize); MAX_RANGES],
+    count: usize,
+    pages: usize,
+    full: bool,
+}
+
+impl TlbBatch {
+    pub const fn new() -> Self {
+        Self {
+            ranges: [(0, 0); MAX_RANGES],
+            count: 0,
+            pages: 0,
+            full: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.full && self.count == 0
+    }
+
+    /// 记录 `[start, start + size)` 需要刷新，与上一个区间相邻时合并
+    pub fn add(&mut self, start: usize, size: usize) {
+        if self.full || size == 0 {
+            return;
+        }
+
+        self.pages += size.div_ceil(PAGE_SIZE);
+        if self.pages > FULL_FLUSH_PAGES {
+            self.full = true;
+            return;
+        }
+
+        if self.count != 0 && self.ranges[self.count - 1].1 == start {
+            self.ranges[self.count - 1].1 = start + size;
+        } else if self.count < MAX_RANGES {
+            self.ranges[self.count] = (start, start + size);
+            self.count += 1;
+        } else {
+            self.full = true;
+        }
+    }
+
+    fn flush_local(&self) {
+        if self.full {
+            CurrentTlbArch::flush_all();
+        } else {
+            for &(start, end) in &self.ranges[..self.count] {
+                CurrentTlbArch::flush_range(start, end);
+            }
+        }
+    }
+
+    /// 在本 CPU 和 `cpus` 中的其他 CPU 上刷新，等待所有 CPU 确认后返回
+    pub fn flush(&self, cpus: &ActiveCpus) {
+        if self.is_empty() {
+            return;
+        }
+
+        let irq = CurrentTlbArch::irq_save();
+        let cpu = CurrentTlbArch::current_cpu();
+
+        self.flush_local();
+
+        let targets = cpus.mask() & !cpu_bit(cpu);
+        if targets != 0 {
+            let guard = loop {
+                if let Some(guard) = SHOOTDOWN_LOCK.try_lock() {
+                    break guard;
+                }
+                poll(cpu);
+                spin_loop();
+            };
+
+            *REQUEST.lock() = *self;
+            PENDING.fetch_or(targets, Ordering::SeqCst);
+            CurrentTlbArch::send_shootdown_ipi(targets);
+
+            while PENDING.load(Ordering::SeqCst) & targets != 0 {
+                poll(cpu);
+                spin_loop();
+            }
+
+            drop(guard);
+        }
+
+        CurrentTlbArch::irq_restore(irq);
+    }
+}
+
+impl Default for TlbBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 同一时刻只允许一个刷新请求
+static SHOOTDOWN_LOCK: Mutex<()> = Mutex::new(());
+/// 当前刷新请求
+static REQUEST: Mutex<TlbBatch> = Mutex::new(TlbBatch::new());
+/// 尚未确认的 CPU 掩码
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+/// 如果有发给 `cpu` 的刷新请求，执行并确认
+fn poll(cpu: usize) {
+    let bit = cpu_bit(cpu);
+    if PENDING.load(Ordering::SeqCst) & bit == 0 {
+        return;
+    }
+
+    let request = *REQUEST.lock();
+    request.flush_local();
+    PENDING.fetch_and(!bit, Ordering::SeqCst);
+}
+
+/// 刷新 IPI 的处理函数
+pub fn handle_shootdown_ipi() {
+    poll(CurrentTlbArch::current_cpu());
+}
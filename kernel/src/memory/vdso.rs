@@ -0,0 +1,52 @@
+//! vDSO 数据页：内核启动时填写，只读映射到每个进程的固定地址
+
+use alloc::sync::Arc;
+use rmm::VirtualAddress;
+use spin::Once;
+
+use crate::{
+    arch::{CurrentTimeArch, time::TimeArch},
+    init::memory::PAGE_SIZE,
+    layout,
+    object::{
+        vmar::{MappingFlags, Vmar, VmarError},
+        vmo::{Vmo, VmoOptions},
+    },
+    vdso::VdsoData,
+};
+
+static VDSO_VMO: Once<Arc<Vmo>> = Once::new();
+
+/// 创建并填写 vDSO 数据页，需要在创建第一个用户进程之前调用
+pub fn init() {
+    let mut data = VdsoData {
+        version: VdsoData::VERSION,
+        ..VdsoData::default()
+    };
+    CurrentTimeArch::fill_vdso(&mut data);
+
+    let vmo = Vmo::create(PAGE_SIZE, VmoOptions::COMMIT, None).expect("Failed to create vDSO");
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&data as *const VdsoData as *const u8, size_of::<VdsoData>())
+    };
+    vmo.write(0, bytes).expect("Failed to write vDSO");
+
+    VDSO_VMO.call_once(|| vmo);
+}
+
+/// 把 vDSO 数据页只读映射到进程的根 VMAR
+pub fn map_into(vmar: &Vmar) -> Result<(), VmarError> {
+    let Some(vmo) = VDSO_VMO.get() else {
+        return Ok(());
+    };
+
+    vmar.map(
+        vmo.clone(),
+        0,
+        PAGE_SIZE,
+        MappingFlags::READ | MappingFlags::SPECIFIC | MappingFlags::PERMANENT,
+        MappingFlags::READ,
+        Some(VirtualAddress::new(layout::VDSO_DATA_ADDR)),
+    )
+    .map(|_| ())
+}
@@ -24,6 +24,14 @@ pub const SYS_CHANNEL_CALL: usize = MICROKERNEL_SYSCALL_BASE + 0x24;
 // 时间
 pub const SYS_CLOCK_GET: usize = MICROKERNEL_SYSCALL_BASE + 0x30;
 pub const SYS_NANOSLEEP: usize = MICROKERNEL_SYSCALL_BASE + 0x31;
+pub const SYS_CLOCK_READ: usize = MICROKERNEL_SYSCALL_BASE + 0x32;
+pub const SYS_CLOCK_SET_UTC_OFFSET: usize = MICROKERNEL_SYSCALL_BASE + 0x33;
+
+// SYS_CLOCK_READ 的时钟 ID
+/// 单调时间，从启动开始计算
+pub const CLOCK_MONOTONIC: usize = 0;
+/// 墙上时间，从 Unix 纪元开始计算（UTC）
+pub const CLOCK_REALTIME: usize = 1;
 
 // 进程/线程
 pub const SYS_PROCESS_CREATE: usize = MICROKERNEL_SYSCALL_BASE + 0x40;
@@ -34,6 +42,8 @@ pub const SYS_YIELD: usize = MICROKERNEL_SYSCALL_BASE + 0x44;
 pub const SYS_PROCESS_GET_INIT_HANDLE: usize = MICROKERNEL_SYSCALL_BASE + 0x45;
 pub const SYS_PROCESS_WAIT: usize = MICROKERNEL_SYSCALL_BASE + 0x46;
 pub const SYS_PROCESS_GET_VMAR_HANDLE: usize = MICROKERNEL_SYSCALL_BASE + 0x47;
+pub const SYS_PROCESS_GET_MEMORY_INFO: usize = MICROKERNEL_SYSCALL_BASE + 0x48;
+pub const SYS_PROCESS_SET_MEMORY_LIMIT: usize = MICROKERNEL_SYSCALL_BASE + 0x49;
 
 pub const SYS_FUTEX_WAIT: usize = MICROKERNEL_SYSCALL_BASE + 0x50;
 pub const SYS_FUTEX_WAKE: usize = MICROKERNEL_SYSCALL_BASE + 0x51;
@@ -47,10 +57,31 @@ pub const SYS_VMO_WRITE: usize = MICROKERNEL_SYSCALL_BASE + 0x64;
 pub const SYS_VMO_GET_SIZE: usize = MICROKERNEL_SYSCALL_BASE + 0x65;
 pub const SYS_VMO_SET_SIZE: usize = MICROKERNEL_SYSCALL_BASE + 0x66;
 pub const SYS_VMO_GET_PHYS: usize = MICROKERNEL_SYSCALL_BASE + 0x67;
+pub const SYS_VMO_PIN: usize = MICROKERNEL_SYSCALL_BASE + 0x68;
+pub const SYS_VMO_OP_RANGE: usize = MICROKERNEL_SYSCALL_BASE + 0x6a;
+pub const SYS_VMO_SET_CACHE_POLICY: usize = MICROKERNEL_SYSCALL_BASE + 0x6b;
+pub const SYS_VMO_GET_COMMITTED: usize = MICROKERNEL_SYSCALL_BASE + 0x6c;
 
 pub const SYS_VMAR_MAP: usize = MICROKERNEL_SYSCALL_BASE + 0x70;
 pub const SYS_VMAR_UNMAP: usize = MICROKERNEL_SYSCALL_BASE + 0x71;
 pub const SYS_VMAR_PROTECT: usize = MICROKERNEL_SYSCALL_BASE + 0x72;
+pub const SYS_VMAR_ALLOCATE: usize = MICROKERNEL_SYSCALL_BASE + 0x73;
+pub const SYS_VMAR_DESTROY: usize = MICROKERNEL_SYSCALL_BASE + 0x74;
+
+// 随机数
+pub const SYS_CPRNG_DRAW: usize = MICROKERNEL_SYSCALL_BASE + 0x80;
+
+// 调试日志
+pub const SYS_DEBUGLOG_CREATE: usize = MICROKERNEL_SYSCALL_BASE + 0x90;
+pub const SYS_DEBUGLOG_READ: usize = MICROKERNEL_SYSCALL_BASE + 0x91;
+
+// 事件跟踪和采样分析
+pub const SYS_KTRACE_CONTROL: usize = MICROKERNEL_SYSCALL_BASE + 0xa0;
+pub const SYS_PROFILE_CONTROL: usize = MICROKERNEL_SYSCALL_BASE + 0xa1;
+
+// 设备中断
+pub const SYS_INTERRUPT_CREATE: usize = MICROKERNEL_SYSCALL_BASE + 0xb0;
+pub const SYS_INTERRUPT_ACK: usize = MICROKERNEL_SYSCALL_BASE + 0xb1;
 
 pub const SYS_GET_TID: usize = MICROKERNEL_SYSCALL_BASE + 0x100;
 pub const SYS_GET_PID: usize = MICROKERNEL_SYSCALL_BASE + 0x101;
@@ -59,8 +90,15 @@ pub const SYS_LOAD_TASK_REGISTERS: usize = MICROKERNEL_SYSCALL_BASE + 0x110;
 pub const SYS_STORE_TASK_REGISTERS: usize = MICROKERNEL_SYSCALL_BASE + 0x111;
 
 pub const SYS_KRES_GET_RSDP: usize = MICROKERNEL_SYSCALL_BASE + 0x200;
+pub const SYS_KRES_GET_MEMORY_PRESSURE_EVENT: usize = MICROKERNEL_SYSCALL_BASE + 0x201;
+pub const SYS_KRES_GET_MEMORY_INFO: usize = MICROKERNEL_SYSCALL_BASE + 0x202;
+pub const SYS_KRES_GET_SLAB_INFO: usize = MICROKERNEL_SYSCALL_BASE + 0x203;
+pub const SYS_KRES_GET_FRAMEBUFFER: usize = MICROKERNEL_SYSCALL_BASE + 0x204;
+pub const SYS_KRES_GET_SERIAL_CONSOLE: usize = MICROKERNEL_SYSCALL_BASE + 0x205;
 
 #[cfg(target_arch = "x86_64")]
 pub const SYS_KRES_GET_FSBASE: usize = MICROKERNEL_SYSCALL_BASE + 0x1000;
 #[cfg(target_arch = "x86_64")]
 pub const SYS_KRES_SET_FSBASE: usize = MICROKERNEL_SYSCALL_BASE + 0x1001;
+#[cfg(target_arch = "x86_64")]
+pub const SYS_IOPORT_REQUEST: usize = MICROKERNEL_SYSCALL_BASE + 0x1002;
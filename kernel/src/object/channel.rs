@@ -4,6 +4,9 @@ use alloc::vec::Vec;
 use core::any::Any;
 use spin::Mutex;
 
+use crate::ktrace::{KTRACE_EVENT_CHANNEL_RECV, KTRACE_EVENT_CHANNEL_SEND, KTRACE_GROUP_IPC};
+use crate::trace;
+
 use super::{KernelObject, ObjectType, Rights, SignalObserver, Signals, wait_queue::WaitQueue};
 
 /// IPC 消息
@@ -133,6 +136,16 @@ impl Channel {
                 return Err(ChannelError::Full);
             }
 
+            if trace::enabled(KTRACE_GROUP_IPC) {
+                trace::record(
+                    KTRACE_EVENT_CHANNEL_SEND,
+                    [
+                        peer.trace_id(),
+                        msg.data.len() as u64,
+                        msg.objects.len() as u64,
+                    ],
+                );
+            }
             peer_inner.messages.push_back(msg);
 
             // 设置 READABLE
@@ -193,11 +206,26 @@ impl Channel {
         }
     }
 
+    /// 跟踪记录中标识这一端的 ID
+    fn trace_id(&self) -> u64 {
+        self as *const Self as u64
+    }
+
     /// 非阻塞接收
     pub fn try_recv(&self) -> Result<Message, ChannelError> {
         let mut inner = self.inner.lock();
 
         if let Some(msg) = inner.messages.pop_front() {
+            if trace::enabled(KTRACE_GROUP_IPC) {
+                trace::record(
+                    KTRACE_EVENT_CHANNEL_RECV,
+                    [
+                        self.trace_id(),
+                        msg.data.len() as u64,
+                        msg.objects.len() as u64,
+                    ],
+                );
+            }
             // 更新信号
             if inner.messages.is_empty() {
                 inner.signals.remove(Signals::READABLE);
@@ -0,0 +1,314 @@
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use crate::{
+    arch::{CurrentTimeArch, time::TimeArch},
+    debuglog::{LOG_MESSAGE_MAX, LOG_NAME_MAX, LogRecordHeader},
+};
+
+use super::{KernelObject, ObjectType, SignalObserver, SignalState, Signals};
+
+/// 环形缓冲区中的记录数，写满后覆盖最旧的记录
+const SLOT_COUNT: usize = 512;
+/// 内核日志获取缓冲区锁时的最大尝试次数
+///
+/// 持有锁时只做内存复制；长时间拿不到锁说明是同一 CPU 上的中断重入，此时放弃保存。
+const KERNEL_LOCK_ATTEMPTS: usize = 1 << 16;
+
+#[derive(Clone, Copy)]
+struct Slot {
+    header: LogRecordHeader,
+    name: [u8; LOG_NAME_MAX],
+    message: [u8; LOG_MESSAGE_MAX],
+}
+
+impl Slot {
+    const EMPTY: Slot = Slot {
+        header: LogRecordHeader {
+            sequence: 0,
+            timestamp: 0,
+            pid: 0,
+            tid: 0,
+            level: 0,
+            dropped: 0,
+            name_len: 0,
+            message_len: 0,
+        },
+        name: [0; LOG_NAME_MAX],
+        message: [0; LOG_MESSAGE_MAX],
+    };
+
+    fn name(&self) -> &[u8] {
+        &self.name[..self.header.name_len as usize]
+    }
+
+    fn message(&self) -> &[u8] {
+        &self.message[..self.header.message_len as usize]
+    }
+}
+
+/// 日志环形缓冲区
+struct Ring {
+    slots: [Slot; SLOT_COUNT],
+    /// 下一条记录的序号
+    next: u64,
+}
+
+impl Ring {
+    /// 仍保留在缓冲区中的最旧记录的序号
+    fn oldest(&self) -> u64 {
+        self.next.saturating_sub(SLOT_COUNT as u64)
+    }
+
+    fn push(&mut self, mut slot: Slot) {
+        slot.header.sequence = self.next;
+        self.slots[self.next as usize % SLOT_COUNT] = slot;
+        self.next += 1;
+    }
+}
+
+static RING: Mutex<Ring> = Mutex::new(Ring {
+    slots: [Slot::EMPTY; SLOT_COUNT],
+    next: 0,
+});
+
+/// 所有读取器，写入新记录后需要通知
+static READERS: Mutex<Vec<Weak<DebugLogReader>>> = Mutex::new(Vec::new());
+/// 有尚未通知读取器的新记录
+static NOTIFY_PENDING: AtomicBool = AtomicBool::new(false);
+/// 时钟是否已经可用，之前的记录时间戳为 0
+static CLOCK_READY: AtomicBool = AtomicBool::new(false);
+
+/// 时钟初始化之后调用，此后的记录带有时间戳
+pub fn init() {
+    CLOCK_READY.store(true, Ordering::Release);
+}
+
+/// 截断到不超过 `max` 字节的字符边界
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// 把格式化输出写入定长缓冲区，超出部分丢弃
+struct MessageWriter {
+    buf: [u8; LOG_MESSAGE_MAX],
+    len: usize,
+}
+
+impl Write for MessageWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let s = truncate(s, LOG_MESSAGE_MAX - self.len);
+        self.buf[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+fn make_slot(level: u32, pid: usize, tid: usize, name: &str) -> Slot {
+    let name = truncate(name, LOG_NAME_MAX);
+    let mut slot = Slot::EMPTY;
+    if CLOCK_READY.load(Ordering::Acquire) {
+        slot.header.timestamp = CurrentTimeArch::nano_time();
+    }
+    slot.header.pid = pid as u64;
+    slot.header.tid = tid as u64;
+    slot.header.level = level;
+    slot.header.name_len = name.len() as u16;
+    slot.name[..name.len()].copy_from_slice(name.as_bytes());
+    slot
+}
+
+/// 保存一条内核日志
+///
+/// 可能在任意上下文（包括中断处理）中调用，因此不会分配内存，也不会直接通知读取器。
+pub fn write_kernel(level: u32, args: fmt::Arguments) {
+    let mut writer = MessageWriter {
+        buf: [0; LOG_MESSAGE_MAX],
+        len: 0,
+    };
+    let _ = writer.write_fmt(args);
+
+    let mut slot = make_slot(level, 0, 0, "kernel");
+    slot.header.message_len = writer.len as u16;
+    slot.message = writer.buf;
+
+    for _ in 0..KERNEL_LOCK_ATTEMPTS {
+        if let Some(mut ring) = RING.try_lock() {
+            ring.push(slot);
+            NOTIFY_PENDING.store(true, Ordering::Release);
+            return;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// 保存一条用户日志，末尾的换行不保存
+pub fn write_user(level: u32, pid: usize, tid: usize, name: &str, message: &str) {
+    let message = truncate(message.trim_end_matches('\n'), LOG_MESSAGE_MAX);
+    let mut slot = make_slot(level, pid, tid, name);
+    slot.header.message_len = message.len() as u16;
+    slot.message[..message.len()].copy_from_slice(message.as_bytes());
+
+    RING.lock().push(slot);
+    NOTIFY_PENDING.store(true, Ordering::Release);
+}
+
+/// 有新记录时置位所有读取器的 READABLE
+///
+/// 信号观察者会唤醒任务、操作 Port，不能在写入日志的任意上下文中执行，
+/// 因此在系统调用返回前统一检查。
+pub fn notify_readers() {
+    if !NOTIFY_PENDING.swap(false, Ordering::Acquire) {
+        return;
+    }
+
+    let readers: Vec<Arc<DebugLogReader>> = {
+        let mut readers = READERS.lock();
+        readers.retain(|reader| reader.strong_count() > 0);
+        readers.iter().filter_map(Weak::upgrade).collect()
+    };
+    for reader in readers {
+        reader.signal_set(Signals::READABLE);
+    }
+}
+
+/// 读取出的一条记录
+pub struct DebugLogRecord {
+    pub header: LogRecordHeader,
+    pub name: [u8; LOG_NAME_MAX],
+    pub message: [u8; LOG_MESSAGE_MAX],
+}
+
+impl DebugLogRecord {
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.header.name_len as usize]
+    }
+
+    pub fn message(&self) -> &[u8] {
+        &self.message[..self.header.message_len as usize]
+    }
+}
+
+struct ReaderState {
+    /// 下一条要读取的记录序号
+    cursor: u64,
+    /// 只返回不低于这个严重程度的记录（数值不大于它）
+    max_level: u32,
+    /// 只返回这个进程的记录
+    pid: Option<usize>,
+    /// 累计丢失的记录数，在下一次返回记录时报告
+    dropped: u64,
+}
+
+/// 调试日志读取器
+///
+/// 每个读取器有独立的读取位置和过滤条件。有新记录时置位 READABLE，
+/// 读到没有符合条件的记录时清除。
+pub struct DebugLogReader {
+    state: Mutex<ReaderState>,
+    signal_state: Mutex<SignalState>,
+}
+
+impl DebugLogReader {
+    /// 创建读取器，`from_now` 为 true 时跳过已有的记录
+    pub fn new(max_level: u32, pid: Option<usize>, from_now: bool) -> Arc<Self> {
+        let ring = RING.lock();
+        let cursor = if from_now { ring.next } else { ring.oldest() };
+        let has_records = cursor < ring.next;
+        drop(ring);
+
+        let reader = Arc::new(Self {
+            state: Mutex::new(ReaderState {
+                cursor,
+                max_level,
+                pid,
+                dropped: 0,
+            }),
+            signal_state: Mutex::new(SignalState::new()),
+        });
+        if has_records {
+            reader.signal_set(Signals::READABLE);
+        }
+        READERS.lock().push(Arc::downgrade(&reader));
+        reader
+    }
+
+    /// 读取下一条符合条件的记录，没有时返回 None 并清除 READABLE
+    pub fn read(&self) -> Option<DebugLogRecord> {
+        let mut state = self.state.lock();
+        let ring = RING.lock();
+
+        let oldest = ring.oldest();
+        if state.cursor < oldest {
+            state.dropped += oldest - state.cursor;
+            state.cursor = oldest;
+        }
+
+        while state.cursor < ring.next {
+            let slot = &ring.slots[state.cursor as usize % SLOT_COUNT];
+            state.cursor += 1;
+
+            if slot.header.level > state.max_level
+                || state.pid.is_some_and(|pid| pid as u64 != slot.header.pid)
+            {
+                continue;
+            }
+
+            let mut record = DebugLogRecord {
+                header: slot.header,
+                name: [0; LOG_NAME_MAX],
+                message: [0; LOG_MESSAGE_MAX],
+            };
+            record.header.dropped = state.dropped.min(u32::MAX as u64) as u32;
+            record.name[..slot.name().len()].copy_from_slice(slot.name());
+            record.message[..slot.message().len()].copy_from_slice(slot.message());
+            state.dropped = 0;
+            return Some(record);
+        }
+
+        drop(ring);
+        self.signal_clear(Signals::READABLE);
+        None
+    }
+}
+
+impl KernelObject for DebugLogReader {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::DebugLog
+    }
+
+    fn signals(&self) -> Signals {
+        self.signal_state.lock().get()
+    }
+
+    fn signal_set(&self, signals: Signals) {
+        self.signal_state.lock().set(signals);
+    }
+
+    fn signal_clear(&self, signals: Signals) {
+        self.signal_state.lock().clear(signals);
+    }
+
+    fn add_signal_observer(&self, observer: SignalObserver) {
+        self.signal_state.lock().add_observer(observer);
+    }
+
+    fn remove_signal_observer(&self, key: u64) {
+        self.signal_state.lock().remove_observer(key);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
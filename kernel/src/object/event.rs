@@ -0,0 +1,51 @@
+use alloc::sync::Arc;
+use core::any::Any;
+use spin::Mutex;
+
+use super::{KernelObject, ObjectType, SignalObserver, SignalState, Signals};
+
+/// 事件对象
+///
+/// 只携带信号状态，由内核或持有者设置/清除信号。
+pub struct Event {
+    signal_state: Mutex<SignalState>,
+}
+
+impl Event {
+    /// 创建事件
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            signal_state: Mutex::new(SignalState::new()),
+        })
+    }
+}
+
+impl KernelObject for Event {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Event
+    }
+
+    fn signals(&self) -> Signals {
+        self.signal_state.lock().get()
+    }
+
+    fn signal_set(&self, signals: Signals) {
+        self.signal_state.lock().set(signals);
+    }
+
+    fn signal_clear(&self, signals: Signals) {
+        self.signal_state.lock().clear(signals);
+    }
+
+    fn add_signal_observer(&self, observer: SignalObserver) {
+        self.signal_state.lock().add_observer(observer);
+    }
+
+    fn remove_signal_observer(&self, key: u64) {
+        self.signal_state.lock().remove_observer(key);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
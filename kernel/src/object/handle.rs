@@ -1,4 +1,5 @@
 use super::KernelObject;
+use crate::random;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -65,24 +66,38 @@ pub struct HandleEntry {
     pub rights: Rights,
 }
 
+/// 句柄值的有效位，保持为正的 i32 以便用户态直接作为返回值使用
+const HANDLE_VALUE_MASK: u32 = 0x7fff_ffff;
+
 /// 进程句柄表
+///
+/// 句柄值由内核 CSPRNG 随机生成，进程无法猜测其他句柄的值，
+/// 也不能依赖句柄的分配顺序。
 pub struct HandleTable {
     handles: BTreeMap<Handle, HandleEntry>,
-    next_id: u32,
 }
 
 impl HandleTable {
     pub fn new() -> Self {
         Self {
             handles: BTreeMap::new(),
-            next_id: 1, // 0 是无效句柄
+        }
+    }
+
+    /// 分配一个未使用的句柄值
+    fn alloc_handle(&self) -> Handle {
+        loop {
+            let handle = Handle(random::next_u64() as u32 & HANDLE_VALUE_MASK);
+            // 0 是无效句柄
+            if handle.is_valid() && !self.handles.contains_key(&handle) {
+                return handle;
+            }
         }
     }
 
     /// 插入对象，返回句柄
     pub fn insert(&mut self, object: Arc<dyn KernelObject>, rights: Rights) -> Handle {
-        let handle = Handle(self.next_id);
-        self.next_id += 1;
+        let handle = self.alloc_handle();
         self.handles.insert(handle, HandleEntry { object, rights });
         handle
     }
@@ -124,8 +139,7 @@ impl HandleTable {
             return None;
         }
         let actual_rights = entry.rights & new_rights;
-        let new_handle = Handle(self.next_id);
-        self.next_id += 1;
+        let new_handle = self.alloc_handle();
         self.handles.insert(
             new_handle,
             HandleEntry {
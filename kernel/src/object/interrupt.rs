@@ -0,0 +1,143 @@
+//! 设备中断对象
+//!
+//! 特权进程为一个设备中断创建中断对象后，内核把该中断路由到这里。中断到达时内核屏蔽它并设置
+//! `SIGNALED`，驱动处理完设备后调用 [`Interrupt::ack`] 清除信号并解除屏蔽。
+//! 每个中断同时只能绑定一个对象，对象销毁时重新屏蔽中断。
+
+use alloc::sync::{Arc, Weak};
+use core::any::Any;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+use super::{KernelObject, ObjectType, SignalObserver, SignalState, Signals};
+use crate::arch::{CurrentIrqArch, irq::IrqArch};
+
+const DEVICE_IRQ_COUNT: usize = <CurrentIrqArch as IrqArch>::DEVICE_IRQ_COUNT;
+
+/// 每个设备中断绑定的对象
+static BOUND: Mutex<[Option<Weak<Interrupt>>; DEVICE_IRQ_COUNT]> =
+    Mutex::new([const { None }; DEVICE_IRQ_COUNT]);
+
+/// 内核自己使用的中断，不能交给用户态
+static RESERVED: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptError {
+    /// 中断号超出范围
+    InvalidIrq,
+    /// 中断已经被内核或其他中断对象使用
+    Busy,
+}
+
+/// 设备中断对象
+pub struct Interrupt {
+    irq: u8,
+    signal_state: Mutex<SignalState>,
+}
+
+/// 标记中断 `irq` 由内核使用
+pub fn reserve(irq: u8) {
+    RESERVED.fetch_or(1u64 << irq, Ordering::SeqCst);
+}
+
+fn reserved(irq: u8) -> bool {
+    RESERVED.load(Ordering::SeqCst) & (1u64 << irq) != 0
+}
+
+impl Interrupt {
+    /// 绑定设备中断 `irq`，创建后中断即开始投递
+    pub fn create(irq: usize) -> Result<Arc<Self>, InterruptError> {
+        let irq = u8::try_from(irq)
+            .ok()
+            .filter(|&irq| (irq as usize) < DEVICE_IRQ_COUNT)
+            .ok_or(InterruptError::InvalidIrq)?;
+        if reserved(irq) {
+            return Err(InterruptError::Busy);
+        }
+
+        let mut bound = BOUND.lock();
+        let slot = &mut bound[irq as usize];
+        if slot
+            .as_ref()
+            .is_some_and(|object| object.strong_count() > 0)
+        {
+            return Err(InterruptError::Busy);
+        }
+
+        let interrupt = Arc::new(Self {
+            irq,
+            signal_state: Mutex::new(SignalState::new()),
+        });
+        *slot = Some(Arc::downgrade(&interrupt));
+        CurrentIrqArch::route_device_irq(irq);
+        CurrentIrqArch::set_device_irq_masked(irq, false);
+
+        Ok(interrupt)
+    }
+
+    pub fn irq(&self) -> u8 {
+        self.irq
+    }
+
+    /// 应答中断：清除 `SIGNALED` 并解除屏蔽
+    pub fn ack(&self) {
+        self.signal_state.lock().clear(Signals::SIGNALED);
+        CurrentIrqArch::set_device_irq_masked(self.irq, false);
+    }
+}
+
+impl Drop for Interrupt {
+    fn drop(&mut self) {
+        let mut bound = BOUND.lock();
+        let slot = &mut bound[self.irq as usize];
+        // 同一个中断可能已经绑定了新的对象
+        if slot
+            .as_ref()
+            .is_some_and(|object| core::ptr::eq(object.as_ptr(), self))
+        {
+            *slot = None;
+            CurrentIrqArch::set_device_irq_masked(self.irq, true);
+        }
+    }
+}
+
+/// 设备中断到达，由体系结构的中断入口在屏蔽该中断后调用
+pub fn handle_irq(irq: u8) {
+    let interrupt = BOUND
+        .lock()
+        .get(irq as usize)
+        .and_then(|slot| slot.as_ref()?.upgrade());
+    if let Some(interrupt) = interrupt {
+        interrupt.signal_state.lock().set(Signals::SIGNALED);
+    }
+}
+
+impl KernelObject for Interrupt {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Interrupt
+    }
+
+    fn signals(&self) -> Signals {
+        self.signal_state.lock().get()
+    }
+
+    fn signal_set(&self, signals: Signals) {
+        self.signal_state.lock().set(signals);
+    }
+
+    fn signal_clear(&self, signals: Signals) {
+        self.signal_state.lock().clear(signals);
+    }
+
+    fn add_signal_observer(&self, observer: SignalObserver) {
+        self.signal_state.lock().add_observer(observer);
+    }
+
+    fn remove_signal_observer(&self, key: u64) {
+        self.signal_state.lock().remove_observer(key);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -1,5 +1,9 @@
 pub mod channel;
+pub mod debuglog;
+pub mod event;
 pub mod handle;
+pub mod interrupt;
+pub mod pmt;
 pub mod port;
 pub mod process;
 pub mod signal;
@@ -8,6 +12,7 @@ pub mod vmo;
 pub mod wait_queue;
 
 pub use channel::{Channel, Message};
+pub use event::Event;
 pub use handle::{Handle, HandleEntry, HandleTable, Rights};
 pub use port::{BindOptions, PacketType, Port, PortPacket};
 pub use process::{ArcProcess, Process, WeakArcProcess, layout};
@@ -31,6 +36,9 @@ pub enum ObjectType {
     Process = 6,
     Thread = 7,
     Vmar = 9,
+    DebugLog = 10,
+    Interrupt = 11,
+    Pmt = 12,
 }
 
 /// 信号观察者
@@ -0,0 +1,84 @@
+//! 固定内存令牌（PMT）
+//!
+//! pin 住 VMO 的一段范围得到的对象。令牌存在期间这些页面不会被 decommit、收缩或回收，
+//! 可以直接交给设备做 DMA。令牌销毁时（包括句柄随进程退出一起关闭）自动解除 pin，
+//! 驱动崩溃后页面不会一直被 pin 住。
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use rmm::PhysicalAddress;
+use spin::Mutex;
+
+use super::{KernelObject, ObjectType, SignalObserver, SignalState, Signals, vmo::Vmo};
+
+/// 固定内存令牌
+pub struct Pmt {
+    /// 令牌持有 VMO 的引用，VMO 不会先于令牌释放
+    vmo: Arc<Vmo>,
+    /// pin 住的页面范围 `[start_page, end_page)`
+    start_page: usize,
+    end_page: usize,
+    /// 物理页面段 (物理地址, 长度)
+    runs: Vec<(PhysicalAddress, usize)>,
+    signal_state: Mutex<SignalState>,
+}
+
+impl Pmt {
+    /// 由 `Vmo::pin` 在计入 pin 计数之后创建
+    pub(super) fn new(
+        vmo: Arc<Vmo>,
+        start_page: usize,
+        end_page: usize,
+        runs: Vec<(PhysicalAddress, usize)>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            vmo,
+            start_page,
+            end_page,
+            runs,
+            signal_state: Mutex::new(SignalState::new()),
+        })
+    }
+
+    /// 物理页面段列表，相邻的物理连续页面已合并为一段
+    pub fn runs(&self) -> &[(PhysicalAddress, usize)] {
+        &self.runs
+    }
+}
+
+impl Drop for Pmt {
+    fn drop(&mut self) {
+        self.vmo.unpin_pages(self.start_page, self.end_page);
+    }
+}
+
+impl KernelObject for Pmt {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Pmt
+    }
+
+    fn signals(&self) -> Signals {
+        self.signal_state.lock().get()
+    }
+
+    fn signal_set(&self, signals: Signals) {
+        self.signal_state.lock().set(signals);
+    }
+
+    fn signal_clear(&self, signals: Signals) {
+        self.signal_state.lock().clear(signals);
+    }
+
+    fn add_signal_observer(&self, observer: SignalObserver) {
+        self.signal_state.lock().add_observer(observer);
+    }
+
+    fn remove_signal_observer(&self, key: u64) {
+        self.signal_state.lock().remove_observer(key);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
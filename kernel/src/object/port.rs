@@ -7,6 +7,8 @@ use spin::Mutex;
 
 use crate::arch::CurrentTimeArch;
 use crate::arch::time::TimeArch;
+use crate::ktrace::{KTRACE_EVENT_PORT_QUEUE, KTRACE_GROUP_IPC};
+use crate::trace;
 
 use super::{KernelObject, ObjectType, SignalObserver, Signals, wait_queue::WaitQueue};
 
@@ -199,6 +201,9 @@ impl Port {
 
     /// 对象信号触发时的回调
     fn on_object_signal(&self, key: u64, signals: Signals) {
+        if trace::enabled(KTRACE_GROUP_IPC) {
+            trace::record(KTRACE_EVENT_PORT_QUEUE, [self.trace_id(), key, 0]);
+        }
         let mut inner = self.inner.lock();
 
         // 创建事件包
@@ -229,6 +234,9 @@ impl Port {
 
     /// 手动投递事件
     pub fn queue(&self, packet: PortPacket) {
+        if trace::enabled(KTRACE_GROUP_IPC) {
+            trace::record(KTRACE_EVENT_PORT_QUEUE, [self.trace_id(), packet.key, 0]);
+        }
         {
             let mut inner = self.inner.lock();
             inner.packets.push_back(packet);
@@ -237,6 +245,11 @@ impl Port {
         self.waiters.wake_one();
     }
 
+    /// 跟踪记录中标识这个 Port 的 ID
+    fn trace_id(&self) -> u64 {
+        self as *const Self as u64
+    }
+
     /// 等待事件（阻塞）
     pub fn wait(
         &self,
@@ -4,6 +4,7 @@ use alloc::{
     vec::Vec,
 };
 use core::any::Any;
+use core::ops::Range;
 use core::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
 use rmm::{PhysicalAddress, VirtualAddress};
 use spin::{Mutex, RwLock};
@@ -13,6 +14,7 @@ use crate::{
     task::{register_task, start_task, stop_task},
 };
 use crate::{
+    memory::account::MemoryAccount,
     object::vmar::Vmar,
     task::{ArcTask, ProcessState, Task, WeakArcTask},
 };
@@ -33,6 +35,9 @@ pub type WeakArcProcess = Weak<RwLock<Process>>;
 /// 全局进程 ID 计数器
 static NEXT_PID: AtomicUsize = AtomicUsize::new(1);
 
+/// 每个进程最多可以持有的 I/O 端口范围数
+pub const PROCESS_MAX_IO_PORT_RANGES: usize = 8;
+
 /// 进程对象
 pub struct Process {
     /// 进程 ID
@@ -70,6 +75,12 @@ pub struct Process {
 
     /// 根 VMAR（进程的地址空间）
     root_vmar: Option<Arc<Vmar>>,
+
+    /// 是否允许调用特权系统调用，见 `syscall::process::require_privileged`
+    privileged: bool,
+
+    /// 允许直接访问的 I/O 端口范围
+    io_ports: Vec<Range<u16>>,
 }
 
 impl Process {
@@ -99,7 +110,15 @@ impl Process {
 
         let user_base = VirtualAddress::new(layout::USER_SPACE_START);
         let user_size = layout::USER_SPACE_END - layout::USER_SPACE_START;
-        let root_vmar = Vmar::create_root(user_base, user_size, user_base.data(), page_table_addr);
+        // 子进程的用量同时计入父进程
+        let parent_account = parent.as_ref().and_then(|p| p.read().account());
+        let root_vmar = Vmar::create_root(
+            user_base,
+            user_size,
+            user_base.data(),
+            page_table_addr,
+            MemoryAccount::new(parent_account),
+        );
 
         let process = Arc::new(RwLock::new(Process {
             pid,
@@ -116,6 +135,8 @@ impl Process {
             signal_state: SignalState::new(),
             self_ref: None,
             root_vmar: Some(root_vmar),
+            privileged: false,
+            io_ports: Vec::new(),
         }));
 
         // 设置自身引用
@@ -157,6 +178,36 @@ impl Process {
         &self.name
     }
 
+    /// 是否允许调用特权系统调用
+    pub fn is_privileged(&self) -> bool {
+        self.privileged
+    }
+
+    pub fn set_privileged(&mut self, privileged: bool) {
+        self.privileged = privileged;
+    }
+
+    /// 允许直接访问的 I/O 端口范围
+    pub fn io_ports(&self) -> &[Range<u16>] {
+        &self.io_ports
+    }
+
+    /// 放行 I/O 端口范围 `ports`，范围数达到上限时返回 false
+    pub fn add_io_ports(&mut self, ports: Range<u16>) -> bool {
+        if self
+            .io_ports
+            .iter()
+            .any(|range| range.start <= ports.start && ports.end <= range.end)
+        {
+            return true;
+        }
+        if self.io_ports.len() >= PROCESS_MAX_IO_PORT_RANGES {
+            return false;
+        }
+        self.io_ports.push(ports);
+        true
+    }
+
     pub fn state(&self) -> ProcessState {
         self.state
     }
@@ -207,7 +258,12 @@ impl Process {
         }
 
         let process_arc = self.self_arc()?;
-        let task = Task::new_user(format!("{}/main", self.name), process_arc);
+        let task = Task::new_user(
+            format!("{}/main", self.name),
+            process_arc,
+            self.root_vmar(),
+            self.account(),
+        )?;
 
         {
             let mut t = task.write();
@@ -230,7 +286,7 @@ impl Process {
         stack_top: usize,
     ) -> Option<ArcTask> {
         let process_arc = self.self_arc()?;
-        let task = Task::new_user(name, process_arc);
+        let task = Task::new_user(name, process_arc, self.root_vmar(), self.account())?;
 
         {
             let mut t = task.write();
@@ -281,8 +337,8 @@ impl Process {
         }
     }
 
-    /// 线程退出回调
-    pub fn on_thread_exit(&mut self, task: ArcTask) {
+    /// 线程退出回调，返回进程是否因此退出
+    pub fn on_thread_exit(&mut self, task: ArcTask) -> bool {
         // 从线程列表移除
         self.threads.retain(|t| {
             t.upgrade()
@@ -301,10 +357,15 @@ impl Process {
         if is_main || self.threads.is_empty() {
             let exit_code = task.read().exit_code().unwrap_or(0);
             self.exit(exit_code);
+            return true;
         }
+        false
     }
 
     /// 进程退出
+    ///
+    /// 地址空间和句柄表由调用者在释放进程锁之后销毁（见 `take_handles`）：
+    /// 销毁地址空间时要等待其他 CPU 刷新 TLB，而它们可能正关着中断等待这把锁。
     pub fn exit(&mut self, exit_code: i32) {
         if self.state == ProcessState::Exited {
             return;
@@ -313,18 +374,20 @@ impl Process {
         self.state = ProcessState::Exited;
         self.exit_code.store(exit_code, Ordering::SeqCst);
 
-        // 终止所有剩余线程
+        // 终止所有剩余线程，不能再通过 `exit_task` 回调本进程（调用者持有进程锁）
         for thread_weak in self.threads.drain(..) {
             if let Some(thread) = thread_weak.upgrade() {
-                crate::task::exit_task(thread, exit_code);
+                crate::task::terminate_task(&thread, exit_code);
             }
         }
 
         // 设置 TERMINATED 信号
         self.signal_state.set(Signals::TERMINATED);
+    }
 
-        // 清理句柄表
-        // self.handles.clear();
+    /// 取出句柄表，进程退出后关闭其中的句柄（例如释放驱动遗留的固定内存令牌）
+    pub fn take_handles(&mut self) -> HandleTable {
+        core::mem::replace(&mut self.handles, HandleTable::new())
     }
 
     /// 添加初始句柄
@@ -349,6 +412,11 @@ impl Process {
     pub fn root_vmar(&self) -> Option<Arc<Vmar>> {
         self.root_vmar.clone()
     }
+
+    /// 进程的内存账户（即根 VMAR 的账户）
+    pub fn account(&self) -> Option<Arc<MemoryAccount>> {
+        self.root_vmar.as_ref().map(|vmar| vmar.account().clone())
+    }
 }
 
 #[allow(unused_variables)]
@@ -1,17 +1,32 @@
 use alloc::collections::BTreeMap;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use bitflags::bitflags;
 use core::any::Any;
-use rmm::{PageFlags, PageMapper, PhysicalAddress, VirtualAddress};
+use core::sync::atomic::{AtomicBool, Ordering};
+use rmm::{
+    Arch, BuddyAllocator, FrameAllocator, PageEntry, PageFlags, PageMapper, PageTable,
+    PhysicalAddress, VirtualAddress,
+};
 use spin::Mutex;
 
 use crate::{
-    arch::CurrentRmmArch,
+    arch::{CurrentRmmArch, CurrentTlbArch, rmm::max_huge_page_level, tlb::TlbArch},
+    cmdline,
     init::memory::{FRAME_ALLOCATOR, PAGE_SIZE, align_down, align_up},
+    layout,
+    memory::{
+        account::{self, AccountedFrames, MemoryAccount, MemoryKind},
+        reclaim,
+        tlb::{ActiveCpus, TlbBatch},
+    },
+    random,
 };
 
-use super::{KernelObject, ObjectType, SignalObserver, SignalState, Signals, vmo::Vmo};
+use super::{
+    KernelObject, ObjectType, SignalObserver, SignalState, Signals,
+    vmo::{CachePolicy, Vmo, VmoError},
+};
 
 bitflags! {
     /// 映射权限
@@ -24,6 +39,15 @@ bitflags! {
         const SPECIFIC = 1 << 3;
         /// 允许地址偏移（用于 ASLR）
         const OFFSET_IS_UPPER_LIMIT = 1 << 4;
+        /// 不允许解除映射或修改权限，只用于内核安装的映射（例如 vDSO）
+        const PERMANENT = 1 << 5;
+    }
+}
+
+impl MappingFlags {
+    /// 只保留访问权限位
+    pub fn permissions(self) -> MappingFlags {
+        self & (MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE)
     }
 }
 
@@ -38,6 +62,29 @@ pub struct Mapping {
     pub size: usize,
     /// 权限
     pub flags: MappingFlags,
+    /// `protect` 最多能放宽到的权限（映射时由 VMO 句柄的权限决定）
+    pub max_flags: MappingFlags,
+    /// 缓存策略（映射时从 VMO 取得）
+    pub cache_policy: CachePolicy,
+}
+
+/// 缺页时的访问类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultAccess {
+    Read,
+    Write,
+    Execute,
+}
+
+impl FaultAccess {
+    /// 映射需要具有的权限
+    fn required(self) -> MappingFlags {
+        match self {
+            FaultAccess::Read => MappingFlags::READ,
+            FaultAccess::Write => MappingFlags::WRITE,
+            FaultAccess::Execute => MappingFlags::EXECUTE,
+        }
+    }
 }
 
 /// VMAR 内部状态
@@ -48,93 +95,430 @@ struct VmarInner {
     size: usize,
     /// 是否为根 VMAR
     is_root: bool,
+    /// 是否已销毁
+    destroyed: bool,
     /// 映射表 (虚拟地址 -> 映射)
     mappings: BTreeMap<usize, Mapping>,
-    /// 子 VMAR
-    children: Vec<Arc<Vmar>>,
-    /// 下一个可用地址（简化分配）
-    next_alloc: usize,
+    /// 子 VMAR (基地址 -> 子 VMAR)
+    children: BTreeMap<usize, Arc<Vmar>>,
+    /// 已占用的区间 (起始地址 -> 结束地址)，包括映射和子 VMAR
+    regions: BTreeMap<usize, usize>,
+    /// 自动分配时优先使用的最低地址
+    alloc_hint: usize,
+    /// 是否对自动分配的地址随机化
+    aslr: bool,
     /// 信号状态
     signal_state: SignalState,
     /// 页表（对于根 VMAR）
     page_table: Option<PhysicalAddress>,
 }
 
+impl VmarInner {
+    fn new(
+        base: VirtualAddress,
+        size: usize,
+        is_root: bool,
+        alloc_hint: usize,
+        page_table: Option<PhysicalAddress>,
+    ) -> Self {
+        Self {
+            base,
+            size,
+            is_root,
+            destroyed: false,
+            mappings: BTreeMap::new(),
+            children: BTreeMap::new(),
+            regions: BTreeMap::new(),
+            alloc_hint,
+            aslr: cmdline::options().aslr,
+            signal_state: SignalState::new(),
+            page_table,
+        }
+    }
+
+    fn end(&self) -> usize {
+        self.base.data() + self.size
+    }
+
+    /// `[start, start + size)` 是否在范围内且未被占用
+    fn is_free(&self, start: usize, size: usize) -> bool {
+        let Some(end) = start.checked_add(size) else {
+            return false;
+        };
+
+        if start < self.base.data() || end > self.end() {
+            return false;
+        }
+
+        // 起始地址小于 end 的最后一个区间不能越过 start
+        match self.regions.range(..end).next_back() {
+            Some((_, &region_end)) => region_end <= start,
+            None => true,
+        }
+    }
+
+    /// `[lo, hi)` 内所有空闲区间
+    fn gaps(&self, lo: usize, hi: usize) -> Vec<(usize, usize)> {
+        let mut gaps = Vec::new();
+        let mut cursor = lo;
+
+        for (&start, &end) in &self.regions {
+            if end <= cursor {
+                continue;
+            }
+            if start >= hi {
+                break;
+            }
+            if start > cursor {
+                gaps.push((cursor, start));
+            }
+            cursor = core::cmp::max(cursor, end);
+        }
+
+        if cursor < hi {
+            gaps.push((cursor, hi));
+        }
+
+        gaps
+    }
+
+    /// 为 `size` 字节寻找空闲地址
+    ///
+    /// 优先在 `alloc_hint` 之上分配；开启 ASLR 时在所有合适的位置中随机选取。
+    fn find_free(&mut self, size: usize) -> Option<usize> {
+        let hint = self.alloc_hint.clamp(self.base.data(), self.end());
+
+        for lo in [hint, self.base.data()] {
+            let gaps: Vec<(usize, usize)> = self
+                .gaps(lo, self.end())
+                .into_iter()
+                .filter(|&(start, end)| end - start >= size)
+                .collect();
+
+            if gaps.is_empty() {
+                continue;
+            }
+
+            if !self.aslr {
+                return Some(gaps[0].0);
+            }
+
+            // 每个空闲区间中可放置的页数之和
+            let slots: usize = gaps
+                .iter()
+                .map(|&(start, end)| (end - start - size) / PAGE_SIZE + 1)
+                .sum();
+            let mut pick = (random::next_u64() as usize) % slots;
+
+            for (start, end) in gaps {
+                let count = (end - start - size) / PAGE_SIZE + 1;
+                if pick < count {
+                    return Some(start + pick * PAGE_SIZE);
+                }
+                pick -= count;
+            }
+        }
+
+        None
+    }
+}
+
 /// Virtual Memory Address Region
 pub struct Vmar {
     inner: Mutex<VmarInner>,
+    /// 自身的弱引用（注册到被映射的 VMO 上）
+    self_ref: Weak<Vmar>,
+    /// 父 VMAR
+    parent: Weak<Vmar>,
+    /// 加载了该地址空间的 CPU（与根 VMAR 共享）
+    active_cpus: Arc<ActiveCpus>,
+    /// 页表计入的内存账户（与根 VMAR 共享）
+    account: Arc<MemoryAccount>,
+    /// 是否允许同时可写可执行的映射（与根 VMAR 共享）
+    allow_write_execute: Arc<AtomicBool>,
 }
 
 impl Vmar {
-    /// 创建根 VMAR（进程的整个用户地址空间）
+    /// 创建根 VMAR（进程的整个用户地址空间），`page_table` 仍归原来的所有者
     pub fn create_root(
         base: VirtualAddress,
         size: usize,
-        next_alloc: usize,
+        alloc_hint: usize,
         page_table: PhysicalAddress,
+        account: Arc<MemoryAccount>,
     ) -> Arc<Self> {
-        Arc::new(Self {
-            inner: Mutex::new(VmarInner {
+        Self::new_root(
+            base,
+            size,
+            alloc_hint,
+            page_table,
+            account,
+            ActiveCpus::new(),
+        )
+    }
+
+    /// 创建拥有顶层页表 `page_table` 的根 VMAR，顶层页表计入 `account`
+    ///
+    /// 进程退出时由 `destroy_address_space` 释放其余页表。
+    pub fn create_address_space(
+        base: VirtualAddress,
+        size: usize,
+        alloc_hint: usize,
+        page_table: PhysicalAddress,
+        account: Arc<MemoryAccount>,
+    ) -> Arc<Self> {
+        account::record(Some(account.as_ref()), MemoryKind::PageTable, 1);
+        Self::new_root(
+            base,
+            size,
+            alloc_hint,
+            page_table,
+            account,
+            ActiveCpus::with_top_table(page_table),
+        )
+    }
+
+    fn new_root(
+        base: VirtualAddress,
+        size: usize,
+        alloc_hint: usize,
+        page_table: PhysicalAddress,
+        account: Arc<MemoryAccount>,
+        active_cpus: Arc<ActiveCpus>,
+    ) -> Arc<Self> {
+        Arc::new_cyclic(|self_ref| Self {
+            inner: Mutex::new(VmarInner::new(
                 base,
                 size,
-                is_root: true,
-                mappings: BTreeMap::new(),
-                children: Vec::new(),
-                next_alloc,
-                signal_state: SignalState::new(),
-                page_table: Some(page_table),
-            }),
+                true,
+                alloc_hint,
+                Some(page_table),
+            )),
+            self_ref: self_ref.clone(),
+            parent: Weak::new(),
+            active_cpus,
+            account,
+            allow_write_execute: Arc::new(AtomicBool::new(false)),
         })
     }
 
     /// 创建子 VMAR
-    pub fn create_child(&self, offset: usize, size: usize) -> Result<Arc<Vmar>, VmarError> {
+    ///
+    /// `offset` 为 None 时自动选择位置。
+    pub fn create_child(&self, offset: Option<usize>, size: usize) -> Result<Arc<Vmar>, VmarError> {
         let mut inner = self.inner.lock();
 
-        let child_base = inner.base.add(offset);
+        if inner.destroyed {
+            return Err(VmarError::Destroyed);
+        }
 
-        // 检查范围
-        if offset + size > inner.size {
-            return Err(VmarError::OutOfRange);
+        if size == 0 {
+            return Err(VmarError::InvalidArgs);
         }
+        let size = align_up(size);
+
+        let child_base = match offset {
+            Some(offset) => {
+                if offset % PAGE_SIZE != 0 {
+                    return Err(VmarError::InvalidArgs);
+                }
 
-        // TODO: 检查是否与现有映射或子 VMAR 重叠
+                // 检查范围
+                if offset.checked_add(size).is_none_or(|end| end > inner.size) {
+                    return Err(VmarError::OutOfRange);
+                }
 
-        let child = Arc::new(Vmar {
-            inner: Mutex::new(VmarInner {
-                base: child_base,
+                let base = inner.base.data() + offset;
+                if !inner.is_free(base, size) {
+                    return Err(VmarError::Overlap);
+                }
+                base
+            }
+            None => inner.find_free(size).ok_or(VmarError::NoSpace)?,
+        };
+
+        let child = Arc::new_cyclic(|self_ref| Vmar {
+            inner: Mutex::new(VmarInner::new(
+                VirtualAddress::new(child_base),
                 size,
-                is_root: false,
-                mappings: BTreeMap::new(),
-                children: Vec::new(),
-                next_alloc: child_base.data(),
-                signal_state: SignalState::new(),
-                page_table: inner.page_table,
-            }),
+                false,
+                child_base,
+                inner.page_table,
+            )),
+            self_ref: self_ref.clone(),
+            parent: self.self_ref.clone(),
+            active_cpus: self.active_cpus.clone(),
+            account: self.account.clone(),
+            allow_write_execute: self.allow_write_execute.clone(),
         });
 
-        inner.children.push(child.clone());
+        inner.regions.insert(child_base, child_base + size);
+        inner.children.insert(child_base, child.clone());
 
         Ok(child)
     }
 
+    /// 销毁 VMAR：解除其中所有映射并递归销毁子 VMAR
+    ///
+    /// 销毁后该 VMAR 占用的地址范围归还给父 VMAR。
+    pub fn destroy(&self) -> Result<(), VmarError> {
+        if self.inner.lock().is_root {
+            return Err(VmarError::InvalidArgs);
+        }
+
+        self.destroy_inner();
+
+        if let Some(parent) = self.parent.upgrade() {
+            let base = self.base().data();
+            let mut parent_inner = parent.inner.lock();
+            parent_inner.children.remove(&base);
+            parent_inner.regions.remove(&base);
+        }
+
+        Ok(())
+    }
+
+    /// 进程退出时销毁整个地址空间
+    ///
+    /// 解除所有映射并释放顶层以下的用户页表，顶层页表不再计入账户链。
+    /// 不拥有页表的根 VMAR 只解除映射。
+    pub fn destroy_address_space(&self) {
+        let (is_root, page_table) = {
+            let inner = self.inner.lock();
+            (inner.is_root, inner.page_table)
+        };
+        if !is_root || !self.destroy_inner() {
+            return;
+        }
+        let Some(page_table) = page_table else {
+            return;
+        };
+        if !self.active_cpus.owns_top_table() {
+            return;
+        }
+
+        unsafe { free_user_tables(page_table, &self.account, &self.active_cpus) };
+
+        // 顶层页表可能仍被某个 CPU 加载着，之后随 `ActiveCpus` 释放，
+        // 在此之前只计入全局用量
+        account::uncharge(Some(self.account.as_ref()), MemoryKind::PageTable, 1);
+        account::record(None, MemoryKind::PageTable, 1);
+    }
+
+    /// 已经销毁过时返回 false
+    fn destroy_inner(&self) -> bool {
+        let (mappings, children, page_table) = {
+            let mut inner = self.inner.lock();
+            if inner.destroyed {
+                return false;
+            }
+            inner.destroyed = true;
+            inner.regions.clear();
+            (
+                core::mem::take(&mut inner.mappings),
+                core::mem::take(&mut inner.children),
+                inner.page_table,
+            )
+        };
+
+        for child in children.values() {
+            child.destroy_inner();
+        }
+
+        let mut batch = TlbBatch::new();
+        for (&addr, mapping) in &mappings {
+            if let Some(page_table) = page_table {
+                unsafe {
+                    unmap_range(page_table, &self.account, addr, mapping.size, &mut batch);
+                }
+            }
+            mapping.vmo.remove_mapper(&self.self_ref);
+        }
+
+        // 刷新完成前不能释放映射持有的 VMO
+        batch.flush(&self.active_cpus);
+        drop(mappings);
+        true
+    }
+
     pub fn page_table_addr(&self) -> Option<PhysicalAddress> {
         self.inner.lock().page_table
     }
 
+    /// 该地址空间的内存账户
+    pub fn account(&self) -> &Arc<MemoryAccount> {
+        &self.account
+    }
+
+    /// 加载了该地址空间的 CPU
+    pub fn active_cpus(&self) -> &Arc<ActiveCpus> {
+        &self.active_cpus
+    }
+
+    /// 该地址空间是否允许同时可写可执行的映射
+    pub fn allows_write_execute(&self) -> bool {
+        self.allow_write_execute.load(Ordering::Relaxed)
+    }
+
+    /// 设置该地址空间是否允许同时可写可执行的映射，只在创建进程时设置
+    pub fn set_allow_write_execute(&self, allow: bool) {
+        self.allow_write_execute.store(allow, Ordering::Relaxed);
+    }
+
+    /// W^X：默认拒绝同时可写可执行的映射
+    fn check_write_execute(&self, flags: MappingFlags) -> Result<(), VmarError> {
+        if flags.contains(MappingFlags::WRITE | MappingFlags::EXECUTE)
+            && !self.allows_write_execute()
+        {
+            return Err(VmarError::AccessDenied);
+        }
+        Ok(())
+    }
+
     /// 映射 VMO
+    ///
+    /// `max_flags` 是之后 `protect` 能放宽到的最高权限，`flags` 不能超过它。
     pub fn map(
         &self,
         vmo: Arc<Vmo>,
         vmo_offset: usize,
         size: usize,
         flags: MappingFlags,
+        max_flags: MappingFlags,
+        vaddr: Option<VirtualAddress>,
+    ) -> Result<VirtualAddress, VmarError> {
+        reclaim::retry_after_reclaim(
+            || self.try_map(vmo.clone(), vmo_offset, size, flags, max_flags, vaddr),
+            out_of_memory,
+        )
+    }
+
+    fn try_map(
+        &self,
+        vmo: Arc<Vmo>,
+        vmo_offset: usize,
+        size: usize,
+        flags: MappingFlags,
+        max_flags: MappingFlags,
         vaddr: Option<VirtualAddress>,
     ) -> Result<VirtualAddress, VmarError> {
+        let max_flags = max_flags.permissions();
+        if !max_flags.contains(flags.permissions()) {
+            return Err(VmarError::AccessDenied);
+        }
+        self.check_write_execute(flags)?;
+
         let mut inner = self.inner.lock();
 
+        if inner.destroyed {
+            return Err(VmarError::Destroyed);
+        }
+
         // 对齐检查
         let aligned_size = align_up(size);
+        if aligned_size == 0 {
+            return Err(VmarError::InvalidArgs);
+        }
 
         // 确定虚拟地址
         let map_addr = if let Some(addr) = vaddr {
@@ -144,55 +528,92 @@ impl Vmar {
 
             // 检查地址是否在范围内
             if addr.data() < inner.base.data()
-                || addr.data() + aligned_size > inner.base.data() + inner.size
+                || addr.data().saturating_add(aligned_size) > inner.end()
             {
                 return Err(VmarError::OutOfRange);
             }
 
-            addr
-        } else {
-            // 自动分配地址
-            let addr = VirtualAddress::new(inner.next_alloc);
-
-            // 检查是否有足够空间
-            if inner.next_alloc + aligned_size > inner.base.data() + inner.size {
-                return Err(VmarError::NoSpace);
+            // 检查是否与现有映射或子 VMAR 重叠
+            if !inner.is_free(addr.data(), aligned_size) {
+                return Err(VmarError::Overlap);
             }
 
-            inner.next_alloc += aligned_size;
             addr
+        } else {
+            // 自动分配地址
+            VirtualAddress::new(inner.find_free(aligned_size).ok_or(VmarError::NoSpace)?)
         };
 
-        // 检查是否与现有映射重叠
-        for (&existing_addr, mapping) in &inner.mappings {
-            let existing_end = existing_addr + mapping.size;
-            let new_end = map_addr.data() + aligned_size;
-
-            if !(new_end <= existing_addr || map_addr.data() >= existing_end) {
-                return Err(VmarError::Overlap);
-            }
-        }
+        let cache_policy = vmo.cache_policy();
 
         // 创建页表映射
         if let Some(page_table) = inner.page_table {
-            let page_count = aligned_size / PAGE_SIZE;
-
-            for i in 0..page_count {
-                let virt = map_addr.add(i * PAGE_SIZE);
-
-                // 获取物理页面
-                let phys = vmo
-                    .get_page(vmo_offset + i * PAGE_SIZE, false)
-                    .map_err(|_| VmarError::VmoError)?;
+            let mut mapped = 0;
+
+            while mapped < aligned_size {
+                let virt = map_addr.add(mapped);
+                let offset = vmo_offset + mapped;
+
+                // 物理连续且对齐时直接使用大页
+                if let Some((phys, level)) = huge_leaf(&vmo, offset, virt, aligned_size - mapped)
+                    && unsafe {
+                        map_huge_page(
+                            page_table,
+                            &self.account,
+                            virt,
+                            phys,
+                            flags,
+                            cache_policy,
+                            level,
+                        )
+                    }
+                {
+                    mapped += CurrentRmmArch::level_size(level);
+                    continue;
+                }
 
-                // 设置页表项
-                unsafe {
-                    map_page(page_table, virt, phys, flags);
+                // 获取物理页面并设置页表项，与父 VMO 共享的页面只读映射
+                let result = match vmo.get_page_for_map(offset, false) {
+                    Ok((phys, shared)) => unsafe {
+                        map_page(
+                            page_table,
+                            &self.account,
+                            virt,
+                            phys,
+                            map_flags(flags, shared),
+                            cache_policy,
+                        )
+                    },
+                    Err(e) => Err(e.into()),
+                };
+
+                if let Err(e) = result {
+                    // 撤销已建立的页表项
+                    let mut batch = TlbBatch::new();
+                    unsafe {
+                        unmap_range(
+                            page_table,
+                            &self.account,
+                            map_addr.data(),
+                            mapped,
+                            &mut batch,
+                        );
+                    }
+                    drop(inner);
+                    batch.flush(&self.active_cpus);
+                    return Err(e);
                 }
+                mapped += PAGE_SIZE;
             }
         }
 
+        // 登记到 VMO，以便 VMO 释放页面时能撤销映射
+        vmo.add_mapper(self.self_ref.clone());
+
         // 保存映射信息
+        inner
+            .regions
+            .insert(map_addr.data(), map_addr.data() + aligned_size);
         inner.mappings.insert(
             map_addr.data(),
             Mapping {
@@ -200,6 +621,8 @@ impl Vmar {
                 vmo_offset,
                 size: aligned_size,
                 flags,
+                max_flags,
+                cache_policy,
             },
         );
 
@@ -218,27 +641,82 @@ impl Vmar {
             .remove(&addr.data())
             .ok_or(VmarError::NotMapped)?;
 
+        if mapping.flags.contains(MappingFlags::PERMANENT) {
+            inner.mappings.insert(addr.data(), mapping);
+            return Err(VmarError::AccessDenied);
+        }
+
         if mapping.size != aligned_size {
             // 部分解除映射（复杂，暂不支持）
             inner.mappings.insert(addr.data(), mapping);
             return Err(VmarError::InvalidArgs);
         }
 
+        inner.regions.remove(&addr.data());
+
         // 清除页表项
+        let mut batch = TlbBatch::new();
         if let Some(page_table) = inner.page_table {
-            let page_count = aligned_size / PAGE_SIZE;
-
-            for i in 0..page_count {
-                let virt = addr.add(i * PAGE_SIZE);
-                unsafe {
-                    unmap_page(page_table, virt);
-                }
+            unsafe {
+                unmap_range(
+                    page_table,
+                    &self.account,
+                    addr.data(),
+                    aligned_size,
+                    &mut batch,
+                );
             }
         }
+        drop(inner);
+
+        // 所有 CPU 都刷新后才放开对 VMO 的引用
+        batch.flush(&self.active_cpus);
+        mapping.vmo.remove_mapper(&self.self_ref);
 
         Ok(())
     }
 
+    /// 撤销该 VMAR 中映射了 `vmo` 的 `[offset, offset + size)` 范围的页表项
+    ///
+    /// 之后的访问会触发缺页，重新从 VMO 获取页面。返回前所有 CPU 都已刷新 TLB，
+    /// 调用者可以安全释放对应的物理页。
+    pub fn unmap_vmo_range(&self, vmo: &Vmo, offset: usize, size: usize) {
+        let inner = self.inner.lock();
+
+        let Some(page_table) = inner.page_table else {
+            return;
+        };
+
+        let start = align_down(offset);
+        let end = align_up(offset + size);
+
+        let mut batch = TlbBatch::new();
+        for (&base, mapping) in &inner.mappings {
+            if !core::ptr::eq(Arc::as_ptr(&mapping.vmo), vmo) {
+                continue;
+            }
+
+            let map_start = core::cmp::max(start, mapping.vmo_offset);
+            let map_end = core::cmp::min(end, mapping.vmo_offset + mapping.size);
+            if map_start >= map_end {
+                continue;
+            }
+
+            unsafe {
+                unmap_range(
+                    page_table,
+                    &self.account,
+                    base + map_start - mapping.vmo_offset,
+                    map_end - map_start,
+                    &mut batch,
+                );
+            }
+        }
+        drop(inner);
+
+        batch.flush(&self.active_cpus);
+    }
+
     /// 修改映射权限
     pub fn protect(
         &self,
@@ -246,6 +724,8 @@ impl Vmar {
         _size: usize,
         flags: MappingFlags,
     ) -> Result<(), VmarError> {
+        self.check_write_execute(flags)?;
+
         let mut inner = self.inner.lock();
 
         let page_table = inner.page_table;
@@ -255,20 +735,34 @@ impl Vmar {
             .get_mut(&addr.data())
             .ok_or(VmarError::NotMapped)?;
 
+        // 不能修改内核安装的映射，也不能超过映射时句柄给予的权限
+        if mapping.flags.contains(MappingFlags::PERMANENT)
+            || !mapping.max_flags.contains(flags.permissions())
+        {
+            return Err(VmarError::AccessDenied);
+        }
+
         // 更新权限
         mapping.flags = flags;
 
         // 更新页表
+        let mut batch = TlbBatch::new();
         if let Some(page_table) = page_table {
-            let page_count = mapping.size / PAGE_SIZE;
-
-            for i in 0..page_count {
-                let virt = addr.add(i * PAGE_SIZE);
-                unsafe {
-                    update_page_flags(page_table, virt, flags);
-                }
+            unsafe {
+                protect_range(
+                    page_table,
+                    &self.account,
+                    addr.data(),
+                    mapping.size,
+                    flags,
+                    mapping.cache_policy,
+                    &mut batch,
+                );
             }
         }
+        drop(inner);
+
+        batch.flush(&self.active_cpus);
 
         Ok(())
     }
@@ -284,39 +778,98 @@ impl Vmar {
     }
 
     /// 处理缺页异常
-    pub fn handle_page_fault(&self, addr: VirtualAddress, write: bool) -> Result<(), VmarError> {
+    ///
+    /// 只修复还没有页表项的页面和写时复制页面上的写入。其余情况（例如执行不可执行的映射、
+    /// 写只读映射）返回 `AccessDenied`，否则同一条指令会不停地缺页。
+    pub fn handle_page_fault(
+        &self,
+        addr: VirtualAddress,
+        access: FaultAccess,
+    ) -> Result<(), VmarError> {
+        reclaim::retry_after_reclaim(|| self.resolve_fault(addr, access), out_of_memory)
+    }
+
+    fn resolve_fault(&self, addr: VirtualAddress, access: FaultAccess) -> Result<(), VmarError> {
         let inner = self.inner.lock();
 
         // 查找包含该地址的映射
-        for (&base, mapping) in &inner.mappings {
-            if addr.data() >= base && addr.data() < base + mapping.size {
+        if let Some((&base, mapping)) = inner.mappings.range(..=addr.data()).next_back() {
+            if addr.data() < base + mapping.size {
                 // 检查权限
-                if write && !mapping.flags.contains(MappingFlags::WRITE) {
+                if !mapping.flags.contains(access.required()) {
                     return Err(VmarError::AccessDenied);
                 }
 
                 // 计算偏移
                 let offset_in_mapping = addr.data() - base;
                 let page_offset = align_down(offset_in_mapping);
+                let virt = VirtualAddress::new(base + page_offset);
+                let write = access == FaultAccess::Write;
+
+                let Some(page_table) = inner.page_table else {
+                    return Ok(());
+                };
+
+                let present = unsafe { leaf_flags(page_table, &self.account, virt) };
+                if let Some(present) = present {
+                    let permitted = match access {
+                        FaultAccess::Read => true,
+                        FaultAccess::Write => present.has_write(),
+                        FaultAccess::Execute => present.has_execute(),
+                    };
+                    if permitted {
+                        // 其他 CPU 已经修复了这个页面，本 CPU 上可能还缓存着旧的页表项
+                        drop(inner);
+                        CurrentTlbArch::flush_range(virt.data(), virt.data() + PAGE_SIZE);
+                        return Ok(());
+                    }
+
+                    // 已映射的页面上只有写时复制的写入需要处理
+                    if !write {
+                        return Err(VmarError::AccessDenied);
+                    }
+                }
 
                 // 获取物理页面（可能触发 COW）
-                let phys = mapping
+                let (phys, shared) = mapping
                     .vmo
-                    .get_page(mapping.vmo_offset + page_offset, write)
-                    .map_err(|_| VmarError::VmoError)?;
+                    .get_page_for_map(mapping.vmo_offset + page_offset, write)?;
 
                 // 更新页表
-                if let Some(page_table) = inner.page_table {
-                    let virt = VirtualAddress::new(base + page_offset);
-                    unsafe {
-                        map_page(page_table, virt, phys, mapping.flags);
-                    }
+                let result = unsafe {
+                    map_page(
+                        page_table,
+                        &self.account,
+                        virt,
+                        phys,
+                        map_flags(mapping.flags, shared),
+                        mapping.cache_policy,
+                    )
+                };
+                drop(inner);
+                result?;
+
+                // 写时复制替换了物理页，其他 CPU 上可能还缓存着旧页
+                if present.is_some() {
+                    let mut batch = TlbBatch::new();
+                    batch.add(virt.data(), PAGE_SIZE);
+                    batch.flush(&self.active_cpus);
                 }
 
                 return Ok(());
             }
         }
 
+        // 交给包含该地址的子 VMAR 处理
+        if let Some((&child_base, child)) = inner.children.range(..=addr.data()).next_back() {
+            let child = child.clone();
+            let child_end = inner.regions.get(&child_base).copied();
+            if child_end.is_some_and(|end| addr.data() < end) {
+                drop(inner);
+                return child.resolve_fault(addr, access);
+            }
+        }
+
         Err(VmarError::NotMapped)
     }
 }
@@ -361,59 +914,279 @@ pub enum VmarError {
     NotMapped,
     VmoError,
     AccessDenied,
+    /// VMAR 已被销毁
+    Destroyed,
+    /// 物理内存不足或超出内存限额
+    NoMemory,
+}
+
+impl From<VmoError> for VmarError {
+    fn from(e: VmoError) -> Self {
+        match e {
+            VmoError::NoMemory => VmarError::NoMemory,
+            _ => VmarError::VmoError,
+        }
+    }
+}
+
+/// 查找 `virt` 处可用的最大大页：虚拟地址和物理地址都按大页对齐，且剩余长度足够
+fn huge_leaf(
+    vmo: &Vmo,
+    vmo_offset: usize,
+    virt: VirtualAddress,
+    remaining: usize,
+) -> Option<(PhysicalAddress, usize)> {
+    for level in (1..=max_huge_page_level()).rev() {
+        let size = CurrentRmmArch::level_size(level);
+        if !virt.data().is_multiple_of(size) || remaining < size {
+            continue;
+        }
+
+        if let Some(phys) = vmo.phys_contiguous(vmo_offset, size)
+            && phys.data().is_multiple_of(size)
+        {
+            return Some((phys, level));
+        }
+    }
+
+    None
+}
+
+/// 页面的实际映射权限：仍与父 VMO 共享的写时复制页面不能写
+fn map_flags(flags: MappingFlags, shared: bool) -> MappingFlags {
+    if shared {
+        flags - MappingFlags::WRITE
+    } else {
+        flags
+    }
+}
+
+fn page_flags(flags: MappingFlags, cache_policy: CachePolicy) -> PageFlags<CurrentRmmArch> {
+    let page_flags = PageFlags::<CurrentRmmArch>::new()
+        .execute(flags.contains(MappingFlags::EXECUTE))
+        .write(flags.contains(MappingFlags::WRITE))
+        .user(true);
+
+    match cache_policy {
+        CachePolicy::Cached => page_flags,
+        CachePolicy::Uncached => page_flags.uncached(true),
+        CachePolicy::UncachedDevice => page_flags.uncached_device(true),
+        CachePolicy::WriteCombining => page_flags.write_combining(true),
+    }
+}
+
+/// 错误是否由内存不足引起（可以回收后重试）
+fn out_of_memory(e: &VmarError) -> bool {
+    *e == VmarError::NoMemory
+}
+
+/// 用户页表的映射器，新分配的页表计入 `account`
+fn user_mapper<'a>(
+    page_table: PhysicalAddress,
+    account: &'a MemoryAccount,
+    frame_allocator: &'a mut BuddyAllocator<CurrentRmmArch>,
+) -> PageMapper<CurrentRmmArch, AccountedFrames<'a>> {
+    let frames = AccountedFrames::new(frame_allocator, account, MemoryKind::PageTable);
+    unsafe { PageMapper::new(rmm::TableKind::User, page_table, frames) }
 }
 
 // 页表操作（架构相关，需要根据实际实现）
+//
+// 无法分配页表（物理内存不足或超出限额）时返回 NoMemory
 unsafe fn map_page(
     page_table: PhysicalAddress,
+    account: &MemoryAccount,
     virt: VirtualAddress,
     phys: PhysicalAddress,
     flags: MappingFlags,
+    cache_policy: CachePolicy,
+) -> Result<(), VmarError> {
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    let mut mapper = user_mapper(page_table, account, &mut frame_allocator);
+    let flusher = unsafe { mapper.map_phys(virt, phys, page_flags(flags, cache_policy)) }
+        .ok_or(VmarError::NoMemory)?;
+    flusher.flush();
+    Ok(())
+}
+
+/// `virt` 处已有页表项的权限，没有映射时返回 None
+unsafe fn leaf_flags(
+    page_table: PhysicalAddress,
+    account: &MemoryAccount,
+    virt: VirtualAddress,
+) -> Option<PageFlags<CurrentRmmArch>> {
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    let mapper = user_mapper(page_table, account, &mut frame_allocator);
+    mapper.translate(virt).map(|(_, flags)| flags)
+}
+
+/// 释放 `page_table` 中用户地址范围内顶层以下的所有页表，叶子映射必须已经解除
+///
+/// 先从顶层摘下这些页表并刷新所有 CPU 的 TLB，之后才归还物理页。
+unsafe fn free_user_tables(
+    page_table: PhysicalAddress,
+    account: &MemoryAccount,
+    active_cpus: &ActiveCpus,
 ) {
+    let mut detached = Vec::new();
+    {
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        let mapper = user_mapper(page_table, account, &mut frame_allocator);
+        let mut top = mapper.table();
+
+        for i in 0..CurrentRmmArch::PAGE_ENTRIES {
+            let in_user = top
+                .entry_base(i)
+                .is_some_and(|base| base.data() < layout::USER_SPACE_END);
+            let table = unsafe {
+                match top.entry(i) {
+                    Some(entry) if in_user && entry.present() && !entry.huge() => top.next(i),
+                    _ => None,
+                }
+            };
+            if let Some(table) = table {
+                detached.push(table);
+                unsafe { top.set_entry(i, PageEntry::new(0, 0)) };
+            }
+        }
+    }
+
+    let mut batch = TlbBatch::new();
+    batch.add(
+        layout::USER_SPACE_START,
+        layout::USER_SPACE_END - layout::USER_SPACE_START,
+    );
+    batch.flush(active_cpus);
+
     let mut frame_allocator = FRAME_ALLOCATOR.lock();
-    let mut mapper =
-        unsafe { PageMapper::new(rmm::TableKind::User, page_table, &mut *frame_allocator) };
-    let page_flags = PageFlags::<CurrentRmmArch>::new()
-        .execute(flags.contains(MappingFlags::EXECUTE))
-        .write(flags.contains(MappingFlags::WRITE))
-        .user(true);
-    if let Some(flusher) = mapper.map_phys(virt, phys, page_flags) {
-        flusher.flush();
+    let mut frames = AccountedFrames::new(&mut frame_allocator, account, MemoryKind::PageTable);
+    for table in detached {
+        unsafe { free_table_tree(table, &mut frames) };
     }
 }
 
-unsafe fn unmap_page(page_table: PhysicalAddress, virt: VirtualAddress) {
-    let mut frame_allocator = FRAME_ALLOCATOR.lock();
-    let mut mapper = unsafe {
-        PageMapper::<CurrentRmmArch, _>::new(
-            rmm::TableKind::User,
-            page_table,
-            &mut *frame_allocator,
-        )
-    };
-    if let Some((_phys, _flags, flusher)) = mapper.unmap_phys(virt, true) {
-        flusher.flush();
+/// 释放 `table` 及其下所有页表（不包括叶子映射的物理页）
+unsafe fn free_table_tree(table: PageTable<CurrentRmmArch>, frames: &mut AccountedFrames<'_>) {
+    if table.level() > 0 {
+        for i in 0..CurrentRmmArch::PAGE_ENTRIES {
+            let next = unsafe {
+                match table.entry(i) {
+                    Some(entry) if entry.present() && !entry.huge() => table.next(i),
+                    _ => None,
+                }
+            };
+            if let Some(next) = next {
+                unsafe { free_table_tree(next, frames) };
+            }
+        }
     }
+    unsafe { frames.free_one(table.phys()) };
 }
 
-unsafe fn update_page_flags(
+/// 用 `level` 层的大页映射，该位置已被占用时返回 false
+unsafe fn map_huge_page(
     page_table: PhysicalAddress,
+    account: &MemoryAccount,
     virt: VirtualAddress,
+    phys: PhysicalAddress,
     flags: MappingFlags,
+    cache_policy: CachePolicy,
+    level: usize,
+) -> bool {
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    let mut mapper = user_mapper(page_table, account, &mut frame_allocator);
+    match unsafe { mapper.map_phys_huge(virt, phys, page_flags(flags, cache_policy), level) } {
+        Some(flusher) => {
+            flusher.flush();
+            true
+        }
+        None => false,
+    }
+}
+
+/// 解除 `[start, start + size)` 内的映射，TLB 刷新记录到 `batch` 中由调用者统一完成
+///
+/// 只有一部分落在范围内的大页先被拆分；拆分失败时整个大页被解除，
+/// 范围外的部分之后通过缺页重新映射。
+unsafe fn unmap_range(
+    page_table: PhysicalAddress,
+    account: &MemoryAccount,
+    start: usize,
+    size: usize,
+    batch: &mut TlbBatch,
 ) {
+    let end = start + size;
     let mut frame_allocator = FRAME_ALLOCATOR.lock();
-    let mut mapper = unsafe {
-        PageMapper::<CurrentRmmArch, _>::new(
-            rmm::TableKind::User,
-            page_table,
-            &mut *frame_allocator,
-        )
-    };
-    let page_flags = PageFlags::<CurrentRmmArch>::new()
-        .execute(flags.contains(MappingFlags::EXECUTE))
-        .write(flags.contains(MappingFlags::WRITE))
-        .user(true);
-    if let Some((_flags, _addr, flusher)) = mapper.remap_with(virt, |_| page_flags) {
-        flusher.flush();
+    let mut mapper = user_mapper(page_table, account, &mut frame_allocator);
+
+    let mut addr = start;
+    while addr < end {
+        let virt = VirtualAddress::new(addr);
+        let Some((_, _, level)) = mapper.translate_leaf(virt) else {
+            addr += PAGE_SIZE;
+            continue;
+        };
+
+        let leaf_size = CurrentRmmArch::level_size(level);
+        let leaf_start = addr & !(leaf_size - 1);
+        if (leaf_start < start || leaf_start + leaf_size > end)
+            && unsafe { mapper.split(virt) }.is_some()
+        {
+            continue;
+        }
+
+        if let Some((_phys, _flags, _level, flusher)) = unsafe { mapper.unmap_leaf(virt, true) } {
+            unsafe { flusher.ignore() };
+            batch.add(leaf_start, leaf_size);
+        }
+        addr = leaf_start + leaf_size;
+    }
+}
+
+/// 修改 `[start, start + size)` 内映射的权限，规则同 `unmap_range`
+///
+/// 原来只读的页面在改为可写时被解除映射：它可能是与父 VMO 共享的写时复制页面，
+/// 由之后的缺页决定能否直接写入。
+unsafe fn protect_range(
+    page_table: PhysicalAddress,
+    account: &MemoryAccount,
+    start: usize,
+    size: usize,
+    flags: MappingFlags,
+    cache_policy: CachePolicy,
+    batch: &mut TlbBatch,
+) {
+    let end = start + size;
+    let mut frame_allocator = FRAME_ALLOCATOR.lock();
+    let mut mapper = user_mapper(page_table, account, &mut frame_allocator);
+
+    let mut addr = start;
+    while addr < end {
+        let virt = VirtualAddress::new(addr);
+        let Some((_, old_flags, level)) = mapper.translate_leaf(virt) else {
+            addr += PAGE_SIZE;
+            continue;
+        };
+
+        let leaf_size = CurrentRmmArch::level_size(level);
+        let leaf_start = addr & !(leaf_size - 1);
+        let partial = leaf_start < start || leaf_start + leaf_size > end;
+        if partial && unsafe { mapper.split(virt) }.is_some() {
+            continue;
+        }
+
+        let upgrade = flags.contains(MappingFlags::WRITE) && !old_flags.has_write();
+        let flusher = if partial || upgrade {
+            // 无法拆分或者需要重新检查写时复制：解除映射，缺页时按新权限重新映射
+            unsafe { mapper.unmap_leaf(virt, true) }.map(|(_, _, _, flusher)| flusher)
+        } else {
+            unsafe { mapper.remap_leaf_with(virt, |_| page_flags(flags, cache_policy)) }
+                .map(|(_, _, _, flusher)| flusher)
+        };
+        if let Some(flusher) = flusher {
+            unsafe { flusher.ignore() };
+            batch.add(leaf_start, leaf_size);
+        }
+        addr = leaf_start + leaf_size;
     }
 }
@@ -1,17 +1,21 @@
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use bitflags::bitflags;
 use core::any::Any;
-use rmm::{Arch, FrameAllocator, FrameCount, PhysicalAddress};
+use rmm::{Arch, BuddyAllocator, FrameAllocator, FrameCount, PhysicalAddress};
 use spin::Mutex;
 
 use crate::{
     EINVAL, Error, Result,
-    arch::CurrentRmmArch,
+    arch::{CurrentRmmArch, rmm::max_huge_page_level},
     init::memory::{FRAME_ALLOCATOR, PAGE_SIZE},
+    memory::{
+        account::{self, MemoryAccount, MemoryKind},
+        reclaim,
+    },
 };
 
-use super::{KernelObject, ObjectType, SignalObserver, SignalState, Signals};
+use super::{KernelObject, ObjectType, SignalObserver, SignalState, Signals, pmt::Pmt, vmar::Vmar};
 
 bitflags! {
     /// VMO 创建选项
@@ -49,6 +53,71 @@ bitflags! {
     }
 }
 
+/// VMO 范围操作
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmoOp {
+    /// 立即提交范围内的页面
+    Commit = 1,
+    /// 取消提交，释放物理页面
+    Decommit = 2,
+    /// 清零
+    Zero = 3,
+    /// 查询每个页面的状态
+    Query = 4,
+    /// 锁定可丢弃 VMO（锁定期间不会被回收）
+    Lock = 5,
+    /// 解锁可丢弃 VMO
+    Unlock = 6,
+}
+
+impl VmoOp {
+    pub fn from_raw(op: u32) -> Option<Self> {
+        match op {
+            1 => Some(VmoOp::Commit),
+            2 => Some(VmoOp::Decommit),
+            3 => Some(VmoOp::Zero),
+            4 => Some(VmoOp::Query),
+            5 => Some(VmoOp::Lock),
+            6 => Some(VmoOp::Unlock),
+            _ => None,
+        }
+    }
+}
+
+/// 页面未提交（`VmoOp::Query` 的结果）
+pub const VMO_PAGE_UNCOMMITTED: u8 = 0;
+/// 页面已提交
+pub const VMO_PAGE_COMMITTED: u8 = 1;
+/// 页面与父 VMO 共享（写时复制）
+pub const VMO_PAGE_COPY_ON_WRITE: u8 = 2;
+
+/// 映射时使用的缓存策略
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// 普通内存（写回）
+    Cached = 0,
+    /// 不缓存
+    Uncached = 1,
+    /// 严格不缓存，用于设备寄存器
+    UncachedDevice = 2,
+    /// 写合并，用于帧缓冲
+    WriteCombining = 3,
+}
+
+impl CachePolicy {
+    pub fn from_raw(policy: u32) -> Option<Self> {
+        match policy {
+            0 => Some(CachePolicy::Cached),
+            1 => Some(CachePolicy::Uncached),
+            2 => Some(CachePolicy::UncachedDevice),
+            3 => Some(CachePolicy::WriteCombining),
+            _ => None,
+        }
+    }
+}
+
 /// 页面状态
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum PageState {
@@ -66,6 +135,8 @@ struct VmoInner {
     size: usize,
     /// 页面状态数组
     pages: Vec<PageState>,
+    /// 每个页面的 pin 计数（DMA 进行中的页面不可被释放）
+    pin_counts: Vec<u32>,
     /// 选项
     options: VmoOptions,
     /// 父 VMO（用于 COW）
@@ -74,78 +145,259 @@ struct VmoInner {
     share_count: usize,
     /// 信号状态
     signal_state: SignalState,
+    /// 映射了该 VMO 的 VMAR
+    mappers: Vec<Weak<Vmar>>,
+    /// 写时复制的子 VMO，它们的映射可能直接指向本 VMO 的页面
+    children: Vec<Weak<Vmo>>,
+    /// 锁定计数（仅用于 DISCARDABLE）
+    lock_count: usize,
+    /// 自上次解锁后是否被回收过
+    discarded: bool,
+    /// 缓存策略
+    cache_policy: CachePolicy,
+}
+
+impl VmoInner {
+    /// 范围内是否有被 pin 住的页面
+    fn is_pinned(&self, start_page: usize, end_page: usize) -> bool {
+        self.pin_counts[start_page..end_page]
+            .iter()
+            .any(|&c| c != 0)
+    }
+
+    /// 检查并换算页面范围
+    fn page_range(&self, offset: usize, size: usize) -> Result<(usize, usize), VmoError> {
+        let end = offset.checked_add(size).ok_or(VmoError::OutOfRange)?;
+        let start_page = offset / PAGE_SIZE;
+        let end_page = end.div_ceil(PAGE_SIZE);
+
+        if end_page > self.pages.len() {
+            return Err(VmoError::OutOfRange);
+        }
+
+        Ok((start_page, end_page))
+    }
+
+    /// 将页面置为未提交，返回需要归还给分配器的物理页面
+    fn release_page(&mut self, index: usize) -> Option<PhysicalAddress> {
+        let old = core::mem::replace(&mut self.pages[index], PageState::Uncommitted);
+        match old {
+            PageState::Committed(phys, true) => Some(phys),
+            PageState::Committed(_, false) => {
+                // 不归我们所有的页面（物理 VMO）保持原样
+                self.pages[index] = old;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// 获取指定页面的物理地址（可能触发分配或 COW），调用者需持有锁
+    fn resolve_page(
+        &mut self,
+        index: usize,
+        write: bool,
+        account: Option<&MemoryAccount>,
+    ) -> Result<PhysicalAddress, VmoError> {
+        if index >= self.pages.len() {
+            return Err(VmoError::OutOfRange);
+        }
+
+        match self.pages[index] {
+            PageState::Committed(phys, _) => Ok(phys),
+
+            PageState::Uncommitted => {
+                // 按需分配
+                let phys = allocate_frames(account, 1)?;
+
+                // 清零
+                unsafe {
+                    let virt = CurrentRmmArch::phys_to_virt(phys);
+                    core::ptr::write_bytes(virt.data() as *mut u8, 0, PAGE_SIZE);
+                }
+
+                self.pages[index] = PageState::Committed(phys, true);
+                Ok(phys)
+            }
+
+            PageState::CopyOnWrite { parent_offset } => {
+                if write {
+                    // 需要复制
+                    let parent = self.parent.as_ref().ok_or(VmoError::InvalidState)?;
+                    let parent_phys = parent.get_page(parent_offset, false)?;
+
+                    // 分配新页面
+                    let new_phys = allocate_frames(account, 1)?;
+
+                    // 复制内容
+                    unsafe {
+                        let src = CurrentRmmArch::phys_to_virt(parent_phys);
+                        let dst = CurrentRmmArch::phys_to_virt(new_phys);
+                        core::ptr::copy_nonoverlapping(
+                            src.data() as *const u8,
+                            dst.data() as *mut u8,
+                            PAGE_SIZE,
+                        );
+                    }
+
+                    self.pages[index] = PageState::Committed(new_phys, true);
+                    Ok(new_phys)
+                } else {
+                    // 只读访问，返回父页面
+                    let parent = self.parent.as_ref().ok_or(VmoError::InvalidState)?;
+                    parent.get_page(parent_offset, false)
+                }
+            }
+        }
+    }
+}
+
+/// 计入账户后从物理页分配器分配，并检查内存压力
+///
+/// 超出账户限额或物理内存不足时返回 `NoMemory`。调用者可能持有 VMO 或 VMAR 的锁，
+/// 这里不做同步回收，由不持锁的入口通过 `reclaim::retry_after_reclaim` 重试。
+fn allocate_charged(
+    account: Option<&MemoryAccount>,
+    count: usize,
+    allocate: impl FnOnce(&mut BuddyAllocator<CurrentRmmArch>) -> Option<PhysicalAddress>,
+) -> Result<PhysicalAddress, VmoError> {
+    if !account::charge(account, MemoryKind::Vmo, count) {
+        return Err(VmoError::NoMemory);
+    }
+
+    let phys = allocate(&mut FRAME_ALLOCATOR.lock());
+    reclaim::update_pressure();
+
+    phys.ok_or_else(|| {
+        account::uncharge(account, MemoryKind::Vmo, count);
+        VmoError::NoMemory
+    })
+}
+
+/// 错误是否由内存不足引起（可以回收后重试）
+fn out_of_memory(e: &VmoError) -> bool {
+    *e == VmoError::NoMemory
+}
+
+/// 分配连续页面
+fn allocate_frames(
+    account: Option<&MemoryAccount>,
+    count: usize,
+) -> Result<PhysicalAddress, VmoError> {
+    allocate_charged(account, count, |allocator| unsafe {
+        allocator.allocate(FrameCount::new(count))
+    })
+}
+
+/// 分配连续页面，起始地址按 `align` 页对齐
+fn allocate_frames_aligned(
+    account: Option<&MemoryAccount>,
+    count: usize,
+    align: usize,
+) -> Result<PhysicalAddress, VmoError> {
+    allocate_charged(account, count, |allocator| unsafe {
+        allocator.allocate_aligned(FrameCount::new(count), FrameCount::new(align))
+    })
+}
+
+/// 分配连续页面，足够大时尽量按大页对齐，以便映射时使用大页
+fn allocate_contiguous_frames(
+    account: Option<&MemoryAccount>,
+    count: usize,
+) -> Result<PhysicalAddress, VmoError> {
+    for level in (1..=max_huge_page_level()).rev() {
+        let align = CurrentRmmArch::level_size(level) / PAGE_SIZE;
+        if count >= align
+            && let Ok(phys) = allocate_frames_aligned(account, count, align)
+        {
+            return Ok(phys);
+        }
+    }
+
+    allocate_frames(account, count)
+}
+
+/// 分配长度为 `len` 的 Vec，内核堆不足时返回 `NoMemory` 而不是 panic
+fn try_vec<T: Clone>(len: usize, value: T) -> Result<Vec<T>, VmoError> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(len).map_err(|_| VmoError::NoMemory)?;
+    vec.resize(len, value);
+    Ok(vec)
 }
 
 /// Virtual Memory Object
 pub struct Vmo {
     inner: Mutex<VmoInner>,
+    /// 已提交页面计入的账户（None 表示内核自身）
+    account: Option<Arc<MemoryAccount>>,
 }
 
 impl Vmo {
-    /// 创建新的 VMO
-    pub fn create(size: usize, options: VmoOptions) -> Result<Arc<Self>, VmoError> {
+    /// 创建新的 VMO，提交的页面计入 `account`
+    pub fn create(
+        size: usize,
+        options: VmoOptions,
+        account: Option<Arc<MemoryAccount>>,
+    ) -> Result<Arc<Self>, VmoError> {
         if size == 0 {
             return Err(VmoError::InvalidSize);
         }
 
-        // 页对齐
-        let aligned_size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        // 页对齐，大小来自用户态，不能溢出
+        let aligned_size = size
+            .checked_add(PAGE_SIZE - 1)
+            .ok_or(VmoError::InvalidSize)?
+            & !(PAGE_SIZE - 1);
         let page_count = aligned_size / PAGE_SIZE;
 
-        let mut pages = Vec::with_capacity(page_count);
+        let vmo = Arc::try_new(Self {
+            inner: Mutex::new(VmoInner {
+                size: aligned_size,
+                pin_counts: try_vec(page_count, 0)?,
+                pages: try_vec(page_count, PageState::Uncommitted)?,
+                options,
+                parent: None,
+                share_count: 1,
+                signal_state: SignalState::new(),
+                mappers: Vec::new(),
+                children: Vec::new(),
+                lock_count: 0,
+                discarded: false,
+                cache_policy: CachePolicy::Cached,
+            }),
+            account,
+        })
+        .map_err(|_| VmoError::NoMemory)?;
 
         if options.contains(VmoOptions::COMMIT) {
             // 立即分配所有页面
             if options.contains(VmoOptions::CONTIGUOUS) {
                 // 分配连续物理内存
-                let phys = unsafe {
-                    FRAME_ALLOCATOR
-                        .lock()
-                        .allocate(FrameCount::new(page_count))
-                        .ok_or(VmoError::NoMemory)?
-                };
+                let phys = reclaim::retry_after_reclaim(
+                    || allocate_contiguous_frames(vmo.account.as_deref(), page_count),
+                    out_of_memory,
+                )?;
 
                 let virt = unsafe { CurrentRmmArch::phys_to_virt(phys) };
                 unsafe {
                     core::ptr::write_bytes(virt.data() as *mut u8, 0, page_count * PAGE_SIZE);
                 }
 
-                for i in 0..page_count {
-                    pages.push(PageState::Committed(phys.add(i * PAGE_SIZE), true));
+                let mut inner = vmo.inner.lock();
+                for (i, page) in inner.pages.iter_mut().enumerate() {
+                    *page = PageState::Committed(phys.add(i * PAGE_SIZE), true);
                 }
             } else {
-                // 分配非连续页面
-                for _ in 0..page_count {
-                    let phys = unsafe {
-                        FRAME_ALLOCATOR
-                            .lock()
-                            .allocate_one()
-                            .ok_or(VmoError::NoMemory)?
-                    };
-
-                    let virt = unsafe { CurrentRmmArch::phys_to_virt(phys) };
-                    unsafe {
-                        core::ptr::write_bytes(virt.data() as *mut u8, 0, PAGE_SIZE);
-                    }
-
-                    pages.push(PageState::Committed(phys, true));
-                }
+                // 分配非连续页面，中途失败时已分配的页面随 VMO 一起释放
+                vmo.commit(0, aligned_size)?;
             }
-        } else {
-            // 按需分配
-            pages.resize(page_count, PageState::Uncommitted);
         }
 
-        Ok(Arc::new(Self {
-            inner: Mutex::new(VmoInner {
-                size: aligned_size,
-                pages,
-                options,
-                parent: None,
-                share_count: 1,
-                signal_state: SignalState::new(),
-            }),
-        }))
+        if options.contains(VmoOptions::DISCARDABLE) {
+            reclaim::register_discardable(&vmo);
+        }
+
+        Ok(vmo)
     }
 
     /// 创建物理内存 VMO（用于 MMIO）
@@ -154,61 +406,92 @@ impl Vmo {
             return Err(VmoError::InvalidSize);
         }
 
-        let aligned_size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let aligned_size = size
+            .checked_add(PAGE_SIZE - 1)
+            .ok_or(VmoError::InvalidSize)?
+            & !(PAGE_SIZE - 1);
         let page_count = aligned_size / PAGE_SIZE;
 
-        let mut pages = Vec::with_capacity(page_count);
-        for i in 0..page_count {
-            pages.push(PageState::Committed(phys_addr.add(i * PAGE_SIZE), false));
+        let mut pages = try_vec(page_count, PageState::Uncommitted)?;
+        for (i, page) in pages.iter_mut().enumerate() {
+            *page = PageState::Committed(phys_addr.add(i * PAGE_SIZE), false);
         }
 
-        Ok(Arc::new(Self {
+        Arc::try_new(Self {
             inner: Mutex::new(VmoInner {
                 size: aligned_size,
+                pin_counts: try_vec(page_count, 0)?,
                 pages,
                 options: VmoOptions::empty(),
                 parent: None,
                 share_count: 1,
                 signal_state: SignalState::new(),
+                mappers: Vec::new(),
+                children: Vec::new(),
+                lock_count: 0,
+                discarded: false,
+                cache_policy: CachePolicy::Cached,
             }),
-        }))
+            account: None,
+        })
+        .map_err(|_| VmoError::NoMemory)
     }
 
-    /// 创建 COW 克隆
+    /// 创建 COW 克隆，复制出的页面计入 `account`
     pub fn create_cow_clone(
         self: &Arc<Self>,
         offset: usize,
         size: usize,
+        account: Option<Arc<MemoryAccount>>,
     ) -> Result<Arc<Self>, VmoError> {
         let inner = self.inner.lock();
 
-        if offset + size > inner.size {
+        let end = offset.checked_add(size).ok_or(VmoError::OutOfRange)?;
+        if end > inner.size {
             return Err(VmoError::OutOfRange);
         }
 
-        let aligned_size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let aligned_size = size
+            .checked_add(PAGE_SIZE - 1)
+            .ok_or(VmoError::InvalidSize)?
+            & !(PAGE_SIZE - 1);
         let page_count = aligned_size / PAGE_SIZE;
         let start_page = offset / PAGE_SIZE;
 
-        let mut pages = Vec::with_capacity(page_count);
-        for i in 0..page_count {
-            pages.push(PageState::CopyOnWrite {
+        let mut pages = try_vec(page_count, PageState::Uncommitted)?;
+        for (i, page) in pages.iter_mut().enumerate() {
+            *page = PageState::CopyOnWrite {
                 parent_offset: (start_page + i) * PAGE_SIZE,
-            });
+            };
         }
 
         drop(inner);
 
-        Ok(Arc::new(Self {
+        let child = Arc::try_new(Self {
             inner: Mutex::new(VmoInner {
                 size: aligned_size,
+                pin_counts: try_vec(page_count, 0)?,
                 pages,
                 options: VmoOptions::empty(),
                 parent: Some(self.clone()),
                 share_count: 1,
                 signal_state: SignalState::new(),
+                mappers: Vec::new(),
+                children: Vec::new(),
+                lock_count: 0,
+                discarded: false,
+                cache_policy: CachePolicy::Cached,
             }),
-        }))
+            account,
+        })
+        .map_err(|_| VmoError::NoMemory)?;
+
+        let mut inner = self.inner.lock();
+        inner.children.retain(|c| c.strong_count() != 0);
+        inner.children.push(Arc::downgrade(&child));
+        drop(inner);
+
+        Ok(child)
     }
 
     /// 获取大小
@@ -224,24 +507,54 @@ impl Vmo {
             return Err(VmoError::NotResizable);
         }
 
-        let new_aligned = (new_size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let new_aligned = new_size
+            .checked_add(PAGE_SIZE - 1)
+            .ok_or(VmoError::InvalidSize)?
+            & !(PAGE_SIZE - 1);
         let new_page_count = new_aligned / PAGE_SIZE;
         let old_page_count = inner.pages.len();
 
         if new_page_count > old_page_count {
             // 扩展
+            let additional = new_page_count - old_page_count;
+            inner
+                .pages
+                .try_reserve_exact(additional)
+                .map_err(|_| VmoError::NoMemory)?;
+            inner
+                .pin_counts
+                .try_reserve_exact(additional)
+                .map_err(|_| VmoError::NoMemory)?;
             inner.pages.resize(new_page_count, PageState::Uncommitted);
+            inner.pin_counts.resize(new_page_count, 0);
         } else if new_page_count < old_page_count {
-            // 收缩：释放多余页面
-            for page in inner.pages.drain(new_page_count..) {
-                if let PageState::Committed(phys, can_free) = page {
-                    if can_free {
-                        unsafe {
-                            FRAME_ALLOCATOR.lock().free_one(phys);
-                        }
-                    }
-                }
+            // 被 pin 住的页面不能被收缩掉
+            if inner.is_pinned(new_page_count, old_page_count) {
+                return Err(VmoError::Pinned);
             }
+
+            // 收缩：释放多余页面
+            inner.pin_counts.truncate(new_page_count);
+            let frames: Vec<PhysicalAddress> = inner
+                .pages
+                .drain(new_page_count..)
+                .filter_map(|page| match page {
+                    PageState::Committed(phys, true) => Some(phys),
+                    _ => None,
+                })
+                .collect();
+
+            inner.size = new_aligned;
+            let mappers = inner.mappers.clone();
+            drop(inner);
+
+            self.release_frames(
+                mappers,
+                new_aligned,
+                (old_page_count - new_page_count) * PAGE_SIZE,
+                frames,
+            );
+            return Ok(());
         }
 
         inner.size = new_aligned;
@@ -263,23 +576,17 @@ impl Vmo {
 
     /// 提交页面（分配物理内存）
     pub fn commit(&self, offset: usize, size: usize) -> Result<(), VmoError> {
-        let mut inner = self.inner.lock();
+        reclaim::retry_after_reclaim(|| self.try_commit(offset, size), out_of_memory)
+    }
 
-        let start_page = offset / PAGE_SIZE;
-        let end_page = (offset + size + PAGE_SIZE - 1) / PAGE_SIZE;
+    fn try_commit(&self, offset: usize, size: usize) -> Result<(), VmoError> {
+        let mut inner = self.inner.lock();
 
-        if end_page > inner.pages.len() {
-            return Err(VmoError::OutOfRange);
-        }
+        let (start_page, end_page) = inner.page_range(offset, size)?;
 
         for i in start_page..end_page {
             if let PageState::Uncommitted = inner.pages[i] {
-                let phys = unsafe {
-                    FRAME_ALLOCATOR
-                        .lock()
-                        .allocate_one()
-                        .ok_or(VmoError::NoMemory)?
-                };
+                let phys = allocate_frames(self.account.as_deref(), 1)?;
 
                 // 清零
                 unsafe {
@@ -295,95 +602,397 @@ impl Vmo {
     }
 
     /// 取消提交（释放物理内存）
+    ///
+    /// 已映射的页表项会被撤销，之后再访问会得到新的零页面。
     pub fn decommit(&self, offset: usize, size: usize) -> Result<(), VmoError> {
         let mut inner = self.inner.lock();
 
-        let start_page = offset / PAGE_SIZE;
-        let end_page = (offset + size + PAGE_SIZE - 1) / PAGE_SIZE;
+        // 连续 VMO 的物理地址已交给设备，不能打散
+        if inner.options.contains(VmoOptions::CONTIGUOUS) {
+            return Err(VmoError::NotSupported);
+        }
 
-        if end_page > inner.pages.len() {
-            return Err(VmoError::OutOfRange);
+        let (start_page, end_page) = inner.page_range(offset, size)?;
+
+        if inner.is_pinned(start_page, end_page) {
+            return Err(VmoError::Pinned);
         }
 
+        let mut frames = Vec::new();
+        for i in start_page..end_page {
+            frames.extend(inner.release_page(i));
+        }
+
+        let mappers = inner.mappers.clone();
+        drop(inner);
+
+        self.release_frames(
+            mappers,
+            start_page * PAGE_SIZE,
+            (end_page - start_page) * PAGE_SIZE,
+            frames,
+        );
+
+        Ok(())
+    }
+
+    /// 清零一段范围
+    ///
+    /// 完整覆盖的页面直接释放（下次访问时得到零页面），其余部分原地清零。
+    pub fn zero(&self, offset: usize, size: usize) -> Result<(), VmoError> {
+        let mut inner = self.inner.lock();
+
+        let (start_page, end_page) = inner.page_range(offset, size)?;
+        let end = offset + size;
+        let in_place = inner.options.contains(VmoOptions::CONTIGUOUS);
+
+        let mut frames = Vec::new();
         for i in start_page..end_page {
-            if let PageState::Committed(phys, can_free) = inner.pages[i] {
-                if can_free {
+            let page_start = i * PAGE_SIZE;
+            let zero_start = core::cmp::max(page_start, offset);
+            let zero_end = core::cmp::min(page_start + PAGE_SIZE, end);
+            let whole_page = zero_end - zero_start == PAGE_SIZE;
+
+            let state = inner.pages[i];
+            match state {
+                PageState::Uncommitted => {}
+                PageState::Committed(_, true)
+                    if whole_page && !in_place && inner.pin_counts[i] == 0 =>
+                {
+                    frames.extend(inner.release_page(i));
+                }
+                PageState::CopyOnWrite { .. } if whole_page => {
+                    inner.pages[i] = PageState::Uncommitted;
+                }
+                _ => {
+                    let phys = inner.resolve_page(i, true, self.account.as_deref())?;
                     unsafe {
-                        FRAME_ALLOCATOR.lock().free_one(phys);
+                        let virt = CurrentRmmArch::phys_to_virt(phys).add(zero_start - page_start);
+                        core::ptr::write_bytes(virt.data() as *mut u8, 0, zero_end - zero_start);
                     }
-                    inner.pages[i] = PageState::Uncommitted;
                 }
             }
         }
 
+        let mappers = inner.mappers.clone();
+        drop(inner);
+
+        // COW 页面可能已被替换，统一撤销映射让其重新缺页
+        self.release_frames(
+            mappers,
+            start_page * PAGE_SIZE,
+            (end_page - start_page) * PAGE_SIZE,
+            frames,
+        );
+
         Ok(())
     }
 
-    /// 获取指定偏移的物理地址（可能触发分配或 COW）
-    pub fn get_page(&self, offset: usize, write: bool) -> Result<PhysicalAddress, VmoError> {
+    /// 查询范围内每个页面的状态，结果写入 `out`（每页一个字节）
+    pub fn query(&self, offset: usize, size: usize, out: &mut [u8]) -> Result<usize, VmoError> {
+        let inner = self.inner.lock();
+
+        let (start_page, end_page) = inner.page_range(offset, size)?;
+        let count = end_page - start_page;
+
+        if out.len() < count {
+            return Err(VmoError::InvalidSize);
+        }
+
+        for (slot, page) in out.iter_mut().zip(&inner.pages[start_page..end_page]) {
+            *slot = match page {
+                PageState::Uncommitted => VMO_PAGE_UNCOMMITTED,
+                PageState::Committed(..) => VMO_PAGE_COMMITTED,
+                PageState::CopyOnWrite { .. } => VMO_PAGE_COPY_ON_WRITE,
+            };
+        }
+
+        Ok(count)
+    }
+
+    /// 锁定可丢弃 VMO，返回自上次解锁以来内容是否被回收过
+    pub fn lock(&self) -> Result<bool, VmoError> {
         let mut inner = self.inner.lock();
 
-        let page_index = offset / PAGE_SIZE;
-        if page_index >= inner.pages.len() {
-            return Err(VmoError::OutOfRange);
+        if !inner.options.contains(VmoOptions::DISCARDABLE) {
+            return Err(VmoError::NotSupported);
         }
 
-        match inner.pages[page_index] {
-            PageState::Committed(phys, _) => Ok(phys),
+        inner.lock_count += 1;
+        Ok(core::mem::replace(&mut inner.discarded, false))
+    }
 
-            PageState::Uncommitted => {
-                // 按需分配
-                let phys = unsafe {
-                    FRAME_ALLOCATOR
-                        .lock()
-                        .allocate(FrameCount::new(1))
-                        .ok_or(VmoError::NoMemory)?
-                };
+    /// 解锁可丢弃 VMO
+    pub fn unlock(&self) -> Result<(), VmoError> {
+        let mut inner = self.inner.lock();
 
-                // 清零
-                unsafe {
-                    let virt = CurrentRmmArch::phys_to_virt(phys);
-                    core::ptr::write_bytes(virt.data() as *mut u8, 0, PAGE_SIZE);
-                }
+        if !inner.options.contains(VmoOptions::DISCARDABLE) {
+            return Err(VmoError::NotSupported);
+        }
 
-                inner.pages[page_index] = PageState::Committed(phys, true);
-                Ok(phys)
+        if inner.lock_count == 0 {
+            return Err(VmoError::InvalidState);
+        }
+
+        inner.lock_count -= 1;
+        Ok(())
+    }
+
+    /// 在内存压力下丢弃全部页面，返回释放的页数
+    ///
+    /// 仅对未锁定、未 pin 的可丢弃 VMO 生效；VMO 正忙时直接跳过。
+    pub fn try_discard(&self) -> usize {
+        let Some(mut inner) = self.inner.try_lock() else {
+            return 0;
+        };
+
+        if !inner.options.contains(VmoOptions::DISCARDABLE)
+            || inner.options.contains(VmoOptions::CONTIGUOUS)
+            || inner.lock_count != 0
+            || inner.is_pinned(0, inner.pages.len())
+        {
+            return 0;
+        }
+
+        let mut frames = Vec::new();
+        for i in 0..inner.pages.len() {
+            frames.extend(inner.release_page(i));
+        }
+
+        if frames.is_empty() {
+            return 0;
+        }
+
+        inner.discarded = true;
+        let size = inner.size;
+        let mappers = inner.mappers.clone();
+        drop(inner);
+
+        let count = frames.len();
+        self.release_frames(mappers, 0, size, frames);
+        count
+    }
+
+    /// 登记映射了该 VMO 的 VMAR
+    pub fn add_mapper(&self, vmar: Weak<Vmar>) {
+        let mut inner = self.inner.lock();
+        inner.mappers.retain(|m| m.strong_count() != 0);
+        inner.mappers.push(vmar);
+    }
+
+    /// 取消一次登记
+    pub fn remove_mapper(&self, vmar: &Weak<Vmar>) {
+        let mut inner = self.inner.lock();
+        if let Some(pos) = inner.mappers.iter().position(|m| m.ptr_eq(vmar)) {
+            inner.mappers.swap_remove(pos);
+        }
+    }
+
+    /// 撤销所有映射（包括写时复制子 VMO 的映射）中的对应范围，然后归还物理页面
+    ///
+    /// 调用时不能持有 VMO 的锁（VMAR 会在持有自身锁时访问 VMO）。
+    fn release_frames(
+        &self,
+        mappers: Vec<Weak<Vmar>>,
+        offset: usize,
+        size: usize,
+        frames: Vec<PhysicalAddress>,
+    ) {
+        for vmar in mappers.iter().filter_map(|m| m.upgrade()) {
+            vmar.unmap_vmo_range(self, offset, size);
+        }
+        self.unmap_children(offset, size);
+
+        // 页表项全部撤销后才能归还，否则其他映射仍可能访问到被复用的页面
+        let count = frames.len();
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        for phys in frames {
+            unsafe {
+                allocator.free_one(phys);
             }
+        }
+        drop(allocator);
 
-            PageState::CopyOnWrite { parent_offset } => {
-                if write {
-                    // 需要复制
-                    let parent = inner.parent.as_ref().ok_or(VmoError::InvalidState)?;
-                    let parent_phys = parent.get_page(parent_offset, false)?;
+        account::uncharge(self.account.as_deref(), MemoryKind::Vmo, count);
 
-                    // 分配新页面
-                    let new_phys = unsafe {
-                        FRAME_ALLOCATOR
-                            .lock()
-                            .allocate(FrameCount::new(1))
-                            .ok_or(VmoError::NoMemory)?
-                    };
+        reclaim::update_pressure();
+    }
 
-                    // 复制内容
-                    unsafe {
-                        let src = CurrentRmmArch::phys_to_virt(parent_phys);
-                        let dst = CurrentRmmArch::phys_to_virt(new_phys);
-                        core::ptr::copy_nonoverlapping(
-                            src.data() as *const u8,
-                            dst.data() as *mut u8,
-                            PAGE_SIZE,
-                        );
+    /// 撤销子 VMO（以及它们的子 VMO）的映射中仍指向 `[offset, offset + size)` 的写时复制页面
+    ///
+    /// 调用时不能持有任何 VMO 的锁。
+    fn unmap_children(&self, offset: usize, size: usize) {
+        let end = offset + size;
+        let children = self.inner.lock().children.clone();
+
+        for child in children.iter().filter_map(|c| c.upgrade()) {
+            let inner = child.inner.lock();
+            let shared = |page: &PageState| {
+                matches!(*page, PageState::CopyOnWrite { parent_offset }
+                    if parent_offset >= offset && parent_offset < end)
+            };
+            let first = inner.pages.iter().position(shared);
+            let last = inner.pages.iter().rposition(shared);
+            let mappers = inner.mappers.clone();
+            drop(inner);
+
+            let (Some(first), Some(last)) = (first, last) else {
+                continue;
+            };
+            let child_offset = first * PAGE_SIZE;
+            let child_size = (last + 1 - first) * PAGE_SIZE;
+
+            for vmar in mappers.iter().filter_map(|m| m.upgrade()) {
+                vmar.unmap_vmo_range(&child, child_offset, child_size);
+            }
+            child.unmap_children(child_offset, child_size);
+        }
+    }
+
+    /// 已提交且归该 VMO 所有的页数
+    pub fn committed_pages(&self) -> usize {
+        self.inner
+            .lock()
+            .pages
+            .iter()
+            .filter(|page| matches!(page, PageState::Committed(_, true)))
+            .count()
+    }
+
+    /// 获取缓存策略
+    pub fn cache_policy(&self) -> CachePolicy {
+        self.inner.lock().cache_policy
+    }
+
+    /// 设置缓存策略，只能在没有映射时修改
+    pub fn set_cache_policy(&self, policy: CachePolicy) -> Result<(), VmoError> {
+        let mut inner = self.inner.lock();
+
+        if inner.mappers.iter().any(|v| v.strong_count() != 0) {
+            return Err(VmoError::InvalidState);
+        }
+
+        inner.cache_policy = policy;
+        Ok(())
+    }
+
+    /// 如果 `[offset, offset + size)` 由不会变化的物理连续页面组成，返回其起始物理地址
+    ///
+    /// 只有连续 VMO 和物理 VMO 的页面不会被替换，可以用大页映射。
+    pub fn phys_contiguous(&self, offset: usize, size: usize) -> Option<PhysicalAddress> {
+        let inner = self.inner.lock();
+        let (start_page, end_page) = inner.page_range(offset, size).ok()?;
+        if start_page == end_page {
+            return None;
+        }
+
+        let PageState::Committed(base, owned) = inner.pages[start_page] else {
+            return None;
+        };
+        if owned && !inner.options.contains(VmoOptions::CONTIGUOUS) {
+            return None;
+        }
+
+        let contiguous = inner.pages[start_page..end_page]
+            .iter()
+            .enumerate()
+            .all(|(i, &page)| page == PageState::Committed(base.add(i * PAGE_SIZE), owned));
+
+        contiguous.then_some(base)
+    }
+
+    /// 获取指定偏移的物理地址（可能触发分配或 COW）
+    pub fn get_page(&self, offset: usize, write: bool) -> Result<PhysicalAddress, VmoError> {
+        let mut inner = self.inner.lock();
+        inner.resolve_page(offset / PAGE_SIZE, write, self.account.as_deref())
+    }
+
+    /// 获取用于映射的物理页面，同时返回它是否仍与父 VMO 共享
+    ///
+    /// 共享的页面只能只读映射，写入时通过缺页复制。
+    pub fn get_page_for_map(
+        &self,
+        offset: usize,
+        write: bool,
+    ) -> Result<(PhysicalAddress, bool), VmoError> {
+        let mut inner = self.inner.lock();
+        let index = offset / PAGE_SIZE;
+        let phys = inner.resolve_page(index, write, self.account.as_deref())?;
+        let shared = matches!(inner.pages[index], PageState::CopyOnWrite { .. });
+        Ok((phys, shared))
+    }
+
+    /// Pin 住一段范围，返回固定内存令牌
+    ///
+    /// 范围内的页面会被提交（COW 页面会被复制），并且在令牌销毁之前不会被
+    /// decommit、收缩或回收。令牌中的每一段为 (物理地址, 长度)，相邻的物理
+    /// 连续页面会被合并为一段。
+    pub fn pin(self: &Arc<Self>, offset: usize, size: usize) -> Result<Arc<Pmt>, VmoError> {
+        reclaim::retry_after_reclaim(|| self.try_pin(offset, size), out_of_memory)
+    }
+
+    fn try_pin(self: &Arc<Self>, offset: usize, size: usize) -> Result<Arc<Pmt>, VmoError> {
+        if size == 0 {
+            return Err(VmoError::InvalidSize);
+        }
+
+        let mut inner = self.inner.lock();
+
+        let end = offset.checked_add(size).ok_or(VmoError::OutOfRange)?;
+        if end > inner.size {
+            return Err(VmoError::OutOfRange);
+        }
+
+        let start_page = offset / PAGE_SIZE;
+        let end_page = (end + PAGE_SIZE - 1) / PAGE_SIZE;
+
+        // 先确保所有页面都已提交，失败时不留下任何 pin
+        let mut frames = Vec::new();
+        frames
+            .try_reserve_exact(end_page - start_page)
+            .map_err(|_| VmoError::NoMemory)?;
+        for i in start_page..end_page {
+            frames.push(inner.resolve_page(i, true, self.account.as_deref())?);
+        }
+
+        for i in start_page..end_page {
+            match inner.pin_counts[i].checked_add(1) {
+                Some(count) => inner.pin_counts[i] = count,
+                None => {
+                    for count in &mut inner.pin_counts[start_page..i] {
+                        *count -= 1;
                     }
+                    return Err(VmoError::TooManyPins);
+                }
+            }
+        }
+        drop(inner);
 
-                    inner.pages[page_index] = PageState::Committed(new_phys, true);
-                    Ok(new_phys)
-                } else {
-                    // 只读访问，返回父页面
-                    let parent = inner.parent.as_ref().ok_or(VmoError::InvalidState)?;
-                    parent.get_page(parent_offset, false)
+        let mut runs: Vec<(PhysicalAddress, usize)> = Vec::new();
+        for (i, phys) in frames.into_iter().enumerate() {
+            let page_start = (start_page + i) * PAGE_SIZE;
+            let chunk_start = core::cmp::max(page_start, offset);
+            let chunk_end = core::cmp::min(page_start + PAGE_SIZE, end);
+            let chunk_phys = phys.add(chunk_start - page_start);
+            let chunk_len = chunk_end - chunk_start;
+
+            match runs.last_mut() {
+                Some((run_phys, run_len)) if run_phys.add(*run_len) == chunk_phys => {
+                    *run_len += chunk_len;
                 }
+                _ => runs.push((chunk_phys, chunk_len)),
             }
         }
+
+        Ok(Pmt::new(self.clone(), start_page, end_page, runs))
+    }
+
+    /// 解除 `[start_page, end_page)` 的 pin，只由 `Pmt` 销毁时调用
+    pub(super) fn unpin_pages(&self, start_page: usize, end_page: usize) {
+        let mut inner = self.inner.lock();
+        for count in &mut inner.pin_counts[start_page..end_page] {
+            *count -= 1;
+        }
     }
 
     /// 读取数据
@@ -402,7 +1011,10 @@ impl Vmo {
             let page_offset = (offset + bytes_read) % PAGE_SIZE;
             let chunk_len = core::cmp::min(PAGE_SIZE - page_offset, read_len - bytes_read);
 
-            let phys = self.get_page(offset + bytes_read, false)?;
+            let phys = reclaim::retry_after_reclaim(
+                || self.get_page(offset + bytes_read, false),
+                out_of_memory,
+            )?;
 
             unsafe {
                 let src = CurrentRmmArch::phys_to_virt(phys).add(page_offset);
@@ -435,7 +1047,10 @@ impl Vmo {
             let page_offset = (offset + bytes_written) % PAGE_SIZE;
             let chunk_len = core::cmp::min(PAGE_SIZE - page_offset, write_len - bytes_written);
 
-            let phys = self.get_page(offset + bytes_written, true)?;
+            let phys = reclaim::retry_after_reclaim(
+                || self.get_page(offset + bytes_written, true),
+                out_of_memory,
+            )?;
 
             unsafe {
                 let dst = CurrentRmmArch::phys_to_virt(phys).add(page_offset);
@@ -458,15 +1073,20 @@ impl Drop for Vmo {
         let inner = self.inner.lock();
 
         // 只释放自己分配的页面（不释放 COW 指向的父页面）
+        let mut freed = 0;
+        // 固定内存令牌持有 VMO 的引用，这里不会再有 pin 住的页面
         for page in &inner.pages {
             if let PageState::Committed(phys, can_free) = page {
                 if *can_free {
                     unsafe {
                         FRAME_ALLOCATOR.lock().free_one(*phys);
                     }
+                    freed += 1;
                 }
             }
         }
+
+        account::uncharge(self.account.as_deref(), MemoryKind::Vmo, freed);
     }
 }
 
@@ -509,4 +1129,10 @@ pub enum VmoError {
     NotResizable,
     InvalidState,
     AccessDenied,
+    /// 页面被 pin 住
+    Pinned,
+    /// 页面的 pin 计数溢出
+    TooManyPins,
+    /// 该 VMO 不支持此操作
+    NotSupported,
 }
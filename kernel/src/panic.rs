@@ -1,16 +1,20 @@
-use core::{hint::spin_loop, panic::PanicInfo, ptr::addr_of_mut};
+use core::{fmt, panic::PanicInfo, ptr::addr_of_mut, sync::atomic::Ordering};
 
-use crate::object::process::current_process;
-use alloc::{boxed::Box, format};
+#[cfg(target_arch = "x86_64")]
+use crate::drivers::ns16550::{self, SERIAL};
+#[cfg(target_arch = "riscv64")]
+use crate::drivers::sbi_console::SERIAL;
+use crate::{
+    arch::{self, CurrentIrqArch, CurrentTimeArch, irq::IrqArch, time::TimeArch},
+    cmdline, crash,
+    drivers::fbterm::{self, TERMINAL, TERMINAL_INITIALIZED},
+};
 use limine::request::ExecutableFileRequest;
 use log::debug;
 use object::{File, Object, ObjectSymbol};
 use rustc_demangle::demangle;
 use spin::Lazy;
-use unwinding::{
-    abi::{_Unwind_Backtrace, _Unwind_GetIP, UnwindContext, UnwindReasonCode},
-    panic::begin_panic,
-};
+use unwinding::abi::{_Unwind_Backtrace, _Unwind_GetIP, UnwindContext, UnwindReasonCode};
 
 #[used]
 #[unsafe(link_section = ".requests")]
@@ -22,50 +26,116 @@ static KERNEL_FILE: Lazy<File> = Lazy::new(|| unsafe {
     File::parse(bin).expect("Failed to parse kernel file")
 });
 
+/// 地址所在的内核符号，格式化时解码符号名
+///
+/// 不分配内存，其他 CPU 被停下后仍可使用。
+pub struct Symbol(Option<&'static str>);
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(name) => write!(f, "{:#}", demangle(name)),
+            None => f.write_str("<unknown>"),
+        }
+    }
+}
+
+/// 查找地址所在的内核符号
+pub fn symbol(address: usize) -> Symbol {
+    Symbol(
+        KERNEL_FILE
+            .symbols()
+            .find(|symbol| {
+                let start = symbol.address();
+                let end = start + symbol.size();
+                (start..end).contains(&(address as u64))
+            })
+            .and_then(|symbol| symbol.name().ok()),
+    )
+}
+
+/// 强制释放输出使用的锁，被 NMI 停下的 CPU 可能正持有它们
+///
+/// 帧缓冲或串口已经交给用户态时也重新接管，保证 panic 信息可见。
+fn unlock_console() {
+    unsafe {
+        SERIAL.force_unlock();
+        if TERMINAL_INITIALIZED.load(Ordering::SeqCst) {
+            TERMINAL.force_unlock();
+        }
+    }
+    fbterm::reclaim();
+    #[cfg(target_arch = "x86_64")]
+    ns16550::reclaim();
+}
+
+/// panic 后的处理：命令行带有 `panic_reboot[=秒数]` 时等待后重启，否则停机
+fn halt_or_reboot() -> ! {
+    if let Some(seconds) = cmdline::options().panic_reboot {
+        log::error!("Rebooting in {} seconds", seconds);
+        CurrentTimeArch::delay(seconds.saturating_mul(1_000_000_000));
+        arch::reboot();
+    }
+    arch::halt()
+}
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    CurrentIrqArch::disable_global_irq();
+    if !crash::begin() {
+        // 其他 CPU 正在处理 panic，或者处理过程中再次 panic
+        arch::halt();
+    }
+    crash::stop_other_cpus();
+    unlock_console();
+
     log::error!("{}", info);
     log::error!("Backtrace:");
 
-    struct Counter(usize);
+    struct Backtrace {
+        count: usize,
+        addresses: [u64; crash::BACKTRACE_MAX],
+    }
 
     extern "C" fn callback(
         unwind_ctx: &UnwindContext<'_>,
         arg: *mut core::ffi::c_void,
     ) -> UnwindReasonCode {
         let address = _Unwind_GetIP(unwind_ctx);
-        let counter = unsafe { (arg as *mut Counter).as_mut() }.unwrap();
-
-        let symbol = KERNEL_FILE
-            .symbols()
-            .find(|symbol| {
-                let start = symbol.address();
-                let end = start + symbol.size();
-                (start..end).contains(&(address as u64))
-            })
-            .and_then(|symbol| symbol.name().ok())
-            .map(|name| format!("{:#}", demangle(name)))
-            .unwrap_or("<unknown>".into());
+        let backtrace = unsafe { (arg as *mut Backtrace).as_mut() }.unwrap();
 
-        log::error!("{:4}:{:#19x} -> {}", counter.0, address, symbol);
-        counter.0 += 1;
+        log::error!(
+            "{:4}:{:#19x} -> {}",
+            backtrace.count,
+            address,
+            symbol(address)
+        );
+        if let Some(slot) = backtrace.addresses.get_mut(backtrace.count) {
+            *slot = address as u64;
+        }
+        backtrace.count += 1;
         UnwindReasonCode::NO_REASON
     }
 
-    let mut counter = Counter(0);
-    _Unwind_Backtrace(callback, addr_of_mut!(counter) as *mut _);
+    let mut backtrace = Backtrace {
+        count: 0,
+        addresses: [0; crash::BACKTRACE_MAX],
+    };
+    _Unwind_Backtrace(callback, addr_of_mut!(backtrace) as *mut _);
 
-    if info.can_unwind() {
-        struct NoPayload;
-        let code = begin_panic(Box::new(NoPayload));
-        log::error!("Unwind reason code: {}", code.0);
-    }
+    let recorded = backtrace.count.min(crash::BACKTRACE_MAX);
+    crash::record_panic(format_args!("{}", info), &backtrace.addresses[..recorded]);
+    crash::finish();
+    crash::dump_cpu_states();
+    gdbstub::handle_panic(crash::fault_regs());
 
-    if let Some(process) = current_process() {
-        debug!("Faulting process name: {}", process.read().name());
+    // 其他 CPU 已被停下，可能持有调度器或进程的锁，只能不加锁或尝试加锁地访问
+    if let Some(task) = unsafe { arch::current_task_ptr().as_ref() }
+        && let Some(process) = task.process()
+        && let Some(process) = process.try_read()
+    {
+        debug!("Faulting process name: {}", process.name());
     }
 
-    loop {
-        spin_loop();
-    }
+    halt_or_reboot()
 }
@@ -0,0 +1,45 @@
+//! 采样分析器（profiler）的记录格式
+//!
+//! 内核与用户态共用这里的定义。分析器在时钟中断中记录被中断的指令地址和按帧指针回溯的调用栈，
+//! `SYS_PROFILE_CONTROL` 的 `PROFILE_ACTION_READ` 返回连续的 [`ProfileSample`]。
+
+/// 每个样本最多记录的栈帧数，包括被中断的指令地址
+pub const PROFILE_STACK_DEPTH: usize = 16;
+
+/// 采样范围，`PROFILE_ACTION_START` 的参数为要采样的范围掩码
+pub const PROFILE_MODE_KERNEL: u32 = 1 << 0;
+pub const PROFILE_MODE_USER: u32 = 1 << 1;
+pub const PROFILE_MODE_ALL: u32 = PROFILE_MODE_KERNEL | PROFILE_MODE_USER;
+
+/// 样本来自用户态
+pub const PROFILE_FLAG_USER: u16 = 1 << 0;
+/// 缓冲区已满，有样本被丢弃，丢失的数量在 `frames[0]` 中，`depth` 为 0
+pub const PROFILE_FLAG_DROPPED: u16 = 1 << 1;
+
+/// 开始采样，参数为范围掩码
+pub const PROFILE_ACTION_START: usize = 0;
+/// 停止采样，已有的样本保留
+pub const PROFILE_ACTION_STOP: usize = 1;
+/// 清空所有 CPU 的缓冲区
+pub const PROFILE_ACTION_REWIND: usize = 2;
+/// 取出缓冲区中的样本，返回写入的字节数
+pub const PROFILE_ACTION_READ: usize = 3;
+
+/// 一个采样
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProfileSample {
+    /// 单调时间（纳秒）
+    pub timestamp: u64,
+    /// 被中断的进程，内核任务为 0
+    pub pid: u32,
+    pub tid: u32,
+    /// CPU 的硬件编号
+    pub cpu: u16,
+    /// `PROFILE_FLAG_*` 的组合
+    pub flags: u16,
+    /// `frames` 中有效的栈帧数
+    pub depth: u32,
+    /// 栈帧地址，`frames[0]` 为被中断的指令地址，其后为各层的返回地址
+    pub frames: [u64; PROFILE_STACK_DEPTH],
+}
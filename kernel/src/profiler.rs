@@ -0,0 +1,229 @@
+//! 采样分析器
+//!
+//! 开启后每次时钟中断（默认 `SCHED_HZ` 次每秒，见命令行选项 `sched_hz`）记录被中断的指令地址和按帧指针回溯的调用栈。
+//! 每个 CPU 有独立的缓冲区，缓冲区满时丢弃新的样本，并在读取时以 `PROFILE_FLAG_DROPPED` 报告丢失的数量。
+//! 回溯只通过页表读取栈内存，不会触发缺页；没有帧指针的代码只能得到被中断的指令地址。
+
+use alloc::collections::{TryReserveError, VecDeque};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use rmm::Arch;
+use spin::Mutex;
+
+use crate::{
+    arch::{CurrentRmmArch, CurrentTlbArch, Ptrace, irq::IrqRegsArch, tlb::TlbArch},
+    layout::USER_SPACE_END,
+    memory::{direct_map, tlb::MAX_CPUS},
+    profile::{
+        PROFILE_FLAG_DROPPED, PROFILE_FLAG_USER, PROFILE_MODE_KERNEL, PROFILE_MODE_USER,
+        PROFILE_STACK_DEPTH, ProfileSample,
+    },
+    smp::CPUID_TO_ARCHID,
+    trace,
+};
+
+/// 每个 CPU 缓冲区的样本数
+pub const BUFFER_SAMPLES: usize = 4096;
+
+/// 当前的采样范围，为 0 时没有开启
+static MODE: AtomicU32 = AtomicU32::new(0);
+
+struct SampleBuffer {
+    samples: VecDeque<ProfileSample>,
+    /// 缓冲区满时丢弃的样本数
+    dropped: u64,
+}
+
+impl SampleBuffer {
+    const fn new() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, sample: ProfileSample) {
+        // 只使用开启时预留的空间，中断中不分配内存
+        if self.samples.capacity() < BUFFER_SAMPLES {
+            return;
+        }
+        if self.samples.len() < BUFFER_SAMPLES {
+            self.samples.push_back(sample);
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
+
+static BUFFERS: [Mutex<SampleBuffer>; MAX_CPUS] =
+    [const { Mutex::new(SampleBuffer::new()) }; MAX_CPUS];
+
+/// 是否在采样
+#[inline]
+pub fn running() -> bool {
+    MODE.load(Ordering::Relaxed) != 0
+}
+
+/// 读取栈上的一个字，页面未映射时返回 None
+fn read_stack_word(address: usize) -> Option<u64> {
+    if !address.is_multiple_of(size_of::<u64>()) {
+        return None;
+    }
+    let ptr = direct_map(address)?;
+    Some(unsafe { (ptr as *const u64).read_volatile() })
+}
+
+/// 帧指针是否可能指向 `user` 对应地址空间中的栈
+fn frame_pointer_valid(fp: usize, user: bool) -> bool {
+    if user {
+        fp != 0 && fp < USER_SPACE_END
+    } else {
+        fp >= CurrentRmmArch::PHYS_OFFSET
+    }
+}
+
+/// 按帧指针链回溯，返回写入 `frames` 的栈帧数
+///
+/// 栈向低地址增长，上一层的帧指针必然更高，不满足时说明链已经损坏。
+fn walk_stack(regs: &Ptrace, user: bool, frames: &mut [u64; PROFILE_STACK_DEPTH]) -> usize {
+    frames[0] = regs.get_ip();
+    let mut depth = 1;
+    let mut fp = regs.get_fp() as usize;
+
+    while depth < PROFILE_STACK_DEPTH && frame_pointer_valid(fp, user) {
+        let Some(next) = read_stack_word(fp) else {
+            break;
+        };
+        let Some(ret) = fp.checked_add(size_of::<u64>()).and_then(read_stack_word) else {
+            break;
+        };
+        if ret == 0 {
+            break;
+        }
+        frames[depth] = ret;
+        depth += 1;
+
+        let next = next as usize;
+        if next <= fp {
+            break;
+        }
+        fp = next;
+    }
+    depth
+}
+
+/// 时钟中断中调用，记录一个样本
+pub fn sample(regs: &Ptrace) {
+    let mode = MODE.load(Ordering::Relaxed);
+    let user = regs.is_user_mode();
+    let wanted = if user {
+        PROFILE_MODE_USER
+    } else {
+        PROFILE_MODE_KERNEL
+    };
+    if mode & wanted == 0 {
+        return;
+    }
+
+    let cpu = CurrentTlbArch::current_cpu();
+    let (pid, tid) = trace::current_ids(cpu);
+    let mut sample = ProfileSample {
+        timestamp: trace::timestamp(),
+        pid,
+        tid,
+        cpu: cpu as u16,
+        flags: if user { PROFILE_FLAG_USER } else { 0 },
+        ..ProfileSample::default()
+    };
+    sample.depth = walk_stack(regs, user, &mut sample.frames) as u32;
+
+    let irq = CurrentTlbArch::irq_save();
+    BUFFERS[cpu].lock().push(sample);
+    CurrentTlbArch::irq_restore(irq);
+}
+
+/// 开始采样 `mode` 范围内的样本，第一次开启时为每个 CPU 分配缓冲区
+pub fn start(mode: u32) -> Result<(), TryReserveError> {
+    let cpus: Vec<usize> = CPUID_TO_ARCHID.lock().values().copied().collect();
+    for cpu in cpus.into_iter().filter(|&cpu| cpu < MAX_CPUS) {
+        let irq = CurrentTlbArch::irq_save();
+        let reserved = BUFFERS[cpu].lock().samples.capacity() >= BUFFER_SAMPLES;
+        CurrentTlbArch::irq_restore(irq);
+        if reserved {
+            continue;
+        }
+
+        // 在锁外分配，避免关中断期间分配大块内存
+        let mut samples = VecDeque::new();
+        samples.try_reserve_exact(BUFFER_SAMPLES)?;
+
+        let irq = CurrentTlbArch::irq_save();
+        {
+            let mut buffer = BUFFERS[cpu].lock();
+            if buffer.samples.capacity() < BUFFER_SAMPLES {
+                buffer.samples = samples;
+            }
+        }
+        CurrentTlbArch::irq_restore(irq);
+    }
+
+    MODE.store(mode, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 停止采样，已有的样本保留到读取或清空
+pub fn stop() {
+    MODE.store(0, Ordering::Relaxed);
+}
+
+/// 清空所有 CPU 的缓冲区
+pub fn rewind() {
+    for buffer in BUFFERS.iter() {
+        let irq = CurrentTlbArch::irq_save();
+        {
+            let mut buffer = buffer.lock();
+            buffer.samples.clear();
+            buffer.dropped = 0;
+        }
+        CurrentTlbArch::irq_restore(irq);
+    }
+}
+
+/// 依次取出各 CPU 缓冲区中的样本，一次最多取出 `max` 和 `BUFFER_SAMPLES` 中较小者个
+///
+/// 某个 CPU 有样本被丢弃时，先返回一个带 `PROFILE_FLAG_DROPPED` 的样本。
+pub fn drain(max: usize) -> Result<Vec<ProfileSample>, TryReserveError> {
+    let limit = max.min(BUFFER_SAMPLES);
+    let mut out = Vec::new();
+    out.try_reserve_exact(limit)?;
+
+    for (cpu, buffer) in BUFFERS.iter().enumerate() {
+        let irq = CurrentTlbArch::irq_save();
+        let mut buffer = buffer.lock();
+        if buffer.dropped != 0 && out.len() < limit {
+            let mut frames = [0; PROFILE_STACK_DEPTH];
+            frames[0] = buffer.dropped;
+            out.push(ProfileSample {
+                timestamp: trace::timestamp(),
+                cpu: cpu as u16,
+                flags: PROFILE_FLAG_DROPPED,
+                frames,
+                ..ProfileSample::default()
+            });
+            buffer.dropped = 0;
+        }
+        while out.len() < limit {
+            match buffer.samples.pop_front() {
+                Some(sample) => out.push(sample),
+                None => break,
+            }
+        }
+        drop(buffer);
+        CurrentTlbArch::irq_restore(irq);
+
+        if out.len() >= limit {
+            break;
+        }
+    }
+    Ok(out)
+}
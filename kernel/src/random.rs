@@ -0,0 +1,138 @@
+//! 内核随机数发生器
+//!
+//! 使用 ChaCha20 作为 CSPRNG，每次输出后立即用同一次生成的密钥流替换密钥（fast key erasure），
+//! 泄露当前状态也无法还原之前的输出。种子来自 RDSEED/RDRAND 和周期计数器，
+//! 运行期间中断的时间抖动会持续混入。
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use chacha20::BLOCK_SIZE;
+use spin::{Lazy, Mutex};
+
+use crate::arch::{CurrentRandomArch, random::RandomArch};
+
+/// 从硬件熵源重新取种子的间隔（输出次数）
+const RESEED_INTERVAL: u64 = 1024;
+/// 没有硬件熵源时，初始化时采样周期计数器的次数
+const JITTER_SAMPLES: usize = 64;
+
+/// 中断时间抖动的累积值，中断处理中无锁更新
+static INTERRUPT_JITTER: AtomicU64 = AtomicU64::new(0);
+
+static CPRNG: Lazy<Mutex<ChaCha20Rng>> = Lazy::new(|| Mutex::new(ChaCha20Rng::new(seed())));
+
+/// 计算一个 ChaCha20 块，nonce 固定为 0
+fn chacha20_block(key: &[u32; 8], counter: u32) -> [u8; BLOCK_SIZE] {
+    chacha20::block(key, counter, &[0; 3])
+}
+
+/// 收集初始种子：优先使用 RDSEED，其次 RDRAND，并混入周期计数器
+fn seed() -> [u32; 8] {
+    let mut words = [0u64; 4];
+    let mut hardware = true;
+    for word in words.iter_mut() {
+        match CurrentRandomArch::hardware_seed().or_else(CurrentRandomArch::hardware_random) {
+            Some(value) => *word = value,
+            None => hardware = false,
+        }
+    }
+
+    if !hardware {
+        warn!("No hardware entropy source, seeding CPRNG from timing jitter only");
+    }
+    for i in 0..JITTER_SAMPLES {
+        let sample = CurrentRandomArch::cycle_counter();
+        words[i % 4] = words[i % 4].rotate_left(7) ^ sample;
+    }
+
+    let mut key = [0u32; 8];
+    for (i, word) in words.iter().enumerate() {
+        key[i * 2] = *word as u32;
+        key[i * 2 + 1] = (*word >> 32) as u32;
+    }
+    key
+}
+
+struct ChaCha20Rng {
+    key: [u32; 8],
+    /// 已经输出的次数
+    draws: u64,
+    /// 上次混入的中断抖动
+    last_jitter: u64,
+}
+
+impl ChaCha20Rng {
+    fn new(key: [u32; 8]) -> Self {
+        Self {
+            key,
+            draws: 0,
+            last_jitter: 0,
+        }
+    }
+
+    /// 把新的熵异或进密钥，之后的密钥由 ChaCha20 派生，混入的值不会直接出现在输出中
+    fn mix(&mut self, value: u64) {
+        let index = (self.draws as usize % 4) * 2;
+        self.key[index] ^= value as u32;
+        self.key[index + 1] ^= (value >> 32) as u32;
+    }
+
+    fn reseed_if_needed(&mut self) {
+        let jitter = INTERRUPT_JITTER.load(Ordering::Relaxed);
+        if jitter != self.last_jitter {
+            self.last_jitter = jitter;
+            self.mix(jitter);
+        }
+        self.mix(CurrentRandomArch::cycle_counter());
+
+        if self.draws % RESEED_INTERVAL == 0
+            && let Some(value) =
+                CurrentRandomArch::hardware_seed().or_else(CurrentRandomArch::hardware_random)
+        {
+            self.mix(value);
+        }
+    }
+
+    fn fill_bytes(&mut self, buf: &mut [u8]) {
+        self.reseed_if_needed();
+        self.draws += 1;
+
+        // 块 0 的前 32 字节作为下一个密钥，其余块用于输出
+        let first = chacha20_block(&self.key, 0);
+        let mut counter = 1;
+        for chunk in buf.chunks_mut(BLOCK_SIZE) {
+            let block = chacha20_block(&self.key, counter);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+            counter += 1;
+        }
+
+        for (i, word) in self.key.iter_mut().enumerate() {
+            *word = u32::from_le_bytes(first[i * 4..i * 4 + 4].try_into().unwrap());
+        }
+    }
+}
+
+/// 提前完成播种，避免第一次使用时才等待硬件熵源
+pub fn init() {
+    Lazy::force(&CPRNG);
+    info!("Kernel CPRNG initialized");
+}
+
+/// 中断处理中调用，把中断到达的时间混入熵池
+pub fn add_interrupt_entropy() {
+    let sample = CurrentRandomArch::cycle_counter();
+    let old = INTERRUPT_JITTER.load(Ordering::Relaxed);
+    INTERRUPT_JITTER.store(old.rotate_left(7) ^ sample, Ordering::Relaxed);
+}
+
+/// 用随机字节填充 `buf`
+pub fn fill_bytes(buf: &mut [u8]) {
+    CPRNG.lock().fill_bytes(buf);
+}
+
+/// 生成一个 64 位随机数
+pub fn next_u64() -> u64 {
+    let mut buf = [0u8; 8];
+    fill_bytes(&mut buf);
+    u64::from_le_bytes(buf)
+}
@@ -0,0 +1,21 @@
+//! 串口控制台交接
+//!
+//! 内核与用户态共用这里的定义。内核按命令行的 `console=` 或 ACPI SPCR 表选择串口控制台，
+//! `SYS_KRES_GET_SERIAL_CONSOLE` 写出一个 [`SerialConsoleInfo`] 告诉 UART 驱动它的位置。
+//! 驱动用 `SYS_IOPORT_REQUEST` 取得这些端口后，内核不再在串口上输出日志，只在 panic 时重新接管。
+
+/// 串口控制台的位置和线路参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SerialConsoleInfo {
+    /// 寄存器的 I/O 端口基址
+    pub port: u16,
+    /// 设备中断号（ISA IRQ）
+    pub irq: u8,
+    pub reserved: u8,
+    /// 固件使用的波特率，0 表示未知
+    pub baud_rate: u32,
+}
+
+/// 16550 寄存器占用的 I/O 端口数
+pub const SERIAL_PORT_COUNT: usize = 8;
@@ -1,15 +1,20 @@
 use alloc::collections::btree_map::BTreeMap;
+#[cfg(target_arch = "x86_64")]
 use limine::request::MpRequest;
 use spin::Mutex;
 
+/// RISC-V 上由内核通过 SBI HSM 自行启动各个 hart
+#[cfg(target_arch = "x86_64")]
 #[used]
 #[unsafe(link_section = ".requests")]
 pub static MP_REQUEST: MpRequest = MpRequest::new();
 
-use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{AtomicU64, AtomicUsize};
 
 pub static BSP_CPUARCHID: AtomicUsize = AtomicUsize::new(0);
 pub static CPU_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// 已启动的 CPU 的硬件编号掩码，不包括命令行禁用的 CPU
+pub static ONLINE_CPUS: AtomicU64 = AtomicU64::new(0);
 
 pub static CPUID_TO_ARCHID: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
 
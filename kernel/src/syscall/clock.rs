@@ -1,12 +1,70 @@
+use core::sync::atomic::{AtomicI64, Ordering};
+
+use spin::Once;
+
 use crate::{
-    Result,
+    EINVAL, ENODEV, Error, Result,
     arch::{CurrentTimeArch, time::TimeArch},
+    syscall::{
+        nr::{CLOCK_MONOTONIC, CLOCK_REALTIME},
+        process::require_privileged,
+    },
 };
 
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+/// UTC 偏移的最大绝对值（秒）
+const MAX_UTC_OFFSET: i64 = 24 * 3600;
+
+/// 单调时间为 0 时 RTC 的读数（纳秒），RTC 不可用时未设置
+static RTC_BASE_NS: Once<i64> = Once::new();
+/// RTC 时间减去 UTC 时间的差值（秒），RTC 按本地时间保存时由特权进程设置
+static UTC_OFFSET: AtomicI64 = AtomicI64::new(0);
+
+/// 启动时读取一次 RTC，之后的墙上时间由单调时间推算
+pub fn init() {
+    let Some(seconds) = CurrentTimeArch::read_rtc() else {
+        warn!("RTC is not available, CLOCK_REALTIME is disabled");
+        return;
+    };
+    let now = CurrentTimeArch::nano_time() as i64;
+    RTC_BASE_NS.call_once(|| seconds as i64 * NANOS_PER_SEC - now);
+    info!("RTC time: {} seconds since epoch", seconds);
+}
+
+fn realtime() -> Option<i64> {
+    let base = RTC_BASE_NS.get()?;
+    let offset = UTC_OFFSET.load(Ordering::Relaxed) * NANOS_PER_SEC;
+    Some(base + CurrentTimeArch::nano_time() as i64 - offset)
+}
+
 pub fn sys_clock_get() -> Result<usize> {
     Ok(CurrentTimeArch::nano_time() as usize)
 }
 
+/// 读取指定时钟（纳秒）
+pub fn sys_clock_read(clock_id: usize) -> Result<usize> {
+    match clock_id {
+        CLOCK_MONOTONIC => sys_clock_get(),
+        CLOCK_REALTIME => realtime()
+            .filter(|ns| *ns >= 0)
+            .map(|ns| ns as usize)
+            .ok_or(Error::new(ENODEV)),
+        _ => Err(Error::new(EINVAL)),
+    }
+}
+
+/// 设置 RTC 相对 UTC 的偏移（秒），需要特权
+pub fn sys_clock_set_utc_offset(offset: usize) -> Result<usize> {
+    require_privileged()?;
+
+    let offset = offset as isize as i64;
+    if offset.abs() > MAX_UTC_OFFSET {
+        return Err(Error::new(EINVAL));
+    }
+    UTC_OFFSET.store(offset, Ordering::Relaxed);
+    Ok(0)
+}
+
 pub fn sys_nanosleep(ns: usize) -> Result<usize> {
     let start_ns = CurrentTimeArch::nano_time();
     while CurrentTimeArch::nano_time() - start_ns < ns as u64 {
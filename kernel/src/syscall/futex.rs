@@ -1,12 +1,12 @@
 use alloc::collections::btree_map::BTreeMap;
 use spin::Mutex;
 
-use crate::{EINVAL, EPERM, Error, Result, object::WaitQueue};
+use crate::{EINVAL, EPERM, Error, Result, object::WaitQueue, syscall::user};
 
 static FUTEXES: Mutex<BTreeMap<usize, WaitQueue>> = Mutex::new(BTreeMap::new());
 
 pub fn sys_futex_wait(ptr: usize, val: usize, _deadline: usize) -> Result<usize> {
-    let val_user = unsafe { core::ptr::read_unaligned(ptr as *const u32) };
+    let val_user = user::read::<u32>(ptr)?;
     if val as u32 != val_user {
         return Err(Error::new(EPERM));
     }
@@ -0,0 +1,50 @@
+use alloc::sync::Arc;
+
+use crate::{
+    EBADF, EBUSY, EINVAL, ESRCH, Error, Result,
+    object::{
+        KernelObject, Rights,
+        interrupt::{Interrupt, InterruptError},
+        process::current_process,
+    },
+    syscall::{process::require_privileged, user},
+};
+
+/// 为设备中断 `irq` 创建中断对象，需要特权
+///
+/// 中断到达时对象的 `SIGNALED` 置位，驱动处理完设备后调用 `SYS_INTERRUPT_ACK`。
+pub fn sys_interrupt_create(irq: usize, handle_out: usize) -> Result<usize> {
+    require_privileged()?;
+    user::check_range(handle_out, size_of::<u32>())?;
+
+    let interrupt = Interrupt::create(irq).map_err(|e| match e {
+        InterruptError::InvalidIrq => Error::new(EINVAL),
+        InterruptError::Busy => Error::new(EBUSY),
+    })?;
+
+    let process = current_process().ok_or(Error::new(ESRCH))?;
+    let handle = process.write().handles_mut().insert(
+        interrupt as Arc<dyn KernelObject>,
+        Rights::WAIT | Rights::SIGNAL | Rights::DUPLICATE | Rights::TRANSFER,
+    );
+
+    user::write(handle_out, handle.raw())?;
+    Ok(0)
+}
+
+/// 应答中断：清除 `SIGNALED` 并解除屏蔽
+pub fn sys_interrupt_ack(handle: usize) -> Result<usize> {
+    let process = current_process().ok_or(Error::new(ESRCH))?;
+    let object = process
+        .read()
+        .handles()
+        .get(handle.into(), Rights::SIGNAL)
+        .ok_or(Error::new(EBADF))?;
+    let interrupt = object
+        .as_any()
+        .downcast_ref::<Interrupt>()
+        .ok_or(Error::new(EINVAL))?;
+
+    interrupt.ack();
+    Ok(0)
+}
@@ -0,0 +1,52 @@
+use core::ops::Range;
+
+use alloc::vec::Vec;
+
+use crate::{
+    EBUSY, EINVAL, ENOSPC, ESRCH, Error, Result,
+    arch::{CurrentDebugArch, debug::DebugArch},
+    drivers::ns16550,
+    gdbstub,
+    object::process::current_process,
+    serial::SERIAL_PORT_COUNT,
+    syscall::process::require_privileged,
+};
+
+fn overlaps(ports: &Range<u16>, base: u16) -> bool {
+    ports.start < base.saturating_add(SERIAL_PORT_COUNT as u16) && base < ports.end
+}
+
+/// 允许当前进程直接访问 `[base, base + count)` 的 I/O 端口，需要特权
+///
+/// GDB 桩开启时调试串口的端口不能交出。范围与串口控制台重叠时，内核不再在串口上输出日志。
+pub fn sys_ioport_request(base: usize, count: usize) -> Result<usize> {
+    require_privileged()?;
+
+    let end = base
+        .checked_add(count)
+        .filter(|&end| count != 0 && end <= u16::MAX as usize)
+        .ok_or(Error::new(EINVAL))?;
+    let ports = base as u16..end as u16;
+
+    if gdbstub::enabled()
+        && CurrentDebugArch::SERIAL_IO_BASE.is_some_and(|debug| overlaps(&ports, debug))
+    {
+        return Err(Error::new(EBUSY));
+    }
+
+    let process = current_process().ok_or(Error::new(ESRCH))?;
+    let io_ports: Vec<_> = {
+        let mut proc = process.write();
+        if !proc.add_io_ports(ports.clone()) {
+            return Err(Error::new(ENOSPC));
+        }
+        proc.io_ports().to_vec()
+    };
+
+    if ns16550::console().is_some_and(|console| overlaps(&ports, console.port)) {
+        ns16550::release();
+    }
+    crate::arch::load_io_ports(&io_ports);
+
+    Ok(0)
+}
@@ -1,7 +1,24 @@
+use alloc::sync::Arc;
+use rmm::{Arch, FrameAllocator, PhysicalAddress};
+
 use crate::{
-    ENOENT, ESRCH, Error, Result,
-    arch::Ptrace,
-    drivers::acpi::RSDP_REQUEST,
+    EINVAL, ENOENT, ENOMEM, ESRCH, Error, Result,
+    arch::{CurrentRmmArch, Ptrace},
+    display::FramebufferInfo,
+    drivers::{acpi::RSDP_REQUEST, fbterm, framebuffer::FRAME_BUFFERS},
+    heap::HEAP_ALLOCATOR,
+    init::memory::{FRAME_ALLOCATOR, PAGE_SIZE},
+    memory::{
+        account::{MemoryKind, global_usage},
+        reclaim::MEMORY_PRESSURE_EVENT,
+        slab::{self, SLAB_CLASS_COUNT, SlabInfo},
+    },
+    object::{
+        KernelObject, Rights,
+        process::current_process,
+        vmo::{CachePolicy, Vmo},
+    },
+    syscall::{process::require_privileged, user},
     task::{TASKS, block_task, unblock_task},
 };
 
@@ -12,6 +29,170 @@ pub fn get_rsdp() -> Result<usize> {
         .map(|rsdp_response| rsdp_response.address())
 }
 
+/// 获取内存压力事件
+pub fn get_memory_pressure_event(handle_out: usize) -> Result<usize> {
+    if handle_out == 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let process = current_process().ok_or(Error::new(EINVAL))?;
+    let mut proc = process.write();
+
+    let event = MEMORY_PRESSURE_EVENT.clone();
+    let handle = proc.handles_mut().insert(
+        event as Arc<dyn KernelObject>,
+        Rights::READ | Rights::WAIT | Rights::DUPLICATE | Rights::TRANSFER,
+    );
+    drop(proc);
+
+    user::write(handle_out, handle.raw())?;
+
+    Ok(0)
+}
+
+/// 内核范围的内存统计（字节）
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KernelMemoryInfo {
+    /// 物理内存总量
+    pub total: usize,
+    /// 空闲物理内存
+    pub free: usize,
+    /// 内核堆当前的大小（不含 slab）
+    pub kernel_heap: usize,
+    /// 内核堆中已使用的部分
+    pub kernel_heap_used: usize,
+    /// slab 分配器占用的页面
+    pub kernel_slab: usize,
+    /// 用户地址空间的页表
+    pub page_tables: usize,
+    /// 线程的内核栈
+    pub kernel_stacks: usize,
+    /// VMO 已提交的页面
+    pub user: usize,
+}
+
+/// 获取内核范围的内存统计
+pub fn get_memory_info(info_out: usize) -> Result<usize> {
+    if info_out == 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let frames = unsafe { FRAME_ALLOCATOR.lock().usage() };
+    let usage = global_usage();
+
+    let info = KernelMemoryInfo {
+        total: frames.total().data() * PAGE_SIZE,
+        free: frames.free().data() * PAGE_SIZE,
+        kernel_heap: HEAP_ALLOCATOR.size(),
+        kernel_heap_used: HEAP_ALLOCATOR.used(),
+        kernel_slab: slab::size(),
+        page_tables: usage.pages(MemoryKind::PageTable) * PAGE_SIZE,
+        kernel_stacks: usage.pages(MemoryKind::KernelStack) * PAGE_SIZE,
+        user: usage.pages(MemoryKind::Vmo) * PAGE_SIZE,
+    };
+
+    user::write(info_out, info)?;
+
+    Ok(0)
+}
+
+/// 获取 slab 分配器各大小类的统计
+///
+/// 最多写入 `count` 项，返回大小类的总数。
+pub fn get_slab_info(info_out: usize, count: usize) -> Result<usize> {
+    if info_out == 0 && count != 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    user::with_slice_mut(
+        info_out,
+        count.min(SLAB_CLASS_COUNT),
+        |out: &mut [SlabInfo]| {
+            for (class, info) in out.iter_mut().enumerate() {
+                *info = slab::info(class);
+            }
+        },
+    )?;
+
+    Ok(SLAB_CLASS_COUNT)
+}
+
+/// 把第 `index` 个帧缓冲交给调用者，需要特权
+///
+/// 帧缓冲以写合并的物理 VMO 返回，几何信息和像素格式写入 `info_out`。
+/// 交出内核终端使用的帧缓冲后，内核不再在上面输出日志，只在 panic 时重新接管。
+pub fn get_framebuffer(index: usize, info_out: usize, handle_out: usize) -> Result<usize> {
+    require_privileged()?;
+
+    if info_out == 0 || handle_out == 0 {
+        return Err(Error::new(EINVAL));
+    }
+    user::check_range(handle_out, size_of::<u32>())?;
+
+    let fb = FRAME_BUFFERS
+        .lock()
+        .get(index)
+        .copied()
+        .ok_or(Error::new(ENOENT))?;
+
+    // Limine 给出的帧缓冲地址在 HHDM 中，并且页对齐
+    let phys = fb.address as usize - CurrentRmmArch::PHYS_OFFSET;
+    if !phys.is_multiple_of(PAGE_SIZE) {
+        return Err(Error::new(EINVAL));
+    }
+    let phys = PhysicalAddress::new(phys);
+    let size = (fb.pitch * fb.height).next_multiple_of(PAGE_SIZE);
+    let vmo = Vmo::create_physical(phys, size).map_err(|_| Error::new(ENOMEM))?;
+    vmo.set_cache_policy(CachePolicy::WriteCombining)
+        .map_err(|_| Error::new(EINVAL))?;
+
+    user::write(
+        info_out,
+        FramebufferInfo {
+            width: fb.width as u32,
+            height: fb.height as u32,
+            pitch: fb.pitch as u32,
+            bpp: fb.bpp as u32,
+            size: size as u64,
+            red_mask_size: fb.red_mask_size as u8,
+            red_mask_shift: fb.red_mask_shift as u8,
+            green_mask_size: fb.green_mask_size as u8,
+            green_mask_shift: fb.green_mask_shift as u8,
+            blue_mask_size: fb.blue_mask_size as u8,
+            blue_mask_shift: fb.blue_mask_shift as u8,
+            reserved: [0; 2],
+        },
+    )?;
+
+    let process = current_process().ok_or(Error::new(EINVAL))?;
+    let handle = process.write().handles_mut().insert(
+        vmo as Arc<dyn KernelObject>,
+        Rights::BASIC | Rights::MAP | Rights::DUPLICATE | Rights::TRANSFER,
+    );
+    user::write(handle_out, handle.raw())?;
+
+    if index == fbterm::FRAMEBUFFER_INDEX {
+        fbterm::release();
+    }
+
+    Ok(0)
+}
+
+/// 写出串口控制台的位置，供用户态的 UART 驱动使用
+#[cfg(target_arch = "x86_64")]
+pub fn get_serial_console(info_out: usize) -> Result<usize> {
+    let console = crate::drivers::ns16550::console().ok_or(Error::new(ENOENT))?;
+    user::write(info_out, console)?;
+    Ok(0)
+}
+
+/// RISC-V 上内核经由 SBI 输出，没有可以交给用户态的串口
+#[cfg(not(target_arch = "x86_64"))]
+pub fn get_serial_console(_info_out: usize) -> Result<usize> {
+    Err(Error::new(ENOENT))
+}
+
 #[cfg(target_arch = "x86_64")]
 pub fn get_fsbase(tid: usize) -> Result<usize> {
     let tasks = TASKS.lock();
@@ -46,10 +227,9 @@ pub fn sys_load_task_registers(tid: usize, reg: *mut Ptrace) -> Result<usize> {
         .ok_or(Error::new(ESRCH))?;
     block_task(task.clone());
     let regs_ptr = task.write().pt_regs();
-    unsafe {
-        reg.write_unaligned(regs_ptr.read_unaligned());
-    }
+    let regs = unsafe { regs_ptr.read_unaligned() };
     unblock_task(task.clone());
+    user::write(reg as usize, regs)?;
     Ok(0)
 }
 
@@ -59,10 +239,11 @@ pub fn sys_store_task_registers(tid: usize, reg: *const Ptrace) -> Result<usize>
         .iter()
         .find(|t| t.read().tid() == tid)
         .ok_or(Error::new(ESRCH))?;
+    let regs = user::read::<Ptrace>(reg as usize)?;
     block_task(task.clone());
     let regs_ptr = task.write().pt_regs();
     unsafe {
-        regs_ptr.write_unaligned(reg.read_unaligned());
+        regs_ptr.write_unaligned(regs);
     }
     unblock_task(task.clone());
     Ok(0)
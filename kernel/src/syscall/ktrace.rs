@@ -0,0 +1,50 @@
+use crate::{
+    EINVAL, ENOMEM, Error, Result,
+    ktrace::{
+        KTRACE_ACTION_READ, KTRACE_ACTION_REWIND, KTRACE_ACTION_START, KTRACE_ACTION_STOP,
+        KTRACE_GROUP_ALL, KtraceRecord,
+    },
+    syscall::{process::require_privileged, user},
+    trace,
+};
+
+/// 控制内核事件跟踪，需要特权
+///
+/// `KTRACE_ACTION_READ` 把记录写入 `[buf, buf + len)`，返回写入的字节数，
+/// 返回 0 表示缓冲区已经取空。
+pub fn sys_ktrace_control(action: usize, arg: usize, buf: usize, len: usize) -> Result<usize> {
+    require_privileged()?;
+
+    match action {
+        KTRACE_ACTION_START => {
+            let groups = arg as u32;
+            if groups == 0 || groups & !KTRACE_GROUP_ALL != 0 {
+                return Err(Error::new(EINVAL));
+            }
+            trace::start(groups).map_err(|_| Error::new(ENOMEM))?;
+            Ok(0)
+        }
+        KTRACE_ACTION_STOP => {
+            trace::stop();
+            Ok(0)
+        }
+        KTRACE_ACTION_REWIND => {
+            trace::rewind();
+            Ok(0)
+        }
+        KTRACE_ACTION_READ => {
+            let max = len / size_of::<KtraceRecord>();
+            if max == 0 {
+                return Err(Error::new(EINVAL));
+            }
+            user::check_range(buf, len)?;
+
+            let records = trace::drain(max).map_err(|_| Error::new(ENOMEM))?;
+            user::with_slice_mut(buf, records.len(), |dst: &mut [KtraceRecord]| {
+                dst.copy_from_slice(&records)
+            })?;
+            Ok(records.len() * size_of::<KtraceRecord>())
+        }
+        _ => Err(Error::new(EINVAL)),
+    }
+}
@@ -1,7 +1,21 @@
-use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
 use spin::Mutex;
 
-use crate::{EINVAL, Error, Result};
+use crate::{
+    EAGAIN, EBADF, EINVAL, EMSGSIZE, ESRCH, Error, Result,
+    debuglog::{
+        DEBUGLOG_FROM_NOW, LOG_LEVEL_INFO, LOG_LEVEL_RAW, LOG_LEVEL_TRACE, LOG_RECORD_MAX,
+        LogRecordHeader,
+    },
+    object::{
+        KernelObject, Rights,
+        debuglog::{self, DebugLogReader},
+        process::current_process,
+    },
+    syscall::{process::require_privileged, user},
+    task::get_current_task,
+};
 
 pub struct UserLogger;
 
@@ -15,9 +29,119 @@ impl UserLogger {
 
 pub static LOCKED_USER_LOGGER: Mutex<UserLogger> = Mutex::new(UserLogger);
 
-pub fn sys_log(buf: usize, len: usize) -> Result<usize> {
-    let buf = unsafe { core::slice::from_raw_parts(buf as *const u8, len) }.to_vec();
-    let string = String::from_utf8(buf).map_err(|_| Error::new(EINVAL))?;
-    LOCKED_USER_LOGGER.lock().log(&string).unwrap();
+fn level_name(level: u32) -> (&'static str, &'static str) {
+    match level {
+        1 => ("ERROR", "31"),
+        2 => ("WARN", "33"),
+        3 => ("INFO", "32"),
+        4 => ("DEBUG", "34"),
+        _ => ("TRACE", "35"),
+    }
+}
+
+/// 输出用户日志并保存到调试日志
+///
+/// `level` 为 `LOG_LEVEL_RAW` 时按原样输出，否则按内核日志的格式加上级别和进程名。
+pub fn sys_log(buf: usize, len: usize, level: usize) -> Result<usize> {
+    let level = level as u32;
+    if level > LOG_LEVEL_TRACE {
+        return Err(Error::new(EINVAL));
+    }
+
+    let task = get_current_task().ok_or(Error::new(ESRCH))?;
+    let tid = task.read().tid();
+    let process = current_process().ok_or(Error::new(ESRCH))?;
+    let (pid, name) = {
+        let proc = process.read();
+        (proc.pid(), proc.name().to_string())
+    };
+
+    // 先复制到内核，之后的格式化、加锁和输出都不在用户访问窗口内进行
+    let buf = user::read_vec::<u8>(buf, len)?;
+    let string = core::str::from_utf8(&buf).map_err(|_| Error::new(EINVAL))?;
+    if level == LOG_LEVEL_RAW {
+        LOCKED_USER_LOGGER.lock().log(string).unwrap();
+        debuglog::write_user(LOG_LEVEL_INFO, pid, tid, &name, string);
+    } else {
+        let (level_str, color) = level_name(level);
+        let content = format!(
+            "[\x1b[{}m{}\x1b[0m] {}[{}]: {}\n",
+            color,
+            level_str,
+            name,
+            pid,
+            string.trim_end_matches('\n')
+        );
+        LOCKED_USER_LOGGER.lock().log(&content).unwrap();
+        debuglog::write_user(level, pid, tid, &name, string);
+    }
     Ok(len)
 }
+
+/// 创建调试日志读取器，需要特权
+///
+/// 只读取级别数值不大于 `max_level` 的记录；`pid` 不为 0 时只读取该进程的记录。
+pub fn sys_debuglog_create(
+    max_level: usize,
+    pid: usize,
+    options: usize,
+    handle_out: usize,
+) -> Result<usize> {
+    require_privileged()?;
+
+    let max_level = max_level as u32;
+    if max_level == 0 || max_level > LOG_LEVEL_TRACE || options & !DEBUGLOG_FROM_NOW != 0 {
+        return Err(Error::new(EINVAL));
+    }
+    user::check_range(handle_out, size_of::<u32>())?;
+
+    let pid = (pid != 0).then_some(pid);
+    let reader = DebugLogReader::new(max_level, pid, options & DEBUGLOG_FROM_NOW != 0);
+
+    let process = current_process().ok_or(Error::new(ESRCH))?;
+    let handle = process.write().handles_mut().insert(
+        reader as Arc<dyn KernelObject>,
+        Rights::READ | Rights::WAIT | Rights::DUPLICATE | Rights::TRANSFER,
+    );
+
+    user::write(handle_out, handle.raw())?;
+    Ok(0)
+}
+
+/// 读取一条记录，返回写入的字节数，没有记录时返回 EAGAIN
+///
+/// 缓冲区不能小于 `LOG_RECORD_MAX`。
+pub fn sys_debuglog_read(handle: usize, buf: usize, len: usize) -> Result<usize> {
+    if len < LOG_RECORD_MAX {
+        return Err(Error::new(EMSGSIZE));
+    }
+    user::check_range(buf, len)?;
+
+    let process = current_process().ok_or(Error::new(ESRCH))?;
+    let object = process
+        .read()
+        .handles()
+        .get(handle.into(), Rights::READ)
+        .ok_or(Error::new(EBADF))?;
+    let reader = object
+        .as_any()
+        .downcast_ref::<DebugLogReader>()
+        .ok_or(Error::new(EINVAL))?;
+
+    let record = reader.read().ok_or(Error::new(EAGAIN))?;
+    let header_size = size_of::<LogRecordHeader>();
+    let name = record.name();
+    let message = record.message();
+    let total = header_size + name.len() + message.len();
+
+    user::with_slice_mut(buf, total, |dst: &mut [u8]| {
+        let header = unsafe {
+            core::slice::from_raw_parts(&record.header as *const _ as *const u8, header_size)
+        };
+        dst[..header_size].copy_from_slice(header);
+        dst[header_size..header_size + name.len()].copy_from_slice(name);
+        dst[header_size + name.len()..].copy_from_slice(message);
+    })?;
+
+    Ok(total)
+}
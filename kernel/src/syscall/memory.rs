@@ -5,19 +5,23 @@ use rmm::{PhysicalAddress, VirtualAddress};
 
 use crate::{
     EPERM,
+    init::memory::PAGE_SIZE,
     object::{
         Handle, KernelObject, Rights,
         process::current_process,
         vmar::{MappingFlags, Vmar, VmarError},
-        vmo::{Vmo, VmoError, VmoOptions},
+        vmo::{CachePolicy, Vmo, VmoError, VmoOp, VmoOptions},
     },
+    syscall::user,
 };
 
-use super::error::{EACCES, EBADF, EEXIST, EINVAL, ENOENT, ENOMEM, Error, Result};
+use super::error::{
+    EACCES, EBADF, EBUSY, EEXIST, EINVAL, ENOENT, ENOMEM, EOPNOTSUPP, ERANGE, Error, Result,
+};
 
 /// VMO 创建参数
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct VmoCreateArgs {
     /// 大小
     pub size: usize,
@@ -25,32 +29,35 @@ pub struct VmoCreateArgs {
     pub options: u32,
 }
 
+unsafe impl user::Pod for VmoCreateArgs {}
+
 /// 创建 VMO
 pub fn sys_vmo_create(args_ptr: usize, handle_out: usize) -> Result<usize> {
     if args_ptr == 0 || handle_out == 0 {
         return Err(Error::new(EINVAL));
     }
 
-    let args = unsafe { &*(args_ptr as *const VmoCreateArgs) };
+    let args = user::read::<VmoCreateArgs>(args_ptr)?;
     let options = VmoOptions::from_bits_truncate(args.options);
 
-    let vmo = Vmo::create(args.size, options).map_err(|e| match e {
+    let process = current_process().ok_or(Error::new(EINVAL))?;
+    let account = process.read().account();
+
+    let vmo = Vmo::create(args.size, options, account).map_err(|e| match e {
         VmoError::InvalidSize => Error::new(EINVAL),
         VmoError::NoMemory => Error::new(ENOMEM),
         _ => Error::new(EINVAL),
     })?;
 
-    let process = current_process().ok_or(Error::new(EINVAL))?;
     let mut proc = process.write();
 
     let handle = proc.handles_mut().insert(
         vmo as Arc<dyn KernelObject>,
-        Rights::BASIC | Rights::MAP | Rights::DUPLICATE | Rights::TRANSFER,
+        Rights::BASIC | Rights::EXECUTE | Rights::MAP | Rights::DUPLICATE | Rights::TRANSFER,
     );
+    drop(proc);
 
-    unsafe {
-        *(handle_out as *mut u32) = handle.raw();
-    }
+    user::write(handle_out, handle.raw())?;
 
     Ok(0)
 }
@@ -74,10 +81,9 @@ pub fn sys_vmo_create_physical(phys_addr: usize, size: usize, handle_out: usize)
         vmo as Arc<dyn KernelObject>,
         Rights::BASIC | Rights::MAP | Rights::DUPLICATE | Rights::TRANSFER,
     );
+    drop(proc);
 
-    unsafe {
-        *(handle_out as *mut u32) = handle.raw();
-    }
+    user::write(handle_out, handle.raw())?;
 
     Ok(0)
 }
@@ -93,13 +99,18 @@ pub fn sys_vmo_create_child(
         return Err(Error::new(EINVAL));
     }
 
-    let vmo_arc = {
+    // 子对象只有在父句柄可执行时才可执行
+    let (vmo_arc, execute) = {
         let process = current_process().ok_or(Error::new(EINVAL))?;
         let proc = process.read();
+        let handle = Handle::from(vmo_handle);
 
-        proc.handles()
-            .get(Handle::from(vmo_handle), Rights::DUPLICATE)
-            .ok_or(Error::new(EBADF))?
+        let obj = proc
+            .handles()
+            .get(handle, Rights::DUPLICATE)
+            .ok_or(Error::new(EBADF))?;
+        let rights = proc.handles().get_rights(handle).ok_or(Error::new(EBADF))?;
+        (obj, rights & Rights::EXECUTE)
     };
 
     let _vmo = vmo_arc
@@ -114,25 +125,26 @@ pub fn sys_vmo_create_child(
         Arc::from_raw(ptr)
     };
 
+    let process = current_process().ok_or(Error::new(EINVAL))?;
+    let account = process.read().account();
+
     let child = vmo_arc_typed
-        .create_cow_clone(offset, size)
+        .create_cow_clone(offset, size, account)
         .map_err(|e| match e {
             VmoError::OutOfRange => Error::new(EINVAL),
             VmoError::NoMemory => Error::new(ENOMEM),
             _ => Error::new(EINVAL),
         })?;
 
-    let process = current_process().ok_or(Error::new(EINVAL))?;
     let mut proc = process.write();
 
     let handle = proc.handles_mut().insert(
         child as Arc<dyn KernelObject>,
-        Rights::BASIC | Rights::MAP | Rights::DUPLICATE | Rights::TRANSFER,
+        Rights::BASIC | execute | Rights::MAP | Rights::DUPLICATE | Rights::TRANSFER,
     );
+    drop(proc);
 
-    unsafe {
-        *(handle_out as *mut u32) = handle.raw();
-    }
+    user::write(handle_out, handle.raw())?;
 
     Ok(0)
 }
@@ -162,9 +174,8 @@ pub fn sys_vmo_read(
         .downcast_ref::<Vmo>()
         .ok_or(Error::new(EINVAL))?;
 
-    let buf = unsafe { core::slice::from_raw_parts_mut(buf_ptr as *mut u8, buf_len) };
-
-    let bytes_read = vmo.read(offset, buf).map_err(|_| Error::new(EINVAL))?;
+    let bytes_read = user::with_slice_mut(buf_ptr, buf_len, |buf| vmo.read(offset, buf))?
+        .map_err(|_| Error::new(EINVAL))?;
 
     Ok(bytes_read)
 }
@@ -194,9 +205,8 @@ pub fn sys_vmo_write(
         .downcast_ref::<Vmo>()
         .ok_or(Error::new(EINVAL))?;
 
-    let buf = unsafe { core::slice::from_raw_parts(buf_ptr as *const u8, buf_len) };
-
-    let bytes_written = vmo.write(offset, buf).map_err(|_| Error::new(EINVAL))?;
+    let bytes_written = user::with_slice(buf_ptr, buf_len, |buf| vmo.write(offset, buf))?
+        .map_err(|_| Error::new(EINVAL))?;
 
     Ok(bytes_written)
 }
@@ -220,6 +230,25 @@ pub fn sys_vmo_get_size(vmo_handle: usize) -> Result<usize> {
     Ok(vmo.size())
 }
 
+/// 获取 VMO 已提交且归其所有的字节数
+pub fn sys_vmo_get_committed(vmo_handle: usize) -> Result<usize> {
+    let vmo_obj = {
+        let process = current_process().ok_or(Error::new(EINVAL))?;
+        let proc = process.read();
+
+        proc.handles()
+            .get(Handle::from(vmo_handle), Rights::READ)
+            .ok_or(Error::new(EBADF))?
+    };
+
+    let vmo = vmo_obj
+        .as_any()
+        .downcast_ref::<Vmo>()
+        .ok_or(Error::new(EINVAL))?;
+
+    Ok(vmo.committed_pages() * PAGE_SIZE)
+}
+
 /// 设置 VMO 大小
 pub fn sys_vmo_set_size(vmo_handle: usize, size: usize) -> Result<usize> {
     let vmo_obj = {
@@ -236,7 +265,10 @@ pub fn sys_vmo_set_size(vmo_handle: usize, size: usize) -> Result<usize> {
         .downcast_ref::<Vmo>()
         .ok_or(Error::new(EINVAL))?;
 
-    vmo.resize(size).map_err(|_| Error::new(EPERM))?;
+    vmo.resize(size).map_err(|e| match e {
+        VmoError::Pinned => Error::new(EBUSY),
+        _ => Error::new(EPERM),
+    })?;
 
     Ok(0)
 }
@@ -260,9 +292,189 @@ pub fn sys_vmo_get_phys(vmo_handle: usize) -> Result<usize> {
     vmo.get_physical()
 }
 
+/// 物理页面段
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PhysRun {
+    /// 物理地址
+    pub phys: usize,
+    /// 长度（字节）
+    pub len: usize,
+}
+
+unsafe impl user::Pod for PhysRun {}
+
+/// VMO pin 参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VmoPinArgs {
+    /// VMO 句柄
+    pub vmo_handle: u32,
+    /// VMO 内偏移
+    pub offset: usize,
+    /// 大小
+    pub size: usize,
+    /// 输出的物理页面段数组
+    pub runs: usize,
+    /// 数组容量
+    pub runs_cap: usize,
+    /// 输出的固定内存令牌句柄
+    pub pmt_out: usize,
+}
+
+unsafe impl user::Pod for VmoPinArgs {}
+
+/// Pin 住 VMO 的一段范围，返回写入的物理页面段数量
+///
+/// pin 由写入 `pmt_out` 的固定内存令牌持有，关闭令牌句柄（或进程退出）时解除。
+pub fn sys_vmo_pin(args_ptr: usize) -> Result<usize> {
+    if args_ptr == 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let args = user::read::<VmoPinArgs>(args_ptr)?;
+    if args.runs == 0 || args.runs_cap == 0 || args.pmt_out == 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let process = current_process().ok_or(Error::new(EINVAL))?;
+    let vmo_obj = process
+        .read()
+        .handles()
+        .get(Handle::from(args.vmo_handle as usize), Rights::WRITE)
+        .ok_or(Error::new(EBADF))?;
+
+    vmo_obj
+        .as_any()
+        .downcast_ref::<Vmo>()
+        .ok_or(Error::new(EINVAL))?;
+
+    let vmo = unsafe {
+        let ptr = Arc::as_ptr(&vmo_obj) as *const Vmo;
+        Arc::increment_strong_count(ptr);
+        Arc::from_raw(ptr)
+    };
+
+    // 出错返回时令牌随之销毁，pin 自动解除
+    let pmt = vmo.pin(args.offset, args.size).map_err(|e| match e {
+        VmoError::NoMemory => Error::new(ENOMEM),
+        VmoError::TooManyPins => Error::new(EBUSY),
+        _ => Error::new(EINVAL),
+    })?;
+
+    let runs = pmt.runs();
+    if runs.len() > args.runs_cap {
+        return Err(Error::new(ERANGE));
+    }
+
+    user::with_slice_mut(args.runs, runs.len(), |out: &mut [PhysRun]| {
+        for (slot, (phys, len)) in out.iter_mut().zip(runs.iter()) {
+            *slot = PhysRun {
+                phys: phys.data(),
+                len: *len,
+            };
+        }
+    })?;
+    let count = runs.len();
+
+    let handle = process.write().handles_mut().insert(
+        pmt as Arc<dyn KernelObject>,
+        Rights::DUPLICATE | Rights::TRANSFER,
+    );
+    if let Err(e) = user::write(args.pmt_out, handle.raw()) {
+        process.write().handles_mut().remove(handle);
+        return Err(e);
+    }
+
+    Ok(count)
+}
+
+/// 对 VMO 的一段范围执行操作
+///
+/// `Query` 将每页状态写入 `buf`（每页一个字节）并返回页数；
+/// `Lock` 返回 1 表示内容自上次解锁后已被回收。
+pub fn sys_vmo_op_range(
+    vmo_handle: usize,
+    op: usize,
+    offset: usize,
+    size: usize,
+    buf_ptr: usize,
+    buf_len: usize,
+) -> Result<usize> {
+    let op = VmoOp::from_raw(op as u32).ok_or(Error::new(EINVAL))?;
+
+    let rights = match op {
+        VmoOp::Query => Rights::READ,
+        _ => Rights::WRITE,
+    };
+
+    let vmo_obj = {
+        let process = current_process().ok_or(Error::new(EINVAL))?;
+        let proc = process.read();
+
+        proc.handles()
+            .get(Handle::from(vmo_handle), rights)
+            .ok_or(Error::new(EBADF))?
+    };
+
+    let vmo = vmo_obj
+        .as_any()
+        .downcast_ref::<Vmo>()
+        .ok_or(Error::new(EINVAL))?;
+
+    let result = match op {
+        VmoOp::Commit => vmo.commit(offset, size).map(|_| 0),
+        VmoOp::Decommit => vmo.decommit(offset, size).map(|_| 0),
+        VmoOp::Zero => vmo.zero(offset, size).map(|_| 0),
+        VmoOp::Query => {
+            if buf_ptr == 0 {
+                return Err(Error::new(EINVAL));
+            }
+            user::with_slice_mut(buf_ptr, buf_len, |buf| vmo.query(offset, size, buf))?
+        }
+        VmoOp::Lock => vmo.lock().map(|discarded| discarded as usize),
+        VmoOp::Unlock => vmo.unlock().map(|_| 0),
+    };
+
+    result.map_err(|e| match e {
+        VmoError::NoMemory => Error::new(ENOMEM),
+        VmoError::Pinned => Error::new(EBUSY),
+        VmoError::NotSupported => Error::new(EOPNOTSUPP),
+        _ => Error::new(EINVAL),
+    })
+}
+
+/// 设置 VMO 的缓存策略
+///
+/// VMO 已被映射时返回 `EBUSY`。
+pub fn sys_vmo_set_cache_policy(vmo_handle: usize, policy: usize) -> Result<usize> {
+    let policy = CachePolicy::from_raw(policy as u32).ok_or(Error::new(EINVAL))?;
+
+    let vmo_obj = {
+        let process = current_process().ok_or(Error::new(EINVAL))?;
+        let proc = process.read();
+
+        proc.handles()
+            .get(Handle::from(vmo_handle), Rights::MAP)
+            .ok_or(Error::new(EBADF))?
+    };
+
+    let vmo = vmo_obj
+        .as_any()
+        .downcast_ref::<Vmo>()
+        .ok_or(Error::new(EINVAL))?;
+
+    vmo.set_cache_policy(policy).map_err(|e| match e {
+        VmoError::InvalidState => Error::new(EBUSY),
+        _ => Error::new(EINVAL),
+    })?;
+
+    Ok(0)
+}
+
 /// 映射参数
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct VmarMapArgs {
     /// VMAR 句柄（0 表示进程根 VMAR）
     pub vmar_handle: u32,
@@ -278,14 +490,32 @@ pub struct VmarMapArgs {
     pub vaddr: usize,
 }
 
+unsafe impl user::Pod for VmarMapArgs {}
+
+/// 句柄权限允许的映射权限
+fn mapping_rights(rights: Rights) -> MappingFlags {
+    let mut flags = MappingFlags::empty();
+    if rights.contains(Rights::READ) {
+        flags |= MappingFlags::READ;
+    }
+    if rights.contains(Rights::WRITE) {
+        flags |= MappingFlags::WRITE;
+    }
+    if rights.contains(Rights::EXECUTE) {
+        flags |= MappingFlags::EXECUTE;
+    }
+    flags
+}
+
 /// 映射 VMO 到地址空间
 pub fn sys_vmar_map(args_ptr: usize, addr_out: usize) -> Result<usize> {
     if args_ptr == 0 || addr_out == 0 {
         return Err(Error::new(EINVAL));
     }
 
-    let args = unsafe { &*(args_ptr as *const VmarMapArgs) };
-    let flags = MappingFlags::from_bits_truncate(args.flags);
+    let args = user::read::<VmarMapArgs>(args_ptr)?;
+    // PERMANENT 只给内核安装的映射使用
+    let flags = MappingFlags::from_bits_truncate(args.flags) - MappingFlags::PERMANENT;
 
     let process = current_process().ok_or(Error::new(EINVAL))?;
 
@@ -312,23 +542,26 @@ pub fn sys_vmar_map(args_ptr: usize, addr_out: usize) -> Result<usize> {
         }
     };
 
-    // 获取 VMO
-    let vmo = {
+    // 获取 VMO，映射的最高权限不超过 VMO 句柄的权限
+    let (vmo, max_flags) = {
         let proc = process.read();
+        let handle = Handle::from(args.vmo_handle as usize);
         let obj = proc
             .handles()
-            .get(Handle::from(args.vmo_handle as usize), Rights::MAP)
+            .get(handle, Rights::MAP)
             .ok_or(Error::new(EBADF))?;
+        let rights = proc.handles().get_rights(handle).ok_or(Error::new(EBADF))?;
 
         obj.as_any()
             .downcast_ref::<Vmo>()
             .ok_or(Error::new(EINVAL))?;
 
-        unsafe {
+        let vmo = unsafe {
             let ptr = Arc::as_ptr(&obj) as *const Vmo;
             Arc::increment_strong_count(ptr);
             Arc::from_raw(ptr)
-        }
+        };
+        (vmo, mapping_rights(rights))
     };
 
     // 执行映射
@@ -339,21 +572,116 @@ pub fn sys_vmar_map(args_ptr: usize, addr_out: usize) -> Result<usize> {
     };
 
     let mapped_addr = vmar
-        .map(vmo, args.vmo_offset, args.size, flags, vaddr)
+        .map(vmo, args.vmo_offset, args.size, flags, max_flags, vaddr)
         .map_err(|e| match e {
-            VmarError::NoSpace => Error::new(ENOMEM),
+            VmarError::NoSpace | VmarError::NoMemory => Error::new(ENOMEM),
             VmarError::Overlap => Error::new(EEXIST),
             VmarError::OutOfRange => Error::new(EINVAL),
+            VmarError::AccessDenied => Error::new(EACCES),
+            _ => Error::new(EINVAL),
+        })?;
+
+    user::write(addr_out, mapped_addr.data())?;
+
+    Ok(0)
+}
+
+/// 子 VMAR 分配参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VmarAllocateArgs {
+    /// 父 VMAR 句柄（0 表示进程根 VMAR）
+    pub vmar_handle: u32,
+    /// 标志（SPECIFIC 表示使用 offset 指定的位置）
+    pub flags: u32,
+    /// 相对父 VMAR 基地址的偏移
+    pub offset: usize,
+    /// 大小
+    pub size: usize,
+}
+
+unsafe impl user::Pod for VmarAllocateArgs {}
+
+/// 在 VMAR 中分配子 VMAR
+pub fn sys_vmar_allocate(args_ptr: usize, handle_out: usize, addr_out: usize) -> Result<usize> {
+    if args_ptr == 0 || handle_out == 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let args = user::read::<VmarAllocateArgs>(args_ptr)?;
+    let flags = MappingFlags::from_bits_truncate(args.flags);
+
+    let process = current_process().ok_or(Error::new(EINVAL))?;
+
+    let parent = if args.vmar_handle == 0 {
+        process.read().root_vmar().ok_or(Error::new(EINVAL))?
+    } else {
+        let proc = process.read();
+        let obj = proc
+            .handles()
+            .get(Handle::from(args.vmar_handle as usize), Rights::MANAGE)
+            .ok_or(Error::new(EBADF))?;
+
+        obj.as_any()
+            .downcast_ref::<Vmar>()
+            .ok_or(Error::new(EINVAL))?;
+
+        unsafe {
+            let ptr = Arc::as_ptr(&obj) as *const Vmar;
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        }
+    };
+
+    let offset = flags
+        .contains(MappingFlags::SPECIFIC)
+        .then_some(args.offset);
+
+    let child = parent
+        .create_child(offset, args.size)
+        .map_err(|e| match e {
+            VmarError::NoSpace => Error::new(ENOMEM),
+            VmarError::Overlap => Error::new(EEXIST),
+            VmarError::Destroyed => Error::new(EBADF),
             _ => Error::new(EINVAL),
         })?;
 
-    unsafe {
-        *(addr_out as *mut usize) = mapped_addr.data();
+    let child_base = child.base().data();
+
+    let handle = process.write().handles_mut().insert(
+        child as Arc<dyn KernelObject>,
+        Rights::BASIC | Rights::MANAGE | Rights::MAP | Rights::DUPLICATE | Rights::TRANSFER,
+    );
+
+    user::write(handle_out, handle.raw())?;
+    if addr_out != 0 {
+        user::write(addr_out, child_base)?;
     }
 
     Ok(0)
 }
 
+/// 销毁 VMAR 及其中的所有映射
+pub fn sys_vmar_destroy(vmar_handle: usize) -> Result<usize> {
+    let vmar_obj = {
+        let process = current_process().ok_or(Error::new(EINVAL))?;
+        let proc = process.read();
+
+        proc.handles()
+            .get(Handle::from(vmar_handle), Rights::MANAGE)
+            .ok_or(Error::new(EBADF))?
+    };
+
+    let vmar = vmar_obj
+        .as_any()
+        .downcast_ref::<Vmar>()
+        .ok_or(Error::new(EINVAL))?;
+
+    vmar.destroy().map_err(|_| Error::new(EINVAL))?;
+
+    Ok(0)
+}
+
 /// 解除映射
 pub fn sys_vmar_unmap(vmar_handle: usize, addr: usize, size: usize) -> Result<usize> {
     let process = current_process().ok_or(Error::new(EINVAL))?;
@@ -411,7 +739,7 @@ pub fn sys_vmar_protect(
     vmar.protect(
         VirtualAddress::new(addr),
         size,
-        MappingFlags::from_bits_truncate(flags as u32),
+        MappingFlags::from_bits_truncate(flags as u32) - MappingFlags::PERMANENT,
     )
     .map_err(|e| match e {
         VmarError::NotMapped => Error::new(ENOENT),
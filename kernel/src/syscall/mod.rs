@@ -1,20 +1,29 @@
 use crate::{
     ESRCH,
     arch::{Ptrace, irq::IrqRegsArch},
+    ktrace::{KTRACE_EVENT_SYSCALL_ENTER, KTRACE_EVENT_SYSCALL_EXIT, KTRACE_GROUP_SYSCALL},
     object::process::current_process,
     syscall::error::{ENOSYS, Error},
     task::get_current_task,
+    trace,
 };
 
 pub mod clock;
 pub mod error;
 pub mod futex;
+pub mod interrupt;
+#[cfg(target_arch = "x86_64")]
+pub mod ioport;
 pub mod kernel;
+pub mod ktrace;
 pub mod log;
 pub mod memory;
 pub mod nr;
 pub mod object;
 pub mod process;
+pub mod profile;
+pub mod random;
+pub mod user;
 
 use nr::*;
 
@@ -43,8 +52,13 @@ pub extern "C" fn syscall_handler(regs: *mut Ptrace) {
     //     arg6
     // );
 
+    let trace_start = trace::enabled(KTRACE_GROUP_SYSCALL).then(|| {
+        trace::record(KTRACE_EVENT_SYSCALL_ENTER, [idx as u64, 0, 0]);
+        trace::timestamp()
+    });
+
     let ret = match idx {
-        SYS_LOG => log::sys_log(arg1, arg2),
+        SYS_LOG => log::sys_log(arg1, arg2, arg3),
 
         SYS_HANDLE_CLOSE => object::sys_handle_close(arg1),
         SYS_HANDLE_DUPLICATE => object::sys_handle_duplicate(arg1, arg2),
@@ -62,6 +76,8 @@ pub extern "C" fn syscall_handler(regs: *mut Ptrace) {
 
         SYS_CLOCK_GET => clock::sys_clock_get(),
         SYS_NANOSLEEP => clock::sys_nanosleep(arg1),
+        SYS_CLOCK_READ => clock::sys_clock_read(arg1),
+        SYS_CLOCK_SET_UTC_OFFSET => clock::sys_clock_set_utc_offset(arg1),
 
         SYS_PROCESS_CREATE => process::sys_process_create(arg1, arg2),
         SYS_PROCESS_START => process::sys_process_start(arg1),
@@ -70,6 +86,8 @@ pub extern "C" fn syscall_handler(regs: *mut Ptrace) {
         SYS_PROCESS_GET_INIT_HANDLE => process::sys_process_get_init_handle(arg1),
         SYS_PROCESS_WAIT => process::sys_process_wait(arg1, arg2, arg3),
         SYS_PROCESS_GET_VMAR_HANDLE => process::sys_process_get_vmar_handle(arg1),
+        SYS_PROCESS_GET_MEMORY_INFO => process::sys_process_get_memory_info(arg1, arg2),
+        SYS_PROCESS_SET_MEMORY_LIMIT => process::sys_process_set_memory_limit(arg1, arg2),
 
         SYS_FUTEX_WAIT => futex::sys_futex_wait(arg1, arg2, arg3),
         SYS_FUTEX_WAKE => futex::sys_futex_wake(arg1, arg2),
@@ -82,10 +100,27 @@ pub extern "C" fn syscall_handler(regs: *mut Ptrace) {
         SYS_VMO_GET_SIZE => memory::sys_vmo_get_size(arg1),
         SYS_VMO_SET_SIZE => memory::sys_vmo_set_size(arg1, arg2),
         SYS_VMO_GET_PHYS => memory::sys_vmo_get_phys(arg1),
+        SYS_VMO_PIN => memory::sys_vmo_pin(arg1),
+        SYS_VMO_OP_RANGE => memory::sys_vmo_op_range(arg1, arg2, arg3, arg4, arg5, arg6),
+        SYS_VMO_SET_CACHE_POLICY => memory::sys_vmo_set_cache_policy(arg1, arg2),
+        SYS_VMO_GET_COMMITTED => memory::sys_vmo_get_committed(arg1),
 
         SYS_VMAR_MAP => memory::sys_vmar_map(arg1, arg2),
         SYS_VMAR_UNMAP => memory::sys_vmar_unmap(arg1, arg2, arg3),
         SYS_VMAR_PROTECT => memory::sys_vmar_protect(arg1, arg2, arg3, arg4),
+        SYS_VMAR_ALLOCATE => memory::sys_vmar_allocate(arg1, arg2, arg3),
+        SYS_VMAR_DESTROY => memory::sys_vmar_destroy(arg1),
+
+        SYS_CPRNG_DRAW => random::sys_cprng_draw(arg1, arg2),
+
+        SYS_DEBUGLOG_CREATE => log::sys_debuglog_create(arg1, arg2, arg3, arg4),
+        SYS_DEBUGLOG_READ => log::sys_debuglog_read(arg1, arg2, arg3),
+
+        SYS_KTRACE_CONTROL => ktrace::sys_ktrace_control(arg1, arg2, arg3, arg4),
+        SYS_PROFILE_CONTROL => profile::sys_profile_control(arg1, arg2, arg3, arg4),
+
+        SYS_INTERRUPT_CREATE => interrupt::sys_interrupt_create(arg1, arg2),
+        SYS_INTERRUPT_ACK => interrupt::sys_interrupt_ack(arg1),
 
         SYS_YIELD => {
             crate::task::schedule();
@@ -103,16 +138,35 @@ pub extern "C" fn syscall_handler(regs: *mut Ptrace) {
         SYS_STORE_TASK_REGISTERS => kernel::sys_store_task_registers(arg1, arg2 as *const Ptrace),
 
         SYS_KRES_GET_RSDP => kernel::get_rsdp(),
+        SYS_KRES_GET_MEMORY_PRESSURE_EVENT => kernel::get_memory_pressure_event(arg1),
+        SYS_KRES_GET_MEMORY_INFO => kernel::get_memory_info(arg1),
+        SYS_KRES_GET_SLAB_INFO => kernel::get_slab_info(arg1, arg2),
+        SYS_KRES_GET_FRAMEBUFFER => kernel::get_framebuffer(arg1, arg2, arg3),
+        SYS_KRES_GET_SERIAL_CONSOLE => kernel::get_serial_console(arg1),
 
         #[cfg(target_arch = "x86_64")]
         SYS_KRES_GET_FSBASE => kernel::get_fsbase(arg1),
         #[cfg(target_arch = "x86_64")]
         SYS_KRES_SET_FSBASE => kernel::set_fsbase(arg1, arg2),
+        #[cfg(target_arch = "x86_64")]
+        SYS_IOPORT_REQUEST => ioport::sys_ioport_request(arg1, arg2),
         _ => {
             warn!("Syscall {} not implemented", idx);
             Err(Error::new(ENOSYS))
         }
     };
 
-    regs.set_ret_value(Error::mux(ret) as u64);
+    let ret = Error::mux(ret);
+    if let Some(start) = trace_start
+        && trace::enabled(KTRACE_GROUP_SYSCALL)
+    {
+        trace::record(
+            KTRACE_EVENT_SYSCALL_EXIT,
+            [idx as u64, trace::timestamp() - start, ret as u64],
+        );
+    }
+
+    regs.set_ret_value(ret as u64);
+
+    crate::object::debuglog::notify_readers();
 }
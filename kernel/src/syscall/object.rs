@@ -7,9 +7,10 @@ use crate::{
         BindOptions, Channel, Handle, KernelObject, Message, Port, PortPacket, Rights, Signals,
         channel::ChannelError, port::PortError, process::current_process,
     },
+    syscall::user,
 };
 
-use super::error::{EAGAIN, EBADF, EINVAL, EPERM, EPIPE, Error, Result};
+use super::error::{EAGAIN, EBADF, EINVAL, ENOMEM, EPERM, EPIPE, Error, Result};
 
 /// 关闭句柄
 pub fn sys_handle_close(handle: usize) -> Result<usize> {
@@ -86,9 +87,16 @@ pub fn sys_port_wait(
     // 获取 Port 引用进行操作
     let port = port_arc.as_any().downcast_ref::<Port>().unwrap();
 
-    // 准备缓冲区
-    let packets_slice =
-        unsafe { core::slice::from_raw_parts_mut(packets_ptr as *mut PortPacket, max_count) };
+    // 等待可能阻塞，先收进内核缓冲区，再复制到用户空间
+    let packets_size = max_count
+        .checked_mul(size_of::<PortPacket>())
+        .ok_or(Error::new(EINVAL))?;
+    user::check_range(packets_ptr, packets_size)?;
+    let mut packets = Vec::new();
+    packets
+        .try_reserve_exact(max_count)
+        .map_err(|_| Error::new(ENOMEM))?;
+    packets.resize(max_count, PortPacket::user(0, [0; 4]));
 
     let timeout = if timeout_ns == usize::MAX {
         None
@@ -96,8 +104,12 @@ pub fn sys_port_wait(
         Some(timeout_ns as u64)
     };
 
-    match port.wait(packets_slice, timeout) {
-        Ok(count) => Ok(count),
+    match port.wait(&mut packets, timeout) {
+        Ok(count) => {
+            // PortPacket 含有枚举，不能在用户内存上构造引用
+            user::write_slice(packets_ptr, &packets[..count])?;
+            Ok(count)
+        }
         Err(PortError::WouldBlock) => Err(Error::new(EWOULDBLOCK)),
         Err(PortError::Timeout) => Err(Error::new(EAGAIN)),
         Err(_) => Err(Error::new(EINVAL)),
@@ -197,7 +209,7 @@ pub fn sys_port_queue(port_handle: usize, key: usize, data_ptr: usize) -> Result
         .ok_or(Error::new(EINVAL))?;
 
     let user_data = if data_ptr != 0 {
-        unsafe { *(data_ptr as *const [u64; 4]) }
+        user::read::<[u64; 4]>(data_ptr)?
     } else {
         [0u64; 4]
     };
@@ -227,13 +239,10 @@ pub fn sys_channel_create(handles_out: usize) -> Result<usize> {
         ch1 as Arc<dyn KernelObject>,
         Rights::BASIC | Rights::DUPLICATE | Rights::TRANSFER,
     );
+    drop(proc);
 
     // 写回句柄
-    unsafe {
-        let out = &mut *(handles_out as *mut [u32; 2]);
-        out[0] = h0.raw();
-        out[1] = h1.raw();
-    }
+    user::write(handles_out, [h0.raw(), h1.raw()])?;
 
     Ok(0)
 }
@@ -247,13 +256,13 @@ pub fn sys_channel_send(
     handles_count: usize,
 ) -> Result<usize> {
     // 准备要转移的句柄
-    let handles_to_transfer: Vec<Handle> = if handles_ptr != 0 && handles_count > 0 {
-        let raw_handles =
-            unsafe { core::slice::from_raw_parts(handles_ptr as *const u32, handles_count) };
-        raw_handles.iter().map(|&h| Handle(h)).collect()
-    } else {
-        Vec::new()
-    };
+    let handles_to_transfer: Vec<Handle> = user::read_vec::<u32>(handles_ptr, handles_count)?
+        .into_iter()
+        .map(Handle)
+        .collect();
+
+    // 先复制数据，失败时句柄还未被转移
+    let data = user::read_vec::<u8>(data_ptr, data_len)?;
 
     // 获取 Channel 并转移句柄
     let (channel_obj, transferred) = {
@@ -283,13 +292,6 @@ pub fn sys_channel_send(
         (channel_obj, transferred)
     };
 
-    // 构造消息
-    let data = if data_ptr != 0 && data_len > 0 {
-        unsafe { core::slice::from_raw_parts(data_ptr as *const u8, data_len) }.to_vec()
-    } else {
-        Vec::new()
-    };
-
     // 消息中包含对象和权限（不是句柄值）
     let msg = Message::with_objects(data, transferred);
 
@@ -338,27 +340,25 @@ pub fn sys_channel_recv(
 
     // 复制数据
     let actual_data_len = core::cmp::min(data_len, msg.data.len());
-    if data_ptr != 0 && actual_data_len > 0 {
-        unsafe {
-            core::ptr::copy_nonoverlapping(msg.data.as_ptr(), data_ptr as *mut u8, actual_data_len);
-        }
+    if data_ptr != 0 {
+        user::with_slice_mut(data_ptr, actual_data_len, |out: &mut [u8]| {
+            out.copy_from_slice(&msg.data[..actual_data_len])
+        })?;
     }
 
     // 将接收到的对象转换为当前进程的句柄
     let received_handles = if !msg.objects.is_empty() && handles_ptr != 0 && handles_count > 0 {
         let process = current_process().ok_or(Error::new(EINVAL))?;
-        let mut proc = process.write();
-
-        let handles: Vec<Handle> = proc.handles_mut().receive_many(msg.objects);
+        // 复制到用户空间时可能缺页，不能持有进程锁
+        let handles: Vec<Handle> = process.write().handles_mut().receive_many(msg.objects);
 
         // 复制句柄到用户空间
         let copy_count = core::cmp::min(handles_count, handles.len());
-        unsafe {
-            let out = core::slice::from_raw_parts_mut(handles_ptr as *mut u32, copy_count);
+        user::with_slice_mut(handles_ptr, copy_count, |out: &mut [u32]| {
             for (i, h) in handles.iter().take(copy_count).enumerate() {
                 out[i] = h.raw();
             }
-        }
+        })?;
 
         handles.len()
     } else {
@@ -367,11 +367,7 @@ pub fn sys_channel_recv(
 
     // 写回实际长度
     if actual_out != 0 {
-        unsafe {
-            let out = &mut *(actual_out as *mut [usize; 2]);
-            out[0] = msg.data.len();
-            out[1] = received_handles;
-        }
+        user::write(actual_out, [msg.data.len(), received_handles])?;
     }
 
     Ok(0)
@@ -410,26 +406,24 @@ pub fn sys_channel_try_recv(
 
     // 复制数据
     let actual_data_len = core::cmp::min(data_len, msg.data.len());
-    if data_ptr != 0 && actual_data_len > 0 {
-        unsafe {
-            core::ptr::copy_nonoverlapping(msg.data.as_ptr(), data_ptr as *mut u8, actual_data_len);
-        }
+    if data_ptr != 0 {
+        user::with_slice_mut(data_ptr, actual_data_len, |out: &mut [u8]| {
+            out.copy_from_slice(&msg.data[..actual_data_len])
+        })?;
     }
 
     // 将接收到的对象转换为当前进程的句柄
     let received_handles = if !msg.objects.is_empty() && handles_ptr != 0 && handles_count > 0 {
         let process = current_process().ok_or(Error::new(EINVAL))?;
-        let mut proc = process.write();
-
-        let handles: Vec<Handle> = proc.handles_mut().receive_many(msg.objects);
+        // 复制到用户空间时可能缺页，不能持有进程锁
+        let handles: Vec<Handle> = process.write().handles_mut().receive_many(msg.objects);
 
         let copy_count = core::cmp::min(handles_count, handles.len());
-        unsafe {
-            let out = core::slice::from_raw_parts_mut(handles_ptr as *mut u32, copy_count);
+        user::with_slice_mut(handles_ptr, copy_count, |out: &mut [u32]| {
             for (i, h) in handles.iter().take(copy_count).enumerate() {
                 out[i] = h.raw();
             }
-        }
+        })?;
 
         handles.len()
     } else {
@@ -438,11 +432,7 @@ pub fn sys_channel_try_recv(
 
     // 写回实际长度
     if actual_out != 0 {
-        unsafe {
-            let out = &mut *(actual_out as *mut [usize; 2]);
-            out[0] = msg.data.len();
-            out[1] = received_handles;
-        }
+        user::write(actual_out, [msg.data.len(), received_handles])?;
     }
 
     Ok(0)
@@ -1,5 +1,6 @@
 use alloc::string::ToString;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use rmm::{Arch, FrameAllocator, VirtualAddress};
 use spin::RwLock;
 
@@ -8,15 +9,20 @@ use crate::{
     arch::{CurrentRmmArch, irq::IrqRegsArch},
     init::memory::{FRAME_ALLOCATOR, PAGE_SIZE},
     layout,
-    loader::{LoaderError, ProgramLoader},
+    loader::ProgramLoader,
+    memory::{
+        account::{MemoryAccount, MemoryKind},
+        vdso,
+    },
     object::{
         Handle, KernelObject, Process, Rights, Signals,
         process::{current_process, register_process},
         vmar::Vmar,
     },
+    syscall::user,
 };
 
-use super::error::{EBADF, EINVAL, ENOMEM, Error, Result};
+use super::error::{EBADF, EINVAL, ENOMEM, EPERM, ESRCH, Error, Result};
 
 /// 进程创建选项
 #[repr(C)]
@@ -26,12 +32,39 @@ pub struct ProcessCreateOptions {
     pub name_ptr: usize,
     /// 进程名长度
     pub name_len: usize,
-    /// 是否创建 bootstrap channel
-    pub create_bootstrap: bool,
+    /// 非 0 时创建 bootstrap channel
+    pub create_bootstrap: u8,
+    /// 非 0 时允许同时可写可执行的映射（JIT 等场景），默认拒绝
+    pub allow_write_execute: u8,
+    /// 非 0 时授予特权，只有父进程本身拥有特权时才生效
+    pub privileged: u8,
+    /// 初始句柄数组（`ProcessInitHandle`）指针
+    pub handles_ptr: usize,
+    /// 初始句柄个数，最多 `PROCESS_MAX_INIT_HANDLES` 个
+    pub handles_count: usize,
+}
+
+unsafe impl user::Pod for ProcessCreateOptions {}
+
+/// 进程初始句柄
+///
+/// 父进程的句柄被复制到子进程，子进程通过 `SYS_PROCESS_GET_INIT_HANDLE` 按顺序取得。
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessInitHandle {
+    /// 父进程中的句柄，需要有 TRANSFER 权限
+    pub handle: u32,
+    /// 子进程中的权限，不能超过父进程句柄的权限
+    pub rights: u32,
 }
 
+unsafe impl user::Pod for ProcessInitHandle {}
+
+/// 每个进程最多的初始句柄个数
+pub const PROCESS_MAX_INIT_HANDLES: usize = 16;
+
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct ProcessCreateResult {
     /// 进程句柄
     pub process_handle: u32,
@@ -39,22 +72,36 @@ pub struct ProcessCreateResult {
     pub bootstrap_handle: u32,
 }
 
+/// 检查当前进程拥有特权，否则返回 EPERM
+///
+/// 句柄权限授权对某个对象的操作；特权授权的是没有对应对象、影响整个系统的操作，
+/// 例如设置时钟、读取内核日志和跟踪数据、使用性能采样、取得 I/O 端口、中断和帧缓冲。
+/// 这些资源不值得各自做成内核对象，因此用进程上的一个标志表示。
+/// 特权只能由内核授予 init，或由拥有特权的父进程在创建子进程时授予。
+/// 同样属于系统策略的内存限额除了句柄权限之外也需要特权。
+pub fn require_privileged() -> Result<()> {
+    let process = current_process().ok_or(Error::new(ESRCH))?;
+    if !process.read().is_privileged() {
+        return Err(Error::new(EPERM));
+    }
+    Ok(())
+}
+
 /// 创建进程
 pub fn sys_process_create(options_ptr: usize, result_ptr: usize) -> Result<usize> {
     if options_ptr == 0 || result_ptr == 0 {
         return Err(Error::new(EINVAL));
     }
 
-    let options = unsafe { (options_ptr as *const ProcessCreateOptions).as_ref_unchecked() };
-    let result = unsafe { (result_ptr as *mut ProcessCreateResult).as_mut_unchecked() };
+    let options = user::read::<ProcessCreateOptions>(options_ptr)?;
+    user::check_range(result_ptr, size_of::<ProcessCreateResult>())?;
 
     // 获取进程名
     let name = if options.name_ptr != 0 && options.name_len > 0 {
-        let name_slice =
-            unsafe { core::slice::from_raw_parts(options.name_ptr as *const u8, options.name_len) };
-        core::str::from_utf8(name_slice)
-            .map_err(|_| Error::new(EINVAL))?
-            .to_string()
+        user::with_slice(options.name_ptr, options.name_len, |name: &[u8]| {
+            core::str::from_utf8(name).map(ToString::to_string)
+        })?
+        .map_err(|_| Error::new(EINVAL))?
     } else {
         "unnamed".to_string()
     };
@@ -62,28 +109,76 @@ pub fn sys_process_create(options_ptr: usize, result_ptr: usize) -> Result<usize
     // 获取当前进程作为父进程
     let parent = current_process();
 
+    // 先取出初始句柄引用的对象，句柄无效时还没有创建任何对象
+    if options.handles_count > PROCESS_MAX_INIT_HANDLES {
+        return Err(Error::new(EINVAL));
+    }
+    let requested = user::with_slice(
+        options.handles_ptr,
+        options.handles_count,
+        |handles: &[ProcessInitHandle]| handles.to_vec(),
+    )?;
+    let mut init_handles = Vec::new();
+    if !requested.is_empty() {
+        let parent = parent.as_ref().ok_or(Error::new(EINVAL))?;
+        let parent = parent.read();
+        for init_handle in requested {
+            let entry = parent
+                .handles()
+                .get_entry(Handle::from_raw(init_handle.handle))
+                .ok_or(Error::new(EBADF))?;
+            if !entry.rights.contains(Rights::TRANSFER) {
+                return Err(Error::new(EPERM));
+            }
+            let rights = Rights::from_bits_truncate(init_handle.rights) & entry.rights;
+            init_handles.push((entry.object.clone(), rights));
+        }
+    }
+
+    // 先分配顶层页表，内存不足时还没有创建任何对象
+    let new_page_table =
+        unsafe { FRAME_ALLOCATOR.lock().allocate_one() }.ok_or(Error::new(ENOMEM))?;
+    let new_page_table_virt = unsafe { CurrentRmmArch::phys_to_virt(new_page_table) };
+    unsafe { core::ptr::write_bytes(new_page_table_virt.data() as *mut u8, 0, PAGE_SIZE) };
+    if unsafe { ProgramLoader::copy_kernel_mappings(new_page_table) }.is_err() {
+        unsafe { FRAME_ALLOCATOR.lock().free_one(new_page_table) };
+        return Err(Error::new(ENOMEM));
+    }
+
     // 创建新进程
-    let (new_process, bootstrap_parent) = if options.create_bootstrap {
+    let (new_process, bootstrap_parent) = if options.create_bootstrap != 0 {
         Process::new_with_bootstrap(name, parent.clone())
     } else {
         (Process::new(name, parent.clone()), None)
     };
 
-    let new_page_table = unsafe { FRAME_ALLOCATOR.lock().allocate_one() }
-        .ok_or(LoaderError::OutOfMemory)
-        .expect("No enougth memory to create new process");
-    let new_page_table_virt = unsafe { CurrentRmmArch::phys_to_virt(new_page_table) };
-    unsafe { core::ptr::write_bytes(new_page_table_virt.data() as *mut u8, 0, PAGE_SIZE) };
-    unsafe { ProgramLoader::copy_kernel_mappings(new_page_table) }.unwrap();
+    // 子进程的用量同时计入父进程，顶层页表也计入子进程
+    let account = MemoryAccount::new(parent.as_ref().and_then(|p| p.read().account()));
 
     let user_base = VirtualAddress::new(layout::USER_SPACE_START);
     let user_size = layout::USER_SPACE_END - layout::USER_SPACE_START;
-    new_process.write().set_root_vmar(Vmar::create_root(
+    let root_vmar = Vmar::create_address_space(
         user_base,
         user_size,
         layout::ALLOC_START,
         new_page_table,
-    ));
+        account,
+    );
+    root_vmar.set_allow_write_execute(options.allow_write_execute != 0);
+    if vdso::map_into(&root_vmar).is_err() {
+        root_vmar.destroy_address_space();
+        return Err(Error::new(ENOMEM));
+    }
+    let privileged =
+        options.privileged != 0 && parent.as_ref().is_some_and(|p| p.read().is_privileged());
+    {
+        let mut new_proc = new_process.write();
+        new_proc.set_root_vmar(root_vmar);
+        new_proc.set_privileged(privileged);
+        for (object, rights) in init_handles {
+            new_proc.add_init_handle(object, rights);
+        }
+    }
 
     // 注册进程
     register_process(new_process.clone());
@@ -113,8 +208,13 @@ pub fn sys_process_create(options_ptr: usize, result_ptr: usize) -> Result<usize
         Handle::INVALID
     };
 
-    result.process_handle = process_handle.raw();
-    result.bootstrap_handle = bootstrap_handle.raw();
+    user::write(
+        result_ptr,
+        ProcessCreateResult {
+            process_handle: process_handle.raw(),
+            bootstrap_handle: bootstrap_handle.raw(),
+        },
+    )?;
 
     Ok(0)
 }
@@ -137,13 +237,15 @@ pub struct ThreadCreateOptions {
     pub arg: usize,
 }
 
+unsafe impl user::Pod for ThreadCreateOptions {}
+
 /// 在进程中创建线程
 pub fn sys_thread_create(options_ptr: usize, thread_handle_out: usize) -> Result<usize> {
     if options_ptr == 0 {
         return Err(Error::new(EINVAL));
     }
 
-    let options = unsafe { (options_ptr as *const ThreadCreateOptions).as_ref_unchecked() };
+    let options = user::read::<ThreadCreateOptions>(options_ptr)?;
 
     // 获取目标进程
     let process = if options.process_handle == 0 {
@@ -166,11 +268,10 @@ pub fn sys_thread_create(options_ptr: usize, thread_handle_out: usize) -> Result
 
     // 获取线程名
     let name = if options.name_ptr != 0 && options.name_len > 0 {
-        let name_slice =
-            unsafe { core::slice::from_raw_parts(options.name_ptr as *const u8, options.name_len) };
-        core::str::from_utf8(name_slice)
-            .map_err(|_| Error::new(EINVAL))?
-            .to_string()
+        user::with_slice(options.name_ptr, options.name_len, |name: &[u8]| {
+            core::str::from_utf8(name).map(ToString::to_string)
+        })?
+        .map_err(|_| Error::new(EINVAL))?
     } else {
         "thread".to_string()
     };
@@ -196,9 +297,7 @@ pub fn sys_thread_create(options_ptr: usize, thread_handle_out: usize) -> Result
 
     // 返回线程 ID 或句柄
     if thread_handle_out != 0 {
-        unsafe {
-            *(thread_handle_out as *mut u32) = task.read().tid() as u32;
-        }
+        user::write(thread_handle_out, task.read().tid() as u32)?;
     }
 
     Ok(task.read().tid())
@@ -275,6 +374,80 @@ pub fn sys_process_get_vmar_handle(process_handle: usize) -> Result<usize> {
     }
 }
 
+/// 进程内存用量（字节）
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessMemoryInfo {
+    /// VMO 已提交的页面
+    pub vmo: usize,
+    /// 页表
+    pub page_tables: usize,
+    /// 线程的内核栈
+    pub kernel_stacks: usize,
+    /// 包括子孙进程在内的总用量
+    pub total: usize,
+    /// 内存限额（0 表示不限）
+    pub limit: usize,
+}
+
+/// 通过句柄获取进程的内存账户（句柄为 0 表示当前进程）
+fn process_account(process_handle: usize, rights: Rights) -> Result<Arc<MemoryAccount>> {
+    let process = current_process().ok_or(Error::new(EINVAL))?;
+
+    let process = if process_handle == 0 {
+        process
+    } else {
+        let object = process
+            .read()
+            .handles()
+            .get(Handle::from_raw(process_handle as u32), rights)
+            .ok_or(Error::new(EBADF))?;
+
+        Arc::downcast::<RwLock<Process>>(object).map_err(|_| Error::new(EINVAL))?
+    };
+
+    let account = process.read().account();
+    account.ok_or(Error::new(EINVAL))
+}
+
+/// 查询进程的内存用量
+pub fn sys_process_get_memory_info(process_handle: usize, info_out: usize) -> Result<usize> {
+    if info_out == 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let account = process_account(process_handle, Rights::READ)?;
+    let usage = account.usage();
+
+    let info = ProcessMemoryInfo {
+        vmo: usage.pages(MemoryKind::Vmo) * PAGE_SIZE,
+        page_tables: usage.pages(MemoryKind::PageTable) * PAGE_SIZE,
+        kernel_stacks: usage.pages(MemoryKind::KernelStack) * PAGE_SIZE,
+        total: account.total() * PAGE_SIZE,
+        limit: account.limit().map_or(0, |limit| limit * PAGE_SIZE),
+    };
+
+    user::write(info_out, info)?;
+
+    Ok(0)
+}
+
+/// 设置进程的内存限额（字节，0 表示不限）
+///
+/// 限额同时约束该进程的所有子孙进程，超出后分配返回 `ENOMEM`。
+/// 需要特权和进程句柄的 MANAGE 权限，不能通过句柄 0 修改自身的限额。
+pub fn sys_process_set_memory_limit(process_handle: usize, limit: usize) -> Result<usize> {
+    require_privileged()?;
+    if process_handle == 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let account = process_account(process_handle, Rights::MANAGE)?;
+    account.set_limit((limit != 0).then_some(limit / PAGE_SIZE));
+
+    Ok(0)
+}
+
 /// 退出当前进程
 pub fn sys_exit(exit_code: usize) -> Result<usize> {
     let code = exit_code as i32;
@@ -307,9 +480,7 @@ pub fn sys_process_wait(
             // 需要类型转换
             if let Some(proc) = process_obj.as_any().downcast_ref::<RwLock<Process>>() {
                 let code = proc.read().exit_code();
-                unsafe {
-                    *(exit_code_out as *mut i32) = code;
-                }
+                user::write(exit_code_out, code)?;
             }
         }
         return Ok(0);
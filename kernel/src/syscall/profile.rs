@@ -0,0 +1,50 @@
+use crate::{
+    EINVAL, ENOMEM, Error, Result,
+    profile::{
+        PROFILE_ACTION_READ, PROFILE_ACTION_REWIND, PROFILE_ACTION_START, PROFILE_ACTION_STOP,
+        PROFILE_MODE_ALL, ProfileSample,
+    },
+    profiler,
+    syscall::{process::require_privileged, user},
+};
+
+/// 控制采样分析器，需要特权
+///
+/// `PROFILE_ACTION_READ` 把样本写入 `[buf, buf + len)`，返回写入的字节数，
+/// 返回 0 表示缓冲区已经取空。
+pub fn sys_profile_control(action: usize, arg: usize, buf: usize, len: usize) -> Result<usize> {
+    require_privileged()?;
+
+    match action {
+        PROFILE_ACTION_START => {
+            let mode = arg as u32;
+            if mode == 0 || mode & !PROFILE_MODE_ALL != 0 {
+                return Err(Error::new(EINVAL));
+            }
+            profiler::start(mode).map_err(|_| Error::new(ENOMEM))?;
+            Ok(0)
+        }
+        PROFILE_ACTION_STOP => {
+            profiler::stop();
+            Ok(0)
+        }
+        PROFILE_ACTION_REWIND => {
+            profiler::rewind();
+            Ok(0)
+        }
+        PROFILE_ACTION_READ => {
+            let max = len / size_of::<ProfileSample>();
+            if max == 0 {
+                return Err(Error::new(EINVAL));
+            }
+            user::check_range(buf, len)?;
+
+            let samples = profiler::drain(max).map_err(|_| Error::new(ENOMEM))?;
+            user::with_slice_mut(buf, samples.len(), |dst: &mut [ProfileSample]| {
+                dst.copy_from_slice(&samples)
+            })?;
+            Ok(samples.len() * size_of::<ProfileSample>())
+        }
+        _ => Err(Error::new(EINVAL)),
+    }
+}
@@ -0,0 +1,23 @@
+use crate::{Result, random, syscall::user};
+
+/// 每次从 CSPRNG 取出的最大字节数，较大的请求分多次生成，避免长时间持有锁
+const CHUNK_SIZE: usize = 256;
+
+/// 向用户缓冲区写入 `len` 字节随机数
+pub fn sys_cprng_draw(buf: usize, len: usize) -> Result<usize> {
+    if len == 0 {
+        return Ok(0);
+    }
+    user::check_range(buf, len)?;
+
+    let mut chunk = [0u8; CHUNK_SIZE];
+    let mut offset = 0;
+    while offset < len {
+        let size = (len - offset).min(CHUNK_SIZE);
+        random::fill_bytes(&mut chunk[..size]);
+        user::write_slice(buf + offset, &chunk[..size])?;
+        offset += size;
+    }
+
+    Ok(len)
+}
@@ -0,0 +1,140 @@
+use alloc::vec::Vec;
+use core::mem::MaybeUninit;
+
+use crate::{
+    arch::{CurrentUserAccessArch, user::UserAccessArch},
+    ktrace::KtraceRecord,
+    layout::USER_SPACE_END,
+    memory::slab::SlabInfo,
+    profile::ProfileSample,
+};
+
+use super::error::{EFAULT, ENOMEM, Error, Result};
+
+/// 任意字节都是合法值的类型，只有这些类型可以直接在用户内存上构造
+///
+/// # Safety
+///
+/// 实现者不能含有 `bool`、枚举、引用等存在非法取值的字段。
+pub unsafe trait Pod: Copy {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),* $(,)?) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+// 系统调用直接复制给用户态的记录
+impl_pod!(KtraceRecord, ProfileSample, SlabInfo);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// 在用户访问窗口内执行 `f`
+///
+/// 开启 SMAP 后，内核只有在窗口内才能读写用户页面，窗口之外的访问会触发缺页。
+fn with_user_access<R>(f: impl FnOnce() -> R) -> R {
+    let enabled = CurrentUserAccessArch::user_access_begin();
+    let ret = f();
+    CurrentUserAccessArch::user_access_end(enabled);
+    ret
+}
+
+/// 检查 [ptr, ptr + size) 完全位于用户地址空间内
+pub fn check_range(ptr: usize, size: usize) -> Result<()> {
+    let end = ptr.checked_add(size).ok_or(Error::new(EFAULT))?;
+    if ptr == 0 || end > USER_SPACE_END {
+        return Err(Error::new(EFAULT));
+    }
+    Ok(())
+}
+
+/// 在用户访问窗口内复制 `len` 字节，用户页面无法访问时返回 EFAULT
+///
+/// 调用者需要先检查用户一侧的范围。
+fn copy(dst: *mut u8, src: *const u8, len: usize) -> Result<()> {
+    let copied = with_user_access(|| unsafe { CurrentUserAccessArch::copy(dst, src, len) });
+    if copied {
+        Ok(())
+    } else {
+        Err(Error::new(EFAULT))
+    }
+}
+
+/// 从用户地址读取一个值
+pub fn read<T: Pod>(ptr: usize) -> Result<T> {
+    check_range(ptr, size_of::<T>())?;
+    let mut value = MaybeUninit::<T>::uninit();
+    copy(
+        value.as_mut_ptr() as *mut u8,
+        ptr as *const u8,
+        size_of::<T>(),
+    )?;
+    Ok(unsafe { value.assume_init() })
+}
+
+/// 向用户地址写入一个值
+pub fn write<T>(ptr: usize, value: T) -> Result<()> {
+    check_range(ptr, size_of::<T>())?;
+    copy(
+        ptr as *mut u8,
+        &value as *const T as *const u8,
+        size_of::<T>(),
+    )
+}
+
+/// 检查用户数组 `[ptr, ptr + len)` 的范围和对齐，数组要求指针按元素类型对齐
+fn check_slice<T>(ptr: usize, len: usize) -> Result<()> {
+    let size = len.checked_mul(size_of::<T>()).ok_or(Error::new(EFAULT))?;
+    check_range(ptr, size)?;
+    if !ptr.is_multiple_of(align_of::<T>()) {
+        return Err(Error::new(EFAULT));
+    }
+    Ok(())
+}
+
+/// 复制用户数组后以切片形式访问，`len` 为 0 时 `ptr` 可以为空
+pub fn with_slice<T: Pod, R>(ptr: usize, len: usize, f: impl FnOnce(&[T]) -> R) -> Result<R> {
+    let values = read_vec(ptr, len)?;
+    Ok(f(&values))
+}
+
+/// 复制用户数组，交给 `f` 修改后再写回，`len` 为 0 时 `ptr` 可以为空
+pub fn with_slice_mut<T: Pod, R>(
+    ptr: usize,
+    len: usize,
+    f: impl FnOnce(&mut [T]) -> R,
+) -> Result<R> {
+    let mut values = read_vec(ptr, len)?;
+    let ret = f(&mut values);
+    write_slice(ptr, &values)?;
+    Ok(ret)
+}
+
+/// 复制用户数组，内核堆不足时返回 ENOMEM，`len` 为 0 时 `ptr` 可以为空
+pub fn read_vec<T: Pod>(ptr: usize, len: usize) -> Result<Vec<T>> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    check_slice::<T>(ptr, len)?;
+
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(len).map_err(|_| Error::new(ENOMEM))?;
+    copy(
+        vec.as_mut_ptr() as *mut u8,
+        ptr as *const u8,
+        len * size_of::<T>(),
+    )?;
+    unsafe { vec.set_len(len) };
+    Ok(vec)
+}
+
+/// 把数组写入用户地址，元素类型不必是 [`Pod`]，`ptr` 也不必对齐
+pub fn write_slice<T: Copy>(ptr: usize, values: &[T]) -> Result<()> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let size = size_of_val(values);
+    check_range(ptr, size)?;
+    copy(ptr as *mut u8, values.as_ptr() as *const u8, size)
+}
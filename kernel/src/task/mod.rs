@@ -5,15 +5,28 @@ use alloc::{
     string::{String, ToString},
     sync::{Arc, Weak},
 };
-use rmm::{Arch, FrameAllocator, FrameCount, PhysicalAddress, VirtualAddress};
+use rmm::{
+    Arch, FrameAllocator, FrameCount, PageMapper, PhysicalAddress, TableKind, VirtualAddress,
+};
 use spin::{Mutex, RwLock};
 
 use crate::{
-    arch::{ArchContext, CurrentRmmArch, Ptrace, get_archid, irq::IrqRegsArch, switch_to},
+    arch::{
+        ArchContext, CurrentRmmArch, Ptrace, get_archid, irq::IrqRegsArch, rmm::page_flags,
+        switch_to,
+    },
+    cmdline,
     consts::STACK_SIZE,
     init::memory::{FRAME_ALLOCATOR, PAGE_SIZE},
     initial_kernel_thread,
-    object::process::{ArcProcess, WeakArcProcess},
+    memory::{
+        account::{self, MemoryAccount, MemoryKind},
+        tlb::MAX_CPUS,
+    },
+    object::{
+        process::{ArcProcess, WeakArcProcess},
+        vmar::Vmar,
+    },
     smp::{CPU_COUNT, get_archid_by_cpuid},
     task::sched::{ArcScheduler, SCHEDULERS},
 };
@@ -29,6 +42,9 @@ pub type WeakArcTask = Weak<RwLock<Task>>;
 /// 全局任务 ID 计数器
 pub static NEXT_TID: AtomicUsize = AtomicUsize::new(1);
 
+/// 每个 CPU 上当前任务的时间片已经经过的时钟中断次数
+static SLICE_TICKS: [AtomicUsize; MAX_CPUS] = [const { AtomicUsize::new(0) }; MAX_CPUS];
+
 /// 线程（任务）
 pub struct Task {
     /// 任务 ID（线程 ID）
@@ -37,10 +53,14 @@ pub struct Task {
     name: String,
     /// 所属进程
     process: Option<WeakArcProcess>,
+    /// 所属进程的根 VMAR，缺页处理不经过进程的锁从这里取得
+    root_vmar: Option<Arc<Vmar>>,
     /// 任务状态
     state: TaskState,
     /// 分配的 CPU ID
     cpu_id: usize,
+    /// 分配的 CPU 的架构编号，任务不会迁移，中断中不经过锁即可读取
+    archid: usize,
     /// 退出码
     exit_code: Option<i32>,
 
@@ -50,6 +70,8 @@ pub struct Task {
     pub syscall_stack_top: VirtualAddress,
     /// 用户态 syscall 栈
     pub user_syscall_stack: VirtualAddress,
+    /// 内核栈计入的内存账户
+    account: Option<Arc<MemoryAccount>>,
 
     /// 架构相关上下文
     pub arch_context: ArchContext,
@@ -61,6 +83,29 @@ pub struct Task {
 pub const IDLE_PRIORITY: usize = 20;
 pub const NORMAL_PRIORITY: usize = 0;
 
+/// 取消或恢复栈最低一页在直接映射区中的映射
+///
+/// 取消映射后栈溢出会立即触发缺页，而不是悄悄覆盖相邻的物理页。只刷新本 CPU 的
+/// TLB：其他 CPU 上残留的旧表项最多让溢出晚一点被发现，不影响正确性。
+fn set_stack_guard(
+    frame_allocator: &mut impl FrameAllocator,
+    stack_bottom: VirtualAddress,
+    guard: bool,
+) {
+    let mut mapper = unsafe { PageMapper::current(TableKind::Kernel, frame_allocator) };
+    if guard {
+        if let Some((_, _, flush)) = unsafe { mapper.unmap_phys(stack_bottom, false) } {
+            flush.flush();
+        }
+    } else {
+        let phys = PhysicalAddress::new(stack_bottom.data() - CurrentRmmArch::PHYS_OFFSET);
+        let flags = unsafe { page_flags::<CurrentRmmArch>(stack_bottom) };
+        if let Some(flush) = unsafe { mapper.map_phys(stack_bottom, phys, flags) } {
+            flush.flush();
+        }
+    }
+}
+
 fn alloc_cpuid() -> usize {
     static NEXT_CPUID: AtomicUsize = AtomicUsize::new(0);
     let cpu_count = CPU_COUNT.load(Ordering::SeqCst);
@@ -71,21 +116,31 @@ fn alloc_cpuid() -> usize {
 impl Task {
     /// 创建 idle 任务
     pub fn new_idle(cpu_id: usize) -> ArcTask {
-        Self::new_inner(0, cpu_id, "idle".to_string(), None, true)
+        Self::new_inner(0, cpu_id, "idle".to_string(), None, None, true)
+            .expect("No memory to allocate idle task")
     }
 
     /// 创建内核任务
-    pub fn new_kernel(name: String) -> ArcTask {
+    pub fn new_kernel(name: String) -> Option<ArcTask> {
         let tid = NEXT_TID.fetch_add(1, Ordering::SeqCst);
         let cpu_id = alloc_cpuid();
-        Self::new_inner(tid, cpu_id, name, None, false)
+        Self::new_inner(tid, cpu_id, name, None, None, false)
     }
 
-    /// 创建用户任务（属于某个进程）
-    pub fn new_user(name: String, process: ArcProcess) -> ArcTask {
+    /// 创建用户任务（属于某个进程，地址空间为 `root_vmar`），内核栈计入 `account`
+    ///
+    /// 物理内存不足或超出限额时返回 None。
+    pub fn new_user(
+        name: String,
+        process: ArcProcess,
+        root_vmar: Option<Arc<Vmar>>,
+        account: Option<Arc<MemoryAccount>>,
+    ) -> Option<ArcTask> {
         let tid = NEXT_TID.fetch_add(1, Ordering::SeqCst);
         let cpu_id = alloc_cpuid();
-        Self::new_inner(tid, cpu_id, name, Some(process), false)
+        let task = Self::new_inner(tid, cpu_id, name, Some(process), account, false)?;
+        task.write().root_vmar = root_vmar;
+        Some(task)
     }
 
     fn new_inner(
@@ -93,21 +148,53 @@ impl Task {
         cpu_id: usize,
         name: String,
         process: Option<ArcProcess>,
+        account: Option<Arc<MemoryAccount>>,
         is_idle: bool,
-    ) -> ArcTask {
+    ) -> Option<ArcTask> {
+        // FPU 状态区在栈之前分配，失败时没有需要归还的资源
+        let arch_context = ArchContext::new()?;
+
         let stack_frame_count = FrameCount::new(STACK_SIZE / PAGE_SIZE);
+        let stack_pages = stack_frame_count.data() * 2;
 
-        let kernel_stack_phys = unsafe { FRAME_ALLOCATOR.lock().allocate(stack_frame_count) }
-            .expect("No memory to allocate kernel stack");
-        let kernel_stack_virt = unsafe { CurrentRmmArch::phys_to_virt(kernel_stack_phys) };
+        if !account::charge(account.as_deref(), MemoryKind::KernelStack, stack_pages) {
+            return None;
+        }
+
+        // 两个栈都分配成功才继续，否则归还已分配的部分
+        let stacks = {
+            let mut frame_allocator = FRAME_ALLOCATOR.lock();
+            let stacks =
+                unsafe { frame_allocator.allocate(stack_frame_count) }.and_then(|kernel_stack| {
+                    match unsafe { frame_allocator.allocate(stack_frame_count) } {
+                        Some(syscall_stack) => Some((kernel_stack, syscall_stack)),
+                        None => {
+                            unsafe { frame_allocator.free(kernel_stack, stack_frame_count) };
+                            None
+                        }
+                    }
+                });
+            if let Some((kernel_stack, syscall_stack)) = stacks {
+                for stack in [kernel_stack, syscall_stack] {
+                    let bottom = unsafe { CurrentRmmArch::phys_to_virt(stack) };
+                    set_stack_guard(&mut *frame_allocator, bottom, true);
+                }
+            }
+            stacks
+        };
+
+        let Some((kernel_stack_phys, syscall_stack_phys)) = stacks else {
+            account::uncharge(account.as_deref(), MemoryKind::KernelStack, stack_pages);
+            return None;
+        };
 
-        let syscall_stack_phys = unsafe { FRAME_ALLOCATOR.lock().allocate(stack_frame_count) }
-            .expect("No memory to allocate syscall stack");
+        let kernel_stack_virt = unsafe { CurrentRmmArch::phys_to_virt(kernel_stack_phys) };
         let syscall_stack_virt = unsafe { CurrentRmmArch::phys_to_virt(syscall_stack_phys) };
 
         let task = Task {
             tid,
             name,
+            root_vmar: None,
             process: process.map(|p| Arc::downgrade(&p)),
             state: if is_idle {
                 TaskState::Ready
@@ -115,15 +202,18 @@ impl Task {
                 TaskState::Created
             },
             cpu_id,
+            archid: get_archid_by_cpuid(cpu_id),
             exit_code: None,
             kernel_stack_top: kernel_stack_virt.add(STACK_SIZE),
             syscall_stack_top: syscall_stack_virt.add(STACK_SIZE),
             user_syscall_stack: VirtualAddress::new(0),
-            arch_context: ArchContext::default(),
+            account,
+            arch_context,
             running: false,
         };
 
-        Arc::new(RwLock::new(task))
+        // 失败时 task 被丢弃，栈在 Drop 中归还
+        Arc::try_new(RwLock::new(task)).ok()
     }
 
     pub fn tid(&self) -> usize {
@@ -134,6 +224,11 @@ impl Task {
         self.name.clone()
     }
 
+    /// 任务名，不复制
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn state(&self) -> TaskState {
         self.state
     }
@@ -146,14 +241,34 @@ impl Task {
         self.process.as_ref().and_then(|p| p.upgrade())
     }
 
+    /// 所属进程的根 VMAR，不获取任何锁
+    pub fn root_vmar(&self) -> Option<&Arc<Vmar>> {
+        self.root_vmar.as_ref()
+    }
+
     pub fn get_cpu_id(&self) -> usize {
         self.cpu_id
     }
 
+    /// 分配的 CPU 的架构编号（x86 为 LAPIC ID）
+    pub fn archid(&self) -> usize {
+        self.archid
+    }
+
     pub fn get_kernel_stack_top(&self) -> VirtualAddress {
         self.kernel_stack_top
     }
 
+    /// 地址是否落在本任务某个栈的保护页内，用于在缺页时报告栈溢出
+    pub fn in_stack_guard(&self, address: usize) -> bool {
+        [self.kernel_stack_top, self.syscall_stack_top]
+            .iter()
+            .any(|top| {
+                let bottom = top.sub(STACK_SIZE).data();
+                (bottom..bottom + PAGE_SIZE).contains(&address)
+            })
+    }
+
     pub fn exit_code(&self) -> Option<i32> {
         self.exit_code
     }
@@ -205,21 +320,22 @@ impl Drop for Task {
         let mut frame_allocator = FRAME_ALLOCATOR.lock();
         let stack_frame_count = FrameCount::new(STACK_SIZE / PAGE_SIZE);
 
-        let kernel_stack_phys = PhysicalAddress::new(
-            self.kernel_stack_top
-                .sub(CurrentRmmArch::PHYS_OFFSET)
-                .data(),
-        )
-        .sub(STACK_SIZE);
-        unsafe { frame_allocator.free(kernel_stack_phys, stack_frame_count) };
-
-        let syscall_stack_phys = PhysicalAddress::new(
-            self.syscall_stack_top
-                .sub(CurrentRmmArch::PHYS_OFFSET)
-                .data(),
-        )
-        .sub(STACK_SIZE);
-        unsafe { frame_allocator.free(syscall_stack_phys, stack_frame_count) };
+        for stack_top in [self.kernel_stack_top, self.syscall_stack_top] {
+            // 归还前恢复保护页的映射，之后分配到这些页的代码可以正常访问
+            let stack_bottom = stack_top.sub(STACK_SIZE);
+            set_stack_guard(&mut *frame_allocator, stack_bottom, false);
+
+            let stack_phys =
+                PhysicalAddress::new(stack_bottom.sub(CurrentRmmArch::PHYS_OFFSET).data());
+            unsafe { frame_allocator.free(stack_phys, stack_frame_count) };
+        }
+        drop(frame_allocator);
+
+        account::uncharge(
+            self.account.as_deref(),
+            MemoryKind::KernelStack,
+            stack_frame_count.data() * 2,
+        );
     }
 }
 
@@ -297,8 +413,8 @@ pub fn stop_task(task: ArcTask) {
     scheduler.write().stop_task(task);
 }
 
-/// 退出任务
-pub fn exit_task(task: ArcTask, exit_code: i32) {
+/// 终止任务，不通知所属进程（进程退出时终止其余线程）
+pub fn terminate_task(task: &ArcTask, exit_code: i32) {
     {
         let mut t = task.write();
         t.set_state(TaskState::Exited);
@@ -308,10 +424,26 @@ pub fn exit_task(task: ArcTask, exit_code: i32) {
     let cpu_id = task.read().get_cpu_id();
     let scheduler = get_scheduler_by_cpuid(cpu_id);
     scheduler.write().remove_task(task.clone());
+}
 
-    // 通知所属进程
-    if let Some(process) = task.clone().read().process() {
-        process.write().on_thread_exit(task);
+/// 退出任务
+pub fn exit_task(task: ArcTask, exit_code: i32) {
+    terminate_task(&task, exit_code);
+
+    // 通知所属进程，进程随之退出时关闭它的句柄并销毁地址空间
+    let process = task.read().process();
+    if let Some(process) = process {
+        let exited = process.write().on_thread_exit(task);
+        if exited {
+            // 在进程锁之外关闭句柄，关闭时可能需要获取其他对象的锁
+            let handles = process.write().take_handles();
+            drop(handles);
+
+            let root_vmar = process.read().root_vmar();
+            if let Some(root_vmar) = root_vmar {
+                root_vmar.destroy_address_space();
+            }
+        }
     }
 }
 
@@ -337,7 +469,7 @@ pub fn get_current_task() -> Option<ArcTask> {
 
 /// 创建并启动内核任务
 pub fn create_kernel_task(name: String, entry: usize) -> Option<ArcTask> {
-    let task = Task::new_kernel(name);
+    let task = Task::new_kernel(name)?;
     {
         let mut task_guard = task.write();
         let stack_top = VirtualAddress::new(task_guard.pt_regs() as usize);
@@ -357,6 +489,26 @@ pub fn exit_current(exit_code: i32) -> ! {
     }
 }
 
+/// 线程因无法处理的异常被终止时的退出码
+pub const EXIT_CODE_FAULT: i32 = -1;
+
+/// 用户态触发了无法处理的异常（例如访问栈保护页）：终止当前线程并切换到其他任务
+///
+/// 只在从用户态陷入的异常处理中调用，此时内核没有持有任何锁。
+pub fn kill_current_on_fault(exception: &str, pc: usize) -> ! {
+    if let Some(task) = get_current_task() {
+        let task = task.read();
+        warn!(
+            "Task {} ({}) killed by {} at {:#x}",
+            task.tid(),
+            task.name(),
+            exception,
+            pc
+        );
+    }
+    exit_current(EXIT_CODE_FAULT)
+}
+
 pub static TASK_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
 /// 初始化调度系统
@@ -378,8 +530,17 @@ pub fn init() -> Option<ArcTask> {
     task
 }
 
+/// 时钟中断中调用，当前任务的时间片（命令行选项 `sched_slice`）用完时切换任务
+pub fn timer_tick() {
+    let ticks = SLICE_TICKS[get_archid()].fetch_add(1, Ordering::Relaxed) + 1;
+    if ticks >= cmdline::options().sched_slice {
+        schedule();
+    }
+}
+
 /// 调度
 pub fn schedule() {
+    SLICE_TICKS[get_archid()].store(0, Ordering::Relaxed);
     let current_scheduler = get_scheduler();
     let prev = current_scheduler
         .read()
@@ -389,6 +550,7 @@ pub fn schedule() {
     let next = current_scheduler.write().schedule();
     next.write().running = true;
     drop(current_scheduler);
+    crate::trace::context_switch(&prev, &next);
     switch_to(prev, next);
 }
 
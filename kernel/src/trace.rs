@@ -0,0 +1,226 @@
+//! 内核事件跟踪（ktrace）
+//!
+//! 每个 CPU 有独立的环形缓冲区，记录时关中断并只访问本 CPU 的缓冲区；
+//! 缓冲区满时覆盖最旧的记录，并在读取时以 `KTRACE_EVENT_DROPPED` 报告丢失的数量。
+//! 未开启时每个跟踪点只有一次原子读取。
+
+use alloc::collections::TryReserveError;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::{
+    arch::{CurrentTimeArch, CurrentTlbArch, time::TimeArch, tlb::TlbArch},
+    ktrace::{
+        KTRACE_EVENT_CONTEXT_SWITCH, KTRACE_EVENT_DROPPED, KTRACE_GROUP_CONTEXT_SWITCH,
+        KtraceRecord,
+    },
+    memory::tlb::MAX_CPUS,
+    smp::CPUID_TO_ARCHID,
+    task::ArcTask,
+};
+
+/// 每个 CPU 缓冲区的记录数
+pub const BUFFER_RECORDS: usize = 8192;
+
+/// 当前开启的事件分组
+static GROUPS: AtomicU32 = AtomicU32::new(0);
+
+/// 每个 CPU 当前运行任务的 PID 和 TID，在上下文切换时更新，避免在跟踪点获取调度器的锁
+static CURRENT_IDS: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(0) }; MAX_CPUS];
+
+struct TraceBuffer {
+    records: Vec<KtraceRecord>,
+    /// 最旧记录的位置
+    start: usize,
+    len: usize,
+    /// 被覆盖的记录数
+    dropped: u64,
+}
+
+impl TraceBuffer {
+    const fn new() -> Self {
+        Self {
+            records: Vec::new(),
+            start: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, record: KtraceRecord) {
+        let capacity = self.records.capacity();
+        if capacity == 0 {
+            return;
+        }
+        if self.records.len() < capacity {
+            self.records.push(record);
+            self.len += 1;
+            return;
+        }
+
+        let index = (self.start + self.len) % capacity;
+        self.records[index] = record;
+        if self.len == capacity {
+            self.start = (self.start + 1) % capacity;
+            self.dropped += 1;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<KtraceRecord> {
+        if self.len == 0 {
+            return None;
+        }
+        let record = self.records[self.start];
+        self.start = (self.start + 1) % self.records.capacity();
+        self.len -= 1;
+        Some(record)
+    }
+
+    fn clear(&mut self) {
+        self.records.clear();
+        self.start = 0;
+        self.len = 0;
+        self.dropped = 0;
+    }
+}
+
+static BUFFERS: [Mutex<TraceBuffer>; MAX_CPUS] =
+    [const { Mutex::new(TraceBuffer::new()) }; MAX_CPUS];
+
+/// 分组 `group` 是否在记录
+#[inline]
+pub fn enabled(group: u32) -> bool {
+    GROUPS.load(Ordering::Relaxed) & group != 0
+}
+
+/// 当前单调时间，供跟踪点计算耗时
+#[inline]
+pub fn timestamp() -> u64 {
+    CurrentTimeArch::nano_time()
+}
+
+/// CPU `cpu` 上当前运行任务的 PID 和 TID
+pub fn current_ids(cpu: usize) -> (u32, u32) {
+    let ids = CURRENT_IDS[cpu].load(Ordering::Relaxed);
+    ((ids >> 32) as u32, ids as u32)
+}
+
+/// 记录一个事件，调用前应先用 [`enabled`] 检查分组
+pub fn record(event: u16, args: [u64; 3]) {
+    let irq = CurrentTlbArch::irq_save();
+    let cpu = CurrentTlbArch::current_cpu();
+    let (pid, tid) = current_ids(cpu);
+    let record = KtraceRecord {
+        timestamp: timestamp(),
+        pid,
+        tid,
+        event,
+        cpu: cpu as u16,
+        reserved: 0,
+        args,
+    };
+    BUFFERS[cpu].lock().push(record);
+    CurrentTlbArch::irq_restore(irq);
+}
+
+/// 调度器切换任务前调用，更新当前 CPU 的任务并记录切换事件
+///
+/// 跟踪关闭时也更新当前任务，开始跟踪后的第一条记录就带有正确的 PID 和 TID。
+pub fn context_switch(prev: &ArcTask, next: &ArcTask) {
+    let prev_tid = prev.try_read().map_or(0, |task| task.tid());
+    let (next_tid, next_pid) = next.try_read().map_or((0, 0), |task| {
+        let pid = task
+            .process()
+            .and_then(|process| process.try_read().map(|process| process.pid()))
+            .unwrap_or(0);
+        (task.tid(), pid)
+    });
+
+    let cpu = CurrentTlbArch::current_cpu();
+    CURRENT_IDS[cpu].store(
+        ((next_pid as u64) << 32) | next_tid as u32 as u64,
+        Ordering::Relaxed,
+    );
+
+    if enabled(KTRACE_GROUP_CONTEXT_SWITCH) {
+        record(
+            KTRACE_EVENT_CONTEXT_SWITCH,
+            [prev_tid as u64, next_tid as u64, next_pid as u64],
+        );
+    }
+}
+
+/// 开始记录 `groups` 中的事件，第一次开启时为每个 CPU 分配缓冲区
+pub fn start(groups: u32) -> Result<(), TryReserveError> {
+    let cpus: Vec<usize> = CPUID_TO_ARCHID.lock().values().copied().collect();
+    for cpu in cpus.into_iter().filter(|&cpu| cpu < MAX_CPUS) {
+        let irq = CurrentTlbArch::irq_save();
+        let result = {
+            let mut buffer = BUFFERS[cpu].lock();
+            if buffer.records.capacity() == 0 {
+                buffer.records.try_reserve_exact(BUFFER_RECORDS)
+            } else {
+                Ok(())
+            }
+        };
+        CurrentTlbArch::irq_restore(irq);
+        result?;
+    }
+
+    GROUPS.store(groups, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 停止记录，已有的记录保留到读取或清空
+pub fn stop() {
+    GROUPS.store(0, Ordering::Relaxed);
+}
+
+/// 清空所有 CPU 的缓冲区
+pub fn rewind() {
+    for buffer in BUFFERS.iter() {
+        let irq = CurrentTlbArch::irq_save();
+        buffer.lock().clear();
+        CurrentTlbArch::irq_restore(irq);
+    }
+}
+
+/// 依次取出各 CPU 缓冲区中的记录，一次最多取出 `max` 和 `BUFFER_RECORDS` 中较小者条
+///
+/// 某个 CPU 有记录被覆盖时，先返回一条 `KTRACE_EVENT_DROPPED`。
+pub fn drain(max: usize) -> Result<Vec<KtraceRecord>, TryReserveError> {
+    let limit = max.min(BUFFER_RECORDS);
+    let mut out = Vec::new();
+    out.try_reserve_exact(limit)?;
+
+    for (cpu, buffer) in BUFFERS.iter().enumerate() {
+        let irq = CurrentTlbArch::irq_save();
+        let mut buffer = buffer.lock();
+        if buffer.dropped != 0 && out.len() < limit {
+            out.push(KtraceRecord {
+                timestamp: timestamp(),
+                event: KTRACE_EVENT_DROPPED,
+                cpu: cpu as u16,
+                args: [buffer.dropped, 0, 0],
+                ..KtraceRecord::default()
+            });
+            buffer.dropped = 0;
+        }
+        while out.len() < limit {
+            match buffer.pop() {
+                Some(record) => out.push(record),
+                None => break,
+            }
+        }
+        drop(buffer);
+        CurrentTlbArch::irq_restore(irq);
+
+        if out.len() >= limit {
+            break;
+        }
+    }
+    Ok(out)
+}
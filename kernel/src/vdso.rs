@@ -0,0 +1,31 @@
+//! 内核映射到每个进程的只读数据页
+//!
+//! 内核与用户态共用这里的定义，用户态通过 [`layout::VDSO_DATA_ADDR`](crate::layout::VDSO_DATA_ADDR)
+//! 访问，不需要陷入内核就能换算单调时间。
+
+/// vDSO 数据页的布局
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VdsoData {
+    /// 布局版本，不兼容修改时递增
+    pub version: u32,
+    /// 为 1 时可以在用户态读取计数器换算单调时间，否则需要使用系统调用
+    pub counter_usable: u32,
+    /// 换算基准时的计数器读数
+    pub counter_base: u64,
+    /// 换算基准时的单调时间（纳秒）
+    pub ns_base: u64,
+    /// 计数器增量乘以 `mult` 再右移 `shift` 位得到纳秒
+    pub mult: u64,
+    pub shift: u32,
+}
+
+impl VdsoData {
+    pub const VERSION: u32 = 1;
+
+    /// 把计数器读数换算为单调时间（纳秒）
+    pub fn counter_to_nanos(&self, counter: u64) -> u64 {
+        let delta = counter.wrapping_sub(self.counter_base) as u128;
+        self.ns_base + ((delta * self.mult as u128) >> self.shift) as u64
+    }
+}
@@ -1,6 +1,8 @@
 //! DMA 内存管理
 //!
 //! 提供物理连续内存的分配和管理，用于设备 DMA 操作。
+//! 对于非连续的缓冲区，可以通过 [`PinnedRegion`] 将 VMO 的一段 pin 住，
+//! 得到其物理页面段列表（scatter-gather）。
 
 use alloc::vec::Vec;
 use core::ops::{Deref, DerefMut};
@@ -9,7 +11,7 @@ use libradon::syscall::{self, nr};
 use spin::Mutex;
 
 use libradon::handle::Handle;
-use libradon::memory::{map_vmo, unmap, MappingFlags, Vmo, VmoOptions};
+use libradon::memory::{map_vmo, unmap, MappingFlags, PhysRun, Pmt, Vmo, VmoOptions};
 
 use crate::{DriverError, Result};
 
@@ -187,6 +189,65 @@ impl Drop for DmaRegion {
 unsafe impl Send for DmaRegion {}
 unsafe impl Sync for DmaRegion {}
 
+/// Pin 住的 VMO 区域
+///
+/// 将任意（不要求物理连续的）VMO 范围 pin 住用于 DMA，
+/// 释放时固定内存令牌随之关闭，自动 unpin。
+pub struct PinnedRegion<'a> {
+    vmo: &'a Vmo,
+    /// 固定内存令牌
+    _pmt: Pmt,
+    /// 大小
+    size: usize,
+    /// 物理页面段
+    segments: Vec<(PhysAddr, usize)>,
+}
+
+impl<'a> PinnedRegion<'a> {
+    /// Pin 住 VMO 的 `[offset, offset + size)` 范围
+    pub fn pin(vmo: &'a Vmo, offset: usize, size: usize) -> Result<Self> {
+        if size == 0 {
+            return Err(DriverError::InvalidArgument);
+        }
+
+        // 最坏情况下每页一段（首尾页可能不对齐）
+        let max_runs = (offset % 4096 + size + 4095) / 4096;
+        let mut runs = alloc::vec![PhysRun::default(); max_runs];
+
+        let (pmt, count) = vmo.pin(offset, size, &mut runs)?;
+
+        let segments = runs[..count]
+            .iter()
+            .map(|run| (PhysAddr::new(run.phys as u64), run.len))
+            .collect();
+
+        Ok(Self {
+            vmo,
+            _pmt: pmt,
+            size,
+            segments,
+        })
+    }
+
+    /// 物理页面段列表 (物理地址, 长度)
+    #[inline]
+    pub fn segments(&self) -> &[(PhysAddr, usize)] {
+        &self.segments
+    }
+
+    /// 获取大小
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// 获取 VMO
+    #[inline]
+    pub fn vmo(&self) -> &Vmo {
+        self.vmo
+    }
+}
+
 /// DMA 缓冲区
 ///
 /// 简单的 DMA 缓冲区封装，带读写位置跟踪。
@@ -486,6 +547,13 @@ impl SgList {
         }
     }
 
+    /// 添加 pin 住的区域的所有物理页面段
+    pub fn push_pinned(&mut self, region: &PinnedRegion) {
+        for &(phys, len) in region.segments() {
+            self.push(phys, len as u32);
+        }
+    }
+
     /// 描述符数量
     pub fn len(&self) -> usize {
         self.descriptors.len()
@@ -92,8 +92,7 @@ impl IrqHandler {
 
     /// 确认中断
     pub fn ack(&self) -> Result<()> {
-        // TODO: 调用 SYS_IRQ_ACK syscall
-        // unsafe { syscall1(SYS_IRQ_ACK, self.token.handle.raw() as usize) }
+        libradon::interrupt::ack(self.token.handle)?;
         Ok(())
     }
 
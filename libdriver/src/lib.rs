@@ -15,7 +15,7 @@ pub mod server;
 // 重新导出常用类型
 pub use buffer::{BufferPool, SharedBuffer};
 pub use client::{DriverClient, RpcClient};
-pub use dma::{DmaBuffer, DmaPool, DmaRegion, PhysAddr};
+pub use dma::{DmaBuffer, DmaPool, DmaRegion, PhysAddr, PinnedRegion};
 pub use irq::{IrqHandler, IrqToken};
 pub use mmio::MmioRegion;
 pub use protocol::{DriverOp, MessageHeader, Request, Response};
@@ -3,7 +3,7 @@
 use core::marker::PhantomData;
 use core::ptr::{read_volatile, write_volatile};
 
-use libradon::memory::{map_vmo, MappingFlags, Vmo};
+use libradon::memory::{map_vmo, CachePolicy, MappingFlags, Vmo};
 
 use crate::{DriverError, PhysAddr, Result};
 
@@ -39,6 +39,8 @@ impl MmioRegion {
         // 创建物理内存 VMO
         // 注意：需要特殊权限
         let vmo = Vmo::create_physical(aligned_phys.as_u64() as usize, aligned_size)?;
+        // 设备寄存器不能被缓存
+        vmo.set_cache_policy(CachePolicy::UncachedDevice)?;
 
         // 映射
         let base = map_vmo(
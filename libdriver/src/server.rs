@@ -10,7 +10,7 @@ use spin::Mutex;
 
 use libradon::{
     channel::Channel,
-    handle::Handle,
+    handle::{AsHandle, Handle},
     port::{BindOptions, Deadline, Port, PortPacket},
     signal::Signals,
 };
@@ -30,8 +30,17 @@ pub trait RequestHandler: Send + Sync {
 
     /// 处理连接断开
     fn on_disconnect(&self, _ctx: &ConnectionContext) {}
+
+    /// 被 [`DriverServer::watch`] 监视的对象有信号到达
+    fn on_signal(&self, _key: u64, _signals: Signals) {}
 }
 
+/// `watch` 使用的 port key 带有这一位，与连接 ID 区分
+const WATCH_KEY_FLAG: u64 = 1 << 63;
+
+/// `stop` 用来唤醒服务线程的 port key
+const WAKE_KEY: u64 = u64::MAX;
+
 /// 请求上下文
 pub struct RequestContext {
     /// 连接 ID
@@ -107,20 +116,43 @@ impl DriverServer {
         &self.name
     }
 
+    /// 监视 `object` 的 `signals`，信号到达时在服务线程上调用处理器的 `on_signal(key, ..)`
+    ///
+    /// 用于中断等需要和请求在同一线程上处理的事件，`key` 不能使用最高位。
+    pub fn watch<T: AsHandle>(&self, key: u64, object: &T, signals: Signals) -> Result<()> {
+        if key & WATCH_KEY_FLAG != 0 {
+            return Err(DriverError::InvalidArgument);
+        }
+        self.port.bind(
+            key | WATCH_KEY_FLAG,
+            object,
+            signals,
+            BindOptions::Persistent,
+        )?;
+        Ok(())
+    }
+
+    fn dispatch(&self, packet: &PortPacket) -> Result<()> {
+        match packet.key {
+            // 接受连接请求
+            0 => self.handle_accept(),
+            WAKE_KEY => Ok(()),
+            key if key & WATCH_KEY_FLAG != 0 => {
+                self.handler
+                    .on_signal(key & !WATCH_KEY_FLAG, packet.signals);
+                Ok(())
+            }
+            // 客户端消息
+            key => self.handle_client_event(key, packet.signals),
+        }
+    }
+
     pub fn run_once(&self) -> Result<()> {
         let mut packets = [PortPacket::zeroed(); 32];
 
         if let Ok(count) = self.port.try_wait(&mut packets) {
-            for i in 0..count {
-                let packet = &packets[i];
-
-                if packet.key == 0 {
-                    // 接受连接请求
-                    self.handle_accept()?;
-                } else {
-                    // 客户端消息
-                    self.handle_client_event(packet.key, packet.signals)?;
-                }
+            for packet in &packets[..count] {
+                self.dispatch(packet)?;
             }
         }
 
@@ -136,16 +168,8 @@ impl DriverServer {
         while *self.running.lock() {
             let count = self.port.wait_blocking(&mut packets)?;
 
-            for i in 0..count {
-                let packet = &packets[i];
-
-                if packet.key == 0 {
-                    // 接受连接请求
-                    self.handle_accept()?;
-                } else {
-                    // 客户端消息
-                    self.handle_client_event(packet.key, packet.signals)?;
-                }
+            for packet in &packets[..count] {
+                self.dispatch(packet)?;
             }
         }
 
@@ -156,7 +180,7 @@ impl DriverServer {
     pub fn stop(&self) {
         *self.running.lock() = false;
         // 发送一个唤醒事件
-        let _ = self.port.queue_user(u64::MAX, [0; 4]);
+        let _ = self.port.queue_user(WAKE_KEY, [0; 4]);
     }
 
     /// 处理连接请求
@@ -0,0 +1,116 @@
+use core::arch::asm;
+
+#[inline(always)]
+pub unsafe fn syscall0(nr: usize) -> usize {
+    let ret: usize;
+    asm!(
+        "ecall",
+        lateout("a0") ret,
+        in("a7") nr,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+#[inline(always)]
+pub unsafe fn syscall1(nr: usize, a1: usize) -> usize {
+    let ret: usize;
+    asm!(
+        "ecall",
+        inlateout("a0") a1 => ret,
+        in("a7") nr,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+#[inline(always)]
+pub unsafe fn syscall2(nr: usize, a1: usize, a2: usize) -> usize {
+    let ret: usize;
+    asm!(
+        "ecall",
+        inlateout("a0") a1 => ret,
+        in("a1") a2,
+        in("a7") nr,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+#[inline(always)]
+pub unsafe fn syscall3(nr: usize, a1: usize, a2: usize, a3: usize) -> usize {
+    let ret: usize;
+    asm!(
+        "ecall",
+        inlateout("a0") a1 => ret,
+        in("a1") a2,
+        in("a2") a3,
+        in("a7") nr,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+#[inline(always)]
+pub unsafe fn syscall4(nr: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+    let ret: usize;
+    asm!(
+        "ecall",
+        inlateout("a0") a1 => ret,
+        in("a1") a2,
+        in("a2") a3,
+        in("a3") a4,
+        in("a7") nr,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+#[inline(always)]
+pub unsafe fn syscall5(nr: usize, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    let ret: usize;
+    asm!(
+        "ecall",
+        inlateout("a0") a1 => ret,
+        in("a1") a2,
+        in("a2") a3,
+        in("a3") a4,
+        in("a4") a5,
+        in("a7") nr,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+#[inline(always)]
+pub unsafe fn syscall6(
+    nr: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+    a6: usize,
+) -> usize {
+    let ret: usize;
+    asm!(
+        "ecall",
+        inlateout("a0") a1 => ret,
+        in("a1") a2,
+        in("a2") a3,
+        in("a3") a4,
+        in("a4") a5,
+        in("a5") a6,
+        in("a7") nr,
+        options(nostack, preserves_flags)
+    );
+    ret
+}
+
+/// 读取 vDSO 使用的计数器（`time` CSR）
+#[inline(always)]
+pub fn read_counter() -> u64 {
+    let time: u64;
+    unsafe { asm!("rdtime {}", out(reg) time, options(nomem, nostack, preserves_flags)) };
+    time
+}
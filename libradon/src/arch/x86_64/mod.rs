@@ -119,3 +119,9 @@ pub unsafe fn syscall6(
     );
     ret
 }
+
+/// 读取 vDSO 使用的计数器（TSC）
+#[inline(always)]
+pub fn read_counter() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
@@ -0,0 +1,25 @@
+//! 内核转发给 init 的启动选项
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec,
+};
+use radon_kernel::{EINVAL, Error, Result};
+
+use crate::process::get_bootstrap_channel;
+
+pub use radon_kernel::bootopts::*;
+
+/// 从 bootstrap channel 取出内核发送的启动选项，只有内核启动的 init 会收到
+///
+/// 同名选项以最后一次为准。
+pub fn receive() -> Result<BTreeMap<String, String>> {
+    let channel = get_bootstrap_channel()?;
+    let mut buf = vec![0u8; BOOT_OPTIONS_MAX];
+    let result = channel.try_recv(&mut buf)?;
+    let options = parse(&buf[..result.data_len]).ok_or(Error::new(EINVAL))?;
+    Ok(options
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect())
+}
@@ -0,0 +1,133 @@
+use alloc::string::{String, ToString};
+use alloc::vec;
+use log::Level;
+use radon_kernel::{
+    EAGAIN, Result,
+    debuglog::{DEBUGLOG_FROM_NOW, LOG_RECORD_MAX, LogRecordHeader},
+};
+
+use crate::handle::{AsHandle, Handle, OwnedHandle};
+use crate::port::{BindOptions, Port};
+use crate::signal::Signals;
+use crate::syscall::{self, nr, result_from_retval};
+
+/// 一条调试日志记录
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    /// 记录序号，连续递增
+    pub sequence: u64,
+    /// 写入时的单调时间（纳秒）
+    pub timestamp: u64,
+    /// 进程 ID，内核记录为 0
+    pub pid: u64,
+    /// 线程 ID，内核记录为 0
+    pub tid: u64,
+    pub level: Level,
+    /// 这条记录之前因缓冲区被覆盖而丢失的记录数
+    pub dropped: u32,
+    /// 进程名，内核记录为 "kernel"
+    pub name: String,
+    pub message: String,
+}
+
+fn level_from_raw(raw: u32) -> Level {
+    match raw {
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+/// 内核调试日志的读取器
+///
+/// 内核保存最近的内核日志和用户日志，每个读取器有独立的读取位置。创建读取器需要特权。
+pub struct DebugLog {
+    handle: OwnedHandle,
+}
+
+impl DebugLog {
+    /// 创建读取器
+    ///
+    /// 只读取不低于 `max_level` 严重程度的记录；`pid` 不为 None 时只读取该进程的记录；
+    /// `from_now` 为 true 时跳过已有的记录。
+    pub fn create(max_level: Level, pid: Option<usize>, from_now: bool) -> Result<Self> {
+        let options = if from_now { DEBUGLOG_FROM_NOW } else { 0 };
+        let mut handle: u32 = 0;
+
+        let ret = unsafe {
+            syscall::syscall4(
+                nr::SYS_DEBUGLOG_CREATE,
+                max_level as usize,
+                pid.unwrap_or(0),
+                options,
+                &mut handle as *mut _ as usize,
+            )
+        };
+        result_from_retval(ret)?;
+
+        Ok(Self {
+            handle: OwnedHandle::from_raw(handle),
+        })
+    }
+
+    /// 获取句柄
+    pub fn handle(&self) -> Handle {
+        self.handle.handle()
+    }
+
+    /// 读取下一条记录，暂时没有记录时返回 None
+    pub fn read(&self) -> Result<Option<LogRecord>> {
+        let mut buf = vec![0u8; LOG_RECORD_MAX];
+        let ret = unsafe {
+            syscall::syscall3(
+                nr::SYS_DEBUGLOG_READ,
+                self.handle.raw() as usize,
+                buf.as_mut_ptr() as usize,
+                buf.len(),
+            )
+        };
+        let len = match result_from_retval(ret) {
+            Ok(len) => len,
+            Err(err) if err.errno == EAGAIN => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let header_size = size_of::<LogRecordHeader>();
+        let header = unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const LogRecordHeader) };
+        let name_end = header_size + header.name_len as usize;
+        let message_end = (name_end + header.message_len as usize).min(len);
+
+        Ok(Some(LogRecord {
+            sequence: header.sequence,
+            timestamp: header.timestamp,
+            pid: header.pid,
+            tid: header.tid,
+            level: level_from_raw(header.level),
+            dropped: header.dropped,
+            name: String::from_utf8_lossy(&buf[header_size..name_end]).to_string(),
+            message: String::from_utf8_lossy(&buf[name_end..message_end]).to_string(),
+        }))
+    }
+
+    /// 读取下一条记录，没有记录时阻塞等待新记录写入
+    pub fn read_blocking(&self) -> Result<LogRecord> {
+        loop {
+            if let Some(record) = self.read()? {
+                return Ok(record);
+            }
+
+            let port = Port::create()?;
+            // 绑定时已经置位的 READABLE 会立即触发
+            port.bind(0, self, Signals::READABLE, BindOptions::Once)?;
+            port.wait_blocking(&mut [Default::default()])?;
+        }
+    }
+}
+
+impl AsHandle for DebugLog {
+    fn as_handle(&self) -> Handle {
+        self.handle.handle()
+    }
+}
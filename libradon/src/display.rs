@@ -0,0 +1,30 @@
+//! 帧缓冲交接，需要特权
+
+use radon_kernel::Result;
+
+use crate::handle::OwnedHandle;
+use crate::memory::Vmo;
+use crate::syscall::{self, nr, result_from_retval};
+
+pub use radon_kernel::display::*;
+
+/// 取得第 `index` 个帧缓冲的物理 VMO 和几何信息
+///
+/// VMO 使用写合并缓存策略，映射后直接写入像素即可。交出第一个帧缓冲后内核不再在上面输出日志，
+/// 只在 panic 时重新接管。
+pub fn acquire_framebuffer(index: usize) -> Result<(Vmo, FramebufferInfo)> {
+    let mut info = FramebufferInfo::default();
+    let mut handle: u32 = 0;
+
+    let ret = unsafe {
+        syscall::syscall3(
+            nr::SYS_KRES_GET_FRAMEBUFFER,
+            index,
+            &mut info as *mut _ as usize,
+            &mut handle as *mut _ as usize,
+        )
+    };
+    result_from_retval(ret)?;
+
+    Ok((Vmo::from_handle(OwnedHandle::from_raw(handle)), info))
+}
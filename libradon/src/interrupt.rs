@@ -0,0 +1,62 @@
+//! 设备中断对象，创建需要特权
+
+use radon_kernel::Result;
+
+use crate::handle::{AsHandle, Handle, OwnedHandle};
+use crate::syscall::{self, nr, result_from_retval};
+
+/// 设备中断
+///
+/// 中断到达时对象的 `SIGNALED` 置位，同时内核屏蔽该中断。处理完设备后调用 [`Interrupt::ack`]
+/// 清除信号并解除屏蔽。对象销毁时中断重新被屏蔽。
+pub struct Interrupt {
+    handle: OwnedHandle,
+    irq: u32,
+}
+
+impl Interrupt {
+    /// 绑定设备中断 `irq`，中断已被内核或其他进程使用时返回 EBUSY
+    pub fn create(irq: u32) -> Result<Self> {
+        let mut handle: u32 = 0;
+
+        let ret = unsafe {
+            syscall::syscall2(
+                nr::SYS_INTERRUPT_CREATE,
+                irq as usize,
+                &mut handle as *mut _ as usize,
+            )
+        };
+        result_from_retval(ret)?;
+
+        Ok(Self {
+            handle: OwnedHandle::from_raw(handle),
+            irq,
+        })
+    }
+
+    pub fn irq(&self) -> u32 {
+        self.irq
+    }
+
+    /// 获取句柄
+    pub fn handle(&self) -> Handle {
+        self.handle.handle()
+    }
+
+    /// 应答中断
+    pub fn ack(&self) -> Result<()> {
+        ack(self.handle())
+    }
+}
+
+/// 应答句柄 `handle` 指向的中断对象
+pub fn ack(handle: Handle) -> Result<()> {
+    let ret = unsafe { syscall::syscall1(nr::SYS_INTERRUPT_ACK, handle.raw() as usize) };
+    result_from_retval(ret).map(|_| ())
+}
+
+impl AsHandle for Interrupt {
+    fn as_handle(&self) -> Handle {
+        self.handle.handle()
+    }
+}
@@ -0,0 +1,34 @@
+//! x86 I/O 端口访问
+
+use core::arch::asm;
+
+use radon_kernel::Result;
+
+use crate::syscall::{self, nr, result_from_retval};
+
+/// 请求直接访问 `[base, base + count)` 的 I/O 端口，需要特权
+///
+/// 范围与内核的串口控制台重叠时，内核不再在串口上输出日志。
+/// 权限立即对调用线程生效，同一进程的其他线程在下一次被调度时生效。
+pub fn request(base: u16, count: u16) -> Result<()> {
+    let ret = unsafe { syscall::syscall2(nr::SYS_IOPORT_REQUEST, base as usize, count as usize) };
+    result_from_retval(ret).map(|_| ())
+}
+
+/// 读一个字节，需要先用 [`request`] 取得端口
+#[inline]
+pub unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    unsafe {
+        asm!("in al, dx", in("dx") port, out("al") value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// 写一个字节，需要先用 [`request`] 取得端口
+#[inline]
+pub unsafe fn outb(port: u16, value: u8) {
+    unsafe {
+        asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+    }
+}
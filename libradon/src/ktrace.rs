@@ -0,0 +1,69 @@
+//! 内核事件跟踪的控制接口，需要特权
+
+use alloc::vec::Vec;
+use radon_kernel::Result;
+
+use crate::syscall::{self, nr, result_from_retval};
+
+pub use radon_kernel::ktrace::*;
+
+fn control(action: usize, arg: usize, buf: usize, len: usize) -> Result<usize> {
+    let ret = unsafe { syscall::syscall4(nr::SYS_KTRACE_CONTROL, action, arg, buf, len) };
+    result_from_retval(ret)
+}
+
+/// 开始记录 `groups`（`KTRACE_GROUP_*` 的组合）中的事件
+pub fn start(groups: u32) -> Result<()> {
+    control(KTRACE_ACTION_START, groups as usize, 0, 0).map(|_| ())
+}
+
+/// 停止记录，已有的记录保留
+pub fn stop() -> Result<()> {
+    control(KTRACE_ACTION_STOP, 0, 0, 0).map(|_| ())
+}
+
+/// 清空所有 CPU 的缓冲区
+pub fn rewind() -> Result<()> {
+    control(KTRACE_ACTION_REWIND, 0, 0, 0).map(|_| ())
+}
+
+/// 取出记录填入 `records`，返回填入的条数，0 表示已经取空
+pub fn read(records: &mut [KtraceRecord]) -> Result<usize> {
+    let len = control(
+        KTRACE_ACTION_READ,
+        0,
+        records.as_mut_ptr() as usize,
+        size_of_val(records),
+    )?;
+    Ok(len / size_of::<KtraceRecord>())
+}
+
+/// 取出所有记录
+pub fn drain() -> Result<Vec<KtraceRecord>> {
+    let mut out = Vec::new();
+    let mut chunk = [KtraceRecord::default(); 64];
+    loop {
+        let count = read(&mut chunk)?;
+        if count == 0 {
+            return Ok(out);
+        }
+        out.extend_from_slice(&chunk[..count]);
+    }
+}
+
+/// 把记录转换为导出格式：连续的 48 字节小端记录，字段顺序与 [`KtraceRecord`] 相同
+pub fn to_bytes(records: &[KtraceRecord]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(size_of_val(records));
+    for record in records {
+        out.extend_from_slice(&record.timestamp.to_le_bytes());
+        out.extend_from_slice(&record.pid.to_le_bytes());
+        out.extend_from_slice(&record.tid.to_le_bytes());
+        out.extend_from_slice(&record.event.to_le_bytes());
+        out.extend_from_slice(&record.cpu.to_le_bytes());
+        out.extend_from_slice(&record.reserved.to_le_bytes());
+        for arg in record.args {
+            out.extend_from_slice(&arg.to_le_bytes());
+        }
+    }
+    out
+}
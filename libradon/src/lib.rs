@@ -15,12 +15,21 @@ pub use self::arch::{syscall0, syscall1, syscall2, syscall3, syscall4, syscall5,
 pub use log::{debug, error, info, trace, warn};
 
 mod arch;
+pub mod bootopts;
 pub mod channel;
+pub mod debuglog;
+pub mod display;
 pub mod handle;
+pub mod interrupt;
+#[cfg(target_arch = "x86_64")]
+pub mod ioport;
+pub mod ktrace;
 pub mod logger;
 pub mod memory;
 pub mod port;
 pub mod process;
+pub mod profile;
+pub mod serial;
 pub mod signal;
 pub mod syscall;
 
@@ -3,7 +3,7 @@ use core::fmt::{self, Write};
 use alloc::format;
 use log::{Level, Record, set_logger, set_max_level};
 use log::{LevelFilter, Log, Metadata};
-use radon_kernel::nr::SYS_LOG;
+use radon_kernel::{debuglog::LOG_LEVEL_RAW, nr::SYS_LOG};
 use spin::Mutex;
 
 pub fn init() {
@@ -15,11 +15,16 @@ pub struct UserLoggerWriter;
 
 impl Write for UserLoggerWriter {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        unsafe { crate::syscall::syscall2(SYS_LOG, s.as_ptr() as usize, s.len()) };
+        write_log(LOG_LEVEL_RAW, s);
         Ok(())
     }
 }
 
+/// 把一条日志交给内核，由内核加上级别和进程名后输出，并保存到调试日志
+fn write_log(level: u32, s: &str) {
+    unsafe { crate::syscall::syscall3(SYS_LOG, s.as_ptr() as usize, s.len(), level as usize) };
+}
+
 pub static LOCKED_KERNEL_WRITER: Mutex<UserLoggerWriter> = Mutex::new(UserLoggerWriter);
 
 #[doc(hidden)]
@@ -47,30 +52,14 @@ struct Logger;
 
 impl Logger {
     fn log_message(&self, record: &Record, with_location: bool) {
-        let color = match record.level() {
-            Level::Error => "31",
-            Level::Warn => "33",
-            Level::Info => "32",
-            Level::Debug => "34",
-            Level::Trace => "35",
-        };
-
-        if with_location {
+        let message = if with_location {
             let file = record.file().unwrap();
             let line = record.line().unwrap();
-            crate::println!(
-                "[{}] {}{}",
-                format_args!("\x1b[{}m{}\x1b[0m", color, record.level().as_str()),
-                record.args(),
-                format_args!(", {}:{}", file, line)
-            );
+            format!("{}, {}:{}", record.args(), file, line)
         } else {
-            crate::println!(
-                "[{}] {}",
-                format_args!("\x1b[{}m{}\x1b[0m", color, record.level().as_str()),
-                record.args(),
-            );
-        }
+            format!("{}", record.args())
+        };
+        write_log(record.level() as u32, &message);
     }
 }
 
@@ -1,5 +1,8 @@
 use crate::handle::{Handle, OwnedHandle};
+use crate::signal::Signals;
 use crate::syscall::{self, nr, result_from_retval};
+use alloc::vec;
+use alloc::vec::Vec;
 use bitflags::bitflags;
 use radon_kernel::Result;
 
@@ -29,6 +32,67 @@ bitflags! {
     }
 }
 
+/// VMO 范围操作
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmoOp {
+    /// 立即提交范围内的页面
+    Commit = 1,
+    /// 取消提交，释放物理页面
+    Decommit = 2,
+    /// 清零
+    Zero = 3,
+    /// 查询每个页面的状态
+    Query = 4,
+    /// 锁定可丢弃 VMO
+    Lock = 5,
+    /// 解锁可丢弃 VMO
+    Unlock = 6,
+}
+
+/// 映射时使用的缓存策略
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// 普通内存（写回）
+    Cached = 0,
+    /// 不缓存
+    Uncached = 1,
+    /// 严格不缓存，用于设备寄存器
+    UncachedDevice = 2,
+    /// 写合并，用于帧缓冲
+    WriteCombining = 3,
+}
+
+/// 页面未提交（`Vmo::query` 的结果）
+pub const VMO_PAGE_UNCOMMITTED: u8 = 0;
+/// 页面已提交
+pub const VMO_PAGE_COMMITTED: u8 = 1;
+/// 页面与父 VMO 共享（写时复制）
+pub const VMO_PAGE_COPY_ON_WRITE: u8 = 2;
+
+/// 物理页面段（pin 的结果）
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhysRun {
+    /// 物理地址
+    pub phys: usize,
+    /// 长度（字节）
+    pub len: usize,
+}
+
+/// 固定内存令牌（`Vmo::pin` 的结果），释放时解除 pin
+pub struct Pmt {
+    handle: OwnedHandle,
+}
+
+impl Pmt {
+    /// 获取句柄
+    pub fn handle(&self) -> Handle {
+        self.handle.handle()
+    }
+}
+
 /// Virtual Memory Object
 pub struct Vmo {
     handle: OwnedHandle,
@@ -145,11 +209,215 @@ impl Vmo {
         })
     }
 
+    /// Pin 住一段范围，将物理页面段写入 `runs`，返回固定内存令牌和段数
+    ///
+    /// 在令牌释放之前这些页面不会被释放或回收，可直接用于 DMA。
+    pub fn pin(&self, offset: usize, size: usize, runs: &mut [PhysRun]) -> Result<(Pmt, usize)> {
+        #[repr(C)]
+        struct Args {
+            vmo_handle: u32,
+            offset: usize,
+            size: usize,
+            runs: usize,
+            runs_cap: usize,
+            pmt_out: usize,
+        }
+
+        let mut handle: u32 = 0;
+        let args = Args {
+            vmo_handle: self.handle.raw(),
+            offset,
+            size,
+            runs: runs.as_mut_ptr() as usize,
+            runs_cap: runs.len(),
+            pmt_out: &mut handle as *mut _ as usize,
+        };
+
+        let ret = unsafe { syscall::syscall1(nr::SYS_VMO_PIN, &args as *const _ as usize) };
+        let count = result_from_retval(ret)?;
+
+        Ok((
+            Pmt {
+                handle: OwnedHandle::from_raw(handle),
+            },
+            count,
+        ))
+    }
+
+    /// 对一段范围执行操作
+    pub fn op_range(&self, op: VmoOp, offset: usize, size: usize, buf: &mut [u8]) -> Result<usize> {
+        let ret = unsafe {
+            syscall::syscall6(
+                nr::SYS_VMO_OP_RANGE,
+                self.handle.raw() as usize,
+                op as usize,
+                offset,
+                size,
+                buf.as_mut_ptr() as usize,
+                buf.len(),
+            )
+        };
+        result_from_retval(ret)
+    }
+
+    /// 立即提交范围内的页面
+    pub fn commit(&self, offset: usize, size: usize) -> Result<()> {
+        self.op_range(VmoOp::Commit, offset, size, &mut [])?;
+        Ok(())
+    }
+
+    /// 取消提交范围内的页面，物理内存归还给系统
+    pub fn decommit(&self, offset: usize, size: usize) -> Result<()> {
+        self.op_range(VmoOp::Decommit, offset, size, &mut [])?;
+        Ok(())
+    }
+
+    /// 清零一段范围
+    pub fn zero(&self, offset: usize, size: usize) -> Result<()> {
+        self.op_range(VmoOp::Zero, offset, size, &mut [])?;
+        Ok(())
+    }
+
+    /// 查询每个页面的状态（`VMO_PAGE_*`），返回页数
+    pub fn query(&self, offset: usize, size: usize, states: &mut [u8]) -> Result<usize> {
+        self.op_range(VmoOp::Query, offset, size, states)
+    }
+
+    /// 锁定可丢弃 VMO，返回内容是否在上次解锁后被回收
+    pub fn lock(&self) -> Result<bool> {
+        let discarded = self.op_range(VmoOp::Lock, 0, 0, &mut [])?;
+        Ok(discarded != 0)
+    }
+
+    /// 解锁可丢弃 VMO
+    pub fn unlock(&self) -> Result<()> {
+        self.op_range(VmoOp::Unlock, 0, 0, &mut [])?;
+        Ok(())
+    }
+
+    /// 设置缓存策略，必须在映射之前调用
+    pub fn set_cache_policy(&self, policy: CachePolicy) -> Result<()> {
+        let ret = unsafe {
+            syscall::syscall2(
+                nr::SYS_VMO_SET_CACHE_POLICY,
+                self.handle.raw() as usize,
+                policy as usize,
+            )
+        };
+        result_from_retval(ret)?;
+        Ok(())
+    }
+
+    /// 已提交且归该 VMO 所有的字节数
+    pub fn committed_size(&self) -> Result<usize> {
+        let ret =
+            unsafe { syscall::syscall1(nr::SYS_VMO_GET_COMMITTED, self.handle.raw() as usize) };
+        result_from_retval(ret)
+    }
+
     pub fn with_nodrop(&mut self, nodrop: bool) {
         self.handle.with_nodrop(nodrop);
     }
 }
 
+/// 内核范围的内存统计（字节）
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KernelMemoryInfo {
+    /// 物理内存总量
+    pub total: usize,
+    /// 空闲物理内存
+    pub free: usize,
+    /// 内核堆当前的大小（不含 slab）
+    pub kernel_heap: usize,
+    /// 内核堆中已使用的部分
+    pub kernel_heap_used: usize,
+    /// slab 分配器占用的页面
+    pub kernel_slab: usize,
+    /// 用户地址空间的页表
+    pub page_tables: usize,
+    /// 线程的内核栈
+    pub kernel_stacks: usize,
+    /// VMO 已提交的页面
+    pub user: usize,
+}
+
+/// 获取内核范围的内存统计
+pub fn kernel_memory_info() -> Result<KernelMemoryInfo> {
+    let mut info = KernelMemoryInfo::default();
+
+    let ret =
+        unsafe { syscall::syscall1(nr::SYS_KRES_GET_MEMORY_INFO, &mut info as *mut _ as usize) };
+    result_from_retval(ret)?;
+
+    Ok(info)
+}
+
+/// 内核 slab 分配器一个大小类的统计
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlabInfo {
+    /// 对象大小（字节）
+    pub object_size: usize,
+    /// 每个 slab 的大小（字节）
+    pub slab_size: usize,
+    /// slab 总数
+    pub slabs: usize,
+    /// 完全空闲的 slab 数
+    pub empty_slabs: usize,
+    /// 所有 slab 能容纳的对象总数
+    pub objects: usize,
+    /// 正在使用的对象数
+    pub in_use: usize,
+    /// 缓存在各 CPU magazine 中的对象数
+    pub cached: usize,
+    /// 累计分配次数
+    pub allocs: u64,
+    /// 累计释放次数
+    pub frees: u64,
+}
+
+/// 获取内核 slab 分配器各大小类的统计
+pub fn kernel_slab_info() -> Result<Vec<SlabInfo>> {
+    // 先查询大小类的数量
+    let ret = unsafe { syscall::syscall2(nr::SYS_KRES_GET_SLAB_INFO, 0, 0) };
+    let count = result_from_retval(ret)?;
+
+    let mut infos = vec![SlabInfo::default(); count];
+    let ret = unsafe {
+        syscall::syscall2(
+            nr::SYS_KRES_GET_SLAB_INFO,
+            infos.as_mut_ptr() as usize,
+            infos.len(),
+        )
+    };
+    result_from_retval(ret)?;
+
+    Ok(infos)
+}
+
+/// 内存紧张：空闲内存低于低水位，应主动收缩缓存
+pub const MEMORY_PRESSURE_WARNING: Signals = Signals::SIGNALED;
+/// 内存严重不足
+pub const MEMORY_PRESSURE_CRITICAL: Signals = Signals::USER_0;
+
+/// 获取内核的内存压力事件
+///
+/// 可以将其绑定到 Port 上，等待 `MEMORY_PRESSURE_*` 信号。
+pub fn memory_pressure_event() -> Result<OwnedHandle> {
+    let mut handle: u32 = 0;
+
+    let ret = unsafe {
+        syscall::syscall1(
+            nr::SYS_KRES_GET_MEMORY_PRESSURE_EVENT,
+            &mut handle as *mut _ as usize,
+        )
+    };
+    result_from_retval(ret)?;
+
+    Ok(OwnedHandle::from_raw(handle))
+}
+
 /// 映射 VMO 到当前进程地址空间
 pub fn map_vmo(vmo: &Vmo, vmo_offset: usize, size: usize, flags: MappingFlags) -> Result<*mut u8> {
     #[repr(C)]
@@ -307,6 +575,56 @@ pub fn map_vmo_at_in_vmar(
     Ok(addr as *mut u8)
 }
 
+/// 在 VMAR 中分配子 VMAR，返回子 VMAR 句柄和基地址
+///
+/// `vmar_handle` 为无效句柄时使用进程根 VMAR；`offset` 为 None 时自动选择位置。
+pub fn allocate_vmar(
+    vmar_handle: Handle,
+    offset: Option<usize>,
+    size: usize,
+) -> Result<(OwnedHandle, *mut u8)> {
+    #[repr(C)]
+    struct Args {
+        vmar_handle: u32,
+        flags: u32,
+        offset: usize,
+        size: usize,
+    }
+
+    let args = Args {
+        vmar_handle: vmar_handle.raw(),
+        flags: if offset.is_some() {
+            MappingFlags::SPECIFIC.bits()
+        } else {
+            0
+        },
+        offset: offset.unwrap_or(0),
+        size,
+    };
+
+    let mut handle: u32 = 0;
+    let mut addr: usize = 0;
+
+    let ret = unsafe {
+        syscall::syscall3(
+            nr::SYS_VMAR_ALLOCATE,
+            &args as *const _ as usize,
+            &mut handle as *mut _ as usize,
+            &mut addr as *mut _ as usize,
+        )
+    };
+    result_from_retval(ret)?;
+
+    Ok((OwnedHandle::from_raw(handle), addr as *mut u8))
+}
+
+/// 销毁 VMAR，解除其中的所有映射
+pub fn destroy_vmar(vmar_handle: Handle) -> Result<()> {
+    let ret = unsafe { syscall::syscall1(nr::SYS_VMAR_DESTROY, vmar_handle.raw() as usize) };
+    result_from_retval(ret)?;
+    Ok(())
+}
+
 /// 解除映射
 pub fn unmap(addr: *mut u8, size: usize) -> Result<()> {
     let ret = unsafe {
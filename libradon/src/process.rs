@@ -11,6 +11,17 @@ struct ProcessCreateOptions {
     name_ptr: usize,
     name_len: usize,
     create_bootstrap: bool,
+    allow_write_execute: bool,
+    privileged: bool,
+    handles_ptr: usize,
+    handles_count: usize,
+}
+
+/// 进程初始句柄
+#[repr(C)]
+struct ProcessInitHandle {
+    handle: u32,
+    rights: u32,
 }
 
 /// 进程创建结果
@@ -31,6 +42,43 @@ struct ThreadCreateOptions {
     arg: usize,
 }
 
+/// 进程内存用量（字节）
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessMemoryInfo {
+    /// VMO 已提交的页面
+    pub vmo: usize,
+    /// 页表
+    pub page_tables: usize,
+    /// 线程的内核栈
+    pub kernel_stacks: usize,
+    /// 包括子孙进程在内的总用量
+    pub total: usize,
+    /// 内存限额（0 表示不限）
+    pub limit: usize,
+}
+
+/// 查询进程的内存用量（句柄为 0 表示当前进程）
+fn memory_info(process_handle: u32) -> Result<ProcessMemoryInfo> {
+    let mut info = ProcessMemoryInfo::default();
+
+    let ret = unsafe {
+        syscall::syscall2(
+            nr::SYS_PROCESS_GET_MEMORY_INFO,
+            process_handle as usize,
+            &mut info as *mut _ as usize,
+        )
+    };
+    result_from_retval(ret)?;
+
+    Ok(info)
+}
+
+/// 查询当前进程的内存用量
+pub fn current_memory_info() -> Result<ProcessMemoryInfo> {
+    memory_info(0)
+}
+
 /// 进程句柄
 pub struct Process {
     handle: OwnedHandle,
@@ -135,12 +183,34 @@ impl Process {
 
         Ok(exit_code)
     }
+
+    /// 查询进程的内存用量
+    pub fn memory_info(&self) -> Result<ProcessMemoryInfo> {
+        memory_info(self.handle.raw())
+    }
+
+    /// 设置内存限额（字节，`None` 表示不限）
+    ///
+    /// 限额同时约束该进程的子孙进程，超出后分配返回 `ENOMEM`。需要调用者拥有特权。
+    pub fn set_memory_limit(&self, limit: Option<usize>) -> Result<()> {
+        let ret = unsafe {
+            syscall::syscall2(
+                nr::SYS_PROCESS_SET_MEMORY_LIMIT,
+                self.handle.raw() as usize,
+                limit.unwrap_or(0),
+            )
+        };
+        result_from_retval(ret)?;
+        Ok(())
+    }
 }
 
 /// 进程构建器
 pub struct ProcessBuilder {
     name: Vec<u8>,
     create_bootstrap: bool,
+    allow_write_execute: bool,
+    privileged: bool,
     init_handles: Vec<(Handle, Rights)>,
 }
 
@@ -149,6 +219,8 @@ impl ProcessBuilder {
         Self {
             name: name.as_bytes().to_vec(),
             create_bootstrap: true,
+            allow_write_execute: false,
+            privileged: false,
             init_handles: Vec::new(),
         }
     }
@@ -159,7 +231,21 @@ impl ProcessBuilder {
         self
     }
 
+    /// 是否允许同时可写可执行的映射（JIT 等场景），默认不允许
+    pub fn allow_write_execute(mut self, allow: bool) -> Self {
+        self.allow_write_execute = allow;
+        self
+    }
+
+    /// 是否授予特权（设置时钟等），只有当前进程本身拥有特权时才生效，默认不授予
+    pub fn privileged(mut self, privileged: bool) -> Self {
+        self.privileged = privileged;
+        self
+    }
+
     /// 添加初始句柄
+    ///
+    /// 句柄被复制到子进程（需要 TRANSFER 权限），子进程按添加顺序用 `get_init_handle(0)`、`get_init_handle(1)` ... 取得。
     pub fn add_handle(mut self, handle: Handle, rights: Rights) -> Self {
         self.init_handles.push((handle, rights));
         self
@@ -167,10 +253,23 @@ impl ProcessBuilder {
 
     /// 创建进程（不启动）
     pub fn build(self) -> Result<Process> {
+        let handles: Vec<ProcessInitHandle> = self
+            .init_handles
+            .iter()
+            .map(|(handle, rights)| ProcessInitHandle {
+                handle: handle.raw(),
+                rights: rights.bits(),
+            })
+            .collect();
+
         let options = ProcessCreateOptions {
             name_ptr: self.name.as_ptr() as usize,
             name_len: self.name.len(),
             create_bootstrap: self.create_bootstrap,
+            allow_write_execute: self.allow_write_execute,
+            privileged: self.privileged,
+            handles_ptr: handles.as_ptr() as usize,
+            handles_count: handles.len(),
         };
 
         let mut result = ProcessCreateResult {
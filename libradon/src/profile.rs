@@ -0,0 +1,69 @@
+//! 采样分析器的控制接口，需要特权
+
+use alloc::vec::Vec;
+use radon_kernel::Result;
+
+use crate::syscall::{self, nr, result_from_retval};
+
+pub use radon_kernel::profile::*;
+
+fn control(action: usize, arg: usize, buf: usize, len: usize) -> Result<usize> {
+    let ret = unsafe { syscall::syscall4(nr::SYS_PROFILE_CONTROL, action, arg, buf, len) };
+    result_from_retval(ret)
+}
+
+/// 开始采样 `mode`（`PROFILE_MODE_*` 的组合）范围内的样本
+pub fn start(mode: u32) -> Result<()> {
+    control(PROFILE_ACTION_START, mode as usize, 0, 0).map(|_| ())
+}
+
+/// 停止采样，已有的样本保留
+pub fn stop() -> Result<()> {
+    control(PROFILE_ACTION_STOP, 0, 0, 0).map(|_| ())
+}
+
+/// 清空所有 CPU 的缓冲区
+pub fn rewind() -> Result<()> {
+    control(PROFILE_ACTION_REWIND, 0, 0, 0).map(|_| ())
+}
+
+/// 取出样本填入 `samples`，返回填入的个数，0 表示已经取空
+pub fn read(samples: &mut [ProfileSample]) -> Result<usize> {
+    let len = control(
+        PROFILE_ACTION_READ,
+        0,
+        samples.as_mut_ptr() as usize,
+        size_of_val(samples),
+    )?;
+    Ok(len / size_of::<ProfileSample>())
+}
+
+/// 取出所有样本
+pub fn drain() -> Result<Vec<ProfileSample>> {
+    let mut out = Vec::new();
+    let mut chunk = [ProfileSample::default(); 16];
+    loop {
+        let count = read(&mut chunk)?;
+        if count == 0 {
+            return Ok(out);
+        }
+        out.extend_from_slice(&chunk[..count]);
+    }
+}
+
+/// 把样本转换为导出格式：连续的 152 字节小端记录，字段顺序与 [`ProfileSample`] 相同
+pub fn to_bytes(samples: &[ProfileSample]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(size_of_val(samples));
+    for sample in samples {
+        out.extend_from_slice(&sample.timestamp.to_le_bytes());
+        out.extend_from_slice(&sample.pid.to_le_bytes());
+        out.extend_from_slice(&sample.tid.to_le_bytes());
+        out.extend_from_slice(&sample.cpu.to_le_bytes());
+        out.extend_from_slice(&sample.flags.to_le_bytes());
+        out.extend_from_slice(&sample.depth.to_le_bytes());
+        for frame in sample.frames {
+            out.extend_from_slice(&frame.to_le_bytes());
+        }
+    }
+    out
+}
@@ -0,0 +1,22 @@
+//! 串口控制台的位置
+
+use radon_kernel::Result;
+
+use crate::syscall::{self, nr, result_from_retval};
+
+pub use radon_kernel::serial::*;
+
+/// 内核选定的串口控制台（命令行的 `console=` 或 ACPI SPCR 表）
+pub fn console_info() -> Result<SerialConsoleInfo> {
+    let mut info = SerialConsoleInfo::default();
+
+    let ret = unsafe {
+        syscall::syscall1(
+            nr::SYS_KRES_GET_SERIAL_CONSOLE,
+            &mut info as *mut _ as usize,
+        )
+    };
+    result_from_retval(ret)?;
+
+    Ok(info)
+}
@@ -1,10 +1,8 @@
-use core::arch::asm;
-
 pub mod nr {
     pub use radon_kernel::nr::*;
 }
 
-use radon_kernel::{Error, Result};
+use radon_kernel::{Error, Result, layout, vdso::VdsoData};
 
 pub use crate::arch::*;
 
@@ -22,8 +20,9 @@ pub fn exit(code: i32) -> ! {
     unsafe {
         crate::arch::syscall1(nr::SYS_EXIT, code as usize);
     }
+    // SYS_EXIT 不会返回
     loop {
-        unsafe { asm!("hlt") };
+        core::hint::spin_loop();
     }
 }
 
@@ -33,11 +32,70 @@ pub fn yield_now() {
     }
 }
 
+/// 获取单调时间（纳秒）
+///
+/// 内核提供了可用的计数器时直接在用户态读取 vDSO 换算，否则回退到系统调用。
 pub fn clock_get() -> Result<u64> {
+    let vdso = unsafe { core::ptr::read_volatile(layout::VDSO_DATA_ADDR as *const VdsoData) };
+    if vdso.version == VdsoData::VERSION && vdso.counter_usable != 0 {
+        return Ok(vdso.counter_to_nanos(read_counter()));
+    }
+
     let ret = unsafe { syscall0(nr::SYS_CLOCK_GET) };
     result_from_retval(ret).map(|v| v as u64)
 }
 
+/// 可以通过 [`clock_read`] 读取的时钟
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockId {
+    /// 单调时间，从启动开始计算
+    Monotonic,
+    /// 墙上时间，从 Unix 纪元开始计算（UTC）
+    Realtime,
+}
+
+impl ClockId {
+    fn raw(self) -> usize {
+        match self {
+            ClockId::Monotonic => nr::CLOCK_MONOTONIC,
+            ClockId::Realtime => nr::CLOCK_REALTIME,
+        }
+    }
+}
+
+/// 读取指定时钟（纳秒）
+///
+/// 单调时间走 [`clock_get`] 的快速路径；墙上时间在内核没有可用的 RTC 时返回 ENODEV。
+pub fn clock_read(clock: ClockId) -> Result<u64> {
+    if clock == ClockId::Monotonic {
+        return clock_get();
+    }
+    let ret = unsafe { syscall1(nr::SYS_CLOCK_READ, clock.raw()) };
+    result_from_retval(ret).map(|v| v as u64)
+}
+
+/// 设置 RTC 相对 UTC 的偏移（秒），RTC 按本地时间保存时使用，需要特权
+pub fn clock_set_utc_offset(offset: i64) -> Result<()> {
+    let ret = unsafe { syscall1(nr::SYS_CLOCK_SET_UTC_OFFSET, offset as isize as usize) };
+    result_from_retval(ret).map(|_| ())
+}
+
+/// 用内核 CSPRNG 生成的随机字节填充 `buf`
+pub fn getrandom(buf: &mut [u8]) -> Result<()> {
+    if buf.is_empty() {
+        return Ok(());
+    }
+    let ret = unsafe { syscall2(nr::SYS_CPRNG_DRAW, buf.as_mut_ptr() as usize, buf.len()) };
+    result_from_retval(ret).map(|_| ())
+}
+
+/// 生成一个 64 位随机数
+pub fn random_u64() -> Result<u64> {
+    let mut buf = [0u8; 8];
+    getrandom(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
 pub fn nanosleep(ns: u64) -> Result<()> {
     let ret = unsafe { syscall1(nr::SYS_NANOSLEEP, ns as usize) };
     result_from_retval(ret).map(|_| ())
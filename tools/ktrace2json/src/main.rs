@@ -0,0 +1,299 @@
+//! 把 ktrace 导出的二进制记录转换为 Chrome trace JSON，可以在 chrome://tracing 或 Perfetto 中查看
+//!
+//! 输入为连续的 48 字节小端记录，格式与内核 `ktrace::KtraceRecord` 相同。
+//!
+//! 用法：`ktrace2json <输入文件> [输出文件]`，不指定输出文件时写到标准输出。
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::{env, fs, io, process};
+
+const RECORD_SIZE: usize = 48;
+
+const EVENT_SYSCALL_EXIT: u16 = 2;
+const EVENT_CONTEXT_SWITCH: u16 = 3;
+const EVENT_IRQ_EXIT: u16 = 5;
+const EVENT_PORT_QUEUE: u16 = 6;
+const EVENT_CHANNEL_SEND: u16 = 7;
+const EVENT_CHANNEL_RECV: u16 = 8;
+const EVENT_DROPPED: u16 = 9;
+
+/// CPU 轨道的线程号从这里开始，避免和真实的 TID 冲突
+const CPU_TRACK_BASE: u64 = 1 << 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Record {
+    timestamp: u64,
+    pid: u32,
+    tid: u32,
+    event: u16,
+    cpu: u16,
+    args: [u64; 3],
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+/// 解析记录并按时间戳排序，末尾不完整的记录被忽略
+fn parse(bytes: &[u8]) -> Vec<Record> {
+    let mut records: Vec<Record> = bytes
+        .chunks_exact(RECORD_SIZE)
+        .map(|chunk| Record {
+            timestamp: u64_at(chunk, 0),
+            pid: u32_at(chunk, 8),
+            tid: u32_at(chunk, 12),
+            event: u16_at(chunk, 16),
+            cpu: u16_at(chunk, 18),
+            args: [u64_at(chunk, 24), u64_at(chunk, 32), u64_at(chunk, 40)],
+        })
+        .collect();
+    records.sort_by_key(|record| record.timestamp);
+    records
+}
+
+/// 纳秒转换为 Chrome trace 使用的微秒
+fn micros(ns: u64) -> String {
+    format!("{}.{:03}", ns / 1000, ns % 1000)
+}
+
+fn cpu_track(cpu: u16) -> u64 {
+    CPU_TRACK_BASE + cpu as u64
+}
+
+struct Writer {
+    events: Vec<String>,
+}
+
+impl Writer {
+    fn complete(&mut self, name: &str, pid: u64, tid: u64, start: u64, duration: u64, args: &str) {
+        self.events.push(format!(
+            r#"{{"name":"{}","ph":"X","pid":{},"tid":{},"ts":{},"dur":{},"args":{{{}}}}}"#,
+            name,
+            pid,
+            tid,
+            micros(start),
+            micros(duration),
+            args
+        ));
+    }
+
+    fn instant(&mut self, name: &str, pid: u64, tid: u64, ts: u64, scope: &str, args: &str) {
+        self.events.push(format!(
+            r#"{{"name":"{}","ph":"i","s":"{}","pid":{},"tid":{},"ts":{},"args":{{{}}}}}"#,
+            name,
+            scope,
+            pid,
+            tid,
+            micros(ts),
+            args
+        ));
+    }
+
+    fn thread_name(&mut self, pid: u64, tid: u64, name: &str) {
+        self.events.push(format!(
+            r#"{{"name":"thread_name","ph":"M","pid":{},"tid":{},"args":{{"name":"{}"}}}}"#,
+            pid, tid, name
+        ));
+    }
+}
+
+/// 转换为 Chrome trace JSON
+///
+/// 系统调用和中断用返回记录中的耗时生成完整事件，不依赖进入记录是否还在缓冲区中；
+/// 每个 CPU 有一条轨道显示正在运行的线程和中断；IPC 和丢失记录为瞬时事件。
+fn convert(records: &[Record]) -> String {
+    let mut writer = Writer { events: Vec::new() };
+    let mut cpus = BTreeSet::new();
+    // 每个 CPU 上一次切换的时间和切入的线程
+    let mut running: BTreeMap<u16, (u64, u64, u64)> = BTreeMap::new();
+
+    for record in records {
+        let pid = record.pid as u64;
+        let tid = record.tid as u64;
+        let [a0, a1, a2] = record.args;
+        cpus.insert(record.cpu);
+
+        match record.event {
+            EVENT_SYSCALL_EXIT => writer.complete(
+                &format!("syscall {:#x}", a0),
+                pid,
+                tid,
+                record.timestamp.saturating_sub(a1),
+                a1,
+                &format!(r#""ret":{}"#, a2 as i64),
+            ),
+            EVENT_IRQ_EXIT => writer.complete(
+                &format!("irq {:#x}", a0),
+                0,
+                cpu_track(record.cpu),
+                record.timestamp.saturating_sub(a1),
+                a1,
+                "",
+            ),
+            EVENT_CONTEXT_SWITCH => {
+                if let Some((start, pid, tid)) =
+                    running.insert(record.cpu, (record.timestamp, a2, a1))
+                {
+                    writer.complete(
+                        &format!("{}:{}", pid, tid),
+                        0,
+                        cpu_track(record.cpu),
+                        start,
+                        record.timestamp - start,
+                        "",
+                    );
+                }
+                writer.instant(
+                    "context switch",
+                    0,
+                    cpu_track(record.cpu),
+                    record.timestamp,
+                    "t",
+                    &format!(r#""prev_tid":{},"next_tid":{},"next_pid":{}"#, a0, a1, a2),
+                );
+            }
+            EVENT_PORT_QUEUE => writer.instant(
+                "port queue",
+                pid,
+                tid,
+                record.timestamp,
+                "t",
+                &format!(r#""port":"{:#x}","key":{}"#, a0, a1),
+            ),
+            EVENT_CHANNEL_SEND | EVENT_CHANNEL_RECV => writer.instant(
+                if record.event == EVENT_CHANNEL_SEND {
+                    "channel send"
+                } else {
+                    "channel recv"
+                },
+                pid,
+                tid,
+                record.timestamp,
+                "t",
+                &format!(r#""channel":"{:#x}","bytes":{},"handles":{}"#, a0, a1, a2),
+            ),
+            EVENT_DROPPED => writer.instant(
+                "dropped",
+                0,
+                cpu_track(record.cpu),
+                record.timestamp,
+                "g",
+                &format!(r#""count":{}"#, a0),
+            ),
+            // 进入记录不单独转换，完整事件由返回记录生成
+            _ => {}
+        }
+    }
+
+    for cpu in cpus {
+        writer.thread_name(0, cpu_track(cpu), &format!("cpu {}", cpu));
+    }
+
+    let mut out = String::from("{\"displayTimeUnit\":\"ns\",\"traceEvents\":[\n");
+    for (i, event) in writer.events.iter().enumerate() {
+        let sep = if i + 1 == writer.events.len() {
+            ""
+        } else {
+            ","
+        };
+        let _ = writeln!(out, "{}{}", event, sep);
+    }
+    out.push_str("]}\n");
+    out
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("usage: {} <input> [output]", args[0]);
+        process::exit(2);
+    }
+
+    let bytes = fs::read(&args[1]).unwrap_or_else(|err| {
+        eprintln!("failed to read {}: {}", args[1], err);
+        process::exit(1);
+    });
+    if !bytes.len().is_multiple_of(RECORD_SIZE) {
+        eprintln!(
+            "warning: ignoring {} trailing bytes",
+            bytes.len() % RECORD_SIZE
+        );
+    }
+
+    let json = convert(&parse(&bytes));
+    let result = match args.get(2) {
+        Some(path) => fs::write(path, json),
+        None => io::stdout().write_all(json.as_bytes()),
+    };
+    if let Err(err) = result {
+        eprintln!("failed to write output: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(record: &Record) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&record.timestamp.to_le_bytes());
+        out.extend_from_slice(&record.pid.to_le_bytes());
+        out.extend_from_slice(&record.tid.to_le_bytes());
+        out.extend_from_slice(&record.event.to_le_bytes());
+        out.extend_from_slice(&record.cpu.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        for arg in record.args {
+            out.extend_from_slice(&arg.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn parse_sorts_by_timestamp() {
+        let late = Record {
+            timestamp: 2000,
+            pid: 3,
+            tid: 4,
+            event: EVENT_PORT_QUEUE,
+            cpu: 1,
+            args: [5, 6, 7],
+        };
+        let early = Record {
+            timestamp: 1000,
+            ..late
+        };
+        let mut bytes = encode(&late);
+        bytes.extend(encode(&early));
+        bytes.push(0);
+
+        assert_eq!(parse(&bytes), vec![early, late]);
+    }
+
+    #[test]
+    fn syscall_exit_becomes_complete_event() {
+        let record = Record {
+            timestamp: 5000,
+            pid: 1,
+            tid: 2,
+            event: EVENT_SYSCALL_EXIT,
+            cpu: 0,
+            args: [0x12, 1500, u64::MAX],
+        };
+        let json = convert(&[record]);
+
+        assert!(json.contains(
+            r#"{"name":"syscall 0x12","ph":"X","pid":1,"tid":2,"ts":3.500,"dur":1.500,"args":{"ret":-1}}"#
+        ));
+    }
+}
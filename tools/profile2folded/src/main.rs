@@ -0,0 +1,293 @@
+//! 把采样分析器导出的样本符号化为折叠栈格式，可以直接交给 flamegraph.pl 或 inferno 生成火焰图
+//!
+//! 输入为连续的 152 字节小端记录，格式与内核 `profile::ProfileSample` 相同。
+//! 内核地址用内核 ELF 的符号表查找，用户地址依次在给出的用户程序 ELF 中查找，找不到时输出十六进制地址。
+//! 内核栈帧带有 `_[k]` 后缀，火焰图工具会用不同的颜色显示。
+//!
+//! 用法：`profile2folded <样本文件> <内核 ELF> [用户程序 ELF...]`，结果写到标准输出。
+
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::{env, fs, io, process};
+
+use object::{File, Object, ObjectSymbol, SymbolKind};
+use rustc_demangle::demangle;
+
+const SAMPLE_SIZE: usize = 152;
+const STACK_DEPTH: usize = 16;
+
+const FLAG_DROPPED: u16 = 1 << 1;
+
+/// 内核位于高半部分地址空间
+const KERNEL_SPACE_START: u64 = 0xffff_8000_0000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sample {
+    pid: u32,
+    tid: u32,
+    flags: u16,
+    depth: u32,
+    frames: [u64; STACK_DEPTH],
+}
+
+impl Sample {
+    /// 有效的栈帧，从被中断的位置开始
+    fn stack(&self) -> &[u64] {
+        &self.frames[..(self.depth as usize).min(STACK_DEPTH)]
+    }
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+/// 解析样本，末尾不完整的记录被忽略
+fn parse(bytes: &[u8]) -> Vec<Sample> {
+    bytes
+        .chunks_exact(SAMPLE_SIZE)
+        .map(|chunk| Sample {
+            pid: u32_at(chunk, 8),
+            tid: u32_at(chunk, 12),
+            flags: u16_at(chunk, 18),
+            depth: u32_at(chunk, 20),
+            frames: std::array::from_fn(|i| u64_at(chunk, 24 + i * 8)),
+        })
+        .collect()
+}
+
+/// 一个 ELF 文件中的函数符号，按起始地址排序
+struct SymbolTable {
+    symbols: Vec<(u64, u64, String)>,
+}
+
+impl SymbolTable {
+    /// 与内核 panic 时的查找方式相同：按符号的地址范围匹配，名字用 rustc-demangle 还原
+    fn from_elf(bytes: &[u8]) -> Result<Self, object::Error> {
+        let file = File::parse(bytes)?;
+        let symbols = file
+            .symbols()
+            .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.size() != 0)
+            .filter_map(|symbol| {
+                let name = symbol.name().ok()?;
+                Some((
+                    symbol.address(),
+                    symbol.size(),
+                    format!("{:#}", demangle(name)),
+                ))
+            })
+            .collect();
+        Ok(Self::new(symbols))
+    }
+
+    fn new(mut symbols: Vec<(u64, u64, String)>) -> Self {
+        symbols.sort_by_key(|&(start, _, _)| start);
+        Self { symbols }
+    }
+
+    fn lookup(&self, address: u64) -> Option<&str> {
+        let index = self
+            .symbols
+            .partition_point(|&(start, _, _)| start <= address)
+            .checked_sub(1)?;
+        let (start, size, name) = &self.symbols[index];
+        (address - start < *size).then_some(name.as_str())
+    }
+}
+
+struct Symbolizer {
+    kernel: SymbolTable,
+    user: Vec<SymbolTable>,
+}
+
+impl Symbolizer {
+    /// 栈帧的名字，`return_address` 表示地址是调用之后的返回地址
+    fn frame_name(&self, address: u64, return_address: bool) -> String {
+        // 返回地址可能已经是下一个函数的开头，减一后落在调用指令内
+        let lookup = if return_address {
+            address.wrapping_sub(1)
+        } else {
+            address
+        };
+        if lookup >= KERNEL_SPACE_START {
+            return match self.kernel.lookup(lookup) {
+                Some(name) => format!("{}_[k]", name),
+                None => format!("{:#x}_[k]", address),
+            };
+        }
+        self.user
+            .iter()
+            .find_map(|table| table.lookup(lookup))
+            .map_or_else(|| format!("{:#x}", address), str::to_string)
+    }
+}
+
+/// 按栈聚合样本，返回折叠栈格式的文本和被丢弃的样本数
+///
+/// 每行形如 `pid 3;tid 4;main;foo;bar 12`，栈从最外层到被中断的位置，内核任务的根为 `kernel`。
+fn fold(samples: &[Sample], symbolizer: &Symbolizer) -> (String, u64) {
+    let mut stacks: BTreeMap<String, u64> = BTreeMap::new();
+    let mut dropped = 0;
+
+    for sample in samples {
+        if sample.flags & FLAG_DROPPED != 0 {
+            dropped += sample.frames[0];
+            continue;
+        }
+        if sample.stack().is_empty() {
+            continue;
+        }
+
+        let mut names = vec![if sample.pid == 0 {
+            "kernel".to_string()
+        } else {
+            format!("pid {};tid {}", sample.pid, sample.tid)
+        }];
+        names.extend(
+            sample
+                .stack()
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(i, &address)| symbolizer.frame_name(address, i != 0)),
+        );
+        // 折叠栈格式以最后一个空格分隔计数，栈帧名中的空格不影响解析
+        *stacks.entry(names.join(";")).or_default() += 1;
+    }
+
+    let mut out = String::new();
+    for (stack, count) in stacks {
+        out.push_str(&stack);
+        out.push(' ');
+        out.push_str(&count.to_string());
+        out.push('\n');
+    }
+    (out, dropped)
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|err| {
+        eprintln!("failed to read {}: {}", path, err);
+        process::exit(1);
+    })
+}
+
+fn load_symbols(path: &str) -> SymbolTable {
+    SymbolTable::from_elf(&read_file(path)).unwrap_or_else(|err| {
+        eprintln!("failed to parse {}: {}", path, err);
+        process::exit(1);
+    })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!("usage: {} <samples> <kernel elf> [user elf...]", args[0]);
+        process::exit(2);
+    }
+
+    let bytes = read_file(&args[1]);
+    if !bytes.len().is_multiple_of(SAMPLE_SIZE) {
+        eprintln!(
+            "warning: ignoring {} trailing bytes",
+            bytes.len() % SAMPLE_SIZE
+        );
+    }
+    let symbolizer = Symbolizer {
+        kernel: load_symbols(&args[2]),
+        user: args[3..].iter().map(|path| load_symbols(path)).collect(),
+    };
+
+    let (folded, dropped) = fold(&parse(&bytes), &symbolizer);
+    if dropped != 0 {
+        eprintln!("warning: {} samples were dropped by the kernel", dropped);
+    }
+    if let Err(err) = io::stdout().write_all(folded.as_bytes()) {
+        eprintln!("failed to write output: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(sample: &Sample) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&1000u64.to_le_bytes());
+        out.extend_from_slice(&sample.pid.to_le_bytes());
+        out.extend_from_slice(&sample.tid.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&sample.flags.to_le_bytes());
+        out.extend_from_slice(&sample.depth.to_le_bytes());
+        for frame in sample.frames {
+            out.extend_from_slice(&frame.to_le_bytes());
+        }
+        out
+    }
+
+    fn sample(pid: u32, stack: &[u64]) -> Sample {
+        let mut frames = [0; STACK_DEPTH];
+        frames[..stack.len()].copy_from_slice(stack);
+        Sample {
+            pid,
+            tid: pid + 1,
+            flags: 0,
+            depth: stack.len() as u32,
+            frames,
+        }
+    }
+
+    fn symbolizer() -> Symbolizer {
+        Symbolizer {
+            kernel: SymbolTable::new(vec![
+                (0xffff_ffff_8000_1000, 0x100, "kernel::schedule".into()),
+                (0xffff_ffff_8000_0000, 0x100, "kernel::idle".into()),
+            ]),
+            user: vec![SymbolTable::new(vec![
+                (0x1000, 0x10, "app::main".into()),
+                (0x1010, 0x10, "app::work".into()),
+            ])],
+        }
+    }
+
+    #[test]
+    fn parse_reads_all_fields() {
+        let expected = sample(3, &[0x1005, 0x1012]);
+        let mut bytes = encode(&expected);
+        bytes.push(0);
+
+        assert_eq!(parse(&bytes), vec![expected]);
+    }
+
+    #[test]
+    fn fold_aggregates_and_symbolizes() {
+        let samples = [
+            // 返回地址 0x1010 恰好是下一个函数的开头，应当归到调用者 app::main
+            sample(3, &[0x1015, 0x1010]),
+            sample(3, &[0x1015, 0x1010]),
+            sample(0, &[0xffff_ffff_8000_0010, 0xffff_ffff_8000_1008]),
+            sample(3, &[0x2000]),
+            Sample {
+                flags: FLAG_DROPPED,
+                ..sample(0, &[7])
+            },
+        ];
+        let (folded, dropped) = fold(&samples, &symbolizer());
+
+        assert_eq!(dropped, 7);
+        assert_eq!(
+            folded,
+            "kernel;kernel::schedule_[k];kernel::idle_[k] 1\n\
+             pid 3;tid 4;0x2000 1\n\
+             pid 3;tid 4;app::main;app::work 2\n"
+        );
+    }
+}